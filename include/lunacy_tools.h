@@ -0,0 +1,41 @@
+/* The C interface to the lunacy-tools document engine, built with `--features ffi`.
+ *
+ * Functions returning `int` return 0 on success and -1 on failure, with a description of the
+ * failure available from `lunacy_last_error`. */
+
+#ifndef LUNACY_TOOLS_H
+#define LUNACY_TOOLS_H
+
+#include <stddef.h>
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+/* A document opened from a buffer, with the changes made to it since. */
+typedef struct LunacyDocument LunacyDocument;
+
+/* Open the .free document in the `len` bytes at `data`, or return null if it can't be read. */
+LunacyDocument *lunacy_open(const uint8_t *data, size_t len);
+
+/* Apply a color scheme, a json object in the same form as a --color_scheme file, to `group`. */
+int lunacy_update_colors(LunacyDocument *doc, const char *group, const char *scheme);
+
+/* Write the updated document to a new buffer, freed with `lunacy_free_buffer`. */
+int lunacy_commit(LunacyDocument *doc, uint8_t **out, size_t *out_len);
+
+/* Free a buffer returned by `lunacy_commit`. */
+void lunacy_free_buffer(uint8_t *data, size_t len);
+
+/* Close a document, discarding any uncommitted changes. */
+void lunacy_close(LunacyDocument *doc);
+
+/* Get a description of the last failure on the calling thread, or null. */
+const char *lunacy_last_error(void);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif
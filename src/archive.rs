@@ -0,0 +1,224 @@
+use std::io::{Cursor, Read, Write};
+
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use zip::write::SimpleFileOptions;
+use zip::{ZipArchive, ZipWriter};
+
+use crate::palette::{self, ColorScheme};
+use crate::rewrite;
+use crate::schema::{self, DocumentJson};
+use crate::Error;
+
+/// The entry holding the color variables of a document.
+pub const DOCUMENT_ENTRY: &str = "document.json";
+
+/// Limits enforced when extracting a document, guarding against malicious archives.
+#[derive(Clone, Copy, Debug)]
+pub struct ExtractLimits {
+    /// The maximum number of entries in the archive.
+    pub max_entries : usize,
+    /// The maximum total decompressed size of the archive in bytes.
+    pub max_size    : u64,
+}
+
+impl Default for ExtractLimits {
+    fn default() -> Self {
+        Self {
+            max_entries : 100_000,
+            max_size    : 16 * 1024 * 1024 * 1024,
+        }
+    }
+}
+
+/// An error raised when an archive is unsafe to extract.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ArchiveError {
+    /// An entry's path points outside of the extraction directory.
+    UnsafePath(String),
+    /// The archive contains more entries than allowed.
+    TooManyEntries(usize),
+    /// The archive decompresses to more than the allowed number of bytes.
+    TooLarge(u64),
+    /// The archive is encrypted and no password was provided.
+    PasswordRequired,
+}
+
+impl std::fmt::Display for ArchiveError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Self::UnsafePath(x)     => write!(f, "archive entry `{x}` has an unsafe path"),
+            Self::TooManyEntries(x) => write!(f, "archive contains {x} entries which exceeds the entry limit"),
+            Self::TooLarge(x)       => write!(f, "archive decompresses to more than the {x} byte limit"),
+            Self::PasswordRequired  => write!(f, "archive is encrypted and requires a password"),
+        }
+    }
+}
+
+impl std::error::Error for ArchiveError { }
+
+/// A `.free` document held in memory, with the changes made to its `document.json` since it was
+/// opened or last committed.
+pub struct Document {
+    /// The contents of the document as last committed.
+    data    : Vec<u8>,
+    /// The parsed `document.json` of the document, with any changes applied.
+    json    : DocumentJson,
+    /// The limits enforced when reading the document.
+    limits  : ExtractLimits,
+    /// Whether the document has changed since it was last committed.
+    changed : bool,
+}
+
+impl Document {
+    /// Open the document in `data`, enforcing `limits` whenever it's read.
+    pub fn open(data: Vec<u8>, limits: ExtractLimits) -> Result<Self, Error> {
+        let json = read_json(&data, DOCUMENT_ENTRY, limits)?
+            .unwrap_or_default();
+
+        Ok(Self { data, json, limits, changed: false })
+    }
+
+    /// Get the `document.json` of the document, with any changes applied.
+    pub fn json(&self) -> &DocumentJson {
+        &self.json
+    }
+
+    /// Apply the color `scheme` to the colors in `group`.
+    pub fn update_colors(&mut self, group: &str, scheme: &ColorScheme) -> Result<(), Error> {
+        palette::apply(&mut self.json, group, scheme)?;
+        self.changed = true;
+
+        Ok(())
+    }
+
+    /// Commit the changes made to the document, returning its updated contents.
+    ///
+    /// Only the color variables and color styles can change, so only they are written again
+    /// where the original `document.json` has them.
+    pub fn commit(&mut self) -> Result<&[u8], Error> {
+        if self.changed {
+            self.data       = write_entry(&self.data, DOCUMENT_ENTRY, self.limits, |original| {
+                match original.and_then(|x| Some((x, rewrite::find_member(x, "colorVariables")?))) {
+                    Some((original, range)) => {
+                        let json = rewrite::splice(original, range, &self.json.color_variables)?;
+                        match (rewrite::find_member(&json, "colorStyles"), &self.json.color_styles) {
+                            (Some(range), _)    => rewrite::splice(&json, range, &self.json.color_styles),
+                            (None, Some(_))     => rewrite::to_string(&json, &self.json),
+                            (None, None)        => Ok(json),
+                        }
+                    }
+                    None                    => write_text(original, &self.json),
+                }
+            })?;
+            self.changed    = false;
+        }
+
+        Ok(&self.data)
+    }
+}
+
+/// Read the json entry `name` of the `.free` document in `data`, or `None` if it doesn't exist,
+/// enforcing `limits`.
+pub fn read_json<T: DeserializeOwned>(data: &[u8], name: &str, limits: ExtractLimits) -> Result<Option<T>, Error> {
+    let mut archive = open_archive(data, limits)?;
+    let Some(index) = archive.index_for_name(name) else {
+        return Ok(None);
+    };
+
+    let mut json = read_entry(&mut archive, index, limits)?;
+
+    Ok(Some(schema::from_slice(&mut json)?))
+}
+
+/// Apply the color `scheme` to the colors in `group` of the `.free` document in `data`,
+/// returning the updated document.
+pub fn update_colors(data: &[u8], group: &str, scheme: &ColorScheme) -> Result<Vec<u8>, Error> {
+    let mut doc = Document::open(data.to_vec(), ExtractLimits::default())?;
+    doc.update_colors(group, scheme)?;
+
+    Ok(doc.commit()?.to_vec())
+}
+
+/// Replace the json entry `name` of the `.free` document in `data` with `json`, returning the
+/// updated document. The entry is added at the end if it doesn't exist.
+///
+/// Only the entry is written again, every other entry is copied as is, so the order, compression
+/// and timestamps of the original are kept. The entry keeps the text of any values that didn't
+/// change. The original entry is read enforcing `limits`.
+pub fn write_json<T: Serialize>(data: &[u8], name: &str, json: &T, limits: ExtractLimits) -> Result<Vec<u8>, Error> {
+    write_entry(data, name, limits, |original| write_text(original, json))
+}
+
+/// Write `json` as a replacement for the `original` text of an entry, if it has one.
+fn write_text<T: Serialize>(original: Option<&str>, json: &T) -> Result<String, Error> {
+    match original {
+        Some(original)  => rewrite::to_string(original, json),
+        None            => Ok(serde_json::to_string(json)?),
+    }
+}
+
+/// Replace the entry `name` of the `.free` document in `data` with the text `write` returns given
+/// the original text of the entry, if it exists, returning the updated document. The original
+/// entry is read enforcing `limits`.
+fn write_entry(
+    data    : &[u8],
+    name    : &str,
+    limits  : ExtractLimits,
+    write   : impl FnOnce(Option<&str>) -> Result<String, Error>
+)
+    -> Result<Vec<u8>, Error>
+{
+    let mut archive = open_archive(data, limits)?;
+    let mut writer  = ZipWriter::new(Cursor::new(Vec::new()));
+    writer.set_raw_comment(archive.comment().into());
+
+    let mut write = Some(write);
+    for i in 0..archive.len() {
+        let entry = archive.by_index_raw(i)?;
+        if entry.name() != name {
+            writer.raw_copy_file(entry)?;
+            continue;
+        }
+
+        let options = entry.options();
+        drop(entry);
+
+        let original = String::from_utf8(read_entry(&mut archive, i, limits)?)?;
+
+        if let Some(write) = write.take() {
+            writer.start_file(name, options)?;
+            writer.write_all(write(Some(&original))?.as_bytes())?;
+        }
+    }
+
+    if let Some(write) = write {
+        writer.start_file(name, SimpleFileOptions::default())?;
+        writer.write_all(write(None)?.as_bytes())?;
+    }
+
+    Ok(writer.finish()?.into_inner())
+}
+
+/// Open the archive of the `.free` document in `data`, refusing it if it has more entries than
+/// `limits` allow.
+fn open_archive(data: &[u8], limits: ExtractLimits) -> Result<ZipArchive<Cursor<&[u8]>>, Error> {
+    let archive = ZipArchive::new(Cursor::new(data))?;
+    if archive.len() > limits.max_entries {
+        return Err(Box::new(ArchiveError::TooManyEntries(archive.len())));
+    }
+
+    Ok(archive)
+}
+
+/// Read the entry at `index` of `archive`, refusing it if it decompresses to more than `limits`
+/// allow.
+fn read_entry(archive: &mut ZipArchive<Cursor<&[u8]>>, index: usize, limits: ExtractLimits) -> Result<Vec<u8>, Error> {
+    let mut data = Vec::new();
+    archive.by_index(index)?.take(limits.max_size + 1).read_to_end(&mut data)?;
+    if data.len() as u64 > limits.max_size {
+        return Err(Box::new(ArchiveError::TooLarge(limits.max_size)));
+    }
+
+    Ok(data)
+}
@@ -0,0 +1,98 @@
+use std::any::{Any, TypeId};
+use std::collections::VecDeque;
+use std::hash::{BuildHasher, RandomState};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Mutex, OnceLock};
+
+/// The number of parsed entries kept.
+const CAPACITY: usize = 16;
+
+/// Whether parsed entries are cached.
+static ENABLED: AtomicBool = AtomicBool::new(false);
+
+/// The parsed entries, least recently used first.
+static PARSED: Mutex<VecDeque<Parsed>> = Mutex::new(VecDeque::new());
+
+/// The key the contents of entries are hashed with, random for each run so no document can be
+/// made to collide with another.
+static KEY: OnceLock<RandomState> = OnceLock::new();
+
+/// The contents of an entry, identified by a keyed hash of the bytes read and their size.
+///
+/// The checksum is taken of what was actually read, after decryption, rather than what the
+/// archive records, so a document can only ever be given a model parsed from the very same
+/// contents.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Checksum {
+    /// The keyed hash of the contents.
+    pub hash    : u64,
+    /// The size of the contents in bytes.
+    pub size    : u64,
+}
+
+impl Checksum {
+    /// Take the checksum of the contents `data`.
+    pub fn of(data: &[u8]) -> Self {
+        Self {
+            hash    : KEY.get_or_init(RandomState::new).hash_one(data),
+            size    : data.len() as u64,
+        }
+    }
+}
+
+/// An entry parsed into a model.
+struct Parsed {
+    /// The contents the entry was parsed from.
+    checksum: Checksum,
+    /// The type of the model.
+    kind    : TypeId,
+    /// The model.
+    value   : Box<dyn Any + Send>,
+}
+
+/// Cache parsed entries for the rest of the run, so documents opened again, such as by the
+/// requests to a server, don't have their entries extracted and parsed each time.
+///
+/// Caching costs a copy of every model parsed, so it's only worth it for runs that open the same
+/// documents more than once.
+pub fn enable() {
+    ENABLED.store(true, Ordering::Relaxed);
+}
+
+/// Check whether parsed entries are cached.
+pub fn enabled() -> bool {
+    ENABLED.load(Ordering::Relaxed)
+}
+
+/// Get the entry with `checksum` parsed as `T`, if it has been.
+pub fn get<T: Clone + 'static>(checksum: Checksum) -> Option<T> {
+    if !ENABLED.load(Ordering::Relaxed) {
+        return None;
+    }
+
+    let mut parsed  = PARSED.lock().ok()?;
+    let index       = parsed.iter().position(|x| x.checksum == checksum && x.kind == TypeId::of::<T>())?;
+    let entry       = parsed.remove(index)?;
+    let value       = entry.value.downcast_ref::<T>().cloned();
+    parsed.push_back(entry);
+
+    value
+}
+
+/// Keep `value` as the entry with `checksum` parsed as `T`, dropping the least recently used
+/// entry when the cache is full.
+pub fn insert<T: Clone + Send + 'static>(checksum: Checksum, value: &T) {
+    if !ENABLED.load(Ordering::Relaxed) {
+        return;
+    }
+
+    let Ok(mut parsed) = PARSED.lock() else {
+        return;
+    };
+
+    if parsed.len() >= CAPACITY {
+        parsed.pop_front();
+    }
+
+    parsed.push_back(Parsed { checksum, kind: TypeId::of::<T>(), value: Box::new(value.clone()) });
+}
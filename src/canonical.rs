@@ -0,0 +1,75 @@
+use json::JsonValue;
+
+use crate::model::STYLE_KINDS;
+
+/// The number of decimal places numbers are rounded to, enough for any coordinate or color while
+/// hiding floating point noise such as `0.30000000000000004`.
+const DECIMAL_PLACES: i32 = 6;
+
+/// The keys of `document.json` holding lists whose order has no meaning to Lunacy beyond
+/// presentation, which are sorted by name.
+fn unordered_keys() -> impl Iterator<Item = &'static str> {
+    std::iter::once("colorVariables").chain(STYLE_KINDS.iter().map(|(_, key)| *key))
+}
+
+/// Rewrite the json of the document entry `entry` in canonical form, so the same content is
+/// always written the same way and diffs between versions only show real changes.
+///
+/// Object keys are sorted, numbers are rounded to a consistent precision and, in
+/// `document.json`, variables and styles are sorted by name. Layers keep their order since it
+/// decides how they stack.
+pub fn canonicalize(entry: &str, json: &JsonValue) -> JsonValue {
+    let mut json = canonical_value(json);
+    if entry == "document.json" {
+        for key in unordered_keys() {
+            // Indexing mutably would add the key to documents without it.
+            if !json.has_key(key) {
+                continue;
+            }
+
+            if let JsonValue::Array(values) = &mut json[key] {
+                values.sort_by(|a, b| sort_key(a).cmp(&sort_key(b)));
+            }
+        }
+    }
+
+    json
+}
+
+/// Get a copy of `json` with its object keys sorted and its numbers rounded.
+fn canonical_value(json: &JsonValue) -> JsonValue {
+    match json {
+        JsonValue::Object(object) => {
+            let mut entries = object.iter().collect::<Vec<_>>();
+            entries.sort_by_key(|(key, _)| *key);
+
+            let mut sorted = JsonValue::new_object();
+            for (key, value) in entries {
+                sorted[key] = canonical_value(value);
+            }
+
+            sorted
+        }
+        JsonValue::Array(values) => {
+            JsonValue::Array(values.iter().map(canonical_value).collect())
+        }
+        JsonValue::Number(_) => {
+            match json.as_f64() {
+                Some(x) if x.fract() != 0.0 => round(x).into(),
+                _                           => json.clone(),
+            }
+        }
+        _ => json.clone(),
+    }
+}
+
+/// Round `value` to `DECIMAL_PLACES` decimal places.
+fn round(value: f64) -> f64 {
+    let scale = 10f64.powi(DECIMAL_PLACES);
+    (value * scale).round() / scale
+}
+
+/// Get the key a variable or style is sorted by, its name with its id breaking ties.
+fn sort_key(json: &JsonValue) -> (&str, &str) {
+    (json["name"].as_str().unwrap_or_default(), json["id"].as_str().unwrap_or_default())
+}
@@ -0,0 +1,195 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use json::{object, JsonValue};
+
+use crate::document::LunacyDocument;
+use crate::Error;
+
+/// The entry the checkpoints of a document are listed in.
+pub const CHECKPOINTS_ENTRY: &str = "lunacy-tools/checkpoints.json";
+
+/// The directory holding the snapshot taken at each checkpoint.
+pub const CHECKPOINT_DIR: &str = "lunacy-tools/checkpoints/";
+
+/// A named snapshot of the palette of a document, and optionally its whole `document.json`.
+#[derive(Clone, Debug)]
+pub struct Checkpoint {
+    /// The number of the checkpoint, counting from 1 in the order they were created.
+    pub number      : usize,
+    /// When the checkpoint was created, in seconds since the unix epoch, if recorded.
+    pub time        : Option<u64>,
+    /// The version of the tool that created the checkpoint.
+    pub version     : String,
+    /// The message naming the checkpoint, e.g. `Q3 rebrand`.
+    pub message     : String,
+    /// The number of colors in the palette when the checkpoint was created.
+    pub colors      : usize,
+    /// The entry holding the snapshot of the palette.
+    pub palette     : String,
+    /// The entry holding the snapshot of `document.json`, if it was taken.
+    pub document    : Option<String>,
+}
+
+impl Checkpoint {
+    /// Read a checkpoint from its json form.
+    pub fn from_json(json: &JsonValue) -> Self {
+        Self {
+            number      : json["number"].as_usize().unwrap_or_default(),
+            time        : json["time"].as_u64(),
+            version     : json["version"].as_str().unwrap_or_default().to_owned(),
+            message     : json["message"].as_str().unwrap_or_default().to_owned(),
+            colors      : json["colors"].as_usize().unwrap_or_default(),
+            palette     : json["palette"].as_str().unwrap_or_default().to_owned(),
+            document    : json["document"].as_str().map(|x| x.to_owned()),
+        }
+    }
+
+    /// Convert the checkpoint to json for storage.
+    pub fn to_json(&self) -> JsonValue {
+        let mut json = object! {
+            "number"    : self.number,
+            "version"   : self.version.as_str(),
+            "message"   : self.message.as_str(),
+            "colors"    : self.colors,
+            "palette"   : self.palette.as_str(),
+        };
+
+        if let Some(time) = self.time {
+            json["time"] = time.into();
+        }
+        if let Some(document) = &self.document {
+            json["document"] = document.as_str().into();
+        }
+
+        json
+    }
+}
+
+/// Load the checkpoints of `doc`, oldest first.
+pub fn load(doc: &LunacyDocument) -> Result<Vec<Checkpoint>, Error> {
+    if !doc.has_entry(CHECKPOINTS_ENTRY) {
+        return Ok(vec![]);
+    }
+
+    let json = doc.load_json(CHECKPOINTS_ENTRY)?;
+    Ok(json["checkpoints"].members().map(Checkpoint::from_json).collect())
+}
+
+/// Create a checkpoint of `doc` named by `message`, snapshotting its palette, and its whole
+/// `document.json` too when `full`, timestamped now if `timestamp` is set.
+pub fn create(doc: &mut LunacyDocument, message: &str, full: bool, timestamp: bool) -> Result<Checkpoint, Error> {
+    let mut checkpoints = load(doc)?;
+    let number          = checkpoints.iter().map(|x| x.number).max().unwrap_or_default() + 1;
+
+    let colors = doc.load_color_variables()?
+        .iter()
+        .map(|x| object! { "name": x.name.as_str(), "value": x.value.as_str() })
+        .collect::<Vec<_>>();
+
+    let palette = format!("{CHECKPOINT_DIR}{number}.json");
+    doc.save_json(&palette, &object! { "colors": colors.as_slice() })?;
+
+    // The document is copied as is rather than parsed, so the snapshot is exactly as it was.
+    let document = match full {
+        true    => {
+            let entry = format!("{CHECKPOINT_DIR}{number}/document.json");
+            let bytes = doc.load_bytes("document.json")?;
+            doc.save_bytes(&entry, &bytes)?;
+            Some(entry)
+        }
+        false   => None,
+    };
+
+    let time = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .ok()
+        .filter(|_| timestamp)
+        .map(|x| x.as_secs());
+
+    let checkpoint = Checkpoint {
+        number,
+        time,
+        version     : env!("CARGO_PKG_VERSION").to_owned(),
+        message     : message.to_owned(),
+        colors      : colors.len(),
+        palette,
+        document,
+    };
+    checkpoints.push(checkpoint.clone());
+
+    let json = object! {
+        "checkpoints": checkpoints.iter().map(|x| x.to_json()).collect::<Vec<_>>(),
+    };
+    doc.save_json(CHECKPOINTS_ENTRY, &json)?;
+
+    Ok(checkpoint)
+}
+
+/// Merge the checkpoints of two versions of a document, `ours` and `theirs`, with those of their
+/// common ancestor `base`, each given as every entry of the version. Returns the list of
+/// checkpoints and the snapshots of the merged document.
+///
+/// Our checkpoints are kept as they are, and those theirs added are numbered after ours along
+/// with their snapshots, so checkpoints created in both versions never take each other's place.
+pub fn merge(
+    base    : &[(String, Vec<u8>)],
+    ours    : &[(String, Vec<u8>)],
+    theirs  : &[(String, Vec<u8>)]
+)
+    -> Result<Vec<(String, Vec<u8>)>, Error>
+{
+    let find = |entries: &[(String, Vec<u8>)], name: &str| entries.iter()
+        .find(|(x, _)| x == name)
+        .map(|(_, data)| data.clone());
+
+    let checkpoints = |entries: &[(String, Vec<u8>)]| -> Result<Vec<Checkpoint>, Error> {
+        let Some(data) = find(entries, CHECKPOINTS_ENTRY) else {
+            return Ok(vec![]);
+        };
+
+        let json = json::parse(&String::from_utf8_lossy(&data))?;
+        Ok(json["checkpoints"].members().map(Checkpoint::from_json).collect())
+    };
+
+    let mut merged = ours.iter()
+        .filter(|(name, _)| name.starts_with(CHECKPOINT_DIR))
+        .cloned()
+        .collect::<Vec<_>>();
+
+    // Checkpoints theirs shares with the ancestor are already among ours, each counted once so
+    // checkpoints that happen to look the same are still kept.
+    let key         = |x: &Checkpoint| Checkpoint { number: 0, palette: String::new(), document: None, ..x.clone() }.to_json().dump();
+    let mut base    = checkpoints(base)?.iter().map(key).collect::<Vec<_>>();
+    let mut list    = checkpoints(ours)?;
+    let mut number  = list.iter().map(|x| x.number).max().unwrap_or_default();
+    for mut checkpoint in checkpoints(theirs)? {
+        if let Some(index) = base.iter().position(|x| *x == key(&checkpoint)) {
+            base.remove(index);
+            continue;
+        }
+
+        number += 1;
+        checkpoint.number = number;
+
+        if let Some(data) = find(theirs, &checkpoint.palette) {
+            checkpoint.palette = format!("{CHECKPOINT_DIR}{number}.json");
+            merged.push((checkpoint.palette.clone(), data));
+        }
+        if let Some(data) = checkpoint.document.as_deref().and_then(|x| find(theirs, x)) {
+            let document = format!("{CHECKPOINT_DIR}{number}/document.json");
+            merged.push((document.clone(), data));
+            checkpoint.document = Some(document);
+        }
+
+        list.push(checkpoint);
+    }
+
+    if !list.is_empty() {
+        let json = object! {
+            "checkpoints": list.iter().map(|x| x.to_json()).collect::<Vec<_>>(),
+        };
+        merged.insert(0, (CHECKPOINTS_ENTRY.to_owned(), json.dump().into_bytes()));
+    }
+
+    Ok(merged)
+}
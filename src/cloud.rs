@@ -0,0 +1,182 @@
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use tempdir::TempDir;
+
+use crate::Error;
+
+/// The scheme of urls referring to a cloud document by id, e.g. `lunacy://<id>`.
+pub const SCHEME: &str = "lunacy://";
+
+/// The environment variable the cloud access token is read from.
+pub const TOKEN_VAR: &str = "LUNACY_CLOUD_TOKEN";
+
+/// How long to wait for the cloud to respond.
+const CLOUD_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// Options for accessing documents in the cloud.
+#[derive(Clone, Debug, Default)]
+pub struct CloudOptions {
+    /// The base url of the cloud api that `lunacy://` urls are resolved against.
+    pub api     : Option<String>,
+    /// The token sent with every request to authorize it.
+    pub token   : Option<String>,
+}
+
+/// Check whether the document argument `path` is the url of a cloud document rather than a
+/// local path.
+pub fn is_url(path: &Path) -> bool {
+    path.to_str().is_some_and(|x| x.starts_with(SCHEME) || x.starts_with("https://") || x.starts_with("http://"))
+}
+
+/// A cloud document downloaded for edit, uploaded as a new revision when changes are committed.
+pub struct Remote {
+    /// The url the document is downloaded from and uploaded to.
+    url         : String,
+    /// The revision of the document last downloaded or uploaded, as reported by its `ETag`.
+    revision    : Option<String>,
+    /// The token sent with every request to authorize it.
+    token       : Option<String>,
+    /// The local copy of the document.
+    path        : PathBuf,
+    /// The directory holding the local copy.
+    _dir        : TempDir,
+}
+
+impl Remote {
+    /// Download the cloud document at `url`, reading at most `limit` bytes.
+    ///
+    /// `lunacy://<id>` urls refer to the document `<id>` of the configured cloud api, other urls
+    /// are downloaded and uploaded as they are.
+    pub fn fetch(url: &str, options: &CloudOptions, limit: u64) -> Result<Self, Error> {
+        if !url.starts_with(SCHEME) && !url.starts_with("https://") {
+            return Err(Box::new(CloudError::Insecure(url.to_owned())));
+        }
+
+        let url = match url.strip_prefix(SCHEME) {
+            Some(id)    => {
+                let Some(api) = options.api.as_deref() else {
+                    return Err(Box::new(CloudError::NoApi));
+                };
+                if !api.starts_with("https://") {
+                    return Err(Box::new(CloudError::Insecure(api.to_owned())));
+                }
+
+                format!("{}/documents/{id}/content", api.trim_end_matches('/'))
+            }
+            None        => url.to_owned(),
+        };
+
+        // The token is only sent to the configured api, never to any other url given.
+        let token = options.token.clone()
+            .filter(|_| options.api.as_deref().is_some_and(|x| same_origin(x, &url)));
+
+        let mut request = agent().get(&url);
+        if let Some(token) = &token {
+            request = request.header("Authorization", &format!("Bearer {token}"));
+        }
+
+        let mut response = request.call()?;
+        let revision = response.headers()
+            .get("ETag")
+            .and_then(|x| x.to_str().ok())
+            .map(|x| x.to_owned());
+
+        let data = response.body_mut()
+            .with_config()
+            .limit(limit)
+            .read_to_vec()?;
+
+        // The local copy takes the extension of the document so Sketch documents are recognized.
+        let extension = match url.split(['?', '#']).next().unwrap_or_default().ends_with(".sketch") {
+            true    => "sketch",
+            false   => "free",
+        };
+
+        let dir     = TempDir::new("lunacy-tools-cloud")?;
+        let path    = dir.path().join(format!("document.{extension}"));
+        std::fs::write(&path, data)?;
+
+        Ok(Self { url, revision, token, path, _dir: dir })
+    }
+
+    /// Get the path of the local copy of the document.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Get the url of the document.
+    pub fn url(&self) -> &str {
+        &self.url
+    }
+
+    /// Upload the local copy of the document as a new revision.
+    ///
+    /// The upload is conditional on the revision downloaded, so changes made in the cloud since
+    /// are never overwritten, the upload fails instead.
+    pub fn push(&mut self) -> Result<(), Error> {
+        let mut request = agent().put(&self.url)
+            .header("Content-Type", "application/octet-stream");
+        if let Some(token) = &self.token {
+            request = request.header("Authorization", &format!("Bearer {token}"));
+        }
+        if let Some(revision) = &self.revision {
+            request = request.header("If-Match", revision);
+        }
+
+        let response = match request.send(std::fs::read(&self.path)?) {
+            Ok(response)                                            => response,
+            Err(ureq::Error::StatusCode(409 | 412))                 => return Err(Box::new(CloudError::Conflict(self.url.clone()))),
+            Err(e)                                                  => return Err(Box::new(e)),
+        };
+
+        self.revision = response.headers()
+            .get("ETag")
+            .and_then(|x| x.to_str().ok())
+            .map(|x| x.to_owned());
+
+        Ok(())
+    }
+}
+
+/// Check whether the urls `a` and `b` have the same scheme, host and port.
+fn same_origin(a: &str, b: &str) -> bool {
+    let origin = |url: &str| {
+        let (scheme, rest) = url.split_once("://")?;
+        let host = rest.split(['/', '?', '#']).next()?;
+        Some(format!("{scheme}://{host}").to_ascii_lowercase())
+    };
+
+    origin(a).is_some_and(|x| Some(x) == origin(b))
+}
+
+/// Create the agent requests to the cloud are made with.
+fn agent() -> ureq::Agent {
+    ureq::Agent::config_builder()
+        .timeout_global(Some(CLOUD_TIMEOUT))
+        .build()
+        .new_agent()
+}
+
+/// An error raised when accessing a cloud document.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum CloudError {
+    /// A `lunacy://` url was given with no cloud api configured to resolve it against.
+    NoApi,
+    /// The cloud document changed since it was downloaded.
+    Conflict(String),
+    /// A document or the cloud api was to be accessed over plain `http://`.
+    Insecure(String),
+}
+
+impl std::fmt::Display for CloudError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Self::NoApi         => write!(f, "set `cloud_api` in the configuration to open {SCHEME} urls"),
+            Self::Conflict(x)   => write!(f, "{x} changed since it was downloaded, run again to apply the changes to the new revision"),
+            Self::Insecure(x)   => write!(f, "refusing to access {x} over plain http, use https"),
+        }
+    }
+}
+
+impl std::error::Error for CloudError { }
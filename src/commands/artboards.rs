@@ -0,0 +1,97 @@
+use std::collections::BTreeSet;
+use std::path::PathBuf;
+
+use clap::{arg, value_parser, ArgAction, ArgMatches, Command};
+use json::{object, JsonValue};
+
+use crate::document::LunacyDocument;
+use crate::exit::{Exit, OrExit};
+use crate::model::{self, Frame};
+
+/// Create the `artboards` subcommand.
+pub fn command() -> Command {
+    Command::new("artboards")
+        .about("inspect the artboards in a document")
+        .subcommand_required(true)
+        .subcommand(
+            Command::new("list")
+                .about("list the artboards in a document with their sizes and positions")
+                .arg(
+                    arg!([FILE] "the lunacy .free file to inspect")
+                        .required(true)
+                        .value_parser(value_parser!(PathBuf))
+                )
+                .arg(
+                    arg!(--page <NAME> "the name of a page to list artboards on, defaults to every page")
+                        .action(ArgAction::Append)
+                )
+                .arg(
+                    arg!(--json "print the artboards as json")
+                        .action(ArgAction::SetTrue)
+                )
+        )
+}
+
+/// Run the `artboards` subcommand.
+pub fn run(matches: &ArgMatches) {
+    match matches.subcommand() {
+        Some(("list", matches)) => list(matches),
+        _                       => unreachable!("a subcommand is required"),
+    }
+}
+
+/// Print the artboards in a document with their sizes and positions.
+fn list(matches: &ArgMatches) {
+    let Some(path) = matches.get_one::<PathBuf>("FILE") else {
+        panic!("expected .free document as first argument");
+    };
+
+    let selected = matches.get_many::<String>("page")
+        .map(|x| x.map(|x| x.as_str()).collect::<BTreeSet<_>>());
+
+    let options = crate::load_open_options(matches, path);
+    let doc     = LunacyDocument::open(path, &options)
+        .or_exit(Exit::Document, "failed to open document");
+
+    let mut artboards = vec![];
+    for (entry, page) in model::pages(&doc).expect("failed to read pages") {
+        let page_name = page.name.clone().unwrap_or(entry);
+        if selected.as_ref().is_some_and(|x| !x.contains(page_name.as_str())) {
+            continue;
+        }
+
+        for artboard in page.artboards() {
+            let name = artboard.name.clone().unwrap_or_default();
+            let id   = artboard.id().map(|x| x.to_owned());
+            artboards.push((page_name.clone(), name, id, Frame::from_typed(artboard)));
+        }
+    }
+
+    if super::json_output(matches) {
+        let json = artboards.iter()
+            .map(|(page, name, id, frame)| object! {
+                "page"      : page.as_str(),
+                "name"      : name.as_str(),
+                "id"        : id.as_deref(),
+                "x"         : frame.x,
+                "y"         : frame.y,
+                "width"     : frame.width,
+                "height"    : frame.height,
+            })
+            .collect::<Vec<_>>();
+
+        println!("{}", JsonValue::from(json).pretty(4));
+        return;
+    }
+
+    let rows = artboards.iter()
+        .map(|(page, name, _, frame)| [
+            page.clone(),
+            name.clone(),
+            format!("{}x{}", frame.width, frame.height),
+            format!("{}, {}", frame.x, frame.y),
+        ])
+        .collect::<Vec<_>>();
+
+    super::print_table(&["page", "artboard", "size", "position"], &rows);
+}
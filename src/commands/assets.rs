@@ -0,0 +1,538 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::{Path, PathBuf};
+
+use clap::{arg, value_parser, ArgMatches, Command};
+use image::ImageFormat;
+use json::{object, JsonValue};
+
+use crate::commands::{format_size, sanitize_file_name};
+use crate::document::{EntryInfo, LunacyDocument};
+use crate::exit::{Exit, OrExit};
+use crate::images;
+use crate::model;
+use crate::output::say;
+use crate::references::{self, ASSET_DIRS, IMAGES_DIR};
+use crate::Error;
+
+/// The name of the manifest written alongside extracted images.
+const MANIFEST_FILE: &str = "manifest.json";
+
+/// The keys images may have their width and height recorded under, alongside the reference to
+/// them.
+const DIMENSION_KEYS: &[(&str, &str)] = &[
+    ("width",           "height"),
+    ("imageWidth",      "imageHeight"),
+    ("originalWidth",   "originalHeight"),
+];
+
+/// Create the `assets` subcommand.
+pub fn command() -> Command {
+    Command::new("assets")
+        .about("inspect and manage the images and fonts embedded in a document")
+        .subcommand_required(true)
+        .subcommand(
+            Command::new("analyze")
+                .about("list the largest embedded assets")
+                .arg(
+                    arg!([FILE] "the lunacy .free file to analyze")
+                        .required(true)
+                        .value_parser(value_parser!(PathBuf))
+                )
+                .arg(
+                    arg!(--limit <COUNT> "the number of assets to list, defaults to 20")
+                        .value_parser(value_parser!(usize))
+                )
+        )
+        .subcommand(
+            Command::new("extract")
+                .about("write every embedded image to a directory along with a manifest")
+                .arg(
+                    arg!([FILE] "the lunacy .free file to extract images from")
+                        .required(true)
+                        .value_parser(value_parser!(PathBuf))
+                )
+                .arg(
+                    arg!(--out <DIR> "the directory to write images to")
+                        .required(true)
+                        .value_parser(value_parser!(PathBuf))
+                )
+        )
+        .subcommand(
+            Command::new("gc")
+                .about("remove embedded images and fonts that no layer refers to, such as those left behind by deleted layers")
+                .arg(
+                    arg!([FILE] "the lunacy .free file to clean up")
+                        .required(true)
+                        .value_parser(value_parser!(PathBuf))
+                )
+                .arg(
+                    arg!(--dry_run "list the unused assets without removing them")
+                        .id("DRY_RUN")
+                )
+        )
+        .subcommand(
+            Command::new("replace")
+                .about("replace an embedded image, or add it if there's no image to replace")
+                .arg(
+                    arg!([FILE] "the lunacy .free file to modify")
+                        .required(true)
+                        .value_parser(value_parser!(PathBuf))
+                )
+                .arg(
+                    arg!([ASSET] "the id, file name or entry name of the image to replace")
+                        .required(true)
+                )
+                .arg(
+                    arg!([IMAGE] "the image to replace it with")
+                        .required(true)
+                        .value_parser(value_parser!(PathBuf))
+                )
+        )
+        .subcommand(
+            Command::new("strip")
+                .about("remove EXIF, XMP and other metadata from embedded images")
+                .arg(
+                    arg!([FILE] "the lunacy .free file to strip")
+                        .required(true)
+                        .value_parser(value_parser!(PathBuf))
+                )
+        )
+}
+
+/// Run the `assets` subcommand.
+pub fn run(matches: &ArgMatches) {
+    match matches.subcommand() {
+        Some(("analyze", matches))  => analyze(matches),
+        Some(("extract", matches))  => extract(matches),
+        Some(("gc", matches))       => gc(matches),
+        Some(("replace", matches))  => replace(matches),
+        Some(("strip", matches))    => strip(matches),
+        _                           => unreachable!("a subcommand is required"),
+    }
+}
+
+/// List the largest assets embedded in a document.
+fn analyze(matches: &ArgMatches) {
+    let Some(path) = matches.get_one::<PathBuf>("FILE") else {
+        panic!("expected .free document as first argument");
+    };
+
+    let limit = matches.get_one::<usize>("limit")
+        .copied()
+        .unwrap_or(20);
+
+    let options = crate::load_open_options(matches, path);
+    let doc     = LunacyDocument::open(path, &options)
+        .or_exit(Exit::Document, "failed to open document");
+
+    let mut assets = asset_entries(&doc);
+    assets.sort_by(|a, b| b.size.cmp(&a.size).then_with(|| a.name.cmp(&b.name)));
+
+    println!("{:>10}  {:>10}  {:>6}  name", "size", "compressed", "ratio");
+    for asset in assets.iter().take(limit) {
+        let ratio = match asset.size {
+            0 => 1.0,
+            x => asset.compressed_size as f64 / x as f64,
+        };
+
+        println!(
+            "{:>10}  {:>10}  {:>5.0}%  {}",
+            format_size(asset.size),
+            format_size(asset.compressed_size),
+            ratio * 100.0,
+            asset.name
+        );
+    }
+
+    let size        = assets.iter().map(|x| x.size).sum::<u64>();
+    let compressed  = assets.iter().map(|x| x.compressed_size).sum::<u64>();
+    say!("{} assets, {} ({} compressed)", assets.len(), format_size(size), format_size(compressed));
+}
+
+/// Write every image embedded in a document to a directory, along with a manifest mapping asset
+/// entries to the files written.
+fn extract(matches: &ArgMatches) {
+    let Some(path) = matches.get_one::<PathBuf>("FILE") else {
+        panic!("expected .free document as first argument");
+    };
+
+    let Some(out) = matches.get_one::<PathBuf>("out") else {
+        panic!("expected an output directory");
+    };
+
+    let options = crate::load_open_options(matches, path);
+    let doc     = LunacyDocument::open(path, &options)
+        .or_exit(Exit::Document, "failed to open document");
+
+    let layer_names = asset_layer_names(&doc)
+        .expect("failed to read pages");
+
+    std::fs::create_dir_all(out)
+        .expect("failed to create output directory");
+
+    let mut files       = BTreeSet::new();
+    let mut manifest    = JsonValue::new_object();
+    for asset in asset_entries(&doc).into_iter().filter(|x| x.name.starts_with(IMAGES_DIR)) {
+        let data = doc.load_bytes(&asset.name)
+            .expect("failed to read asset");
+
+        // Lunacy doesn't always store images with an extension, so fall back to the format.
+        let entry       = Path::new(&asset.name);
+        let id          = entry.file_stem().and_then(|x| x.to_str()).unwrap_or_default();
+        let extension   = entry.extension()
+            .and_then(|x| x.to_str())
+            .or(image::guess_format(&data).ok().map(|x| x.extensions_str()[0]))
+            .unwrap_or("bin");
+
+        // Name the file after the layer using the image where possible.
+        let layer   = layer_names.get(&asset.name);
+        let stem    = sanitize_file_name(layer.map_or(id, |x| x.as_str()), "image");
+        let mut file = format!("{stem}.{extension}");
+        for i in 2.. {
+            if files.insert(file.clone()) {
+                break;
+            }
+            file = format!("{stem}-{i}.{extension}");
+        }
+
+        std::fs::write(out.join(&file), &data)
+            .expect("failed to write image");
+
+        let mut info = object! {
+            "id"    => id,
+            "entry" => asset.name.as_str(),
+            "file"  => file.as_str(),
+            "layer" => layer.map(|x| x.as_str()),
+        };
+        if let Some((width, height)) = images::dimensions(&data) {
+            info["width"]   = width.into();
+            info["height"]  = height.into();
+        }
+
+        // Keyed by entry since images in different directories or with different extensions can
+        // share an id.
+        manifest[asset.name.as_str()] = info;
+        say!("{} -> {}", asset.name, file);
+    }
+
+    std::fs::write(out.join(MANIFEST_FILE), manifest.pretty(4))
+        .expect("failed to write manifest");
+
+    say!("extracted {} images to {}", files.len(), out.display());
+}
+
+/// Get the name of the first layer referring to each asset in `doc`.
+fn asset_layer_names(doc: &LunacyDocument) -> Result<BTreeMap<String, String>, Error> {
+    let assets  = asset_entries(doc);
+    let mut names = BTreeMap::new();
+    for (_, page) in model::load_pages(doc)? {
+        model::visit_layers(&page, &mut |layer| {
+            let Some(name) = layer["name"].as_str() else {
+                return;
+            };
+
+            // Only look at the layer itself, its children are visited separately.
+            let mut strings = BTreeSet::new();
+            for (_, value) in layer.entries().filter(|(key, _)| *key != model::LAYERS_KEY) {
+                references::collect_strings(value, &mut strings);
+            }
+
+            for asset in assets.iter().filter(|x| is_referenced(&x.name, &strings)) {
+                names.entry(asset.name.clone()).or_insert_with(|| name.to_owned());
+            }
+        });
+    }
+
+    Ok(names)
+}
+
+/// Replace an image embedded in a document, updating references to it if its format changed
+/// and recorded dimensions if its size changed.
+fn replace(matches: &ArgMatches) {
+    let Some(path) = matches.get_one::<PathBuf>("FILE") else {
+        panic!("expected .free document as first argument");
+    };
+
+    let Some(asset) = matches.get_one::<String>("ASSET") else {
+        panic!("expected an asset to replace");
+    };
+
+    let Some(image) = matches.get_one::<PathBuf>("IMAGE") else {
+        panic!("expected an image to replace the asset with");
+    };
+
+    let data    = std::fs::read(image)
+        .expect("failed to read image");
+    let format  = image::guess_format(&data)
+        .expect("failed to detect image format");
+    let to      = images::dimensions(&data)
+        .expect("failed to read image dimensions");
+
+    let options = crate::load_edit_options(matches, path);
+    let mut doc = LunacyDocument::open(path, &options)
+        .or_exit(Exit::Document, "failed to open document");
+    doc.set_zero_timestamps(matches.get_flag("ZERO_TIMESTAMPS"));
+
+    let strings = BTreeSet::from([asset.clone()]);
+    let Some(name) = asset_entries(&doc)
+        .into_iter()
+        .map(|x| x.name)
+        .find(|x| x.starts_with(IMAGES_DIR) && is_referenced(x, &strings))
+    else {
+        // Nothing to replace, so inject it as a new image for the document to refer to.
+        let name = match Path::new(asset).extension() {
+            Some(_) => with_format_extension(&format!("{IMAGES_DIR}{asset}"), format),
+            None    => format!("{IMAGES_DIR}{asset}.{}", format.extensions_str()[0]),
+        };
+
+        doc.save_bytes(&name, &data)
+            .expect("failed to save image");
+        doc.commit()
+            .or_exit(Exit::Document, "failed to commit changes to document");
+
+        say!("added {name}");
+        return;
+    };
+
+    let from = images::dimensions(&doc.load_bytes(&name).expect("failed to read asset"));
+
+    let renamed = with_format_extension(&name, format);
+
+    if renamed != name && doc.has_entry(&renamed) {
+        panic!("can't rename {name} to {renamed} as it already exists");
+    }
+
+    doc.remove_entry(&name)
+        .expect("failed to remove asset");
+    doc.save_bytes(&renamed, &data)
+        .expect("failed to save image");
+
+    if renamed != name {
+        rename_assets(&mut doc, &BTreeMap::from([(name.clone(), renamed.clone())]))
+            .expect("failed to update references");
+        say!("renamed {name} to {renamed}");
+    }
+
+    if let Some(from) = from.filter(|x| *x != to) {
+        let mut updated = 0;
+        for entry in model::json_entries(&doc) {
+            let mut json = doc.load_json(&entry)
+                .expect("failed to read document");
+
+            let count = update_dimensions(&mut json, &renamed, from, to);
+            if count > 0 {
+                doc.save_json(&entry, &json)
+                    .expect("failed to save document");
+            }
+            updated += count;
+        }
+
+        say!("updated {updated} recorded sizes from {}x{} to {}x{}", from.0, from.1, to.0, to.1);
+    }
+
+    doc.commit()
+        .or_exit(Exit::Document, "failed to commit changes to document");
+
+    say!("replaced {renamed}");
+}
+
+/// Change the extension of the entry `name` to match `format` if it names a different image
+/// format, leaving names without a recognised extension alone.
+fn with_format_extension(name: &str, format: ImageFormat) -> String {
+    let path = Path::new(name);
+    match path.extension().and_then(|x| x.to_str()).and_then(ImageFormat::from_extension) {
+        Some(x) if x != format  => path.with_extension(format.extensions_str()[0])
+            .to_string_lossy()
+            .replace('\\', "/"),
+        _                       => name.to_owned(),
+    }
+}
+
+/// Update the recorded dimensions of the image `name` in `json`, returning the number of objects
+/// updated.
+///
+/// Only objects that refer to the image directly and record its exact dimensions under one of the
+/// [`DIMENSION_KEYS`] are updated, so layer frames that merely contain the image are left alone.
+fn update_dimensions(json: &mut JsonValue, name: &str, from: (u32, u32), to: (u32, u32)) -> usize {
+    match json {
+        JsonValue::Object(object) => {
+            let keys        = references::asset_keys(name);
+            let refers      = object.iter().any(|(_, value)| value.as_str().is_some_and(|x| keys.contains(&x)));
+            let mut count   = 0;
+
+            if refers {
+                for (width, height) in DIMENSION_KEYS {
+                    let matches = object.get(width).and_then(|x| x.as_f64()) == Some(from.0 as f64)
+                        && object.get(height).and_then(|x| x.as_f64()) == Some(from.1 as f64);
+
+                    if matches {
+                        object.insert(width, to.0.into());
+                        object.insert(height, to.1.into());
+                        count += 1;
+                    }
+                }
+            }
+
+            count + object.iter_mut()
+                .map(|(_, value)| update_dimensions(value, name, from, to))
+                .sum::<usize>()
+        }
+        JsonValue::Array(values) => {
+            values.iter_mut()
+                .map(|value| update_dimensions(value, name, from, to))
+                .sum()
+        }
+        _ => 0,
+    }
+}
+
+/// Remove metadata from the images embedded in a document.
+fn strip(matches: &ArgMatches) {
+    let Some(path) = matches.get_one::<PathBuf>("FILE") else {
+        panic!("expected .free document as first argument");
+    };
+
+    let options = crate::load_edit_options(matches, path);
+    let mut doc = LunacyDocument::open(path, &options)
+        .or_exit(Exit::Document, "failed to open document");
+    doc.set_zero_timestamps(matches.get_flag("ZERO_TIMESTAMPS"));
+
+    let mut count = 0;
+    for asset in asset_entries(&doc) {
+        let data = doc.load_bytes(&asset.name)
+            .expect("failed to read asset");
+
+        // Assets that aren't images, or that can't be parsed, are left alone.
+        let Ok(Some(stripped)) = images::strip_metadata(&data) else {
+            continue;
+        };
+
+        say!("{}: removed {} of metadata", asset.name, format_size((data.len() - stripped.len()) as u64));
+        doc.save_bytes(&asset.name, &stripped)
+            .expect("failed to save asset");
+        count += 1;
+    }
+
+    doc.commit()
+        .or_exit(Exit::Document, "failed to commit changes to document");
+
+    say!("stripped metadata from {} images", count);
+}
+
+/// Remove the assets embedded in a document that nothing in it refers to.
+fn gc(matches: &ArgMatches) {
+    let Some(path) = matches.get_one::<PathBuf>("FILE") else {
+        panic!("expected .free document as first argument");
+    };
+
+    let options = crate::load_edit_options(matches, path);
+    let mut doc = LunacyDocument::open(path, &options)
+        .or_exit(Exit::Document, "failed to open document");
+    doc.set_zero_timestamps(matches.get_flag("ZERO_TIMESTAMPS"));
+
+    let sizes = asset_entries(&doc)
+        .into_iter()
+        .map(|x| (x.name, x.size))
+        .collect::<BTreeMap<_, _>>();
+
+    let unused = unused_assets(&doc)
+        .expect("failed to find unused assets")
+        .into_iter()
+        .map(|x| {
+            let size = sizes.get(&x).copied().unwrap_or_default();
+            (x, size)
+        })
+        .collect::<Vec<_>>();
+    let freed = unused.iter().map(|x| x.1).sum::<u64>();
+
+    if matches.get_flag("DRY_RUN") {
+        for (name, size) in &unused {
+            println!("{:>10}  {name}", format_size(*size));
+        }
+        say!("{} unused assets, {}", unused.len(), format_size(freed));
+        return;
+    }
+
+    let changes = unused.iter()
+        .map(|(name, size)| format!("remove unused {name} ({})", format_size(*size)))
+        .collect::<Vec<_>>();
+
+    if !crate::confirm(matches, &changes) {
+        return;
+    }
+
+    for (name, _) in &unused {
+        doc.remove_entry(name)
+            .expect("failed to remove asset");
+    }
+
+    doc.commit()
+        .or_exit(Exit::Document, "failed to commit changes to document");
+
+    say!("removed {} unused assets, {}", unused.len(), format_size(freed));
+}
+
+/// Get the entries of `doc` holding embedded assets.
+pub fn asset_entries(doc: &LunacyDocument) -> Vec<EntryInfo> {
+    doc.entries()
+        .iter()
+        .filter(|x| ASSET_DIRS.iter().any(|dir| x.name.starts_with(dir)))
+        .cloned()
+        .collect()
+}
+
+/// Update every reference to the renamed assets in `renames`, keyed by their old entry name.
+///
+/// Assets may be referred to by entry name or file name, so both are updated.
+pub fn rename_assets(doc: &mut LunacyDocument, renames: &BTreeMap<String, String>) -> Result<(), Error> {
+    if renames.is_empty() {
+        return Ok(());
+    }
+
+    let mut strings = renames.clone();
+    for (name, renamed) in renames {
+        strings.insert(file_name(name), file_name(renamed));
+    }
+
+    for entry in model::json_entries(doc) {
+        let mut json = doc.load_json(&entry)?;
+        if references::rename_strings(&mut json, &strings) > 0 {
+            doc.save_json(&entry, &json)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Get the file name of the entry `name`.
+fn file_name(name: &str) -> String {
+    Path::new(name)
+        .file_name()
+        .and_then(|x| x.to_str())
+        .unwrap_or(name)
+        .to_owned()
+}
+
+/// Get the names of the assets in `doc` that nothing in the document refers to.
+///
+/// Assets may be referred to by their entry name, file name or file name without extension, an
+/// asset is considered used if any string in the document matches any of these.
+pub fn unused_assets(doc: &LunacyDocument) -> Result<Vec<String>, Error> {
+    let mut strings = BTreeSet::new();
+    for entry in model::json_entries(doc) {
+        references::collect_strings(&doc.load_json(&entry)?, &mut strings);
+    }
+
+    let unused = asset_entries(doc)
+        .into_iter()
+        .map(|x| x.name)
+        .filter(|name| !is_referenced(name, &strings))
+        .collect();
+
+    Ok(unused)
+}
+
+/// Check whether the asset `name` is referred to by any of `strings`.
+fn is_referenced(name: &str, strings: &BTreeSet<String>) -> bool {
+    references::asset_keys(name).iter().any(|x| strings.contains(*x))
+}
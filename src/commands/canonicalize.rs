@@ -0,0 +1,52 @@
+use std::path::PathBuf;
+
+use clap::{arg, value_parser, ArgMatches, Command};
+
+use crate::canonical;
+use crate::document::LunacyDocument;
+use crate::exit::{Exit, OrExit};
+use crate::model;
+use crate::output::say;
+
+/// Create the `canonicalize` subcommand.
+pub fn command() -> Command {
+    Command::new("canonicalize")
+        .about("rewrite the json in a document in a canonical form so diffs between versions are minimal")
+        .arg(
+            arg!([FILE] "the lunacy .free file to modify")
+                .required(true)
+                .value_parser(value_parser!(PathBuf))
+        )
+}
+
+/// Run the `canonicalize` subcommand.
+pub fn run(matches: &ArgMatches) {
+    let Some(path) = matches.get_one::<PathBuf>("FILE") else {
+        panic!("expected .free document as first argument");
+    };
+
+    let options = crate::load_edit_options(matches, path);
+    let mut doc = LunacyDocument::open(path, &options)
+        .or_exit(Exit::Document, "failed to open document");
+    doc.set_zero_timestamps(matches.get_flag("ZERO_TIMESTAMPS"));
+
+    let mut changed = 0;
+    for entry in model::json_entries(&doc) {
+        let json = doc.load_json(&entry)
+            .expect("failed to read entry");
+
+        let canonical = canonical::canonicalize(&entry, &json);
+        if canonical.dump() != json.dump() {
+            doc.save_json(&entry, &canonical)
+                .expect("failed to save entry");
+
+            say!("canonicalized {entry}");
+            changed += 1;
+        }
+    }
+
+    doc.commit()
+        .or_exit(Exit::Document, "failed to commit changes to document");
+
+    say!("canonicalized {changed} entries");
+}
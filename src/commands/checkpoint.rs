@@ -0,0 +1,113 @@
+use std::path::PathBuf;
+
+use clap::{arg, value_parser, ArgAction, ArgMatches, Command};
+use json::JsonValue;
+
+use crate::checkpoint;
+use crate::document::LunacyDocument;
+use crate::exit::{Exit, OrExit};
+use crate::output::say;
+
+/// Create the `checkpoint` subcommand.
+pub fn command() -> Command {
+    Command::new("checkpoint")
+        .about("keep named snapshots of the palette of a document to refer back to")
+        .subcommand_required(true)
+        .subcommand(
+            Command::new("create")
+                .about("snapshot the palette of a document inside it, named by the -m message")
+                .arg(
+                    arg!([FILE] "the lunacy .free file to snapshot")
+                        .required(true)
+                        .value_parser(value_parser!(PathBuf))
+                )
+                .arg(
+                    arg!(--full "snapshot the whole document.json along with the palette")
+                        .action(ArgAction::SetTrue)
+                )
+        )
+        .subcommand(
+            Command::new("list")
+                .about("print the checkpoints of a document, oldest first")
+                .arg(
+                    arg!([FILE] "the lunacy .free file to inspect")
+                        .required(true)
+                        .value_parser(value_parser!(PathBuf))
+                )
+                .arg(
+                    arg!(--json "print the checkpoints as json")
+                        .action(ArgAction::SetTrue)
+                )
+        )
+}
+
+/// Run the `checkpoint` subcommand.
+pub fn run(matches: &ArgMatches) {
+    match matches.subcommand() {
+        Some(("create", matches))   => create(matches),
+        Some(("list", matches))     => list(matches),
+        _                           => unreachable!("a subcommand is required"),
+    }
+}
+
+/// Snapshot the palette of a document into a new checkpoint.
+fn create(matches: &ArgMatches) {
+    let Some(path) = matches.get_one::<PathBuf>("FILE") else {
+        panic!("expected .free document as first argument");
+    };
+    let Some(message) = matches.get_one::<String>("MESSAGE") else {
+        Exit::InvalidArgs.fail("expected a message naming the checkpoint, e.g. -m \"Q3 rebrand\"");
+    };
+
+    // Checkpoints change nothing in the design, so they're kept apart from the history of
+    // changes rather than recorded in it.
+    let mut options = crate::load_edit_options(matches, path);
+    options.operation = None;
+
+    let mut doc = LunacyDocument::open(path, &options)
+        .or_exit(Exit::Document, "failed to open document");
+    doc.set_zero_timestamps(matches.get_flag("ZERO_TIMESTAMPS"));
+
+    let checkpoint = checkpoint::create(&mut doc, message, matches.get_flag("full"), !matches.get_flag("ZERO_TIMESTAMPS"))
+        .expect("failed to create checkpoint");
+
+    doc.commit()
+        .or_exit(Exit::Document, "failed to commit changes to document");
+
+    say!("created checkpoint #{} of {} colors: {}", checkpoint.number, checkpoint.colors, checkpoint.message);
+}
+
+/// Print the checkpoints of a document, oldest first.
+fn list(matches: &ArgMatches) {
+    let Some(path) = matches.get_one::<PathBuf>("FILE") else {
+        panic!("expected .free document as first argument");
+    };
+
+    let options = crate::load_open_options(matches, path);
+    let doc     = LunacyDocument::open(path, &options)
+        .or_exit(Exit::Document, "failed to open document");
+
+    let checkpoints = checkpoint::load(&doc)
+        .expect("failed to read checkpoints");
+
+    if super::json_output(matches) {
+        let json = checkpoints.iter().map(|x| x.to_json()).collect::<Vec<_>>();
+        println!("{}", JsonValue::from(json).pretty(4));
+        return;
+    }
+
+    let rows = checkpoints.iter()
+        .map(|x| [
+            format!("#{}", x.number),
+            x.time.map(crate::history::format_time).unwrap_or_else(|| String::from("-")),
+            x.colors.to_string(),
+            match x.document.is_some() {
+                true    => String::from("full"),
+                false   => String::from("palette"),
+            },
+            x.message.clone(),
+        ])
+        .collect::<Vec<_>>();
+
+    super::print_table(&["checkpoint", "time", "colors", "snapshot", "message"], &rows);
+}
@@ -0,0 +1,513 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::PathBuf;
+
+use clap::{arg, value_parser, ArgAction, ArgMatches, Command};
+use json::{object, JsonValue};
+
+use crate::document::LunacyDocument;
+use crate::exit::{Exit, OrExit};
+use crate::model::{self, layer_type, COMPONENT_ID_KEY, LAYERS_KEY, NAME_SEPARATOR, OVERRIDE_KEYS, SYMBOL_ID_KEY};
+use crate::Error;
+use crate::output::say;
+
+/// Create the `components` subcommand.
+pub fn command() -> Command {
+    Command::new("components")
+        .about("inspect and manage the components in a document")
+        .subcommand_required(true)
+        .subcommand(
+            Command::new("detached")
+                .about("find layers that look like detached copies of a component")
+                .arg(
+                    arg!([FILE] "the lunacy .free file to inspect")
+                        .required(true)
+                        .value_parser(value_parser!(PathBuf))
+                )
+                .arg(
+                    arg!(--threshold <SIMILARITY> "how similar a layer's structure must be to a component, from 0 to 1, defaults to 0.8")
+                        .value_parser(value_parser!(f64))
+                )
+        )
+        .subcommand(
+            Command::new("list")
+                .about("list every component with its instance count, pages and overrides")
+                .arg(
+                    arg!([FILE] "the lunacy .free file to inspect")
+                        .required(true)
+                        .value_parser(value_parser!(PathBuf))
+                )
+                .arg(
+                    arg!(--json "print the inventory as json")
+                        .action(ArgAction::SetTrue)
+                )
+        )
+        .subcommand(
+            Command::new("swap")
+                .about("retarget every instance of one component to another")
+                .arg(
+                    arg!([FILE] "the lunacy .free file to modify")
+                        .required(true)
+                        .value_parser(value_parser!(PathBuf))
+                )
+                .arg(
+                    arg!(--from <NAME> "the name of the component to swap instances of, e.g. 'Button / Old'")
+                        .required(true)
+                )
+                .arg(
+                    arg!(--to <NAME> "the name of the component to swap instances to")
+                        .required(true)
+                )
+        )
+}
+
+/// Run the `components` subcommand.
+pub fn run(matches: &ArgMatches) {
+    match matches.subcommand() {
+        Some(("detached", matches)) => detached(matches),
+        Some(("list", matches))     => list(matches),
+        Some(("swap", matches))     => swap(matches),
+        _                           => unreachable!("a subcommand is required"),
+    }
+}
+
+/// A component and how it is used within a document.
+#[derive(Clone, Debug, Default)]
+pub struct ComponentUsage {
+    /// The id instances refer to the component by.
+    pub id          : String,
+    /// The name of the component.
+    pub name        : String,
+    /// The name of the page the component is on.
+    pub page        : String,
+    /// The number of instances of the component.
+    pub instances   : usize,
+    /// The names of the pages instances of the component are on.
+    pub pages       : BTreeSet<String>,
+    /// The number of instances with at least one override.
+    pub overridden  : usize,
+    /// The number of times each property is overridden across all instances.
+    pub overrides   : BTreeMap<String, usize>,
+}
+
+impl ComponentUsage {
+    /// Convert the usage to json for reporting.
+    fn to_json(&self) -> JsonValue {
+        let mut overrides = JsonValue::new_object();
+        for (property, count) in &self.overrides {
+            overrides[property.as_str()] = (*count).into();
+        }
+
+        object! {
+            "id"            : self.id.as_str(),
+            "name"          : self.name.as_str(),
+            "page"          : self.page.as_str(),
+            "instances"     : self.instances,
+            "pages"         : self.pages.iter().map(|x| x.as_str()).collect::<Vec<_>>(),
+            "overridden"    : self.overridden,
+            "overrides"     : overrides,
+        }
+    }
+}
+
+/// Collect every component in `doc` along with how it is used, in document order, and the number
+/// of instances of each component id that isn't in the document.
+pub fn component_usage(doc: &LunacyDocument) -> Result<(Vec<ComponentUsage>, BTreeMap<String, usize>), Error> {
+    let pages = model::load_pages(doc)?;
+
+    let mut components  = vec![];
+    let mut by_id       = BTreeMap::new();
+    for (entry, page) in &pages {
+        let page_name = page["name"].as_str().unwrap_or(entry);
+        model::visit_layers(page, &mut |layer| {
+            let Some(id) = model::component_id(layer).filter(|_| model::is_component(layer)) else {
+                return;
+            };
+
+            by_id.insert(id.to_owned(), components.len());
+            components.push(ComponentUsage {
+                id      : id.to_owned(),
+                name    : layer["name"].as_str().unwrap_or_default().to_owned(),
+                page    : page_name.to_owned(),
+                ..Default::default()
+            });
+        });
+    }
+
+    // Instances may be of components from a library that isn't part of the document.
+    let mut missing = BTreeMap::new();
+    for (entry, page) in &pages {
+        let page_name = page["name"].as_str().unwrap_or(entry);
+        model::visit_layers(page, &mut |layer| {
+            let Some(id) = model::instance_component_id(layer).filter(|_| model::is_instance(layer)) else {
+                return;
+            };
+
+            let Some(&index) = by_id.get(id) else {
+                *missing.entry(id.to_owned()).or_default() += 1;
+                return;
+            };
+
+            let usage = &mut components[index];
+            usage.instances += 1;
+            usage.pages.insert(page_name.to_owned());
+
+            let mut overridden = false;
+            model::visit_overrides(layer, &mut |x| {
+                let property = model::override_target(x)
+                    .map(|(_, property)| property)
+                    .unwrap_or("unknown");
+
+                *usage.overrides.entry(property.to_owned()).or_default() += 1;
+                overridden = true;
+            });
+
+            if overridden {
+                usage.overridden += 1;
+            }
+        });
+    }
+
+    Ok((components, missing))
+}
+
+/// Print the components in a document and how they're used.
+fn list(matches: &ArgMatches) {
+    let Some(path) = matches.get_one::<PathBuf>("FILE") else {
+        panic!("expected .free document as first argument");
+    };
+
+    let options = crate::load_open_options(matches, path);
+    let doc     = LunacyDocument::open(path, &options)
+        .or_exit(Exit::Document, "failed to open document");
+
+    let (components, missing) = component_usage(&doc)
+        .expect("failed to read document");
+
+    if super::json_output(matches) {
+        let mut missing_json = JsonValue::new_object();
+        for (id, count) in &missing {
+            missing_json[id.as_str()] = (*count).into();
+        }
+
+        let report = object! {
+            "components"    : components.iter().map(|x| x.to_json()).collect::<Vec<_>>(),
+            "missing"       : missing_json,
+        };
+
+        println!("{}", report.pretty(4));
+        return;
+    }
+
+    let rows = components.iter()
+        .map(|x| {
+            let overridden = match x.overridden {
+                0 => "0".to_owned(),
+                n => {
+                    let properties = x.overrides.keys()
+                        .map(|x| x.as_str())
+                        .collect::<Vec<_>>()
+                        .join(", ");
+
+                    format!("{n} ({properties})")
+                }
+            };
+
+            [
+                x.name.clone(),
+                x.page.clone(),
+                x.instances.to_string(),
+                x.pages.iter().map(|x| x.as_str()).collect::<Vec<_>>().join(", "),
+                overridden,
+            ]
+        })
+        .collect::<Vec<_>>();
+
+    super::print_table(&["component", "page", "instances", "used on", "overridden"], &rows);
+
+    for (id, count) in &missing {
+        println!("{count} instances of missing component {id}");
+    }
+
+    let instances = components.iter().map(|x| x.instances).sum::<usize>();
+    let unused    = components.iter().filter(|x| x.instances == 0).count();
+    say!("{} components, {instances} instances, {unused} unused", components.len());
+}
+
+/// Report layers that look like detached copies of a component, either because they share its
+/// name and some of its structure or because their structure is close enough to it.
+fn detached(matches: &ArgMatches) {
+    let Some(path) = matches.get_one::<PathBuf>("FILE") else {
+        panic!("expected .free document as first argument");
+    };
+
+    let threshold = matches.get_one::<f64>("threshold")
+        .copied()
+        .unwrap_or(0.8);
+
+    let options = crate::load_open_options(matches, path);
+    let doc     = LunacyDocument::open(path, &options)
+        .or_exit(Exit::Document, "failed to open document");
+
+    let pages = model::load_pages(&doc)
+        .expect("failed to read pages");
+
+    let mut components = vec![];
+    for (_, page) in &pages {
+        model::visit_layers(page, &mut |layer| {
+            if model::is_component(layer) {
+                let name = layer["name"].as_str().unwrap_or_default();
+                components.push((name, structure(layer)));
+            }
+        });
+    }
+
+    let mut found = 0;
+    for (entry, page) in &pages {
+        let page_name = page["name"].as_str().unwrap_or(entry);
+        find_detached(page, page_name, &mut |layer, path| {
+            let name        = layer["name"].as_str().unwrap_or_default();
+            let layers      = structure(layer);
+
+            // Prefer the most similar component, then one with a matching name.
+            let best = components.iter()
+                .map(|(component, component_layers)| {
+                    let same_name = name.trim() == component.trim()
+                        || component.rsplit(NAME_SEPARATOR).next().is_some_and(|x| x.trim() == name.trim());
+                    (similarity(&layers, component_layers), same_name, *component)
+                })
+                .filter(|(similarity, same_name, _)| *similarity >= threshold || *same_name && *similarity >= threshold / 2.0)
+                .max_by(|a, b| a.0.total_cmp(&b.0).then(a.1.cmp(&b.1)));
+
+            let Some((similarity, same_name, component)) = best else {
+                return false;
+            };
+
+            let reason = match same_name {
+                true    => "same name",
+                false   => "similar structure",
+            };
+
+            println!("{path}: looks like a detached {component} ({reason}, {:.0}% similar)", similarity * 100.0);
+            found += 1;
+            true
+        });
+    }
+
+    say!("{found} possibly detached components");
+}
+
+/// Visit the layers below `json` that could be detached copies of a component, skipping
+/// components, instances and the children of layers `f` returns true for.
+fn find_detached(json: &JsonValue, parent: &str, f: &mut impl FnMut(&JsonValue, &str) -> bool) {
+    for layer in json[LAYERS_KEY].members() {
+        if model::is_component(layer) || model::is_instance(layer) {
+            continue;
+        }
+
+        let path = format!("{parent} {NAME_SEPARATOR} {}", layer["name"].as_str().unwrap_or_default());
+        let is_candidate = !layer[LAYERS_KEY].is_empty()
+            && model::layer_type(layer) != layer_type::ARTBOARD;
+
+        if is_candidate && f(layer, &path) {
+            continue;
+        }
+
+        find_detached(layer, &path, f);
+    }
+}
+
+/// Describe the layers within `layer` by type and name, sorted for comparison.
+fn structure(layer: &JsonValue) -> Vec<String> {
+    let mut layers = vec![];
+    model::visit_layers(layer, &mut |x| {
+        layers.push(format!("{}:{}", model::layer_type(x), x["name"].as_str().unwrap_or_default()));
+    });
+
+    layers.sort();
+    layers
+}
+
+/// Measure how similar two sorted structures are, as the fraction of layers they share.
+fn similarity(a: &[String], b: &[String]) -> f64 {
+    if a.is_empty() || b.is_empty() {
+        return 0.0;
+    }
+
+    let (mut i, mut j, mut shared) = (0, 0, 0);
+    while i < a.len() && j < b.len() {
+        match a[i].cmp(&b[j]) {
+            std::cmp::Ordering::Less    => i += 1,
+            std::cmp::Ordering::Greater => j += 1,
+            std::cmp::Ordering::Equal   => {
+                shared += 1;
+                i += 1;
+                j += 1;
+            }
+        }
+    }
+
+    shared as f64 / a.len().max(b.len()) as f64
+}
+
+/// Retarget every instance of one component to another, keeping the overrides that apply to a
+/// layer the new component also has.
+fn swap(matches: &ArgMatches) {
+    let Some(path) = matches.get_one::<PathBuf>("FILE") else {
+        panic!("expected .free document as first argument");
+    };
+
+    let (Some(from), Some(to)) = (matches.get_one::<String>("from"), matches.get_one::<String>("to")) else {
+        panic!("expected components to swap from and to");
+    };
+
+    let options = crate::load_edit_options(matches, path);
+    let mut doc = LunacyDocument::open(path, &options)
+        .or_exit(Exit::Document, "failed to open document");
+    doc.set_zero_timestamps(matches.get_flag("ZERO_TIMESTAMPS"));
+
+    let pages = model::load_pages(&doc)
+        .expect("failed to read pages");
+
+    let from_component  = find_component(&pages, from);
+    let to_component    = find_component(&pages, to);
+
+    let from_id = model::component_id(&from_component).unwrap_or_default().to_owned();
+    let to_id   = model::component_id(&to_component).unwrap_or_default().to_owned();
+
+    // Overrides apply to layers by id, so they're carried over to the layer with the same name
+    // in the new component when the ids differ.
+    let from_paths  = layer_paths(&from_component);
+    let to_ids      = layer_paths(&to_component)
+        .into_iter()
+        .map(|(id, path)| (path, id))
+        .collect::<BTreeMap<_, _>>();
+
+    let retarget = |id: &str| -> Option<String> {
+        if to_ids.values().any(|x| x == id) {
+            return Some(id.to_owned());
+        }
+
+        from_paths.get(id)
+            .and_then(|x| to_ids.get(x))
+            .cloned()
+    };
+
+    let mut swapped = 0;
+    let mut kept    = 0;
+    let mut dropped = 0;
+    for (entry, mut page) in pages {
+        let mut changed = false;
+        model::visit_layers_mut(&mut page, &mut |layer| {
+            if !model::is_instance(layer) || model::instance_component_id(layer) != Some(from_id.as_str()) {
+                return;
+            }
+
+            for key in [COMPONENT_ID_KEY, SYMBOL_ID_KEY] {
+                if layer.has_key(key) {
+                    layer[key] = to_id.as_str().into();
+                }
+            }
+
+            // Instances still named after their component follow it.
+            if layer["name"] == from.as_str() {
+                layer["name"] = to.as_str().into();
+            }
+
+            for key in OVERRIDE_KEYS {
+                if !layer.has_key(key) {
+                    continue;
+                }
+
+                let overrides = layer[*key].take();
+                let mut retained = JsonValue::new_array();
+                for mut value in overrides.members().cloned() {
+                    if retarget_override(&mut value, &retarget) {
+                        retained.push(value).expect("failed to keep override");
+                        kept += 1;
+                    }
+                    else {
+                        dropped += 1;
+                    }
+                }
+
+                layer[*key] = retained;
+            }
+
+            swapped += 1;
+            changed  = true;
+        });
+
+        if changed {
+            doc.save_json(&entry, &page)
+                .expect("failed to save page");
+        }
+    }
+
+    doc.commit()
+        .or_exit(Exit::Document, "failed to commit changes to document");
+
+    say!("swapped {swapped} instances from {from} to {to}, kept {kept} overrides, dropped {dropped}");
+}
+
+/// Find the component named `name`, which must be unique.
+fn find_component(pages: &[(String, JsonValue)], name: &str) -> JsonValue {
+    let mut found = vec![];
+    for (_, page) in pages {
+        model::visit_layers(page, &mut |layer| {
+            if model::is_component(layer) && layer["name"] == name {
+                found.push(layer.clone());
+            }
+        });
+    }
+
+    match found.len() {
+        0 => panic!("no component named {name}"),
+        1 => found.remove(0),
+        _ => panic!("more than one component is named {name}"),
+    }
+}
+
+/// Get the path of names to every layer within `component` by id.
+fn layer_paths(component: &JsonValue) -> BTreeMap<String, String> {
+    fn visit(json: &JsonValue, parent: &str, paths: &mut BTreeMap<String, String>) {
+        for layer in json[LAYERS_KEY].members() {
+            let path = format!("{parent}{NAME_SEPARATOR}{}", layer["name"].as_str().unwrap_or_default());
+            if let Some(id) = model::layer_id(layer) {
+                paths.insert(id.to_owned(), path.clone());
+            }
+            visit(layer, &path, paths);
+        }
+    }
+
+    let mut paths = BTreeMap::new();
+    visit(component, "", &mut paths);
+    paths
+}
+
+/// Point `value` at the layer `retarget` maps the layer it applies to to, returning whether the
+/// override still applies.
+fn retarget_override(value: &mut JsonValue, retarget: &impl Fn(&str) -> Option<String>) -> bool {
+    let Some((layers, property)) = model::override_target(value) else {
+        return false;
+    };
+
+    let Some(first) = layers.first().and_then(|x| retarget(x)) else {
+        return false;
+    };
+
+    // Only the outermost layer belongs to the component, the rest are within nested instances.
+    let target = std::iter::once(first.as_str())
+        .chain(layers[1..].iter().copied())
+        .collect::<Vec<_>>()
+        .join(&NAME_SEPARATOR.to_string());
+
+    match value.has_key("layerId") {
+        true    => value["layerId"] = target.into(),
+        false   => {
+            let name = format!("{target}_{property}");
+            value["overrideName"] = name.into();
+        }
+    }
+
+    true
+}
@@ -0,0 +1,257 @@
+use std::path::PathBuf;
+
+use clap::{arg, value_parser, ArgAction, ArgMatches, Command};
+use clap_complete::ArgValueCompleter;
+use lunacy_tools::palette;
+
+use crate::document::LunacyDocument;
+use crate::exit::{Exit, OrExit};
+use crate::output::say;
+use crate::Error;
+
+/// The contrast ratio pairs must reach by default, the WCAG AA level for normal text.
+const DEFAULT_MIN_CONTRAST: f64 = 4.5;
+
+/// The WCAG levels a contrast ratio can reach, from the highest, with the ratio each requires.
+const LEVELS: &[(&str, f64)] = &[
+    ("AAA", 7.0),
+    ("AA", 4.5),
+    ("AA Large", 3.0),
+];
+
+/// Create the `contrast` subcommand.
+pub fn command() -> Command {
+    Command::new("contrast")
+        .about("report the contrast of every color in a group against every other as a grid for accessibility sign-off")
+        .arg(
+            arg!([FILE] "the lunacy .free file to check the colors of")
+                .required(true)
+                .value_parser(value_parser!(PathBuf))
+        )
+        .arg(
+            arg!(--group <GROUP> "the group of colors to check, defaults to the configured group, or one chosen from the document when run interactively, or 'theme'")
+                .value_parser(value_parser!(String))
+                .add(ArgValueCompleter::new(crate::complete_group))
+        )
+        .arg(
+            arg!(--foreground <NAMES> "the comma separated colors used as text, by their name within the group or that of their ramp, defaults to every color")
+                .value_delimiter(',')
+                .value_parser(value_parser!(String))
+        )
+        .arg(
+            arg!(--background <NAMES> "the comma separated colors used as backgrounds, defaults to every color")
+                .value_delimiter(',')
+                .value_parser(value_parser!(String))
+        )
+        .arg(
+            arg!(--min <RATIO> "the contrast ratio a pair must reach to pass, defaults to 4.5")
+                .value_parser(value_parser!(f64))
+        )
+        .arg(
+            arg!(--html <FILE> "write the grid to an html page")
+                .value_parser(value_parser!(PathBuf))
+        )
+        .arg(
+            arg!(--csv <FILE> "write the grid to a csv file")
+                .value_parser(value_parser!(PathBuf))
+        )
+        .arg(
+            arg!(--check "exit with code 6 when any pair fails")
+                .action(ArgAction::SetTrue)
+        )
+}
+
+/// Run the `contrast` subcommand.
+pub fn run(matches: &ArgMatches) {
+    let Some(path) = matches.get_one::<PathBuf>("FILE") else {
+        panic!("expected .free document as first argument");
+    };
+
+    let options = crate::load_open_options(matches, path);
+    let doc     = LunacyDocument::open(path, &options)
+        .or_exit(Exit::Document, "failed to open document");
+
+    let group = match matches.get_one::<String>("group").or(crate::config().group.as_ref()) {
+        Some(group) => group.to_owned(),
+        None        => crate::pick_group(&doc).expect("failed to pick a group"),
+    };
+
+    // Colors are shown by their name within the group, e.g. `blue / blue.500`.
+    let prefix = format!("{group}{}", crate::separator());
+    let colors = doc.load_color_variables()
+        .expect("failed to read color variables")
+        .into_iter()
+        .filter_map(|x| Some((x.name.strip_prefix(&prefix)?.to_owned(), format!("#{}", x.value.to_lowercase()))))
+        .collect::<Vec<_>>();
+
+    if colors.is_empty() {
+        Exit::NothingToDo.fail(format!("no colors in group {group}"));
+    }
+
+    let foreground  = select(&colors, matches.get_many::<String>("foreground"));
+    let background  = select(&colors, matches.get_many::<String>("background"));
+    if foreground.is_empty() || background.is_empty() {
+        Exit::InvalidArgs.fail("no colors match the given names");
+    }
+
+    let min     = matches.get_one::<f64>("min").copied().unwrap_or(DEFAULT_MIN_CONTRAST);
+    let grid    = Grid::new(&foreground, &background, min)
+        .or_exit(Exit::Failure, "failed to compare colors");
+
+    if let Some(output) = matches.get_one::<PathBuf>("html") {
+        std::fs::write(output, grid.html(&group))
+            .expect("failed to write html report");
+        say!("wrote contrast grid to {}", output.display());
+    }
+    if let Some(output) = matches.get_one::<PathBuf>("csv") {
+        grid.write_csv(output)
+            .expect("failed to write csv report");
+        say!("wrote contrast grid to {}", output.display());
+    }
+
+    if !matches.contains_id("html") && !matches.contains_id("csv") {
+        let rows = grid.pairs()
+            .map(|(fg, bg, ratio)| [fg.0.clone(), bg.0.clone(), format!("{ratio:.2}"), badge(ratio, min)])
+            .collect::<Vec<_>>();
+        super::print_table(&["foreground", "background", "ratio", "result"], &rows);
+    }
+
+    let failed  = grid.pairs().filter(|x| x.2 < min).count();
+    let total   = grid.pairs().count();
+    say!("{} of {total} pairs reach a contrast of {min}", total - failed);
+
+    if failed > 0 && matches.get_flag("check") {
+        Exit::Contrast.fail(format!("{failed} pairs fail to reach a contrast of {min}"));
+    }
+}
+
+/// Select the colors named in `names`, by their name or that of the ramp they're a tint of, or
+/// every color when no names are given.
+fn select<'a>(colors: &[(String, String)], names: Option<impl Iterator<Item = &'a String>>) -> Vec<(String, String)> {
+    let Some(names) = names else {
+        return colors.to_vec();
+    };
+
+    let names = names.collect::<Vec<_>>();
+    colors.iter()
+        .filter(|(name, _)| names.iter().any(|x| name == *x || name.starts_with(&format!("{x}{}", crate::separator()))))
+        .cloned()
+        .collect()
+}
+
+/// Get the badge shown for a pair with a contrast of `ratio`, the highest WCAG level it reaches
+/// and whether it passes `min`, e.g. `AA pass`.
+fn badge(ratio: f64, min: f64) -> String {
+    let level = LEVELS.iter()
+        .find(|x| ratio >= x.1)
+        .map_or("-", |x| x.0);
+
+    match ratio >= min {
+        true    => format!("{level} pass"),
+        false   => format!("{level} fail"),
+    }
+}
+
+/// The contrast of every foreground color against every background color.
+struct Grid<'a> {
+    /// The colors used as text, by name and hex value.
+    foreground  : &'a [(String, String)],
+    /// The colors used as backgrounds, by name and hex value.
+    background  : &'a [(String, String)],
+    /// The contrast ratio of each foreground against each background, by row of foreground.
+    ratios      : Vec<Vec<f64>>,
+    /// The contrast ratio a pair must reach to pass.
+    min         : f64,
+}
+
+impl<'a> Grid<'a> {
+    /// Compare every color in `foreground` to every color in `background`.
+    fn new(foreground: &'a [(String, String)], background: &'a [(String, String)], min: f64) -> Result<Self, Error> {
+        let ratios = foreground.iter()
+            .map(|fg| background.iter().map(|bg| palette::contrast_ratio(&fg.1, &bg.1)).collect())
+            .collect::<Result<_, _>>()?;
+
+        Ok(Self { foreground, background, ratios, min })
+    }
+
+    /// Iterate the pairs of foreground and background colors with their contrast ratio, leaving
+    /// out colors paired with themselves.
+    fn pairs(&self) -> impl Iterator<Item = (&(String, String), &(String, String), f64)> {
+        self.foreground.iter()
+            .zip(&self.ratios)
+            .flat_map(|(fg, row)| self.background.iter().zip(row).map(move |(bg, ratio)| (fg, bg, *ratio)))
+            .filter(|(fg, bg, _)| fg.0 != bg.0)
+    }
+
+    /// Write the grid to a csv file at `path`, with a row per foreground and a column per
+    /// background holding the ratio and badge of each pair.
+    fn write_csv(&self, path: &std::path::Path) -> Result<(), Error> {
+        let mut writer = csv::Writer::from_path(path)?;
+        writer.write_record(std::iter::once("").chain(self.background.iter().map(|x| x.0.as_str())))?;
+        for (fg, row) in self.foreground.iter().zip(&self.ratios) {
+            let cells = self.background.iter().zip(row).map(|(bg, x)| match fg.0 == bg.0 {
+                true    => String::new(),
+                false   => format!("{x:.2} {}", badge(*x, self.min)),
+            });
+            writer.write_record(std::iter::once(fg.0.clone()).chain(cells))?;
+        }
+
+        writer.flush()?;
+        Ok(())
+    }
+
+    /// Render the grid as an html page for the colors of `group`, with a sample of each
+    /// foreground on each background.
+    fn html(&self, group: &str) -> String {
+        let header = self.background.iter()
+            .map(|(name, value)| format!("<th><div class=\"swatch\" style=\"background: {value}\"></div>{}</th>", escape(name)))
+            .collect::<String>();
+
+        let rows = self.foreground.iter()
+            .zip(&self.ratios)
+            .map(|((name, fg), row)| {
+                let cells = self.background.iter()
+                    .zip(row)
+                    .map(|((other, bg), ratio)| match name == other {
+                        true    => "<td></td>".to_owned(),
+                        false   => format!(
+                            "<td style=\"color: {fg}; background: {bg}\"><b>Aa</b> {ratio:.2}<span class=\"{}\">{}</span></td>",
+                            match *ratio >= self.min {
+                                true    => "pass",
+                                false   => "fail",
+                            },
+                            badge(*ratio, self.min),
+                        ),
+                    })
+                    .collect::<String>();
+
+                format!("<tr><th>{}</th>{cells}</tr>", escape(name))
+            })
+            .collect::<Vec<_>>()
+            .join("\n");
+
+        format!(
+            "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>{0} contrast</title>\n<style>\n\
+            body {{ font-family: sans-serif; }}\n\
+            table {{ border-collapse: collapse; }}\n\
+            th, td {{ padding: 6px; font-size: 12px; white-space: nowrap; }}\n\
+            th {{ text-align: left; font-weight: normal; }}\n\
+            .swatch {{ width: 100%; height: 12px; border-radius: 2px; }}\n\
+            span {{ display: block; margin-top: 4px; padding: 1px 4px; border-radius: 4px; color: #fff; }}\n\
+            .pass {{ background: #2e7d32; }}\n\
+            .fail {{ background: #c62828; }}\n\
+            </style>\n</head>\n<body>\n<h1>{0} contrast</h1>\n<p>pairs pass with a contrast of at least {1}</p>\n\
+            <table>\n<tr><th></th>{header}</tr>\n{rows}\n</table>\n</body>\n</html>\n",
+            escape(group),
+            self.min,
+        )
+    }
+}
+
+/// Escape `text` for use in html.
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
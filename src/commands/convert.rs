@@ -0,0 +1,88 @@
+use std::fs::File;
+use std::io::Write;
+use std::path::PathBuf;
+
+use clap::{arg, value_parser, ArgMatches, Command};
+use zip::write::SimpleFileOptions;
+use zip::{CompressionMethod, DateTime, ZipWriter};
+
+use crate::convert::{self, Format};
+use crate::document::LunacyDocument;
+use crate::exit::{Exit, OrExit};
+use crate::output::{say, warning};
+
+/// Create the `convert` subcommand.
+pub fn command() -> Command {
+    Command::new("convert")
+        .about("convert a document between the Lunacy .free and Sketch .sketch formats")
+        .arg(
+            arg!([FILE] "the .free or .sketch file to convert")
+                .required(true)
+                .value_parser(value_parser!(PathBuf))
+        )
+        .arg(
+            arg!([OUTPUT] "the file to write the converted document to, its extension choosing the format")
+                .required(true)
+                .value_parser(value_parser!(PathBuf))
+        )
+}
+
+/// Run the `convert` subcommand.
+pub fn run(matches: &ArgMatches) {
+    let Some(path) = matches.get_one::<PathBuf>("FILE") else {
+        panic!("expected .free or .sketch document as first argument");
+    };
+
+    let Some(output) = matches.get_one::<PathBuf>("OUTPUT") else {
+        panic!("expected .free or .sketch document as second argument");
+    };
+
+    let Some(format) = Format::from_path(output) else {
+        panic!("expected the output to have a .free or .sketch extension");
+    };
+
+    let options = crate::load_open_options(matches, path);
+    let doc     = LunacyDocument::open(path, &options)
+        .or_exit(Exit::Document, "failed to open document");
+
+    if Format::of(&doc) == format {
+        panic!("the document is already a {format} document");
+    }
+
+    let conversion = convert::convert(&doc, format)
+        .expect("failed to convert document");
+
+    // Write to a sibling file first so a failed conversion never leaves a half written document.
+    let mut temp_path = output.as_os_str().to_owned();
+    temp_path.push(".tmp");
+
+    let mut writer = ZipWriter::new(File::create(&temp_path).expect("failed to create document"));
+    for (name, json) in &conversion.entries {
+        // Timestamps are zeroed so converting the same document always produces the same file.
+        let options = SimpleFileOptions::default()
+            .compression_method(CompressionMethod::Deflated)
+            .last_modified_time(DateTime::default());
+
+        writer.start_file(name.as_str(), options)
+            .expect("failed to write entry");
+        writer.write_all(json.dump().as_bytes())
+            .expect("failed to write entry");
+    }
+
+    writer.finish()
+        .expect("failed to write document");
+    std::fs::rename(&temp_path, output)
+        .expect("failed to write document");
+
+    for (kind, count) in &conversion.skipped {
+        warning!("warning: left out {count} {kind} layers, which {format} documents don't share");
+    }
+
+    say!(
+        "converted {} pages, {} layers and {} colors into {}",
+        conversion.pages,
+        conversion.layers,
+        conversion.colors,
+        output.display(),
+    );
+}
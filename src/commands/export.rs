@@ -0,0 +1,144 @@
+use std::ffi::OsStr;
+use std::path::PathBuf;
+
+use clap::{arg, value_parser, ArgMatches, Command};
+use clap_complete::{ArgValueCompleter, CompletionCandidate};
+use lunacy_tools::export::{self, ExportOptions, Shape, Target, SHAPES, TARGETS};
+use lunacy_tools::schema::DocumentJson;
+
+use crate::config::Config;
+use crate::document::LunacyDocument;
+use crate::exit::{Exit, OrExit};
+use crate::output::say;
+
+/// Create the `export` subcommand.
+pub fn command() -> Command {
+    Command::new("export")
+        .about("export the colors of a document for use outside of Lunacy, e.g. as css gradients")
+        .arg(
+            arg!([FILE] "the lunacy .free file to export the colors of")
+                .required(true)
+                .value_parser(value_parser!(PathBuf))
+        )
+        .arg(
+            arg!(--target <TARGET> "the format to export the colors to")
+                .required_unless_present("profile")
+                .value_parser(TARGETS.to_vec())
+        )
+        .arg(
+            arg!(--profile <PROFILE> "write every file of an export profile from the configuration instead, e.g. the exports of a platform")
+                .conflicts_with_all(["target", "prefix", "shape", "output"])
+                .value_parser(value_parser!(String))
+                .add(ArgValueCompleter::new(complete_profile))
+        )
+        .arg(
+            arg!(--group <GROUP> "the group of colors to export, defaults to the configured group, or one chosen from the document when run interactively, or 'theme'")
+                .value_parser(value_parser!(String))
+                .add(ArgValueCompleter::new(crate::complete_group))
+        )
+        .arg(
+            arg!(--prefix <PREFIX> "the prefix of every exported name, e.g. THEME_, for targets naming colors freely")
+                .value_parser(value_parser!(String))
+        )
+        .arg(
+            arg!(--shape <SHAPE> "the shape of json exports, objects nested by the levels of color names or a flat object, defaults to nested")
+                .value_parser(SHAPES.to_vec())
+        )
+        .arg(
+            arg!(-o --output <OUTPUT> "the file to write the export to, printed when unspecified")
+                .value_parser(value_parser!(PathBuf))
+        )
+}
+
+/// Run the `export` subcommand.
+pub fn run(matches: &ArgMatches) {
+    let Some(path) = matches.get_one::<PathBuf>("FILE") else {
+        panic!("expected .free document as first argument");
+    };
+
+    let profile = matches.get_one::<String>("profile").map(|name| match crate::config().profiles.get(name) {
+        Some(artifacts) => artifacts,
+        None            => Exit::InvalidArgs.fail(format!("no export profile named `{name}` in the configuration")),
+    });
+
+    let options = crate::load_open_options(matches, path);
+    let doc     = LunacyDocument::open(path, &options)
+        .or_exit(Exit::Document, "failed to open document");
+
+    let group = match matches.get_one::<String>("group").or(crate::config().group.as_ref()) {
+        Some(group) => group.to_owned(),
+        None        => crate::pick_group(&doc).expect("failed to pick a group"),
+    };
+
+    let document = DocumentJson {
+        color_variables : Some(doc.load_color_variables().expect("failed to read color variables")),
+        ..Default::default()
+    };
+
+    if let Some(artifacts) = profile {
+        for artifact in artifacts {
+            let export_options = ExportOptions {
+                separator   : crate::separator().to_owned(),
+                prefix      : artifact.prefix.clone().unwrap_or_default(),
+                shape       : artifact.shape.unwrap_or_default(),
+            };
+
+            if let Some(dir) = artifact.output.parent().filter(|x| !x.as_os_str().is_empty()) {
+                std::fs::create_dir_all(dir)
+                    .expect("failed to create export directory");
+            }
+            std::fs::write(&artifact.output, export::export(&document, &group, artifact.target, &export_options))
+                .expect("failed to write export");
+            say!("wrote {}", artifact.output.display());
+        }
+
+        return;
+    }
+
+    let Some(target) = matches.get_one::<String>("target") else {
+        panic!("expected an export target");
+    };
+    let target = target.parse::<Target>()
+        .expect("expected a valid export target");
+
+    let export_options = ExportOptions {
+        separator   : crate::separator().to_owned(),
+        prefix      : matches.get_one::<String>("prefix").cloned().unwrap_or_default(),
+        shape       : matches.get_one::<String>("shape").map(|x| x.parse().expect("expected a valid json shape")).unwrap_or_default(),
+    };
+
+    let output = export::export(&document, &group, target, &export_options);
+    match matches.get_one::<PathBuf>("output") {
+        Some(path)  => std::fs::write(path, output).expect("failed to write export"),
+        None        => print!("{output}"),
+    }
+}
+
+/// A file written by an export profile.
+#[derive(Clone, Debug)]
+pub struct Artifact {
+    /// The format the colors are exported to.
+    pub target  : Target,
+    /// The file the export is written to.
+    pub output  : PathBuf,
+    /// The prefix of every exported name, for targets naming colors freely.
+    pub prefix  : Option<String>,
+    /// The shape of json exports.
+    pub shape   : Option<Shape>,
+}
+
+/// Complete the names of the export profiles in the configuration. Completion runs before the
+/// configuration is loaded, so it's read here, and an unreadable one completes nothing.
+fn complete_profile(current: &OsStr) -> Vec<CompletionCandidate> {
+    let current = current.to_string_lossy();
+
+    let Ok(config) = Config::load() else {
+        return vec![];
+    };
+
+    config.profiles
+        .keys()
+        .filter(|x| x.starts_with(current.as_ref()))
+        .map(CompletionCandidate::new)
+        .collect()
+}
@@ -0,0 +1,373 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::{Path, PathBuf};
+
+use clap::{arg, value_parser, ArgAction, ArgMatches, Command};
+
+use crate::commands::{assets, find_files};
+use crate::document::LunacyDocument;
+use crate::exit::{Exit, OrExit};
+use crate::fonts::{self, FontMapping};
+use crate::model::{self, FontRef};
+use crate::output::say;
+use crate::references::FONTS_DIR;
+use crate::Error;
+
+/// The number of layer names listed for each font before the rest are summarised.
+const MAX_LISTED_LAYERS: usize = 10;
+
+/// Create the `fonts` subcommand.
+pub fn command() -> Command {
+    Command::new("fonts")
+        .about("inspect and manage the fonts used by a document")
+        .subcommand_required(true)
+        .subcommand(
+            Command::new("audit")
+                .about("list the fonts used by text layers and styles and whether they're embedded")
+                .arg(
+                    arg!([FILE] "the lunacy .free file to audit")
+                        .required(true)
+                        .value_parser(value_parser!(PathBuf))
+                )
+        )
+        .subcommand(
+            Command::new("embed")
+                .about("embed the fonts a document uses from font files or directories")
+                .arg(
+                    arg!([FILE] "the lunacy .free file to modify")
+                        .required(true)
+                        .value_parser(value_parser!(PathBuf))
+                )
+                .arg(
+                    arg!([FONTS] ... "the font files, or directories containing them, to embed from")
+                        .required(true)
+                        .value_parser(value_parser!(PathBuf))
+                )
+        )
+        .subcommand(
+            Command::new("extract")
+                .about("write every embedded font to a directory")
+                .arg(
+                    arg!([FILE] "the lunacy .free file to extract fonts from")
+                        .required(true)
+                        .value_parser(value_parser!(PathBuf))
+                )
+                .arg(
+                    arg!(--out <DIR> "the directory to write fonts to")
+                        .required(true)
+                        .value_parser(value_parser!(PathBuf))
+                )
+        )
+        .subcommand(
+            Command::new("replace")
+                .about("replace the fonts used by text layers and styles")
+                .arg(
+                    arg!([FILE] "the lunacy .free file to modify")
+                        .required(true)
+                        .value_parser(value_parser!(PathBuf))
+                )
+                .arg(
+                    arg!(--map <MAPPING> "a font to replace as `from=to`, e.g. 'Helvetica Neue=Inter', or a semicolon separated list of them")
+                        .action(ArgAction::Append)
+                )
+                .arg(
+                    arg!(--map_file <MAP_FILE> "a json file mapping fonts to their replacements")
+                        .id("MAP_FILE")
+                        .value_parser(value_parser!(PathBuf))
+                )
+        )
+}
+
+/// Run the `fonts` subcommand.
+pub fn run(matches: &ArgMatches) {
+    match matches.subcommand() {
+        Some(("audit", matches))    => audit(matches),
+        Some(("embed", matches))    => embed(matches),
+        Some(("extract", matches))  => extract(matches),
+        Some(("replace", matches))  => replace(matches),
+        _                           => unreachable!("a subcommand is required"),
+    }
+}
+
+/// Where a font is used within a document.
+#[derive(Default)]
+struct FontUsage {
+    /// The names of the layers using the font.
+    layers  : Vec<String>,
+    /// The names of the text styles using the font.
+    styles  : Vec<String>,
+}
+
+/// List the fonts used by a document, whether they're embedded and where they're used.
+fn audit(matches: &ArgMatches) {
+    let Some(path) = matches.get_one::<PathBuf>("FILE") else {
+        panic!("expected .free document as first argument");
+    };
+
+    let options = crate::load_open_options(matches, path);
+    let doc     = LunacyDocument::open(path, &options)
+        .or_exit(Exit::Document, "failed to open document");
+
+    let usage = font_usage(&doc)
+        .expect("failed to read document");
+    let embedded = embedded_fonts(&doc)
+        .expect("failed to read embedded fonts");
+
+    let mut missing = 0;
+    for (font, usage) in &usage {
+        let status = match embedded.contains_key(font) {
+            true    => "embedded",
+            false   => {
+                missing += 1;
+                "missing"
+            }
+        };
+
+        println!("{font} ({status}): {} layers, {} styles", usage.layers.len(), usage.styles.len());
+        if !usage.styles.is_empty() {
+            println!("    styles: {}", usage.styles.join(", "));
+        }
+
+        if !usage.layers.is_empty() {
+            let mut layers = usage.layers.iter()
+                .take(MAX_LISTED_LAYERS)
+                .map(|x| x.as_str())
+                .collect::<Vec<_>>()
+                .join(", ");
+
+            if usage.layers.len() > MAX_LISTED_LAYERS {
+                layers.push_str(&format!(" and {} more", usage.layers.len() - MAX_LISTED_LAYERS));
+            }
+            println!("    layers: {layers}");
+        }
+    }
+
+    // Embedded fonts nothing uses are worth knowing about too, they only bloat the document.
+    for (font, name) in embedded.iter().filter(|(x, _)| !usage.contains_key(*x)) {
+        println!("{font} (unused): embedded as {name}");
+    }
+
+    say!("{} fonts used, {} not embedded", usage.len(), missing);
+}
+
+/// Embed the fonts a document uses but doesn't have embedded from the given font files.
+fn embed(matches: &ArgMatches) {
+    let Some(path) = matches.get_one::<PathBuf>("FILE") else {
+        panic!("expected .free document as first argument");
+    };
+
+    let mut files = vec![];
+    for path in matches.get_many::<PathBuf>("FONTS").into_iter().flatten() {
+        find_files(path, fonts::FONT_EXTENSIONS, &mut files)
+            .expect("failed to find font files");
+    }
+
+    let options = crate::load_edit_options(matches, path);
+    let mut doc = LunacyDocument::open(path, &options)
+        .or_exit(Exit::Document, "failed to open document");
+    doc.set_zero_timestamps(matches.get_flag("ZERO_TIMESTAMPS"));
+
+    let usage = font_usage(&doc)
+        .expect("failed to read document");
+    let mut embedded = embedded_fonts(&doc)
+        .expect("failed to read embedded fonts");
+
+    for file in files {
+        let data = std::fs::read(&file)
+            .expect("failed to read font file");
+
+        let Some(font) = fonts::identify(&data) else {
+            say!("skipped {}: not a font", file.display());
+            continue;
+        };
+
+        if !usage.contains_key(&font) || embedded.contains_key(&font) {
+            continue;
+        }
+
+        // Keep the original file name, adding a suffix if another font already has it.
+        let stem        = file.file_stem().and_then(|x| x.to_str()).unwrap_or("font");
+        let extension   = file.extension().and_then(|x| x.to_str()).unwrap_or_default();
+        let mut name    = format!("{FONTS_DIR}{stem}.{extension}");
+        for i in 2.. {
+            if !doc.has_entry(&name) {
+                break;
+            }
+            name = format!("{FONTS_DIR}{stem}-{i}.{extension}");
+        }
+
+        doc.save_bytes(&name, &data)
+            .expect("failed to embed font");
+
+        say!("embedded {font} from {} as {name}", file.display());
+        embedded.insert(font, name);
+    }
+
+    doc.commit()
+        .or_exit(Exit::Document, "failed to commit changes to document");
+
+    for font in usage.keys().filter(|x| !embedded.contains_key(*x)) {
+        say!("{font} is still missing");
+    }
+}
+
+/// Write every font embedded in a document to a directory, warning about any whose license may
+/// not allow them to be installed.
+fn extract(matches: &ArgMatches) {
+    let Some(path) = matches.get_one::<PathBuf>("FILE") else {
+        panic!("expected .free document as first argument");
+    };
+
+    let Some(out) = matches.get_one::<PathBuf>("out") else {
+        panic!("expected an output directory");
+    };
+
+    let options = crate::load_open_options(matches, path);
+    let doc     = LunacyDocument::open(path, &options)
+        .or_exit(Exit::Document, "failed to open document");
+
+    std::fs::create_dir_all(out)
+        .expect("failed to create output directory");
+
+    let mut written = BTreeSet::new();
+    for asset in assets::asset_entries(&doc).into_iter().filter(|x| x.name.starts_with(FONTS_DIR)) {
+        let data = doc.load_bytes(&asset.name)
+            .expect("failed to read font");
+
+        // Fonts may be nested in subdirectories, so flatten them into the output directory.
+        let entry       = Path::new(&asset.name);
+        let stem        = entry.file_stem().and_then(|x| x.to_str()).unwrap_or("font");
+        let extension   = entry.extension().and_then(|x| x.to_str()).unwrap_or("ttf");
+        let mut file    = out.join(format!("{stem}.{extension}"));
+        for i in 2.. {
+            if written.insert(file.clone()) {
+                break;
+            }
+            file = out.join(format!("{stem}-{i}.{extension}"));
+        }
+
+        std::fs::write(&file, &data)
+            .expect("failed to write font");
+
+        let font = fonts::identify(&data).map_or_else(|| asset.name.clone(), |x| x.to_string());
+        say!("{} -> {} ({font})", asset.name, file.display());
+
+        let Some(license) = fonts::license(&data) else {
+            continue;
+        };
+
+        if !license.installable {
+            say!("    warning: {font} doesn't permit installation, it may only be used within the document");
+        }
+        if let Some(url) = license.url.or(license.description) {
+            say!("    license: {url}");
+        }
+    }
+
+    say!("extracted {} fonts to {}", written.len(), out.display());
+    say!("check each font's license allows it to be installed before doing so");
+}
+
+/// Replace the fonts used by a document according to a mapping.
+fn replace(matches: &ArgMatches) {
+    let Some(path) = matches.get_one::<PathBuf>("FILE") else {
+        panic!("expected .free document as first argument");
+    };
+
+    let mut mapping = FontMapping::default();
+    if let Some(path) = matches.get_one::<PathBuf>("MAP_FILE") {
+        let contents = std::fs::read_to_string(path)
+            .expect("failed to read font mapping file");
+        let json = json::parse(&contents)
+            .expect("failed to parse font mapping file");
+
+        mapping.add_json(&json)
+            .expect("failed to parse font mapping file");
+    }
+
+    let rules = matches.get_many::<String>("map")
+        .into_iter()
+        .flatten()
+        .flat_map(|x| x.split(';'))
+        .filter(|x| !x.trim().is_empty());
+
+    for rule in rules {
+        mapping.add_rule(rule)
+            .expect("failed to parse font mapping");
+    }
+
+    if mapping.is_empty() {
+        panic!("expected at least one font mapping via --map or --map_file");
+    }
+
+    let options = crate::load_edit_options(matches, path);
+    let mut doc = LunacyDocument::open(path, &options)
+        .or_exit(Exit::Document, "failed to open document");
+    doc.set_zero_timestamps(matches.get_flag("ZERO_TIMESTAMPS"));
+
+    let mut replaced = BTreeMap::<(FontRef, FontRef), usize>::new();
+    for entry in model::json_entries(&doc) {
+        let mut json = doc.load_json(&entry)
+            .expect("failed to read document");
+
+        let count = model::update_fonts(&mut json, &mut |font| {
+            let to = mapping.apply(font)?;
+            *replaced.entry((font.clone(), to.clone())).or_default() += 1;
+            Some(to)
+        });
+
+        if count > 0 {
+            doc.save_json(&entry, &json)
+                .expect("failed to save document");
+        }
+    }
+
+    doc.commit()
+        .or_exit(Exit::Document, "failed to commit changes to document");
+
+    for ((from, to), count) in &replaced {
+        say!("replaced {from} with {to} ({count} uses)");
+    }
+    say!("replaced {} font uses", replaced.values().sum::<usize>());
+}
+
+/// Find every font used by the text layers and text styles in `doc`.
+fn font_usage(doc: &LunacyDocument) -> Result<BTreeMap<FontRef, FontUsage>, Error> {
+    let mut usage = BTreeMap::<FontRef, FontUsage>::new();
+    for (_, page) in model::load_pages(doc)? {
+        model::visit_layers(&page, &mut |layer| {
+            let name = layer["name"].as_str().unwrap_or_default();
+
+            // A layer may use the same font in several runs of text, only count it once.
+            let mut fonts = BTreeSet::new();
+            model::visit_fonts(layer, &mut |font| { fonts.insert(font); });
+            for font in fonts {
+                usage.entry(font).or_default().layers.push(name.to_owned());
+            }
+        });
+    }
+
+    let document = doc.load_json("document.json")?;
+    for style in document[model::TEXT_STYLES_KEY].members() {
+        let name = style["name"].as_str().unwrap_or_default();
+
+        let mut fonts = BTreeSet::new();
+        model::visit_fonts(style, &mut |font| { fonts.insert(font); });
+        for font in fonts {
+            usage.entry(font).or_default().styles.push(name.to_owned());
+        }
+    }
+
+    Ok(usage)
+}
+
+/// Identify the fonts embedded in `doc`, along with the entry each is stored in.
+fn embedded_fonts(doc: &LunacyDocument) -> Result<BTreeMap<FontRef, String>, Error> {
+    let mut embedded = BTreeMap::new();
+    for asset in assets::asset_entries(doc).into_iter().filter(|x| x.name.starts_with(FONTS_DIR)) {
+        if let Some(font) = fonts::identify(&doc.load_bytes(&asset.name)?) {
+            embedded.insert(font, asset.name);
+        }
+    }
+
+    Ok(embedded)
+}
@@ -0,0 +1,139 @@
+use std::path::PathBuf;
+
+use clap::{arg, value_parser, ArgMatches, Command};
+use json::JsonValue;
+use lunacy_tools::schema::DocumentJson;
+
+use crate::canonical;
+use crate::commands::pages;
+use crate::commands::styles::IDENTITY_KEYS;
+use crate::document::LunacyDocument;
+use crate::exit::{Exit, OrExit};
+use crate::model::{self, layer_type, Frame, LAYERS_KEY, STYLE_KINDS};
+
+/// The number of spaces each level of the projection is indented by.
+const INDENT: usize = 2;
+
+/// Create the `gitdiff` subcommand.
+pub fn command() -> Command {
+    Command::new("gitdiff")
+        .about("print a stable text projection of a document for use as a git textconv driver")
+        .arg(
+            arg!([FILE] "the lunacy .free file to print")
+                .required(true)
+                .value_parser(value_parser!(PathBuf))
+        )
+}
+
+/// Run the `gitdiff` subcommand.
+pub fn run(matches: &ArgMatches) {
+    let Some(path) = matches.get_one::<PathBuf>("FILE") else {
+        panic!("expected .free document as first argument");
+    };
+
+    let options = crate::load_open_options(matches, path);
+    let doc     = LunacyDocument::open(path, &options)
+        .or_exit(Exit::Document, "failed to open document");
+
+    let document = doc.load_json("document.json")
+        .expect("failed to read document");
+    let document = canonical::canonicalize("document.json", &document);
+    let entries  = doc.load::<DocumentJson>("document.json")
+        .map(|x| pages::ordered_entries(&doc, &x))
+        .expect("failed to read pages");
+
+    println!("colors");
+    for variable in document["colorVariables"].members() {
+        line(1, &format!("{}: {}", name(variable), variable["value"]));
+    }
+
+    for (kind, key) in STYLE_KINDS {
+        println!("{kind} styles");
+        for style in document[*key].members() {
+            line(1, &format!("{}: {}", name(style), properties(style)));
+        }
+    }
+
+    println!("pages");
+    for entry in entries {
+        let page = doc.load_json(&entry)
+            .expect("failed to read page");
+
+        line(1, page["name"].as_str().unwrap_or(&entry));
+        print_layers(&canonical::canonicalize(&entry, &page), 2);
+    }
+
+    println!("assets");
+    let mut assets = doc.entries()
+        .iter()
+        .filter(|x| !x.name.ends_with(".json"))
+        .collect::<Vec<_>>();
+    assets.sort_by(|a, b| a.name.cmp(&b.name));
+
+    for asset in assets {
+        line(1, &format!("{} ({} bytes)", asset.name, asset.size));
+    }
+}
+
+/// Print a summary of every layer below `json`, one per line, indented by `depth`.
+fn print_layers(json: &JsonValue, depth: usize) {
+    for layer in json[LAYERS_KEY].members() {
+        let frame       = Frame::from_layer(layer);
+        let mut summary = format!(
+            "{} {} {}x{} at {}, {}",
+            model::layer_type(layer),
+            name(layer),
+            frame.width,
+            frame.height,
+            frame.x,
+            frame.y
+        );
+
+        if model::layer_type(layer) == layer_type::TEXT {
+            if let Some(key) = model::text_key(layer) {
+                summary.push_str(&format!(": {}", layer[key].dump()));
+            }
+        }
+
+        if let Some(id) = model::instance_component_id(layer).filter(|_| model::is_instance(layer)) {
+            summary.push_str(&format!(" -> {id}"));
+        }
+
+        if !model::is_visible(layer) {
+            summary.push_str(" (hidden)");
+        }
+
+        line(depth, &summary);
+
+        model::visit_overrides(layer, &mut |value| {
+            let summary = match model::override_target(value) {
+                Some((layers, property))    => format!("override {} {property} = {}", layers.join("/"), value["value"].dump()),
+                None                        => format!("override {}", value.dump()),
+            };
+
+            line(depth + 1, &summary);
+        });
+
+        print_layers(layer, depth + 1);
+    }
+}
+
+/// Print `text` indented by `depth` levels.
+fn line(depth: usize, text: &str) {
+    println!("{:indent$}{text}", "", indent = depth * INDENT);
+}
+
+/// Get the name of a variable, style or layer, quoted so surrounding whitespace shows in diffs.
+fn name(json: &JsonValue) -> String {
+    JsonValue::from(json["name"].as_str().unwrap_or_default()).dump()
+}
+
+/// Get the properties of a style as compact json, leaving out those that identify it.
+fn properties(style: &JsonValue) -> String {
+    let mut properties = JsonValue::new_object();
+    for (key, value) in style.entries().filter(|(key, _)| !IDENTITY_KEYS.contains(key)) {
+        properties[key] = value.clone();
+    }
+
+    properties.dump()
+}
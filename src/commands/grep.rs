@@ -0,0 +1,133 @@
+use std::path::PathBuf;
+
+use clap::{arg, value_parser, ArgAction, ArgMatches, Command};
+use json::JsonValue;
+use regex::{Regex, RegexBuilder};
+
+use crate::document::LunacyDocument;
+use crate::exit::{Exit, OrExit};
+use crate::model::{self, LAYERS_KEY, NAME_SEPARATOR};
+use crate::output::say;
+
+/// Create the `grep` subcommand.
+pub fn command() -> Command {
+    Command::new("grep")
+        .about("search layer names, text, styles and colors in a document with a regular expression")
+        .allow_missing_positional(true)
+        .arg(
+            arg!([PATTERN] "the regular expression to search for")
+                .required_unless_present("FIXED_STRINGS")
+                .value_parser(value_parser!(Regex))
+        )
+        .arg(
+            arg!([FILE] "the lunacy .free file to search")
+                .required(true)
+                .value_parser(value_parser!(PathBuf))
+        )
+        .arg(
+            arg!(-i --ignore_case "match regardless of case")
+                .id("IGNORE_CASE")
+                .action(ArgAction::SetTrue)
+        )
+        .arg(
+            arg!(-F --fixed_strings <TEXT> "search for plain text rather than a regular expression")
+                .id("FIXED_STRINGS")
+                .conflicts_with("PATTERN")
+        )
+}
+
+/// Run the `grep` subcommand.
+pub fn run(matches: &ArgMatches) {
+    let Some(path) = matches.get_one::<PathBuf>("FILE") else {
+        panic!("expected .free document as second argument");
+    };
+
+    let pattern = match (matches.get_one::<Regex>("PATTERN"), matches.get_one::<String>("FIXED_STRINGS")) {
+        (Some(regex), _)    => regex.as_str().to_owned(),
+        (None, Some(text))  => regex::escape(text),
+        (None, None)        => panic!("expected a pattern as first argument"),
+    };
+
+    // The pattern was checked when parsed, so only the case may change.
+    let regex = RegexBuilder::new(&pattern)
+        .case_insensitive(matches.get_flag("IGNORE_CASE"))
+        .build()
+        .expect("failed to build pattern");
+
+    let options = crate::load_open_options(matches, path);
+    let doc     = LunacyDocument::open(path, &options)
+        .or_exit(Exit::Document, "failed to open document");
+
+    // Variables and styles are reported by where they sit in `document.json`.
+    let mut found = 0;
+    let document = doc.load_json("document.json")
+        .expect("failed to read document");
+    search(&document, &mut String::new(), &regex, &mut |key, value| {
+        println!("document.json {key}: {value}");
+        found += 1;
+    });
+
+    // Layers are reported by their page and the names of the layers leading to them.
+    for (entry, page) in model::load_pages(&doc).expect("failed to read pages") {
+        let page_name = page["name"].as_str().unwrap_or(&entry).to_owned();
+        search_layers(&page, &page_name, &regex, &mut |path, key, value| {
+            println!("{path} [{key}]: {value}");
+            found += 1;
+        });
+    }
+
+    say!("{found} matches");
+}
+
+/// Search the layers below `json`, reporting the path to each layer along with the matching key.
+fn search_layers(json: &JsonValue, parent: &str, regex: &Regex, f: &mut impl FnMut(&str, &str, &str)) {
+    for layer in json[LAYERS_KEY].members() {
+        let path = format!("{parent} {NAME_SEPARATOR} {}", layer["name"].as_str().unwrap_or_default());
+
+        let mut key = String::new();
+        for (name, value) in layer.entries().filter(|(name, _)| *name != LAYERS_KEY && !is_id_key(name)) {
+            key.push_str(name);
+            search(value, &mut key, regex, &mut |key, value| f(&path, key, value));
+            key.clear();
+        }
+
+        search_layers(layer, &path, regex, f);
+    }
+}
+
+/// Search every string in `json` other than ids, calling `f` with the path to each that matches.
+fn search(json: &JsonValue, path: &mut String, regex: &Regex, f: &mut impl FnMut(&str, &str)) {
+    match json {
+        JsonValue::Object(object) => {
+            for (name, value) in object.iter().filter(|(name, _)| !is_id_key(name)) {
+                let len = path.len();
+                if !path.is_empty() {
+                    path.push('.');
+                }
+                path.push_str(name);
+
+                search(value, path, regex, f);
+                path.truncate(len);
+            }
+        }
+        JsonValue::Array(values) => {
+            for (i, value) in values.iter().enumerate() {
+                let len = path.len();
+                path.push_str(&format!("[{i}]"));
+
+                search(value, path, regex, f);
+                path.truncate(len);
+            }
+        }
+        _ => {
+            if let Some(value) = json.as_str().filter(|x| regex.is_match(x)) {
+                f(path, value);
+            }
+        }
+    }
+}
+
+/// Check whether `key` holds an id, which would only add noise to the results.
+fn is_id_key(key: &str) -> bool {
+    key == "id" || key == "do_objectID" || key.ends_with("Id") || key.ends_with("ID") || key.ends_with("Ids")
+}
@@ -0,0 +1,72 @@
+use std::path::PathBuf;
+
+use clap::{arg, value_parser, ArgAction, ArgMatches, Command};
+use json::JsonValue;
+
+use crate::document::LunacyDocument;
+use crate::exit::{Exit, OrExit};
+use crate::history;
+
+/// Create the `history` subcommand.
+pub fn command() -> Command {
+    Command::new("history")
+        .about("inspect the changes the tool has made to a document")
+        .subcommand_required(true)
+        .subcommand(
+            Command::new("show")
+                .about("print the changes made to a document, newest first")
+                .arg(
+                    arg!([FILE] "the lunacy .free file to inspect")
+                        .required(true)
+                        .value_parser(value_parser!(PathBuf))
+                )
+                .arg(
+                    arg!(-n --count <COUNT> "the number of changes to print, defaults to every change")
+                        .value_parser(value_parser!(usize))
+                )
+                .arg(
+                    arg!(--json "print the changes as json")
+                        .action(ArgAction::SetTrue)
+                )
+        )
+}
+
+/// Run the `history` subcommand.
+pub fn run(matches: &ArgMatches) {
+    match matches.subcommand() {
+        Some(("show", matches)) => show(matches),
+        _                       => unreachable!("a subcommand is required"),
+    }
+}
+
+/// Print the changes made to a document, newest first.
+fn show(matches: &ArgMatches) {
+    let Some(path) = matches.get_one::<PathBuf>("FILE") else {
+        panic!("expected .free document as first argument");
+    };
+
+    let count = matches.get_one::<usize>("count")
+        .copied()
+        .unwrap_or(usize::MAX);
+
+    let options = crate::load_open_options(matches, path);
+    let doc     = LunacyDocument::open(path, &options)
+        .or_exit(Exit::Document, "failed to open document");
+
+    let records = history::load(&doc)
+        .expect("failed to read history");
+
+    let records = records.iter()
+        .rev()
+        .take(count);
+
+    if super::json_output(matches) {
+        let json = records.map(|x| x.to_json()).collect::<Vec<_>>();
+        println!("{}", JsonValue::from(json).pretty(4));
+        return;
+    }
+
+    for record in records {
+        println!("{record}");
+    }
+}
@@ -0,0 +1,194 @@
+use std::path::{Path, PathBuf};
+
+use clap::{arg, value_parser, ArgMatches, Command};
+use json::{object, JsonValue};
+
+use crate::commands::find_files;
+use crate::document::LunacyDocument;
+use crate::exit::{Exit, OrExit};
+use crate::icons;
+use crate::model::{self, layer_type, LAYERS_KEY, PAGES_DIR};
+use crate::Error;
+use crate::output::say;
+
+/// The gap left between icons on the icons page.
+const ICON_SPACING: f64 = 16.0;
+
+/// The number of icons placed in each row of the icons page.
+const ICONS_PER_ROW: usize = 10;
+
+/// Create the `icons` subcommand.
+pub fn command() -> Command {
+    Command::new("icons")
+        .about("manage icon components")
+        .subcommand_required(true)
+        .subcommand(
+            Command::new("import")
+                .about("create or update a component for every svg file in a directory")
+                .arg(
+                    arg!([FILE] "the lunacy .free file to modify")
+                        .required(true)
+                        .value_parser(value_parser!(PathBuf))
+                )
+                .arg(
+                    arg!([DIR] "the directory containing svg icons, searched recursively")
+                        .required(true)
+                        .value_parser(value_parser!(PathBuf))
+                )
+                .arg(
+                    arg!(--page <NAME> "the name of the page to place icons on, created if missing")
+                        .required(true)
+                )
+                .arg(
+                    arg!(--size <SIZE> "the width and height icons are scaled to fit, defaults to 24")
+                        .value_parser(value_parser!(f64))
+                )
+        )
+}
+
+/// Run the `icons` subcommand.
+pub fn run(matches: &ArgMatches) {
+    match matches.subcommand() {
+        Some(("import", matches))   => import(matches),
+        _                           => unreachable!("a subcommand is required"),
+    }
+}
+
+/// Import a directory of svg icons as components on a page.
+fn import(matches: &ArgMatches) {
+    let Some(path) = matches.get_one::<PathBuf>("FILE") else {
+        panic!("expected .free document as first argument");
+    };
+
+    let Some(dir) = matches.get_one::<PathBuf>("DIR") else {
+        panic!("expected a directory of svg icons");
+    };
+
+    let Some(page_name) = matches.get_one::<String>("page") else {
+        panic!("expected a page name");
+    };
+
+    let size = matches.get_one::<f64>("size")
+        .copied()
+        .unwrap_or(24.0);
+
+    let mut files = vec![];
+    find_files(dir, &["svg"], &mut files)
+        .expect("failed to find svg files");
+
+    let options = crate::load_edit_options(matches, path);
+    let mut doc = LunacyDocument::open(path, &options)
+        .or_exit(Exit::Document, "failed to open document");
+    doc.set_zero_timestamps(matches.get_flag("ZERO_TIMESTAMPS"));
+
+    let (entry, mut page) = find_or_create_page(&mut doc, page_name)
+        .expect("failed to read pages");
+
+    let mut created = 0;
+    let mut updated = 0;
+    for file in files {
+        let name = icon_name(dir, &file);
+        let svg  = std::fs::read_to_string(&file)
+            .expect("failed to read svg file");
+
+        let existing = page[LAYERS_KEY].members()
+            .position(|x| model::layer_type(x) == layer_type::COMPONENT && x["name"] == name.as_str());
+
+        // Updated components keep their id so instances of them stay linked.
+        let id = match existing {
+            Some(index) => page[LAYERS_KEY][index]["id"].as_str().map(|x| x.to_owned()),
+            None        => None,
+        };
+        let id = id.unwrap_or_else(|| model::derive_id(&format!("{page_name} / {name}")));
+
+        let layers = match icons::icon_layers(&svg, size, &id) {
+            Ok(layers)  => layers,
+            Err(error)  => {
+                say!("skipped {}: {error}", file.display());
+                continue;
+            }
+        };
+
+        match existing {
+            Some(index) => {
+                let component = &mut page[LAYERS_KEY][index];
+                component["width"]      = size.into();
+                component["height"]     = size.into();
+                component[LAYERS_KEY]   = layers.into();
+
+                say!("updated {name}");
+                updated += 1;
+            }
+            None        => {
+                // New icons are laid out in a grid after the existing layers.
+                let index   = page[LAYERS_KEY].len();
+                let step    = size + ICON_SPACING;
+                let component = object! {
+                    "id"        : id,
+                    "name"      : name.as_str(),
+                    "type"      : layer_type::COMPONENT,
+                    "x"         : (index % ICONS_PER_ROW) as f64 * step,
+                    "y"         : (index / ICONS_PER_ROW) as f64 * step,
+                    "width"     : size,
+                    "height"    : size,
+                    "layers"    : layers,
+                };
+                page[LAYERS_KEY].push(component)
+                    .expect("failed to add component");
+
+                say!("created {name}");
+                created += 1;
+            }
+        }
+    }
+
+    doc.save_json(&entry, &page)
+        .expect("failed to save page");
+    doc.commit()
+        .or_exit(Exit::Document, "failed to commit changes to document");
+
+    say!("{created} icons created, {updated} updated on {page_name}");
+}
+
+/// Find the page named `name`, creating an empty one if the document doesn't have it yet.
+fn find_or_create_page(doc: &mut LunacyDocument, name: &str) -> Result<(String, JsonValue), Error> {
+    if let Some(page) = model::load_pages(doc)?.into_iter().find(|(_, x)| x["name"] == name) {
+        return Ok(page);
+    }
+
+    let id      = model::derive_id(&format!("page / {name}"));
+    let entry   = format!("{PAGES_DIR}{id}.json");
+    let page    = object! {
+        "id"        : id.as_str(),
+        "name"      : name,
+        "layers"    : JsonValue::new_array(),
+    };
+
+    // Documents that list their pages need the new one registered too.
+    let mut document = doc.load_json("document.json")?;
+    if document["pages"].is_array() {
+        document["pages"].push(object! {
+            "_class"        : "MSJSONFileReference",
+            "_ref_class"    : "MSImmutablePage",
+            "_ref"          : format!("{PAGES_DIR}{id}"),
+        })?;
+        doc.save_json("document.json", &document)?;
+    }
+
+    Ok((entry, page))
+}
+
+/// Name an icon after its file, using the directories it's in as groups, e.g. `arrows / left`.
+fn icon_name(dir: &Path, file: &Path) -> String {
+    let relative = match file.strip_prefix(dir) {
+        Ok(relative) if !relative.as_os_str().is_empty()    => relative,
+        _                                                   => Path::new(file.file_name().unwrap_or_default()),
+    };
+
+    relative.with_extension("")
+        .components()
+        .map(|x| x.as_os_str().to_string_lossy())
+        .collect::<Vec<_>>()
+        .join(&format!(" {} ", model::NAME_SEPARATOR))
+}
+
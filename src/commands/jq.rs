@@ -0,0 +1,63 @@
+use std::path::PathBuf;
+
+use clap::{arg, value_parser, ArgAction, ArgMatches, Command};
+
+use crate::document::LunacyDocument;
+use crate::exit::{Exit, OrExit};
+use crate::jq::Filter;
+
+/// Create the `jq` subcommand.
+pub fn command() -> Command {
+    Command::new("jq")
+        .about("run a jq filter against a json entry in a document")
+        .arg(
+            arg!([FILTER] "the jq filter to run, e.g. '.colorVariables[] | .name'")
+                .required(true)
+                .value_parser(value_parser!(Filter))
+        )
+        .arg(
+            arg!([FILE] "the lunacy .free file to read")
+                .required(true)
+                .value_parser(value_parser!(PathBuf))
+        )
+        .arg(
+            arg!(--entry <NAME> "the json entry to run the filter against, defaults to document.json")
+        )
+        .arg(
+            arg!(-r --raw_output "print strings without quotes")
+                .id("RAW_OUTPUT")
+                .action(ArgAction::SetTrue)
+        )
+}
+
+/// Run the `jq` subcommand.
+pub fn run(matches: &ArgMatches) {
+    let Some(filter) = matches.get_one::<Filter>("FILTER") else {
+        panic!("expected a filter as first argument");
+    };
+
+    let Some(path) = matches.get_one::<PathBuf>("FILE") else {
+        panic!("expected .free document as second argument");
+    };
+
+    let entry = matches.get_one::<String>("entry")
+        .map(|x| x.as_str())
+        .unwrap_or("document.json");
+
+    let options = crate::load_open_options(matches, path);
+    let doc     = LunacyDocument::open(path, &options)
+        .or_exit(Exit::Document, "failed to open document");
+
+    let json = doc.load_json(entry)
+        .expect("failed to read entry");
+
+    let results = filter.run(&json)
+        .or_exit(Exit::Failure, "failed to run filter");
+
+    for result in results {
+        match result.as_str() {
+            Some(x) if matches.get_flag("RAW_OUTPUT")   => println!("{x}"),
+            _                                           => println!("{}", result.pretty(2)),
+        }
+    }
+}
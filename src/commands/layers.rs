@@ -0,0 +1,106 @@
+use std::collections::BTreeSet;
+use std::path::PathBuf;
+
+use clap::{arg, value_parser, ArgAction, ArgMatches, Command};
+use regex::Regex;
+
+use crate::document::LunacyDocument;
+use crate::exit::{Exit, OrExit};
+use crate::model;
+use crate::output::say;
+
+/// Create the `layers` subcommand.
+pub fn command() -> Command {
+    Command::new("layers")
+        .about("edit the layers in a document in bulk")
+        .subcommand_required(true)
+        .subcommand(
+            Command::new("rename")
+                .about("rename layers whose names match a regular expression")
+                .arg(
+                    arg!([FILE] "the lunacy .free file to modify")
+                        .required(true)
+                        .value_parser(value_parser!(PathBuf))
+                )
+                .arg(
+                    arg!(--match <PATTERN> "the regular expression to match within layer names, e.g. 'Rectangle \\d+'")
+                        .required(true)
+                        .value_parser(value_parser!(Regex))
+                )
+                .arg(
+                    arg!(--replace <TEXT> "the text to replace matches with, which may refer to groups as $1")
+                        .required(true)
+                )
+                .arg(
+                    arg!(--page <NAME> "the name of a page to rename layers on, defaults to every page")
+                        .action(ArgAction::Append)
+                )
+        )
+}
+
+/// Run the `layers` subcommand.
+pub fn run(matches: &ArgMatches) {
+    match matches.subcommand() {
+        Some(("rename", matches))   => rename(matches),
+        _                           => unreachable!("a subcommand is required"),
+    }
+}
+
+/// Rename the layers whose names match a regular expression.
+fn rename(matches: &ArgMatches) {
+    let Some(path) = matches.get_one::<PathBuf>("FILE") else {
+        panic!("expected .free document as first argument");
+    };
+
+    let Some(regex) = matches.get_one::<Regex>("match") else {
+        panic!("expected a pattern to match");
+    };
+
+    let Some(replace) = matches.get_one::<String>("replace") else {
+        panic!("expected a replacement");
+    };
+
+    let selected = matches.get_many::<String>("page")
+        .map(|x| x.map(|x| x.as_str()).collect::<BTreeSet<_>>());
+
+    let options = crate::load_edit_options(matches, path);
+    let mut doc = LunacyDocument::open(path, &options)
+        .or_exit(Exit::Document, "failed to open document");
+    doc.set_zero_timestamps(matches.get_flag("ZERO_TIMESTAMPS"));
+
+    let mut renamed = 0;
+    for (entry, mut page) in model::load_pages(&doc).expect("failed to read pages") {
+        let page_name = page["name"].as_str().unwrap_or(&entry).to_owned();
+        if selected.as_ref().is_some_and(|x| !x.contains(page_name.as_str())) {
+            continue;
+        }
+
+        let mut changed = false;
+        model::visit_layers_mut(&mut page, &mut |layer| {
+            let Some(name) = layer["name"].as_str().filter(|x| regex.is_match(x)) else {
+                return;
+            };
+
+            let new_name = regex.replace_all(name, replace.as_str()).into_owned();
+            if new_name == name {
+                return;
+            }
+
+            say!("{page_name}: {name} -> {new_name}");
+            layer["name"] = new_name.into();
+
+            renamed += 1;
+            changed  = true;
+        });
+
+        if changed {
+            doc.save_json(&entry, &page)
+                .expect("failed to save page");
+        }
+    }
+
+    doc.commit()
+        .or_exit(Exit::Document, "failed to commit changes to document");
+
+    say!("renamed {renamed} layers");
+}
@@ -0,0 +1,359 @@
+use std::io::{BufRead, Write};
+use std::panic::AssertUnwindSafe;
+use std::path::Path;
+
+use clap::{ArgMatches, Command};
+use json::{array, object, JsonValue};
+use lunacy_tools::palette;
+use lunacy_tools::schema::DocumentJson;
+
+use crate::commands::pages;
+use crate::document::{LunacyDocument, OpenOptions};
+use crate::hooks::Hooks;
+use crate::query::Selector;
+use crate::{model, Error};
+
+/// The versions of the model context protocol the server speaks, oldest first.
+const PROTOCOL_VERSIONS: &[&str] = &["2024-11-05", "2025-03-26", "2025-06-18"];
+
+/// Create the `mcp` subcommand.
+pub fn command() -> Command {
+    Command::new("mcp")
+        .about("serve document inspection and palette tools to AI assistants over the model context protocol on stdio")
+}
+
+/// Run the `mcp` subcommand.
+///
+/// Requests are read from stdin and responses written to stdout as json-rpc messages, one per
+/// line, until stdin is closed.
+pub fn run(matches: &ArgMatches) {
+    // Stdout carries the responses, so hooks printing to it would corrupt them.
+    let mut options = crate::open_options(matches);
+    options.hooks = Hooks::default();
+
+    // Assistants tend to call several tools on the same document in a row.
+    crate::cache::enable();
+
+    let stdin       = std::io::stdin();
+    let mut stdout  = std::io::stdout();
+    for line in stdin.lock().lines() {
+        let line = line.expect("failed to read request");
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        let response = match json::parse(&line) {
+            Ok(request) => handle(&request, &options),
+            Err(e)      => Some(response(JsonValue::Null, Err(RpcError::Parse(e.to_string())))),
+        };
+
+        if let Some(response) = response {
+            writeln!(stdout, "{}", response.dump())
+                .and_then(|_| stdout.flush())
+                .expect("failed to write response");
+        }
+    }
+}
+
+/// Handle a json-rpc `request`, returning the response to it, or `None` for notifications,
+/// which aren't responded to.
+fn handle(request: &JsonValue, options: &OpenOptions) -> Option<JsonValue> {
+    let id      = request["id"].clone();
+    let method  = request["method"].as_str().unwrap_or_default();
+    let params  = &request["params"];
+
+    let result = match method {
+        "initialize"    => Ok(initialize(params)),
+        "ping"          => Ok(JsonValue::new_object()),
+        "tools/list"    => Ok(object! { "tools": tools() }),
+        "tools/call"    => call_tool(params, options),
+        _               => Err(RpcError::MethodNotFound(method.to_owned())),
+    };
+
+    match id.is_null() {
+        true    => None,
+        false   => Some(response(id, result)),
+    }
+}
+
+/// Create the response to the request with `id`.
+fn response(id: JsonValue, result: Result<JsonValue, RpcError>) -> JsonValue {
+    match result {
+        Ok(result)  => object! { "jsonrpc": "2.0", "id": id, "result": result },
+        Err(e)      => object! { "jsonrpc": "2.0", "id": id, "error": { "code": e.code(), "message": e.to_string() } },
+    }
+}
+
+/// Respond to the `initialize` request, agreeing on the client's protocol version if the server
+/// speaks it, or the newest version the server speaks otherwise.
+fn initialize(params: &JsonValue) -> JsonValue {
+    let version = params["protocolVersion"].as_str()
+        .filter(|x| PROTOCOL_VERSIONS.contains(x))
+        .or(PROTOCOL_VERSIONS.last().copied());
+
+    object! {
+        "protocolVersion"   : version,
+        "capabilities"      : { "tools": {} },
+        "serverInfo"        : {
+            "name"      : env!("CARGO_PKG_NAME"),
+            "version"   : env!("CARGO_PKG_VERSION"),
+        },
+        "instructions"      : format!("Inspect and theme Lunacy .free documents on the local machine by path. Color variables are named `{}`.", naming()),
+    }
+}
+
+/// Describe how color variables are named with the configured name format and separator, e.g.
+/// `<group> / <color> / <color>.<stop>`.
+fn naming() -> String {
+    crate::config().name_format.as_deref()
+        .unwrap_or(palette::NAME_FORMAT)
+        .replace("{group}", "<group>")
+        .replace("{sep}", crate::separator())
+        .replace("{name}", "<color>")
+        .replace("{stem}", "<color>")
+        .replace("{stop}", "<stop>")
+}
+
+/// Describe the tools the server provides.
+fn tools() -> JsonValue {
+    /// The schema of the arguments every tool takes to find its document.
+    fn document_properties() -> JsonValue {
+        object! {
+            "path"      : { "type": "string", "description": "the path of the lunacy .free document" },
+            "password"  : { "type": "string", "description": "the password of the document, if it's encrypted" },
+        }
+    }
+
+    /// Describe a tool taking a document and the `extra` properties, of which `required` must be
+    /// given.
+    fn tool(name: &str, description: &str, extra: JsonValue, required: &[&str]) -> JsonValue {
+        let mut properties = document_properties();
+        for (key, value) in extra.entries() {
+            properties[key] = value.clone();
+        }
+
+        let mut required = required.to_vec();
+        required.insert(0, "path");
+
+        object! {
+            "name"          : name,
+            "description"   : description,
+            "inputSchema"   : {
+                "type"          : "object",
+                "properties"    : properties,
+                "required"      : required,
+            },
+        }
+    }
+
+    array![
+        tool(
+            "list_pages",
+            "List the pages in a document in order, with the number of layers and artboards on each.",
+            object! {},
+            &[]
+        ),
+        tool(
+            "query_layers",
+            "Find the layers in a document matching a css-like selector, e.g. `page[\"Home\"] text[fontSize<12]`.",
+            object! { "selector": { "type": "string", "description": "the selector to match" } },
+            &["selector"]
+        ),
+        tool(
+            "list_color_groups",
+            "List the groups of color variables in a document.",
+            object! {},
+            &[]
+        ),
+        tool(
+            "list_colors",
+            "List the color variables in a document, with their ids, names and hex values.",
+            object! { "group": { "type": "string", "description": "only list the colors in this group" } },
+            &[]
+        ),
+        tool(
+            "apply_color_scheme",
+            "Generate a palette of tints for each color in a scheme and apply it to a group of color variables in a document, updating existing colors by name.",
+            object! {
+                "scheme"    : {
+                    "type"                  : "object",
+                    "description"           : "the colors to apply by name, each with a hex `value`, e.g. `#3714AE`, or the name of a color to `link` to, and optionally the `stop` the value is at",
+                    "additionalProperties"  : {
+                        "type"          : "object",
+                        "properties"    : {
+                            "value" : { "type": "string" },
+                            "link"  : { "type": "string" },
+                            "stop"  : { "type": "integer" },
+                        },
+                    },
+                },
+                "group"     : { "type": "string", "description": "the group to apply the colors to, defaults to the configured group or `theme`" },
+                "message"   : { "type": "string", "description": "a message describing the change, recorded in the document history" },
+            },
+            &["scheme"]
+        ),
+    ]
+}
+
+/// Call the tool named in `params` with its arguments.
+///
+/// Errors raised by the tool are reported in the result for the assistant to see, rather than
+/// as json-rpc errors.
+fn call_tool(params: &JsonValue, options: &OpenOptions) -> Result<JsonValue, RpcError> {
+    let name = params["name"].as_str().unwrap_or_default();
+    let tool = match name {
+        "list_pages"            => list_pages,
+        "query_layers"          => query_layers,
+        "list_color_groups"     => list_color_groups,
+        "list_colors"           => list_colors,
+        "apply_color_scheme"    => apply_color_scheme,
+        _                       => return Err(RpcError::InvalidParams(format!("unknown tool `{name}`"))),
+    };
+
+    // Commands report errors by panicking, so panics are caught to keep the server running.
+    let result = std::panic::catch_unwind(AssertUnwindSafe(|| tool(&params["arguments"], options)))
+        .unwrap_or_else(|x| Err(Box::from(super::panic_message(x))));
+
+    let (text, is_error) = match result {
+        Ok(json)    => (json.pretty(2), false),
+        Err(e)      => (e.to_string(), true),
+    };
+
+    let content = object! { "type": "text", "text": text };
+    Ok(object! {
+        "content"   : [content],
+        "isError"   : is_error,
+    })
+}
+
+/// Open the document named by the `path` argument.
+fn open(args: &JsonValue, options: &OpenOptions) -> Result<LunacyDocument, Error> {
+    let Some(path) = args["path"].as_str() else {
+        return Err(Box::new(RpcError::InvalidParams(String::from("expected a `path`"))));
+    };
+
+    let mut options = options.clone();
+    if let Some(password) = args["password"].as_str() {
+        options.password = Some(password.to_owned());
+    }
+
+    LunacyDocument::open(Path::new(path), &options)
+}
+
+/// List the pages in a document.
+fn list_pages(args: &JsonValue, options: &OpenOptions) -> Result<JsonValue, Error> {
+    let doc         = open(args, options)?;
+    let document    = doc.load::<DocumentJson>("document.json")?;
+
+    Ok(pages::page_summaries(&pages::ordered_pages(&doc, &document)?))
+}
+
+/// Find the layers in a document matching a selector.
+fn query_layers(args: &JsonValue, options: &OpenOptions) -> Result<JsonValue, Error> {
+    let Some(selector) = args["selector"].as_str() else {
+        return Err(Box::new(RpcError::InvalidParams(String::from("expected a `selector`"))));
+    };
+
+    let selector    = selector.parse::<Selector>()?;
+    let doc         = open(args, options)?;
+
+    let mut results = JsonValue::new_array();
+    for (entry, page) in &model::load_pages(&doc)? {
+        let page_name = page["name"].as_str().unwrap_or(entry);
+        for found in selector.select(page, page_name) {
+            results.push(object! {
+                "page"  : found.page,
+                "path"  : found.path,
+                "layer" : found.layer.clone(),
+            })?;
+        }
+    }
+
+    Ok(results)
+}
+
+/// List the groups of color variables in a document.
+fn list_color_groups(args: &JsonValue, options: &OpenOptions) -> Result<JsonValue, Error> {
+    let doc = open(args, options)?;
+    Ok(palette::groups(&doc.load::<DocumentJson>("document.json")?, crate::separator()).into())
+}
+
+/// List the color variables in a document, optionally only those in a group.
+fn list_colors(args: &JsonValue, options: &OpenOptions) -> Result<JsonValue, Error> {
+    let prefix  = args["group"].as_str().map(|x| format!("{x}{}", crate::separator()));
+    let doc     = open(args, options)?;
+
+    let colors = doc.load::<DocumentJson>("document.json")?
+        .color_variables()
+        .iter()
+        .filter(|x| prefix.as_ref().is_none_or(|prefix| x.name.starts_with(prefix.as_str())))
+        .map(|x| object! { "id": x.id.as_str(), "name": x.name.as_str(), "value": x.value.as_str() })
+        .collect::<Vec<_>>();
+
+    Ok(colors.into())
+}
+
+/// Apply a color scheme to a group of color variables in a document.
+fn apply_color_scheme(args: &JsonValue, options: &OpenOptions) -> Result<JsonValue, Error> {
+    if !args["scheme"].is_object() {
+        return Err(Box::new(RpcError::InvalidParams(String::from("expected a `scheme` object"))));
+    }
+
+    // Malformed schemes are rejected up front, as adding them to the scheme would panic.
+    palette::check_scheme(&args["scheme"])
+        .map_err(|e| RpcError::InvalidParams(format!("invalid color scheme, {e}")))?;
+
+    let mut scheme = crate::color_scheme(None, None, None, None)?;
+    scheme.add_colors(&args["scheme"]);
+
+    let group = args["group"].as_str()
+        .or(crate::config().group.as_deref())
+        .unwrap_or("theme");
+
+    let mut options = options.clone();
+    options.writable    = true;
+    options.operation   = Some(String::from("update colors"));
+    options.message     = args["message"].as_str().map(|x| x.to_owned()).or(options.message);
+
+    let mut doc = open(args, &options)?;
+    doc.update_colors(group, &scheme)?;
+
+    let entries = doc.changed_entries();
+    doc.commit()?;
+
+    Ok(object! { "group": group, "colors": scheme.colors.len(), "changed": entries })
+}
+
+/// An error responded to a json-rpc request with.
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum RpcError {
+    /// The request isn't valid json.
+    Parse(String),
+    /// The requested method doesn't exist.
+    MethodNotFound(String),
+    /// The parameters of the request aren't valid.
+    InvalidParams(String),
+}
+
+impl RpcError {
+    /// Get the json-rpc error code of the error.
+    fn code(&self) -> i32 {
+        match self {
+            Self::Parse(_)          => -32700,
+            Self::MethodNotFound(_) => -32601,
+            Self::InvalidParams(_)  => -32602,
+        }
+    }
+}
+
+impl std::fmt::Display for RpcError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Self::Parse(x)          => write!(f, "invalid json, {x}"),
+            Self::MethodNotFound(x) => write!(f, "unknown method `{x}`"),
+            Self::InvalidParams(x)  => write!(f, "{x}"),
+        }
+    }
+}
+
+impl std::error::Error for RpcError { }
@@ -0,0 +1,239 @@
+use std::fs::File;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use clap::{arg, value_parser, ArgMatches, Command};
+use zip::write::SimpleFileOptions;
+use zip::{AesMode, CompressionMethod, DateTime, ZipArchive, ZipWriter};
+
+use crate::checkpoint::{self, CHECKPOINTS_ENTRY, CHECKPOINT_DIR};
+use crate::document::{self, ArchiveError, OpenOptions, ZIP64_THRESHOLD};
+use crate::exit::Exit;
+use crate::history::{self, HISTORY_ENTRY, UNDO_DIR};
+use crate::merge;
+use crate::Error;
+
+/// Create the `merge` subcommand.
+pub fn command() -> Command {
+    Command::new("merge")
+        .about("three-way merge two versions of a document, for use as a git merge driver")
+        .arg(
+            arg!([BASE] "the common ancestor of the two versions")
+                .required(true)
+                .value_parser(value_parser!(PathBuf))
+        )
+        .arg(
+            arg!([OURS] "our version of the document")
+                .required(true)
+                .value_parser(value_parser!(PathBuf))
+        )
+        .arg(
+            arg!([THEIRS] "their version of the document")
+                .required(true)
+                .value_parser(value_parser!(PathBuf))
+        )
+        .arg(
+            arg!(-o --out <FILE> "the path to write the merged document to")
+                .required(true)
+                .value_parser(value_parser!(PathBuf))
+        )
+}
+
+/// Run the `merge` subcommand.
+pub fn run(matches: &ArgMatches) {
+    let (Some(base), Some(ours), Some(theirs)) = (
+        matches.get_one::<PathBuf>("BASE"),
+        matches.get_one::<PathBuf>("OURS"),
+        matches.get_one::<PathBuf>("THEIRS"),
+    ) else {
+        panic!("expected base, our and their documents as arguments");
+    };
+
+    let Some(out) = matches.get_one::<PathBuf>("out") else {
+        panic!("expected a path to write the merged document to");
+    };
+
+    // Git hands merge drivers temporary files without the .free extension, so the archives are
+    // read directly rather than opened as documents.
+    let options = crate::load_open_options(matches, ours);
+    let base    = read_archive(base, &options).expect("failed to read base document");
+    let ours    = read_archive(ours, &options).expect("failed to read our document");
+    let theirs  = read_archive(theirs, &options).expect("failed to read their document");
+
+    let mut names = ours.iter().map(|(name, _)| name.as_str()).collect::<Vec<_>>();
+    for (name, _) in &theirs {
+        if !names.contains(&name.as_str()) {
+            names.push(name);
+        }
+    }
+
+    let find = |entries: &'_ [(String, Vec<u8>)], name: &str| entries.iter()
+        .find(|(x, _)| x == name)
+        .map(|(_, data)| data.clone());
+
+    let mut conflicts   = vec![];
+    let mut merged      = vec![];
+    for name in names {
+        if is_tool_entry(name) {
+            continue;
+        }
+
+        let (base, ours, theirs) = (find(&base, name), find(&ours, name), find(&theirs, name));
+
+        let data = match name.ends_with(".json") {
+            true    => merge_json(name, base, ours, theirs, &mut conflicts).expect("failed to merge json"),
+            false   => merge_bytes(name, base, ours, theirs, &mut conflicts),
+        };
+
+        if let Some(data) = data {
+            merged.push((name.to_owned(), data));
+        }
+    }
+
+    merged.extend(merge_tool_entries(&base, &ours, &theirs).expect("failed to merge history"));
+
+    write_archive(out, &merged, options.password.as_deref())
+        .expect("failed to write merged document");
+
+    if !conflicts.is_empty() {
+        for conflict in &conflicts {
+            eprintln!("conflict: {conflict}");
+        }
+
+        eprintln!("{} conflicts were resolved with our version", conflicts.len());
+        Exit::Failure.quit();
+    }
+}
+
+/// Check whether `name` is one of the tool's own entries, merged by `merge_tool_entries` rather
+/// than as json.
+fn is_tool_entry(name: &str) -> bool {
+    name == HISTORY_ENTRY || name == CHECKPOINTS_ENTRY || name.starts_with(UNDO_DIR) || name.starts_with(CHECKPOINT_DIR)
+}
+
+/// Merge the tool's own entries, the history and checkpoints of each version, by what they
+/// record. The undo data of either version can't be applied to the merged document, so is
+/// dropped.
+fn merge_tool_entries(
+    base    : &[(String, Vec<u8>)],
+    ours    : &[(String, Vec<u8>)],
+    theirs  : &[(String, Vec<u8>)]
+)
+    -> Result<Vec<(String, Vec<u8>)>, Error>
+{
+    let history = |entries: &[(String, Vec<u8>)]| entries.iter()
+        .find(|(name, _)| name == HISTORY_ENTRY)
+        .map(|(_, data)| json::parse(&String::from_utf8_lossy(data)))
+        .transpose();
+
+    let (base_history, our_history, their_history) = (history(base)?, history(ours)?, history(theirs)?);
+
+    let mut merged = vec![];
+    if let Some(json) = history::merge(base_history.as_ref(), our_history.as_ref(), their_history.as_ref()) {
+        merged.push((HISTORY_ENTRY.to_owned(), json.dump().into_bytes()));
+    }
+
+    merged.extend(checkpoint::merge(base, ours, theirs)?);
+
+    Ok(merged)
+}
+
+/// Merge a json entry, returning the merged json, or `None` if the entry was deleted.
+fn merge_json(
+    name        : &str,
+    base        : Option<Vec<u8>>,
+    ours        : Option<Vec<u8>>,
+    theirs      : Option<Vec<u8>>,
+    conflicts   : &mut Vec<String>
+)
+    -> Result<Option<Vec<u8>>, Error>
+{
+    let parse = |data: Option<Vec<u8>>| data
+        .map(|x| json::parse(&String::from_utf8_lossy(&x)))
+        .transpose();
+
+    let (base, ours, theirs) = (parse(base)?, parse(ours)?, parse(theirs)?);
+
+    let mut entry_conflicts = vec![];
+    let merged = merge::merge(base.as_ref(), ours.as_ref(), theirs.as_ref(), "", &mut entry_conflicts);
+
+    conflicts.extend(entry_conflicts.into_iter().map(|x| match x.is_empty() {
+        true    => name.to_owned(),
+        false   => format!("{name}: {x}"),
+    }));
+
+    Ok(merged.map(|x| x.dump().into_bytes()))
+}
+
+/// Merge an entry that isn't json as a whole, returning the merged data, or `None` if the entry
+/// was deleted.
+fn merge_bytes(
+    name        : &str,
+    base        : Option<Vec<u8>>,
+    ours        : Option<Vec<u8>>,
+    theirs      : Option<Vec<u8>>,
+    conflicts   : &mut Vec<String>
+)
+    -> Option<Vec<u8>>
+{
+    if ours == theirs || theirs == base {
+        return ours;
+    }
+    if ours == base {
+        return theirs;
+    }
+
+    conflicts.push(name.to_owned());
+    ours.or(theirs)
+}
+
+/// Read every entry of the archive at `path` into memory, in archive order.
+fn read_archive(path: &Path, options: &OpenOptions) -> Result<Vec<(String, Vec<u8>)>, Error> {
+    let mut archive = ZipArchive::new(File::open(path)?)?;
+    if archive.len() > options.limits.max_entries {
+        return Err(Box::new(ArchiveError::TooManyEntries(archive.len())));
+    }
+
+    let mut entries     = vec![];
+    let mut remaining   = options.limits.max_size;
+    for i in 0..archive.len() {
+        let entry = archive.by_index_raw(i)?;
+        if entry.is_dir() {
+            continue;
+        }
+
+        let name = entry.name().to_owned();
+        drop(entry);
+
+        let data = document::read_entry(&mut archive, i, options.password.as_deref(), remaining)?;
+        remaining -= data.len() as u64;
+        entries.push((name, data));
+    }
+
+    Ok(entries)
+}
+
+/// Write `entries` to a new archive at `path`, encrypting them with `password` if given.
+fn write_archive(path: &Path, entries: &[(String, Vec<u8>)], password: Option<&str>) -> Result<(), Error> {
+    // Git hands us our version as the output too, so it's only replaced once fully written.
+    let temp_path   = path.with_extension("merge.tmp");
+    let mut writer  = ZipWriter::new(File::create(&temp_path)?);
+    for (name, data) in entries {
+        let mut options = SimpleFileOptions::default()
+            .compression_method(CompressionMethod::Deflated)
+            .last_modified_time(DateTime::default())
+            .large_file(data.len() as u64 >= ZIP64_THRESHOLD);
+
+        if let Some(password) = password {
+            options = options.with_aes_encryption(AesMode::Aes256, password);
+        }
+
+        writer.start_file(name.as_str(), options)?;
+        writer.write_all(data)?;
+    }
+
+    writer.finish()?;
+    std::fs::rename(&temp_path, path)?;
+
+    Ok(())
+}
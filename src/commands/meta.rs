@@ -0,0 +1,145 @@
+use std::path::PathBuf;
+
+use clap::{arg, value_parser, ArgAction, ArgMatches, Command};
+use json::JsonValue;
+
+use crate::document::LunacyDocument;
+use crate::exit::{Exit, OrExit};
+use crate::format;
+
+/// The entry holding the document metadata.
+const META_ENTRY: &str = "meta.json";
+
+/// Create the `meta` subcommand.
+pub fn command() -> Command {
+    Command::new("meta")
+        .about("inspect and edit document metadata such as the title, author and app version")
+        .subcommand_required(true)
+        .subcommand(
+            Command::new("get")
+                .about("print the document metadata, or a single value of it")
+                .arg(
+                    arg!([FILE] "the lunacy .free file to inspect")
+                        .required(true)
+                        .value_parser(value_parser!(PathBuf))
+                )
+                .arg(
+                    arg!([KEY] "the key of the value to print, with nested keys separated by `.`, e.g. 'created.app'")
+                )
+        )
+        .subcommand(
+            Command::new("set")
+                .about("set a value of the document metadata")
+                .arg(
+                    arg!([FILE] "the lunacy .free file to modify")
+                        .required(true)
+                        .value_parser(value_parser!(PathBuf))
+                )
+                .arg(
+                    arg!([KEY] "the key of the value to set, with nested keys separated by `.`, e.g. 'title'")
+                        .required(true)
+                )
+                .arg(
+                    arg!([VALUE] "the value to set")
+                        .required(true)
+                )
+                .arg(
+                    arg!(--json "parse the value as json rather than storing it as a string")
+                        .action(ArgAction::SetTrue)
+                )
+        )
+}
+
+/// Run the `meta` subcommand.
+pub fn run(matches: &ArgMatches) {
+    match matches.subcommand() {
+        Some(("get", matches))  => get(matches),
+        Some(("set", matches))  => set(matches),
+        _                       => unreachable!("a subcommand is required"),
+    }
+}
+
+/// Print the document metadata, or a single value of it.
+fn get(matches: &ArgMatches) {
+    let Some(path) = matches.get_one::<PathBuf>("FILE") else {
+        panic!("expected .free document as first argument");
+    };
+
+    let options = crate::load_open_options(matches, path);
+    let doc     = LunacyDocument::open(path, &options)
+        .or_exit(Exit::Document, "failed to open document");
+
+    let meta = match doc.has_entry(META_ENTRY) {
+        true    => doc.load_json(META_ENTRY).expect("failed to read metadata"),
+        false   => JsonValue::new_object(),
+    };
+
+    let Some(key) = matches.get_one::<String>("KEY") else {
+        println!("{}", meta.pretty(4));
+        return;
+    };
+
+    let value = key.split('.').try_fold(&meta, |json, key| match json.has_key(key) {
+        true    => Some(&json[key]),
+        false   => None,
+    });
+
+    match value {
+        Some(JsonValue::String(x))  => println!("{x}"),
+        Some(JsonValue::Short(x))   => println!("{x}"),
+        Some(x)                     => println!("{}", x.pretty(4)),
+        None                        => panic!("no metadata value {key}"),
+    }
+}
+
+/// Set a value of the document metadata.
+fn set(matches: &ArgMatches) {
+    let Some(path) = matches.get_one::<PathBuf>("FILE") else {
+        panic!("expected .free document as first argument");
+    };
+
+    let (Some(key), Some(value)) = (matches.get_one::<String>("KEY"), matches.get_one::<String>("VALUE")) else {
+        panic!("expected a key and value to set");
+    };
+
+    // Changing the format version without migrating would leave the document inconsistent.
+    if key == format::VERSION_KEY {
+        panic!("the format version can only be changed with `migrate`");
+    }
+
+    let value = match matches.get_flag("json") {
+        true    => json::parse(value).unwrap_or_else(|x| panic!("invalid json value: {x}")),
+        false   => value.as_str().into(),
+    };
+
+    let options = crate::load_edit_options(matches, path);
+    let mut doc = LunacyDocument::open(path, &options)
+        .or_exit(Exit::Document, "failed to open document");
+    doc.set_zero_timestamps(matches.get_flag("ZERO_TIMESTAMPS"));
+
+    let mut meta = match doc.has_entry(META_ENTRY) {
+        true    => doc.load_json(META_ENTRY).expect("failed to read metadata"),
+        false   => JsonValue::new_object(),
+    };
+
+    // Missing objects along the way are created, but existing values are never replaced by one.
+    let mut json = &mut meta;
+    for key in key.split('.') {
+        if !json.is_object() {
+            panic!("can't set {key} on a value that isn't an object");
+        }
+
+        if !json.has_key(key) {
+            json[key] = JsonValue::new_object();
+        }
+
+        json = &mut json[key];
+    }
+
+    *json = value;
+
+    doc.save_json(META_ENTRY, &meta)
+        .expect("failed to save metadata");
+    doc.commit()
+        .or_exit(Exit::Document, "failed to commit changes to document");
+}
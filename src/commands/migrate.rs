@@ -0,0 +1,66 @@
+use std::path::PathBuf;
+
+use clap::{arg, value_parser, ArgMatches, Command};
+
+use crate::document::LunacyDocument;
+use crate::exit::{Exit, OrExit};
+use crate::format::{self, SUPPORTED_VERSION};
+use crate::output::say;
+
+/// Create the `migrate` subcommand.
+pub fn command() -> Command {
+    Command::new("migrate")
+        .about("upgrade a document from an older format version")
+        .arg(
+            arg!([FILE] "the lunacy .free file to migrate")
+                .required(true)
+                .value_parser(value_parser!(PathBuf))
+        )
+        .arg(
+            arg!(--to <VERSION> "the format version to migrate to, defaults to the newest supported version")
+                .value_parser(value_parser!(u32))
+        )
+        .arg(
+            arg!(--from <VERSION> "the format version of the document, required if the document doesn't record it and must match it if it does")
+                .value_parser(value_parser!(u32))
+        )
+}
+
+/// Run the `migrate` subcommand.
+pub fn run(matches: &ArgMatches) {
+    let Some(path) = matches.get_one::<PathBuf>("FILE") else {
+        panic!("expected .free document as first argument");
+    };
+
+    let to = matches.get_one::<u32>("to")
+        .copied()
+        .unwrap_or(SUPPORTED_VERSION);
+
+    let options = crate::load_edit_options(matches, path);
+    let mut doc = LunacyDocument::open(path, &options)
+        .or_exit(Exit::Document, "failed to open document");
+
+    let recorded = format::read_version(&doc)
+        .or_exit(Exit::Document, "failed to read document format version");
+    let from = match (recorded, matches.get_one::<u32>("from").copied()) {
+        (Some(recorded), Some(from)) if recorded != from
+            => Exit::InvalidArgs.fail(format!("the document records format version {recorded}, not {from}")),
+        (Some(version), _) | (None, Some(version))
+            => version,
+        (None, None)
+            => Exit::InvalidArgs.fail("the document doesn't record its format version, specify it with `--from`"),
+    };
+
+    if from == to {
+        return;
+    }
+
+    let applied = format::migrate(&mut doc, from, to)
+        .expect("failed to migrate document");
+    doc.commit()
+        .or_exit(Exit::Document, "failed to commit changes to document");
+
+    for migration in applied {
+        say!("{} -> {}: {}", migration.from, migration.from + 1, migration.description);
+    }
+}
@@ -0,0 +1,244 @@
+pub mod artboards;
+pub mod assets;
+pub mod canonicalize;
+pub mod checkpoint;
+pub mod components;
+pub mod contrast;
+pub mod convert;
+pub mod export;
+pub mod fonts;
+pub mod gitdiff;
+pub mod grep;
+pub mod history;
+pub mod icons;
+pub mod jq;
+pub mod layers;
+pub mod mcp;
+pub mod merge;
+pub mod meta;
+pub mod migrate;
+pub mod normalize;
+pub mod optimize;
+pub mod pack;
+pub mod pages;
+pub mod preview;
+pub mod query;
+pub mod render;
+pub mod repair;
+pub mod roundtrip;
+pub mod scheme;
+pub mod serve;
+pub mod stats;
+pub mod styles;
+pub mod text;
+pub mod theme;
+pub mod undo;
+pub mod unpack;
+pub mod verify;
+
+use std::any::Any;
+use std::path::{Path, PathBuf};
+
+use clap::ArgMatches;
+use tempdir::TempDir;
+
+use crate::document::{BackupPolicy, OpenOptions};
+use crate::exit::{Exit, Failure};
+use crate::hooks::{self, Hooks};
+use crate::Error;
+
+/// A changed copy of a document, written to a temporary file next to its output so the output is
+/// only replaced once the changes are committed, and never at all if the run fails.
+pub struct DocumentCopy {
+    /// The path the copy is written to once finished.
+    out         : PathBuf,
+    /// The temporary copy the changes are made to.
+    path        : PathBuf,
+    /// The commands run once the copy is written to `out`.
+    post_commit : Vec<String>,
+    /// The directory holding the temporary copy.
+    _dir        : TempDir,
+}
+
+impl DocumentCopy {
+    /// Copy the document at `path` to make the changes to be written to `out`, adjusting the
+    /// `options` it's to be opened with.
+    ///
+    /// Exits when `out` is the document itself, as the original is to be left untouched. The
+    /// original isn't changed, so the copy is neither backed up nor runs `pre_open` hooks, and
+    /// the `post_commit` hooks are run on `out` once it's written.
+    pub fn new(path: &Path, out: &Path, options: &mut OpenOptions) -> Result<Self, Error> {
+        let same = std::fs::canonicalize(out)
+            .is_ok_and(|x| std::fs::canonicalize(path).is_ok_and(|y| x == y));
+        if same {
+            Exit::InvalidArgs.fail("the output can't be the document itself, give another path with --out");
+        }
+
+        let Some(name) = out.file_name() else {
+            Exit::InvalidArgs.fail("expected a file name for the output");
+        };
+
+        // The temp directory is removed again if the copy fails, as it's dropped on returning.
+        let parent  = out.parent().filter(|x| !x.as_os_str().is_empty()).unwrap_or(Path::new("."));
+        let dir     = TempDir::new_in(parent, ".lunacy-tools")?;
+        let copy    = dir.path().join(name);
+        std::fs::copy(path, &copy)?;
+
+        let hooks = std::mem::take(&mut options.hooks);
+        options.hooks = Hooks { webhook: hooks.webhook, ..Hooks::default() };
+        options.backup = BackupPolicy::Never;
+
+        Ok(Self { out: out.to_owned(), path: copy, post_commit: hooks.post_commit, _dir: dir })
+    }
+
+    /// Get the path of the temporary copy to make the changes to.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Replace `out` with the copy, once the changes to it are committed.
+    ///
+    /// The copy is consumed, so its temp directory is already removed by the time an error is
+    /// returned.
+    pub fn finish(self) -> Result<(), Error> {
+        std::fs::rename(&self.path, &self.out)?;
+        hooks::run(&self.post_commit, &self.out)
+    }
+}
+
+/// Format a size in bytes for display.
+pub fn format_size(bytes: u64) -> String {
+    const UNITS: &[&str] = &["B", "KB", "MB", "GB", "TB"];
+
+    let mut size = bytes as f64;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+
+    if unit == 0 {
+        format!("{bytes} B")
+    }
+    else {
+        format!("{size:.1} {}", UNITS[unit])
+    }
+}
+
+/// Replace the characters in `name` that aren't safe to use in a file name, using `fallback` if
+/// nothing is left.
+pub fn sanitize_file_name(name: &str, fallback: &str) -> String {
+    let name = name.trim()
+        .chars()
+        .map(|x| match x {
+            '/' | '\\'                                                  => '-',
+            x if x.is_alphanumeric() || matches!(x, '-' | '_' | '.' | ' ')    => x,
+            _                                                           => '_',
+        })
+        .collect::<String>();
+
+    match name.trim_matches('.') {
+        ""      => fallback.to_owned(),
+        name    => name.to_owned(),
+    }
+}
+
+/// Find the files at `path` with one of `extensions`, compared ignoring case, searching
+/// directories recursively, in a stable order. A `path` that isn't a directory is taken as is.
+pub fn find_files(path: &Path, extensions: &[&str], files: &mut Vec<PathBuf>) -> Result<(), Error> {
+    if !path.is_dir() {
+        files.push(path.to_owned());
+        return Ok(());
+    }
+
+    let mut entries = std::fs::read_dir(path)?
+        .map(|x| x.map(|x| x.path()))
+        .collect::<Result<Vec<_>, _>>()?;
+    entries.sort();
+
+    for entry in entries {
+        let matches = entry.extension()
+            .and_then(|x| x.to_str())
+            .is_some_and(|x| extensions.iter().any(|y| x.eq_ignore_ascii_case(y)));
+
+        if entry.is_dir() {
+            find_files(&entry, extensions, files)?;
+        }
+        else if matches {
+            files.push(entry);
+        }
+    }
+
+    Ok(())
+}
+
+/// The format commands print their output in.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum OutputFormat {
+    /// Human readable tables and text.
+    #[default]
+    Table,
+    /// Json, for use by other tools.
+    Json,
+}
+
+impl std::str::FromStr for OutputFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "table" => Ok(Self::Table),
+            "json"  => Ok(Self::Json),
+            _       => Err(format!("unknown output format `{s}`")),
+        }
+    }
+}
+
+/// Whether to print the output of a command as json, because `--json` was passed or json is the
+/// output format chosen by `--output_format` or the configuration.
+pub fn json_output(matches: &ArgMatches) -> bool {
+    if matches.get_flag("json") {
+        return true;
+    }
+
+    let format = match matches.get_one::<String>("OUTPUT_FORMAT") {
+        Some(format)    => format.parse().expect("expected a valid output format"),
+        None            => crate::config().output_format.unwrap_or_default(),
+    };
+
+    format == OutputFormat::Json
+}
+
+/// Get the message of a panic from its payload.
+pub fn panic_message(payload: Box<dyn Any + Send>) -> String {
+    payload.downcast_ref::<Failure>()
+        .and_then(|x| x.message.clone())
+        .or_else(|| payload.downcast_ref::<String>().cloned())
+        .or_else(|| payload.downcast_ref::<&str>().map(|x| x.to_string()))
+        .unwrap_or_else(|| String::from("unknown error"))
+}
+
+/// Print `rows` as a table with aligned columns.
+pub fn print_table<const N: usize>(headers: &[&str; N], rows: &[[String; N]]) {
+    let mut widths = headers.map(|x| x.chars().count());
+    for row in rows {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.chars().count());
+        }
+    }
+
+    let print_row = |cells: [&str; N]| {
+        let line = cells.iter()
+            .zip(widths)
+            .map(|(cell, width)| format!("{cell:width$}"))
+            .collect::<Vec<_>>()
+            .join("  ");
+
+        println!("{}", line.trim_end());
+    };
+
+    print_row(*headers);
+    for row in rows {
+        print_row(row.each_ref().map(|x| x.as_str()));
+    }
+}
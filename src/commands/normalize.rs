@@ -0,0 +1,144 @@
+use std::path::PathBuf;
+
+use clap::{arg, value_parser, ArgMatches, Command};
+use json::JsonValue;
+
+use crate::document::LunacyDocument;
+use crate::exit::{Exit, OrExit};
+use crate::model;
+use crate::output::say;
+
+/// Create the `normalize` subcommand.
+pub fn command() -> Command {
+    Command::new("normalize")
+        .about("rewrite every color in a document in a consistent case and format so diffs between versions are minimal")
+        .arg(
+            arg!([FILE] "the lunacy .free file to modify")
+                .required(true)
+                .value_parser(value_parser!(PathBuf))
+        )
+        .arg(
+            arg!(--case <CASE> "the case of hex digits, defaults to upper as Lunacy writes them")
+                .id("CASE")
+                .value_parser(["upper", "lower"])
+        )
+        .arg(
+            arg!(--alpha <ALPHA> "whether opaque colors keep an alpha channel, defaults to never")
+                .id("ALPHA")
+                .value_parser(["never", "always"])
+        )
+}
+
+/// Run the `normalize` subcommand.
+pub fn run(matches: &ArgMatches) {
+    let Some(path) = matches.get_one::<PathBuf>("FILE") else {
+        panic!("expected .free document as first argument");
+    };
+
+    let format = ColorFormat {
+        upper   : matches.get_one::<String>("CASE").is_none_or(|x| x == "upper"),
+        alpha   : matches.get_one::<String>("ALPHA").is_some_and(|x| x == "always"),
+    };
+
+    let options = crate::load_edit_options(matches, path);
+    let mut doc = LunacyDocument::open(path, &options)
+        .or_exit(Exit::Document, "failed to open document");
+    doc.set_zero_timestamps(matches.get_flag("ZERO_TIMESTAMPS"));
+
+    let mut total = 0;
+    for entry in model::json_entries(&doc) {
+        let mut json = doc.load_json(&entry)
+            .expect("failed to read entry");
+
+        let mut changed = 0;
+        normalize(&mut json, None, &format, &mut changed);
+        if entry == "document.json" {
+            for variable in json["colorVariables"].members_mut() {
+                normalize_color(&mut variable["value"], &format, &mut changed);
+            }
+        }
+
+        if changed > 0 {
+            doc.save_json(&entry, &json)
+                .expect("failed to save entry");
+
+            say!("normalized {changed} colors in {entry}");
+            total += changed;
+        }
+    }
+
+    doc.commit()
+        .or_exit(Exit::Document, "failed to commit changes to document");
+
+    say!("normalized {total} colors");
+}
+
+/// The format colors are normalized to.
+struct ColorFormat {
+    /// Whether hex digits are upper case rather than lower case.
+    upper   : bool,
+    /// Whether opaque colors keep an alpha channel, e.g. `#3714AEFF`, rather than dropping it.
+    alpha   : bool,
+}
+
+/// Normalize every hex color in `json`, the values of keys named after a color such as `color`
+/// or `textColor`, counting those that changed in `changed`.
+fn normalize(json: &mut JsonValue, key: Option<&str>, format: &ColorFormat, changed: &mut usize) {
+    match json {
+        JsonValue::Object(object) => {
+            for (key, value) in object.iter_mut() {
+                normalize(value, Some(key), format, changed);
+            }
+        }
+        JsonValue::Array(values) => {
+            for value in values {
+                normalize(value, key, format, changed);
+            }
+        }
+        _ if key.is_some_and(|x| x.to_lowercase().ends_with("color")) => {
+            normalize_color(json, format, changed);
+        }
+        _ => { }
+    }
+}
+
+/// Normalize the hex color `json`, keeping whether it has a leading `#`. Anything else, such as
+/// the name of a linked color, is left as it is.
+fn normalize_color(json: &mut JsonValue, format: &ColorFormat, changed: &mut usize) {
+    let Some(value) = json.as_str() else {
+        return;
+    };
+
+    let hex = value.trim_start_matches('#');
+    if !hex.chars().all(|x| x.is_ascii_hexdigit()) {
+        return;
+    }
+
+    // Shorthand colors have each digit doubled, e.g. `#fa0` is `#ffaa00`.
+    let mut hex = match hex.len() {
+        3 | 4   => hex.chars().flat_map(|x| [x, x]).collect::<String>(),
+        6 | 8   => hex.to_owned(),
+        _       => return,
+    };
+
+    match (hex.len(), format.alpha) {
+        (6, true)                                               => hex.push_str("FF"),
+        (8, false) if hex[6..].eq_ignore_ascii_case("ff")       => hex.truncate(6),
+        _                                                       => { }
+    }
+
+    let hex = match format.upper {
+        true    => hex.to_uppercase(),
+        false   => hex.to_lowercase(),
+    };
+
+    let normalized = match value.starts_with('#') {
+        true    => format!("#{hex}"),
+        false   => hex,
+    };
+
+    if normalized != value {
+        *json = normalized.into();
+        *changed += 1;
+    }
+}
@@ -0,0 +1,204 @@
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+use clap::{arg, value_parser, ArgMatches, Command};
+
+use crate::commands::{assets, format_size};
+use crate::document::LunacyDocument;
+use crate::exit::{Exit, OrExit};
+use crate::images;
+use crate::Error;
+use crate::output::say;
+
+/// The default maximum number of pixels in an embedded image.
+const DEFAULT_MAX_PIXELS: u64 = 4096 * 4096;
+
+/// The default quality images are converted with by `--convert`.
+const DEFAULT_QUALITY: u8 = 80;
+
+/// Create the `optimize` subcommand.
+pub fn command() -> Command {
+    Command::new("optimize")
+        .about("shrink a document by recompressing images, removing unused assets and repacking")
+        .arg(
+            arg!([FILE] "the lunacy .free file to optimize")
+                .required(true)
+                .value_parser(value_parser!(PathBuf))
+        )
+        .arg(
+            arg!(--max_pixels <PIXELS> "downscale images with more pixels than this, defaults to 4096x4096")
+                .id("MAX_PIXELS")
+                .value_parser(value_parser!(u64))
+        )
+        .arg(
+            arg!(--convert <FORMAT> "convert opaque PNG and JPEG images to lossy JPEG where that makes them smaller")
+                .id("CONVERT")
+                .value_parser(["jpeg"])
+        )
+        .arg(
+            arg!(--quality <QUALITY> "the quality from 1 to 100 images are converted with, defaults to 80")
+                .id("QUALITY")
+                .requires("CONVERT")
+                .value_parser(value_parser!(u8).range(1..=100))
+        )
+        .arg(
+            arg!(--keep_unused "keep assets that aren't referenced by the document")
+                .id("KEEP_UNUSED")
+        )
+}
+
+/// Run the `optimize` subcommand.
+pub fn run(matches: &ArgMatches) {
+    let Some(path) = matches.get_one::<PathBuf>("FILE") else {
+        panic!("expected .free document as first argument");
+    };
+
+    let max_pixels = matches.get_one::<u64>("MAX_PIXELS")
+        .copied()
+        .unwrap_or(DEFAULT_MAX_PIXELS);
+
+    let before  = std::fs::metadata(path)
+        .expect("failed to read document")
+        .len();
+
+    let options = crate::load_edit_options(matches, path);
+    let mut doc = LunacyDocument::open(path, &options)
+        .or_exit(Exit::Document, "failed to open document");
+    doc.set_zero_timestamps(matches.get_flag("ZERO_TIMESTAMPS"));
+
+    if !matches.get_flag("KEEP_UNUSED") {
+        remove_unused(matches, &mut doc)
+            .expect("failed to remove unused assets");
+    }
+
+    optimize_images(&mut doc, max_pixels)
+        .expect("failed to optimize images");
+
+    // JPEG is the only format offered, as the only lossy format both Lunacy and the tool read.
+    if matches.contains_id("CONVERT") {
+        let quality = matches.get_one::<u8>("QUALITY")
+            .copied()
+            .unwrap_or(DEFAULT_QUALITY);
+
+        convert_images(&mut doc, quality)
+            .expect("failed to convert images");
+    }
+
+    doc.repack(9);
+    doc.commit()
+        .or_exit(Exit::Document, "failed to commit changes to document");
+
+    let after   = std::fs::metadata(path)
+        .expect("failed to read document")
+        .len();
+
+    say!(
+        "saved {} ({} -> {})",
+        format_size(before.saturating_sub(after)),
+        format_size(before),
+        format_size(after)
+    );
+}
+
+/// Remove every asset the document doesn't refer to, once confirmed. The assets are kept if the
+/// removal isn't confirmed, and the rest of the document is still optimized.
+fn remove_unused(matches: &ArgMatches, doc: &mut LunacyDocument) -> Result<(), Error> {
+    let unused  = assets::unused_assets(doc)?;
+    let changes = unused.iter()
+        .map(|x| format!("remove unused {x}"))
+        .collect::<Vec<_>>();
+
+    if !crate::confirm(matches, &changes) {
+        say!("kept {} unused assets", unused.len());
+        return Ok(());
+    }
+
+    for name in unused {
+        doc.remove_entry(&name)?;
+        say!("removed unused {name}");
+    }
+
+    Ok(())
+}
+
+/// Downscale images over `max_pixels` and recompress PNG images.
+fn optimize_images(doc: &mut LunacyDocument, max_pixels: u64) -> Result<(), Error> {
+    for asset in assets::asset_entries(doc) {
+        let name = asset.name;
+        let data = doc.load_bytes(&name)?;
+
+        // Skip anything we can't decode, e.g. fonts or unsupported image formats. AVIF images
+        // can be written but not read.
+        let Ok(format) = image::guess_format(&data) else {
+            continue;
+        };
+
+        if images::dimensions(&data).is_none() {
+            continue;
+        }
+
+        let mut optimized = None;
+        if let Some(downscaled) = images::downscale(&data, max_pixels)? {
+            say!(
+                "downscaled {name} from {}x{} to {}x{}",
+                downscaled.from.0, downscaled.from.1, downscaled.to.0, downscaled.to.1
+            );
+
+            optimized = Some(downscaled.data);
+        }
+
+        if format == image::ImageFormat::Png {
+            let current = optimized.as_deref().unwrap_or(&data);
+            if let Some(recompressed) = images::recompress_png(current)? {
+                optimized = Some(recompressed);
+            }
+        }
+
+        let Some(optimized) = optimized else {
+            continue;
+        };
+
+        if optimized.len() < data.len() {
+            say!("optimized {name}: {} -> {}", format_size(data.len() as u64), format_size(optimized.len() as u64));
+            doc.save_bytes(&name, &optimized)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Convert opaque PNG and JPEG images to lossy JPEGs of `quality` where that makes them smaller,
+/// updating references to the converted images.
+fn convert_images(doc: &mut LunacyDocument, quality: u8) -> Result<(), Error> {
+    let mut renames = BTreeMap::new();
+
+    for asset in assets::asset_entries(doc) {
+        let name = asset.name;
+        let data = doc.load_bytes(&name)?;
+
+        let Ok(Some(converted)) = images::convert_to_jpeg(&data, quality) else {
+            continue;
+        };
+
+        let path    = Path::new(&name);
+        let renamed = match path.extension().and_then(|x| x.to_str()).map(|x| x.to_ascii_lowercase()) {
+            Some(x) if x == "jpg" || x == "jpeg"    => name.clone(),
+            _                                       => path.with_extension("jpg").to_string_lossy().replace('\\', "/"),
+        };
+
+        // Don't clobber an existing asset that happens to share the new name.
+        if renamed != name && doc.has_entry(&renamed) {
+            continue;
+        }
+
+        say!("converted {name} to {renamed}: {} -> {}", format_size(data.len() as u64), format_size(converted.len() as u64));
+
+        if renamed != name {
+            doc.remove_entry(&name)?;
+            renames.insert(name, renamed.clone());
+        }
+        doc.save_bytes(&renamed, &converted)?;
+    }
+
+    assets::rename_assets(doc, &renames)
+}
@@ -0,0 +1,80 @@
+use std::fs::File;
+use std::io::Write;
+use std::path::PathBuf;
+
+use clap::{arg, value_parser, ArgMatches, Command};
+use zip::write::SimpleFileOptions;
+use zip::{CompressionMethod, DateTime, ZipWriter};
+
+use crate::document::{self, ZIP64_THRESHOLD};
+use crate::output::say;
+
+/// Create the `pack` subcommand.
+pub fn command() -> Command {
+    Command::new("pack")
+        .about("rebuild a document from a directory created by `unpack`")
+        .arg(
+            arg!([DIR] "the directory holding the unpacked document")
+                .required(true)
+                .value_parser(value_parser!(PathBuf))
+        )
+        .arg(
+            arg!([FILE] "the lunacy .free file to write")
+                .required(true)
+                .value_parser(value_parser!(PathBuf))
+        )
+}
+
+/// Run the `pack` subcommand.
+pub fn run(matches: &ArgMatches) {
+    let Some(dir) = matches.get_one::<PathBuf>("DIR") else {
+        panic!("expected a directory as first argument");
+    };
+
+    let Some(path) = matches.get_one::<PathBuf>("FILE") else {
+        panic!("expected .free document as second argument");
+    };
+
+    if path.extension().and_then(|x| x.to_str()) != Some("free") {
+        panic!("expected the document to have a .free extension");
+    }
+
+    let entries = document::unpacked_entries(dir)
+        .expect("failed to read directory");
+
+    // Write to a sibling file first so a failed pack never leaves a half written document.
+    let temp_path   = path.with_extension("free.tmp");
+    let mut writer  = ZipWriter::new(File::create(&temp_path).expect("failed to create document"));
+    for entry in &entries {
+        let data = std::fs::read(dir.join(entry))
+            .expect("failed to read entry");
+
+        // Json is stored compactly again, the way Lunacy writes it.
+        let data = match entry.ends_with(".json") {
+            true    => {
+                let json = json::parse(&String::from_utf8_lossy(&data))
+                    .unwrap_or_else(|x| panic!("invalid json in {entry}: {x}"));
+                json.dump().into_bytes()
+            }
+            false   => data,
+        };
+
+        // Timestamps are zeroed so packing the same directory always produces the same document.
+        let options = SimpleFileOptions::default()
+            .compression_method(CompressionMethod::Deflated)
+            .last_modified_time(DateTime::default())
+            .large_file(data.len() as u64 >= ZIP64_THRESHOLD);
+
+        writer.start_file(entry.as_str(), options)
+            .expect("failed to write entry");
+        writer.write_all(&data)
+            .expect("failed to write entry");
+    }
+
+    writer.finish()
+        .expect("failed to write document");
+    std::fs::rename(&temp_path, path)
+        .expect("failed to write document");
+
+    say!("packed {} entries into {}", entries.len(), path.display());
+}
@@ -0,0 +1,359 @@
+use std::path::{Path, PathBuf};
+
+use clap::{arg, value_parser, ArgAction, ArgMatches, Command};
+use json::{object, JsonValue};
+use lunacy_tools::schema::{DocumentJson, Page};
+
+use crate::commands::{assets, components, DocumentCopy};
+use crate::document::{LunacyDocument, OpenOptions};
+use crate::exit::{Exit, OrExit};
+use crate::model;
+use crate::Error;
+use crate::output::{say, warning};
+
+/// Create the `pages` subcommand.
+pub fn command() -> Command {
+    Command::new("pages")
+        .about("list, extract, delete and reorder the pages in a document")
+        .subcommand_required(true)
+        .subcommand(
+            Command::new("list")
+                .about("list the pages in a document in order")
+                .arg(
+                    arg!([FILE] "the lunacy .free file to inspect")
+                        .required(true)
+                        .value_parser(value_parser!(PathBuf))
+                )
+                .arg(
+                    arg!(--json "print the pages as json")
+                        .action(ArgAction::SetTrue)
+                )
+        )
+        .subcommand(
+            Command::new("extract")
+                .about("copy a single page into its own document")
+                .arg(
+                    arg!([FILE] "the lunacy .free file to extract the page from")
+                        .required(true)
+                        .value_parser(value_parser!(PathBuf))
+                )
+                .arg(
+                    arg!(--page <NAME> "the name of the page to extract")
+                        .required(true)
+                )
+                .arg(
+                    arg!(--out <FILE> "the path to write the new document to")
+                        .required(true)
+                        .value_parser(value_parser!(PathBuf))
+                )
+        )
+        .subcommand(
+            Command::new("delete")
+                .about("delete pages from a document")
+                .arg(
+                    arg!([FILE] "the lunacy .free file to modify")
+                        .required(true)
+                        .value_parser(value_parser!(PathBuf))
+                )
+                .arg(
+                    arg!(--page <NAME> "the name of a page to delete")
+                        .required(true)
+                        .action(ArgAction::Append)
+                )
+        )
+        .subcommand(
+            Command::new("reorder")
+                .about("move pages to the front of a document in the given order")
+                .arg(
+                    arg!([FILE] "the lunacy .free file to modify")
+                        .required(true)
+                        .value_parser(value_parser!(PathBuf))
+                )
+                .arg(
+                    arg!([PAGES] ... "the names of the pages in their new order, pages not listed keep their order after them")
+                        .required(true)
+                )
+        )
+}
+
+/// Run the `pages` subcommand.
+pub fn run(matches: &ArgMatches) {
+    match matches.subcommand() {
+        Some(("list", matches))     => list(matches),
+        Some(("extract", matches))  => extract(matches),
+        Some(("delete", matches))   => delete(matches),
+        Some(("reorder", matches))  => reorder(matches),
+        _                           => unreachable!("a subcommand is required"),
+    }
+}
+
+/// Print the pages in a document in order.
+fn list(matches: &ArgMatches) {
+    let Some(path) = matches.get_one::<PathBuf>("FILE") else {
+        panic!("expected .free document as first argument");
+    };
+
+    let options = crate::load_open_options(matches, path);
+    let doc     = LunacyDocument::open(path, &options)
+        .or_exit(Exit::Document, "failed to open document");
+
+    let document = doc.load::<DocumentJson>("document.json")
+        .expect("failed to read document");
+    let pages = ordered_pages(&doc, &document)
+        .expect("failed to read pages");
+
+    if super::json_output(matches) {
+        println!("{}", page_summaries(&pages).pretty(4));
+        return;
+    }
+
+    let rows = pages.iter()
+        .enumerate()
+        .map(|(i, (entry, page))| [
+            (i + 1).to_string(),
+            page_name(entry, page).to_owned(),
+            page.layers().len().to_string(),
+            page.artboards().len().to_string(),
+            page_id(entry).to_owned(),
+        ])
+        .collect::<Vec<_>>();
+
+    super::print_table(&["#", "page", "layers", "artboards", "id"], &rows);
+}
+
+/// Summarize `pages` as json, with the id, name and number of layers and artboards of each.
+pub fn page_summaries(pages: &[(String, Page)]) -> JsonValue {
+    let json = pages.iter()
+        .map(|(entry, page)| object! {
+            "id"        : page_id(entry),
+            "name"      : page_name(entry, page),
+            "layers"    : page.layers().len(),
+            "artboards" : page.artboards().len(),
+        })
+        .collect::<Vec<_>>();
+
+    json.into()
+}
+
+/// Copy a single page of a document into a new document.
+fn extract(matches: &ArgMatches) {
+    let Some(path) = matches.get_one::<PathBuf>("FILE") else {
+        panic!("expected .free document as first argument");
+    };
+
+    let Some(name) = matches.get_one::<String>("page") else {
+        panic!("expected the name of a page to extract");
+    };
+
+    let Some(out) = matches.get_one::<PathBuf>("out") else {
+        panic!("expected a path to write the new document to");
+    };
+
+    // The new document starts as a copy so styles, variables and fonts come along with the page.
+    let mut options = crate::load_edit_options(matches, path);
+    let copy        = DocumentCopy::new(path, out, &mut options)
+        .or_exit(Exit::Document, "failed to copy document");
+
+    // The copy is dropped before exiting so it isn't left next to the output.
+    if let Err(e) = keep_page(matches, copy.path(), &options, name) {
+        drop(copy);
+        Exit::Document.fail(format!("failed to extract page, {e}"));
+    }
+
+    copy.finish()
+        .or_exit(Exit::Failure, "failed to write document");
+
+    say!("extracted {name} to {}", out.display());
+}
+
+/// Remove every page but the one named `name` from the document at `path`, along with the
+/// assets only the other pages used.
+fn keep_page(matches: &ArgMatches, path: &Path, options: &OpenOptions, name: &str) -> Result<(), Error> {
+    let mut doc = LunacyDocument::open(path, options)?;
+    doc.set_zero_timestamps(matches.get_flag("ZERO_TIMESTAMPS"));
+
+    let mut document    = doc.load::<DocumentJson>("document.json")?;
+    let pages           = ordered_pages(&doc, &document)?;
+
+    let (keep, _) = find_page(&pages, name);
+    for (entry, _) in pages.iter().filter(|(entry, _)| entry != keep) {
+        remove_page(&mut doc, &mut document, entry)?;
+    }
+
+    if document.pages.is_some() {
+        doc.save("document.json", &document)?;
+    }
+
+    // Images and fonts only used by the other pages would otherwise be carried along.
+    for entry in assets::unused_assets(&doc)? {
+        doc.remove_entry(&entry)?;
+    }
+
+    let (_, missing) = components::component_usage(&doc)?;
+    let missing = missing.values().sum::<usize>();
+    if missing > 0 {
+        warning!("warning: {missing} instances refer to components that aren't on the extracted page");
+    }
+
+    doc.commit()
+}
+
+/// Delete pages from a document.
+fn delete(matches: &ArgMatches) {
+    let Some(path) = matches.get_one::<PathBuf>("FILE") else {
+        panic!("expected .free document as first argument");
+    };
+
+    let names = matches.get_many::<String>("page")
+        .expect("expected the name of a page to delete")
+        .collect::<Vec<_>>();
+
+    let options = crate::load_edit_options(matches, path);
+    let mut doc = LunacyDocument::open(path, &options)
+        .or_exit(Exit::Document, "failed to open document");
+    doc.set_zero_timestamps(matches.get_flag("ZERO_TIMESTAMPS"));
+
+    let mut document = doc.load::<DocumentJson>("document.json")
+        .expect("failed to read document");
+    let pages = ordered_pages(&doc, &document)
+        .expect("failed to read pages");
+
+    let entries = names.iter()
+        .map(|name| find_page(&pages, name).0.clone())
+        .collect::<Vec<_>>();
+
+    if pages.iter().all(|(entry, _)| entries.contains(entry)) {
+        panic!("a document must keep at least one page");
+    }
+
+    let changes = names.iter()
+        .map(|name| format!("delete page {name} ({} layers)", find_page(&pages, name).1.layers().len()))
+        .collect::<Vec<_>>();
+
+    if !crate::confirm(matches, &changes) {
+        return;
+    }
+
+    for (name, entry) in names.iter().zip(&entries) {
+        remove_page(&mut doc, &mut document, entry)
+            .expect("failed to remove page");
+        say!("deleted {name}");
+    }
+
+    if document.pages.is_some() {
+        doc.save("document.json", &document)
+            .expect("failed to save document");
+    }
+
+    doc.commit()
+        .or_exit(Exit::Document, "failed to commit changes to document");
+}
+
+/// Move pages to the front of a document in the given order.
+fn reorder(matches: &ArgMatches) {
+    let Some(path) = matches.get_one::<PathBuf>("FILE") else {
+        panic!("expected .free document as first argument");
+    };
+
+    let names = matches.get_many::<String>("PAGES")
+        .expect("expected the names of pages to reorder")
+        .collect::<Vec<_>>();
+
+    let options = crate::load_edit_options(matches, path);
+    let mut doc = LunacyDocument::open(path, &options)
+        .or_exit(Exit::Document, "failed to open document");
+    doc.set_zero_timestamps(matches.get_flag("ZERO_TIMESTAMPS"));
+
+    let mut document = doc.load::<DocumentJson>("document.json")
+        .expect("failed to read document");
+    let pages = ordered_pages(&doc, &document)
+        .expect("failed to read pages");
+
+    // Listed pages come first, the rest keep their order after them.
+    let mut order = names.iter()
+        .map(|name| page_id(&find_page(&pages, name).0))
+        .collect::<Vec<_>>();
+    order.dedup();
+
+    for (entry, _) in &pages {
+        if !order.contains(&page_id(entry)) {
+            order.push(page_id(entry));
+        }
+    }
+
+    let Some(refs) = document.pages.as_mut() else {
+        panic!("expected the document to list its pages in `document.json`");
+    };
+    refs.sort_by_key(|x| x.page_id().and_then(|id| order.iter().position(|x| *x == id)).unwrap_or(order.len()));
+
+    doc.save("document.json", &document)
+        .expect("failed to save document");
+    doc.commit()
+        .or_exit(Exit::Document, "failed to commit changes to document");
+
+    for (i, id) in order.iter().enumerate() {
+        let (entry, page) = pages.iter()
+            .find(|(entry, _)| page_id(entry) == *id)
+            .expect("failed to find page");
+
+        println!("{} {}", i + 1, page_name(entry, page));
+    }
+}
+
+/// Get the page entries of `doc` in the order `document.json` lists them, followed by any pages
+/// it doesn't list in archive order.
+pub fn ordered_entries(doc: &LunacyDocument, document: &DocumentJson) -> Vec<String> {
+    let order = document.pages.iter()
+        .flatten()
+        .filter_map(|x| x.page_id())
+        .collect::<Vec<_>>();
+
+    let mut entries = model::page_entries(doc);
+    entries.sort_by_key(|entry| order.iter().position(|x| *x == page_id(entry)).unwrap_or(order.len()));
+
+    entries
+}
+
+/// Load the pages of `doc` in the order `document.json` lists them, followed by any pages it
+/// doesn't list in archive order.
+pub fn ordered_pages(doc: &LunacyDocument, document: &DocumentJson) -> Result<Vec<(String, Page)>, Error> {
+    ordered_entries(doc, document)
+        .into_iter()
+        .map(|entry| {
+            let page = doc.load(&entry)?;
+            Ok((entry, page))
+        })
+        .collect()
+}
+
+/// Find the page named `name`, panicking if there's no such page.
+fn find_page<'a>(pages: &'a [(String, Page)], name: &str) -> &'a (String, Page) {
+    pages.iter()
+        .find(|(entry, page)| page_name(entry, page) == name)
+        .unwrap_or_else(|| panic!("no page named {name}"))
+}
+
+/// Remove the page stored at `entry` from `doc` along with its reference in `document`.
+fn remove_page(doc: &mut LunacyDocument, document: &mut DocumentJson, entry: &str) -> Result<(), Error> {
+    doc.remove_entry(entry)?;
+
+    if let Some(refs) = document.pages.as_mut() {
+        let id = page_id(entry);
+        refs.retain(|x| x.page_id() != Some(id));
+    }
+
+    Ok(())
+}
+
+/// Get the name of `page`, falling back to the entry it was loaded from.
+fn page_name<'a>(entry: &'a str, page: &'a Page) -> &'a str {
+    page.name.as_deref().unwrap_or(entry)
+}
+
+/// Get the id of the page stored at `entry`, which pages are stored under.
+fn page_id(entry: &str) -> &str {
+    Path::new(entry)
+        .file_stem()
+        .and_then(|x| x.to_str())
+        .unwrap_or(entry)
+}
@@ -0,0 +1,80 @@
+use std::path::PathBuf;
+
+use clap::{arg, value_parser, ArgMatches, Command};
+
+use crate::document::LunacyDocument;
+use crate::exit::{Exit, OrExit};
+use crate::images;
+use crate::model;
+use crate::output::say;
+use crate::render::{SvgConverter, SvgRenderer};
+use crate::Error;
+
+/// The entries the preview image may be stored in, the first being used for new previews.
+const PREVIEW_ENTRIES: &[&str] = &["previews/preview.png", "preview.png"];
+
+/// The maximum width or height of a new preview image.
+const PREVIEW_MAX_SIZE: u32 = 2048;
+
+/// Create the `preview` subcommand.
+pub fn command() -> Command {
+    Command::new("preview")
+        .about("regenerate the preview image shown by file browsers and the recent files list")
+        .arg(
+            arg!([FILE] "the lunacy .free file to update")
+                .required(true)
+                .value_parser(value_parser!(PathBuf))
+        )
+}
+
+/// Run the `preview` subcommand.
+pub fn run(matches: &ArgMatches) {
+    let Some(path) = matches.get_one::<PathBuf>("FILE") else {
+        panic!("expected .free document as first argument");
+    };
+
+    let options = crate::load_edit_options(matches, path);
+    let mut doc = LunacyDocument::open(path, &options)
+        .or_exit(Exit::Document, "failed to open document");
+    doc.set_zero_timestamps(matches.get_flag("ZERO_TIMESTAMPS"));
+
+    match update_preview(&mut doc).expect("failed to update preview") {
+        Some(entry) => say!("updated {entry}"),
+        None        => say!("document has no artboards to preview"),
+    }
+
+    doc.commit()
+        .or_exit(Exit::Document, "failed to commit changes to document");
+}
+
+/// Render the first artboard of `doc` over its preview image, keeping the size of any existing
+/// preview. Returns the entry the preview was saved to, or `None` if there's nothing to render.
+pub fn update_preview(doc: &mut LunacyDocument) -> Result<Option<String>, Error> {
+    let Some((_, artboard)) = model::artboards(doc)?.into_iter().next() else {
+        return Ok(None);
+    };
+
+    let entry = PREVIEW_ENTRIES.iter()
+        .find(|x| doc.has_entry(x))
+        .unwrap_or(&PREVIEW_ENTRIES[0])
+        .to_string();
+
+    let svg     = SvgRenderer::new(doc)?.render(&artboard)?;
+    let frame   = model::Frame::from_layer(&artboard);
+    let size    = frame.width.max(frame.height).max(1.0);
+
+    // Match the size of the existing preview so Lunacy and file browsers lay it out the same,
+    // otherwise only scale the artboard down to fit.
+    let existing = doc.has_entry(&entry)
+        .then(|| images::dimensions(&doc.load_bytes(&entry).ok()?))
+        .flatten();
+    let scale = match existing {
+        Some((width, height))   => width.max(height) as f64 / size,
+        None                    => (PREVIEW_MAX_SIZE as f64 / size).min(1.0),
+    };
+
+    let png = SvgConverter::new(doc)?.to_png(&svg, scale as f32)?;
+    doc.save_bytes(&entry, &png)?;
+
+    Ok(Some(entry))
+}
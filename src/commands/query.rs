@@ -0,0 +1,58 @@
+use std::path::PathBuf;
+
+use clap::{arg, value_parser, ArgMatches, Command};
+use json::{object, JsonValue};
+
+use crate::document::LunacyDocument;
+use crate::exit::{Exit, OrExit};
+use crate::model;
+use crate::query::Selector;
+
+/// Create the `query` subcommand.
+pub fn command() -> Command {
+    Command::new("query")
+        .about("print the layers matching a css-like selector as json")
+        .arg(
+            arg!([FILE] "the lunacy .free file to query")
+                .required(true)
+                .value_parser(value_parser!(PathBuf))
+        )
+        .arg(
+            arg!([SELECTOR] "the selector to match, e.g. 'page[\"Home\"] artboard[name^=\"Mobile\"] text[fontSize<12]'")
+                .required(true)
+                .value_parser(value_parser!(Selector))
+        )
+}
+
+/// Run the `query` subcommand.
+pub fn run(matches: &ArgMatches) {
+    let Some(path) = matches.get_one::<PathBuf>("FILE") else {
+        panic!("expected .free document as first argument");
+    };
+
+    let Some(selector) = matches.get_one::<Selector>("SELECTOR") else {
+        panic!("expected a selector");
+    };
+
+    let options = crate::load_open_options(matches, path);
+    let doc     = LunacyDocument::open(path, &options)
+        .or_exit(Exit::Document, "failed to open document");
+
+    let pages = model::load_pages(&doc)
+        .expect("failed to read pages");
+
+    let mut results = JsonValue::new_array();
+    for (entry, page) in &pages {
+        let page_name = page["name"].as_str().unwrap_or(entry);
+        for found in selector.select(page, page_name) {
+            results.push(object! {
+                "page"  : found.page,
+                "path"  : found.path,
+                "layer" : found.layer.clone(),
+            })
+            .expect("failed to collect results");
+        }
+    }
+
+    println!("{}", results.pretty(4));
+}
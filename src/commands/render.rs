@@ -0,0 +1,147 @@
+use std::collections::BTreeSet;
+use std::path::PathBuf;
+
+use clap::{arg, value_parser, ArgAction, ArgMatches, Command};
+
+use crate::commands::sanitize_file_name;
+use crate::document::LunacyDocument;
+use crate::exit::{Exit, OrExit};
+use crate::model;
+use crate::output::say;
+use crate::render::{SvgConverter, SvgRenderer};
+
+/// Create the `render` subcommand.
+pub fn command() -> Command {
+    Command::new("render")
+        .about("render artboards to image files")
+        .arg(
+            arg!([FILE] "the lunacy .free file to render")
+                .required(true)
+                .value_parser(value_parser!(PathBuf))
+        )
+        .arg(
+            arg!(--out <DIR> "the directory to write rendered artboards to")
+                .required(true)
+                .value_parser(value_parser!(PathBuf))
+        )
+        .arg(
+            arg!(--format <FORMAT> "the format to render to, defaults to svg")
+                .value_parser(["svg", "png", "pdf"])
+        )
+        .arg(
+            arg!(--scale <SCALES> "the scales to render png images at, e.g. 1,2,3, defaults to 1")
+                .value_delimiter(',')
+                .value_parser(value_parser!(f32))
+        )
+        .arg(
+            arg!(--artboard <NAME> "the name of an artboard to render, defaults to every artboard")
+                .action(ArgAction::Append)
+        )
+}
+
+/// Run the `render` subcommand.
+pub fn run(matches: &ArgMatches) {
+    let Some(path) = matches.get_one::<PathBuf>("FILE") else {
+        panic!("expected .free document as first argument");
+    };
+
+    let Some(out) = matches.get_one::<PathBuf>("out") else {
+        panic!("expected an output directory");
+    };
+
+    let format = matches.get_one::<String>("format")
+        .map(|x| x.as_str())
+        .unwrap_or("svg");
+
+    let selected = matches.get_many::<String>("artboard")
+        .map(|x| x.map(|x| x.as_str()).collect::<BTreeSet<_>>());
+
+    let options = crate::load_open_options(matches, path);
+    let doc     = LunacyDocument::open(path, &options)
+        .or_exit(Exit::Document, "failed to open document");
+
+    let artboards = model::artboards(&doc)
+        .expect("failed to read pages")
+        .into_iter()
+        .map(|(_, x)| x)
+        .filter(|x| {
+            let name = x["name"].as_str().unwrap_or_default();
+            selected.as_ref().is_none_or(|selected| selected.contains(name))
+        })
+        .collect::<Vec<_>>();
+
+    if artboards.is_empty() {
+        panic!("no artboards to render");
+    }
+
+    std::fs::create_dir_all(out)
+        .expect("failed to create output directory");
+
+    let renderer = SvgRenderer::new(&doc)
+        .expect("failed to read document");
+
+    let svgs = artboards.iter()
+        .map(|x| renderer.render(x).expect("failed to render artboard"))
+        .collect::<Vec<_>>();
+
+    // Every artboard goes in a single document, one per page, named after the document.
+    if format == "pdf" {
+        let stem = path.file_stem().and_then(|x| x.to_str()).unwrap_or_default();
+        let file = out.join(format!("{}.pdf", sanitize_file_name(stem, "artboards")));
+
+        let converter = SvgConverter::new(&doc)
+            .expect("failed to load fonts");
+        let pdf = converter.to_pdf(&svgs)
+            .expect("failed to convert artboards to pdf");
+        std::fs::write(&file, pdf)
+            .expect("failed to write pdf");
+
+        say!("{} artboards -> {}", artboards.len(), file.display());
+        return;
+    }
+
+    let converter = match format {
+        "png"   => Some(SvgConverter::new(&doc).expect("failed to load fonts")),
+        _       => None,
+    };
+
+    // Only raster images can be rendered at different scales.
+    let scales = match converter {
+        Some(_) => matches.get_many::<f32>("scale")
+            .map(|x| x.copied().collect::<Vec<_>>())
+            .unwrap_or_else(|| vec![1.0]),
+        None    => vec![1.0],
+    };
+
+    let mut files = BTreeSet::new();
+    for (artboard, svg) in artboards.iter().zip(&svgs) {
+        let name = artboard["name"].as_str().unwrap_or_default();
+        for &scale in &scales {
+            let data = match &converter {
+                Some(converter) => converter.to_png(svg, scale)
+                    .expect("failed to rasterize artboard"),
+                None            => svg.clone().into_bytes(),
+            };
+
+            // Scaled images follow the usual `@2x` naming, and artboards on different pages may
+            // share a name.
+            let mut stem = sanitize_file_name(name, "artboard");
+            if scale != 1.0 {
+                stem.push_str(&format!("@{scale}x"));
+            }
+
+            let mut file = format!("{stem}.{format}");
+            for i in 2.. {
+                if files.insert(file.clone()) {
+                    break;
+                }
+                file = format!("{stem}-{i}.{format}");
+            }
+
+            std::fs::write(out.join(&file), data)
+                .expect("failed to write rendered artboard");
+
+            say!("{name} -> {}", out.join(&file).display());
+        }
+    }
+}
@@ -0,0 +1,291 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs::File;
+use std::io::{BufReader, Read, Write};
+use std::path::{Path, PathBuf};
+
+use clap::{arg, value_parser, ArgMatches, Command};
+use json::JsonValue;
+use zip::write::SimpleFileOptions;
+use zip::{AesMode, ZipArchive, ZipWriter};
+
+use crate::document::{self, ArchiveError, OpenOptions, ZIP64_THRESHOLD};
+use crate::output::say;
+use crate::references::{self, Reference};
+use crate::Error;
+
+/// Create the `repair` subcommand.
+pub fn command() -> Command {
+    Command::new("repair")
+        .about("salvage what can be read from a damaged document into a new file")
+        .arg(
+            arg!([FILE] "the damaged lunacy .free file to repair")
+                .required(true)
+                .value_parser(value_parser!(PathBuf))
+        )
+        .arg(
+            arg!(-o --output <OUTPUT> "the file to write the repaired document to, defaults to '<FILE>.repaired.free'")
+                .value_parser(value_parser!(PathBuf))
+        )
+}
+
+/// Run the `repair` subcommand.
+pub fn run(matches: &ArgMatches) {
+    let Some(path) = matches.get_one::<PathBuf>("FILE") else {
+        panic!("expected .free document as first argument");
+    };
+
+    let output = matches.get_one::<PathBuf>("output")
+        .cloned()
+        .unwrap_or_else(|| path.with_extension("repaired.free"));
+
+    let options = crate::load_open_options(matches, path);
+    let repairs = repair(path, &output, &options)
+        .expect("failed to repair document");
+
+    for repair in repairs.iter() {
+        say!("{repair}");
+    }
+}
+
+/// A change made while repairing a document.
+#[derive(Clone, Debug)]
+pub enum Repair {
+    /// The archive index was unreadable so entries were recovered by scanning the archive.
+    Rescanned {
+        /// The error raised while reading the archive index.
+        error   : String,
+    },
+    /// An entry was dropped because it couldn't be recovered.
+    DroppedEntry {
+        /// The name of the entry.
+        entry   : String,
+        /// Why the entry couldn't be recovered.
+        reason  : String,
+    },
+    /// A reference to an object or asset that doesn't exist was removed.
+    RemovedReference {
+        /// The name of the entry containing the reference.
+        entry   : String,
+        /// The path the reference was removed from.
+        path    : String,
+        /// The removed reference.
+        reference: Reference,
+    },
+}
+
+impl std::fmt::Display for Repair {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Self::Rescanned { error }                           => write!(f, "archive index unreadable ({error}), recovered entries by scanning"),
+            Self::DroppedEntry { entry, reason }                => write!(f, "{entry}: dropped - {reason}"),
+            Self::RemovedReference { entry, path, reference }   => write!(f, "{entry}: removed {path} referencing missing {reference}"),
+        }
+    }
+}
+
+/// An entry recovered from a damaged document.
+struct Salvaged {
+    /// The name of the entry.
+    name    : String,
+    /// The decompressed contents of the entry.
+    data    : Vec<u8>,
+    /// The options the entry was originally written with.
+    options : SimpleFileOptions,
+    /// Whether the entry was encrypted.
+    encrypted: bool,
+}
+
+/// Repair the document at `path`, writing the result to `output`.
+pub fn repair(path: &Path, output: &Path, options: &OpenOptions) -> Result<Vec<Repair>, Error> {
+    let mut repairs = vec![];
+    let mut entries = salvage(path, options, &mut repairs)?;
+
+    // Parse the JSON entries, dropping any that are no longer valid.
+    let mut documents = BTreeMap::new();
+    entries.retain(|entry| {
+        if !entry.name.ends_with(".json") {
+            return true;
+        }
+
+        let parsed = std::str::from_utf8(&entry.data)
+            .map_err(|x| x.to_string())
+            .and_then(|x| json::parse(x).map_err(|x| x.to_string()));
+
+        match parsed {
+            Ok(x)   => {
+                documents.insert(entry.name.clone(), x);
+                true
+            }
+            Err(x)  => {
+                repairs.push(Repair::DroppedEntry { entry: entry.name.clone(), reason: x });
+                false
+            }
+        }
+    });
+
+    // Drop references to anything that didn't survive.
+    let names = entries.iter()
+        .map(|x| x.name.clone())
+        .collect::<BTreeSet<_>>();
+
+    let ids = documents_ids(&documents);
+    for (entry, json) in documents.iter_mut() {
+        repairs.extend(remove_dangling(entry, json, &ids, &names));
+    }
+
+    write(output, &entries, &documents, options)?;
+
+    Ok(repairs)
+}
+
+/// Collect the id of every object across `documents`.
+fn documents_ids(documents: &BTreeMap<String, JsonValue>) -> BTreeSet<String> {
+    let mut ids = BTreeSet::new();
+    for json in documents.values() {
+        references::collect_ids(json, &mut ids);
+    }
+
+    ids
+}
+
+/// Remove every reference in `json` to an id or entry that doesn't exist.
+fn remove_dangling(
+    entry   : &str,
+    json    : &mut JsonValue,
+    ids     : &BTreeSet<String>,
+    names   : &BTreeSet<String>
+)
+    -> Vec<Repair>
+{
+    let removed = references::retain_references(json, &mut |reference| {
+        match reference {
+            Reference::Id(x)    => ids.contains(x),
+            Reference::Asset(x) => names.contains(x),
+        }
+    });
+
+    removed.into_iter()
+        .map(|(path, reference)| Repair::RemovedReference {
+            entry   : entry.to_owned(),
+            path,
+            reference,
+        })
+        .collect()
+}
+
+/// Recover every readable entry from the document at `path`.
+///
+/// If the archive index can't be read, e.g. because the archive was truncated, entries are
+/// recovered by scanning the archive from the start instead.
+fn salvage(path: &Path, options: &OpenOptions, repairs: &mut Vec<Repair>) -> Result<Vec<Salvaged>, Error> {
+    let mut archive = match ZipArchive::new(File::open(path)?) {
+        Ok(x)   => x,
+        Err(x)  => {
+            repairs.push(Repair::Rescanned { error: x.to_string() });
+            return salvage_stream(path, options, repairs);
+        }
+    };
+
+    if archive.len() > options.limits.max_entries {
+        return Err(Box::new(ArchiveError::TooManyEntries(archive.len())));
+    }
+
+    let mut entries = vec![];
+    for i in 0..archive.len() {
+        let (name, entry_options, encrypted) = {
+            let entry = archive.by_index_raw(i)?;
+            (entry.name().to_owned(), entry.options(), entry.encrypted())
+        };
+
+        if name.ends_with('/') {
+            continue;
+        }
+
+        let password = options.password.as_deref();
+        match document::read_entry(&mut archive, i, password, options.limits.max_size) {
+            Ok(data)    => entries.push(Salvaged { name, data, options: entry_options, encrypted }),
+            Err(x)      => repairs.push(Repair::DroppedEntry { entry: name, reason: x.to_string() }),
+        }
+    }
+
+    Ok(entries)
+}
+
+/// Recover entries by reading local file headers from the start of the document at `path`,
+/// stopping at the first entry that can't be read.
+fn salvage_stream(path: &Path, options: &OpenOptions, repairs: &mut Vec<Repair>) -> Result<Vec<Salvaged>, Error> {
+    let mut reader  = BufReader::new(File::open(path)?);
+    let mut entries = vec![];
+
+    while entries.len() < options.limits.max_entries {
+        let mut entry = match zip::read::read_zipfile_from_stream(&mut reader) {
+            Ok(Some(x)) => x,
+            Ok(None)    => break,
+            Err(_)      => break,
+        };
+
+        let name = entry.name().to_owned();
+        if entry.is_dir() {
+            continue;
+        }
+
+        // Decrypting requires the archive index, so encrypted entries are lost.
+        if entry.encrypted() {
+            repairs.push(Repair::DroppedEntry { entry: name, reason: String::from("encrypted entries can't be recovered without the archive index") });
+            continue;
+        }
+
+        let entry_options   = entry.options();
+        let mut data        = Vec::new();
+        let limit           = options.limits.max_size;
+        let result          = (&mut entry).take(limit + 1).read_to_end(&mut data);
+
+        match result {
+            Ok(x) if x as u64 > limit   => {
+                repairs.push(Repair::DroppedEntry { entry: name, reason: ArchiveError::TooLarge(limit).to_string() });
+                break;
+            }
+            Ok(_)                       => entries.push(Salvaged { name, data, options: entry_options, encrypted: false }),
+            Err(x)                      => {
+                repairs.push(Repair::DroppedEntry { entry: name, reason: x.to_string() });
+                break;
+            }
+        }
+    }
+
+    Ok(entries)
+}
+
+/// Write the salvaged `entries` to `output`, replacing JSON entries with `documents`.
+fn write(
+    output      : &Path,
+    entries     : &[Salvaged],
+    documents   : &BTreeMap<String, JsonValue>,
+    options     : &OpenOptions
+)
+    -> Result<(), Error>
+{
+    let mut writer = ZipWriter::new(File::create(output)?);
+
+    for entry in entries {
+        let json = documents.get(&entry.name)
+            .map(|x| x.to_string());
+        let data = json.as_ref()
+            .map(|x| x.as_bytes())
+            .unwrap_or(&entry.data);
+
+        let entry_options = entry.options
+            .large_file(data.len() as u64 >= ZIP64_THRESHOLD);
+
+        match (entry.encrypted, options.password.as_deref()) {
+            (true, Some(password))  => writer.start_file(entry.name.as_str(), entry_options.with_aes_encryption(AesMode::Aes256, password))?,
+            _                       => writer.start_file(entry.name.as_str(), entry_options)?,
+        }
+
+        writer.write_all(data)?;
+    }
+
+    writer.finish()?;
+
+    Ok(())
+}
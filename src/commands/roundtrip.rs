@@ -0,0 +1,199 @@
+use std::path::{Path, PathBuf};
+
+use clap::{arg, value_parser, ArgMatches, Command};
+use lunacy_tools::schema::{DocumentJson, Page, PAGES_DIR};
+use tempdir::TempDir;
+
+use crate::document::{BackupPolicy, LunacyDocument, OpenOptions};
+use crate::exit::Exit;
+use crate::hooks::Hooks;
+use crate::output::say;
+use crate::patch;
+use crate::Error;
+
+/// Create the `roundtrip` subcommand.
+pub fn command() -> Command {
+    Command::new("roundtrip")
+        .about("check that opening and committing a document changes nothing in it, without modifying it")
+        .arg(
+            arg!([FILE] "the lunacy .free file to check")
+                .required(true)
+                .value_parser(value_parser!(PathBuf))
+        )
+}
+
+/// Run the `roundtrip` subcommand.
+pub fn run(matches: &ArgMatches) {
+    let Some(path) = matches.get_one::<PathBuf>("FILE") else {
+        panic!("expected .free document as first argument");
+    };
+
+    if !path.is_file() {
+        panic!("expected a .free or .sketch file");
+    }
+
+    // The document is only read, the round trip is made on a copy of it, with nothing recorded
+    // and no hooks run.
+    let options = OpenOptions {
+        operation   : None,
+        message     : None,
+        backup      : BackupPolicy::Never,
+        hooks       : Hooks::default(),
+        ..crate::load_edit_options(matches, path)
+    };
+
+    let dir     = TempDir::new("lunacy-tools-roundtrip")
+        .expect("failed to create temp directory");
+    let copy    = dir.path().join(path.file_name().expect("expected a file name"));
+    std::fs::copy(path, &copy)
+        .expect("failed to copy document");
+
+    roundtrip(&copy, &options)
+        .expect("failed to round trip document");
+
+    let (differences, reformatted) = compare(path, &copy, &options)
+        .expect("failed to compare documents");
+
+    for difference in &differences {
+        say!("{difference}");
+    }
+
+    if reformatted > 0 {
+        say!("{reformatted} entries were written with different text but the same content");
+    }
+
+    match differences.is_empty() {
+        true    => say!("round trip ok, the document is unchanged"),
+        false   => {
+            say!("round trip changed the document in {} places", differences.len());
+            Exit::Failure.quit();
+        }
+    }
+}
+
+/// Open the document at `path`, write every entry back through the models the tool edits it
+/// with, and commit it.
+fn roundtrip(path: &Path, options: &OpenOptions) -> Result<(), Error> {
+    let mut doc = LunacyDocument::open(path, options)?;
+    let sketch  = doc.is_sketch();
+
+    let entries = doc.entries()
+        .iter()
+        .map(|x| x.name.clone())
+        .collect::<Vec<_>>();
+
+    for entry in entries {
+        match entry.as_str() {
+            "document.json" if !sketch => {
+                let json = doc.load::<DocumentJson>(&entry)?;
+                doc.save(&entry, &json)?;
+            }
+            x if x.starts_with(PAGES_DIR) && x.ends_with(".json") && !sketch => {
+                let page = doc.load::<Page>(&entry)?;
+                doc.save(&entry, &page)?;
+            }
+            x if x.ends_with(".json") => {
+                let json = doc.load_json(&entry)?;
+                doc.save_json(&entry, &json)?;
+            }
+            _ => {
+                let data = doc.load_bytes(&entry)?;
+                doc.save_bytes(&entry, &data)?;
+            }
+        }
+    }
+
+    doc.commit()
+}
+
+/// Compare the document at `original` to its round tripped copy at `copy`, returning the
+/// differences found and the number of json entries whose text changed without their content
+/// changing.
+fn compare(original: &Path, copy: &Path, options: &OpenOptions) -> Result<(Vec<Difference>, usize), Error> {
+    let original    = LunacyDocument::open(original, options)?;
+    let copy        = LunacyDocument::open(copy, options)?;
+
+    let names       = |doc: &LunacyDocument| doc.entries().iter().map(|x| x.name.clone()).collect::<Vec<_>>();
+    let before      = names(&original);
+    let after       = names(&copy);
+
+    let mut differences = vec![];
+    let mut reformatted = 0;
+
+    for entry in before.iter().filter(|x| !after.contains(x)) {
+        differences.push(Difference::Removed(entry.clone()));
+    }
+    for entry in after.iter().filter(|x| !before.contains(x)) {
+        differences.push(Difference::Added(entry.clone()));
+    }
+
+    let common = |names: &[String], other: &[String]| names.iter().filter(|x| other.contains(x)).cloned().collect::<Vec<_>>();
+    if common(&before, &after) != common(&after, &before) {
+        differences.push(Difference::Reordered);
+    }
+
+    for entry in before.iter().filter(|x| after.contains(x)) {
+        let from    = original.load_bytes(entry)?;
+        let to      = copy.load_bytes(entry)?;
+        if from == to {
+            continue;
+        }
+
+        if !entry.ends_with(".json") {
+            differences.push(Difference::Bytes(entry.clone()));
+            continue;
+        }
+
+        let diff = patch::diff(&original.load_json(entry)?, &copy.load_json(entry)?);
+        if diff.is_empty() {
+            reformatted += 1;
+        }
+
+        for op in diff.members() {
+            differences.push(Difference::Json {
+                entry   : entry.clone(),
+                op      : op["op"].as_str().unwrap_or_default().to_owned(),
+                path    : op["path"].as_str().unwrap_or_default().to_owned(),
+            });
+        }
+    }
+
+    Ok((differences, reformatted))
+}
+
+/// A difference between a document and its round tripped copy.
+#[derive(Clone, Debug)]
+enum Difference {
+    /// An entry of the document is missing from the copy.
+    Removed(String),
+    /// An entry not in the document was added to the copy.
+    Added(String),
+    /// The entries are in a different order.
+    Reordered,
+    /// The contents of a binary entry changed.
+    Bytes(String),
+    /// A value in a json entry changed.
+    Json {
+        /// The name of the entry.
+        entry   : String,
+        /// The patch operation turning the original value into the new one, e.g. `replace`.
+        op      : String,
+        /// The json pointer to the value.
+        path    : String,
+    },
+}
+
+impl std::fmt::Display for Difference {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Self::Removed(x)                => write!(f, "{x}: entry removed"),
+            Self::Added(x)                  => write!(f, "{x}: entry added"),
+            Self::Reordered                 => write!(f, "entries reordered"),
+            Self::Bytes(x)                  => write!(f, "{x}: contents changed"),
+            Self::Json { entry, op, path }  => write!(f, "{entry}: {op} {}", match path.is_empty() {
+                true    => "/",
+                false   => path.as_str(),
+            }),
+        }
+    }
+}
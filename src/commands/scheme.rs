@@ -0,0 +1,161 @@
+use std::path::{Path, PathBuf};
+
+use clap::{arg, value_parser, ArgMatches, Command};
+use lunacy_tools::palette;
+use lunacy_tools::schema::DocumentJson;
+
+use crate::compose::SchemeComposer;
+use crate::exit::{Exit, OrExit};
+use crate::output::say;
+
+/// The group colors are previewed in when none is configured.
+const PREVIEW_GROUP: &str = "theme";
+
+/// Create the `scheme` subcommand.
+pub fn command() -> Command {
+    Command::new("scheme")
+        .about("work with color schemes without a document")
+        .subcommand_required(true)
+        .subcommand(
+            Command::new("preview")
+                .about("print the colors a color scheme generates as terminal swatches, or write them to an html page")
+                .arg(
+                    arg!([SCHEME] "the json file containing the color scheme")
+                        .required(true)
+                        .value_parser(value_parser!(PathBuf))
+                )
+                .arg(
+                    arg!(--stops <STOPS> "the comma separated stops to generate for each color, defaults to 100,200,...,900, or the Material 3 tones for material3 ramps")
+                        .value_delimiter(',')
+                        .value_parser(value_parser!(u32))
+                )
+                .arg(
+                    arg!(--ramp <RAMP> "how the ramp of each color is generated, defaults to tints")
+                        .value_parser(["tints", "material3"])
+                )
+                .arg(
+                    arg!(--ramp_space <SPACE> "the color space tints are interpolated in, defaults to srgb")
+                        .value_parser(["srgb", "linear", "oklab"])
+                )
+                .arg(
+                    arg!(--on_conflict <POLICY> "how an entry defined by more than one of the color schemes or the schemes they compose is resolved, last_wins keeping the one merged last or error failing, defaults to last_wins")
+                        .id("ON_CONFLICT")
+                        .value_parser(["last_wins", "error"])
+                )
+                .arg(
+                    arg!(--html <FILE> "write the preview to an html page instead")
+                        .value_parser(value_parser!(PathBuf))
+                )
+        )
+}
+
+/// Run the `scheme` subcommand.
+pub fn run(matches: &ArgMatches) {
+    match matches.subcommand() {
+        Some(("preview", matches))  => preview(matches),
+        _                           => unreachable!("a subcommand is required"),
+    }
+}
+
+/// Generate the colors of a color scheme into an empty document and show them.
+fn preview(matches: &ArgMatches) {
+    let Some(path) = matches.get_one::<PathBuf>("SCHEME") else {
+        panic!("expected a color scheme as first argument");
+    };
+
+    let mut schemes = SchemeComposer::new(crate::conflict_policy(matches), true);
+    schemes.add(path)
+        .or_exit(Exit::Scheme, "invalid color scheme");
+    let json = schemes.finish();
+
+    let stops = matches.get_many::<u32>("stops")
+        .map(|x| x.copied().collect());
+    let space = matches.get_one::<String>("ramp_space")
+        .map(|x| x.parse().expect("expected a valid ramp space"));
+    let ramp = matches.get_one::<String>("ramp")
+        .map(|x| x.parse().expect("expected a valid ramp"));
+
+    let mut scheme = crate::color_scheme(stops, None, space, ramp)
+        .or_exit(Exit::InvalidArgs, "invalid stops");
+    scheme.add_colors(&json);
+
+    let group       = crate::config().group.clone().unwrap_or_else(|| PREVIEW_GROUP.to_owned());
+    let mut document = DocumentJson::default();
+    palette::apply(&mut document, &group, &scheme)
+        .or_exit(Exit::Scheme, "failed to generate colors");
+
+    // Colors are shown by their name within the group, e.g. `blue / blue.500`.
+    let prefix = format!("{group}{}", crate::separator());
+    let mut colors = document.color_variables()
+        .iter()
+        .map(|x| (x.name.strip_prefix(&prefix).unwrap_or(&x.name).to_owned(), x.value.to_lowercase()))
+        .collect::<Vec<_>>();
+
+    // Tints are named by their stop, so they're ordered by its value rather than as text, with
+    // `blue.10` coming before `blue.100`.
+    colors.sort_by_cached_key(|(name, _)| {
+        let (name, stop) = name.split_at(name.trim_end_matches(|x: char| x.is_ascii_digit()).len());
+        (name.to_owned(), stop.parse::<u64>().ok())
+    });
+
+    match matches.get_one::<PathBuf>("html") {
+        Some(output)    => {
+            std::fs::write(output, html(path, &colors))
+                .expect("failed to write preview");
+            say!("wrote {} colors to {}", colors.len(), output.display());
+        }
+        None            => print!("{}", swatches(&colors)),
+    }
+}
+
+/// Render `colors` as a line each with a swatch of the color in the terminal's true color, with
+/// a blank line between the tints of different colors.
+fn swatches(colors: &[(String, String)]) -> String {
+    let mut output  = String::new();
+    let mut last    = None;
+    for (name, value) in colors {
+        let term = name.split(crate::separator().trim()).next().map(str::trim);
+        if last.is_some() && last != term {
+            output.push('\n');
+        }
+        last = term;
+
+        let rgb = u32::from_str_radix(&value[..6.min(value.len())], 16).unwrap_or_default();
+        output.push_str(&format!(
+            "\x1b[48;2;{};{};{}m      \x1b[0m #{value} {name}\n",
+            rgb >> 16 & 0xff,
+            rgb >> 8 & 0xff,
+            rgb & 0xff,
+        ));
+    }
+
+    output
+}
+
+/// Render `colors` as an html page of swatches titled after the scheme at `path`.
+fn html(path: &Path, colors: &[(String, String)]) -> String {
+    let swatches = colors.iter()
+        .map(|(name, value)| format!(
+            "<div class=\"swatch\"><div style=\"background: #{value}\"></div><code>#{value}</code> {}</div>",
+            escape(name),
+        ))
+        .collect::<Vec<_>>()
+        .join("\n");
+
+    format!(
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>{}</title>\n<style>\n\
+        body {{ font-family: sans-serif; }}\n\
+        .swatch {{ display: flex; align-items: center; gap: 8px; margin: 2px 0; }}\n\
+        .swatch div {{ width: 48px; height: 24px; border-radius: 4px; }}\n\
+        </style>\n</head>\n<body>\n{swatches}\n</body>\n</html>\n",
+        escape(&path.display().to_string()),
+    )
+}
+
+/// Escape `text` for use in html.
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
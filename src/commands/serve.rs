@@ -0,0 +1,293 @@
+use std::io::Read;
+use std::panic::AssertUnwindSafe;
+
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD;
+use clap::{arg, value_parser, ArgMatches, Command};
+use json::{object, JsonValue};
+use lunacy_tools::palette;
+use lunacy_tools::schema::DocumentJson;
+use tiny_http::{Header, Request, Response, Server};
+
+use crate::document::{LunacyDocument, OpenOptions};
+use crate::exit::Exit;
+use crate::hooks::Hooks;
+use crate::Error;
+use crate::output::say;
+
+/// Create the `serve` subcommand.
+pub fn command() -> Command {
+    Command::new("serve")
+        .about("serve the theming tools over http so they can back a web service")
+        .arg(
+            arg!(--port <PORT> "the port to listen on")
+                .default_value("8080")
+                .value_parser(value_parser!(u16))
+        )
+        .arg(
+            arg!(--host <HOST> "the address to listen on, only the local machine by default")
+                .default_value("127.0.0.1")
+                .value_parser(value_parser!(String))
+        )
+        .arg(
+            arg!(--max_upload <MEGABYTES> "the maximum size of a request in megabytes")
+                .default_value("100")
+                .value_parser(value_parser!(u64))
+        )
+}
+
+/// Run the `serve` subcommand.
+pub fn run(matches: &ArgMatches) {
+    let (Some(host), Some(port), Some(max_upload)) = (
+        matches.get_one::<String>("host"),
+        matches.get_one::<u16>("port"),
+        matches.get_one::<u64>("max_upload"),
+    )
+    else {
+        panic!("expected a host, port and maximum upload size");
+    };
+
+    let max_upload = match max_upload.checked_mul(1024 * 1024) {
+        Some(x) => x,
+        None    => Exit::InvalidArgs.fail(format!("--max_upload {max_upload} is too large, expected at most {} megabytes", u64::MAX / (1024 * 1024))),
+    };
+
+    // Uploaded documents only live in a temp directory for the length of the request, so there's
+    // nothing to back up and no hooks to run.
+    let mut options = crate::open_options(matches);
+    options.operation   = Some(String::from("update colors"));
+    options.backup      = Default::default();
+    options.hooks       = Hooks::default();
+
+    // Clients tend to send the same document to several endpoints in a row.
+    crate::cache::enable();
+
+    let server = Server::http((host.as_str(), *port))
+        .expect("failed to start server");
+
+    say!("listening on http://{host}:{port}");
+
+    for mut request in server.incoming_requests() {
+        // Commands report errors by panicking, so a panic while handling a request is reported
+        // to the client rather than taking the server down.
+        let reply = std::panic::catch_unwind(AssertUnwindSafe(|| handle(&mut request, &options, max_upload)))
+            .unwrap_or_else(|x| Err(HttpError::BadRequest(super::panic_message(x))))
+            .unwrap_or_else(|e| Reply::json(e.status(), object! { "error": e.to_string() }));
+
+        let response = Response::from_data(reply.body)
+            .with_status_code(reply.status)
+            .with_header(Header::from_bytes("Content-Type", reply.content_type).expect("expected a valid header"));
+
+        if let Err(e) = request.respond(response) {
+            eprintln!("failed to respond to request, {e}");
+        }
+    }
+}
+
+/// A response to a request.
+struct Reply {
+    /// The http status code.
+    status      : u16,
+    /// The mime type of the body.
+    content_type: &'static str,
+    /// The body of the response.
+    body        : Vec<u8>,
+}
+
+impl Reply {
+    /// Create a response with a json body.
+    fn json(status: u16, json: JsonValue) -> Self {
+        Self { status, content_type: "application/json", body: json.dump().into_bytes() }
+    }
+}
+
+/// Route `request` to the endpoint handling it.
+///
+/// Every endpoint other than `/health` takes a json body with the document as base64 in
+/// `document`, and the `password` to open it with if it's encrypted.
+fn handle(request: &mut Request, options: &OpenOptions, max_upload: u64) -> Result<Reply, HttpError> {
+    let path    = request.url().split('?').next().unwrap_or_default().to_owned();
+    let method  = request.method().as_str().to_owned();
+
+    match (method.as_str(), path.as_str()) {
+        ("GET", "/health")      => Ok(Reply::json(200, object! { "status": "ok", "version": env!("CARGO_PKG_VERSION") })),
+        ("POST", "/apply")      => apply(&read_body(request, max_upload)?, options),
+        ("POST", "/groups")     => groups(&read_body(request, max_upload)?, options),
+        ("POST", "/palette")    => palette(&read_body(request, max_upload)?, options),
+        (_, "/health" | "/apply" | "/groups" | "/palette") => Err(HttpError::MethodNotAllowed(method)),
+        _                       => Err(HttpError::NotFound(path)),
+    }
+}
+
+/// Apply the color `scheme` in a request to its document, responding with the updated document.
+///
+/// The colors are added to `group`, or the configured group, or `theme`. `stops`, `ramp` and
+/// `ramp_space` override the configuration as the matching options do.
+fn apply(body: &JsonValue, options: &OpenOptions) -> Result<Reply, HttpError> {
+    if !body["scheme"].is_object() {
+        return Err(HttpError::BadRequest(String::from("expected a `scheme` object")));
+    }
+
+    // Malformed schemes are rejected up front, as adding them to the scheme would panic.
+    palette::check_scheme(&body["scheme"])
+        .map_err(|e| HttpError::BadRequest(format!("invalid color scheme, {e}")))?;
+
+    let stops = match body["stops"].is_array() {
+        true    => {
+            let stops = body["stops"].members()
+                .map(|x| x.as_u32())
+                .collect::<Option<Vec<_>>>()
+                .ok_or_else(|| HttpError::BadRequest(format!("invalid stops {}", body["stops"])))?;
+
+            Some(stops)
+        }
+        false   => None,
+    };
+    let space = body["ramp_space"].as_str()
+        .map(|x| x.parse().map_err(HttpError::BadRequest))
+        .transpose()?;
+    let ramp = body["ramp"].as_str()
+        .map(|x| x.parse().map_err(HttpError::BadRequest))
+        .transpose()?;
+
+    let mut scheme = crate::color_scheme(stops, None, space, ramp)
+        .map_err(HttpError::BadRequest)?;
+    scheme.add_colors(&body["scheme"]);
+
+    let group = body["group"].as_str()
+        .or(crate::config().group.as_deref())
+        .unwrap_or("theme")
+        .to_owned();
+
+    let mut options = options.clone();
+    options.writable = true;
+    if let Some(message) = body["message"].as_str() {
+        options.message = Some(message.to_owned());
+    }
+
+    let document = with_document(body, &options, |doc| {
+        doc.update_colors(&group, &scheme)?;
+        doc.commit()
+    })?;
+
+    Ok(Reply { status: 200, content_type: "application/octet-stream", body: document })
+}
+
+/// List the groups of color variables in the document of a request.
+fn groups(body: &JsonValue, options: &OpenOptions) -> Result<Reply, HttpError> {
+    let mut groups = vec![];
+    with_document(body, options, |doc| {
+        groups = palette::groups(&doc.load::<DocumentJson>("document.json")?, crate::separator());
+        Ok(())
+    })?;
+
+    Ok(Reply::json(200, groups.into()))
+}
+
+/// List the color variables in the document of a request, optionally only those in `group`.
+fn palette(body: &JsonValue, options: &OpenOptions) -> Result<Reply, HttpError> {
+    let prefix = body["group"].as_str().map(|x| format!("{x}{}", crate::separator()));
+
+    let mut colors = vec![];
+    with_document(body, options, |doc| {
+        colors = doc.load::<DocumentJson>("document.json")?
+            .color_variables()
+            .iter()
+            .filter(|x| prefix.as_ref().is_none_or(|prefix| x.name.starts_with(prefix.as_str())))
+            .map(|x| object! { "id": x.id.as_str(), "name": x.name.as_str(), "value": x.value.as_str() })
+            .collect();
+
+        Ok(())
+    })?;
+
+    Ok(Reply::json(200, colors.into()))
+}
+
+/// Read the json body of `request`, refusing bodies larger than `max_upload` bytes.
+fn read_body(request: &mut Request, max_upload: u64) -> Result<JsonValue, HttpError> {
+    let mut body = String::new();
+    request.as_reader()
+        .take(max_upload + 1)
+        .read_to_string(&mut body)
+        .map_err(|e| HttpError::BadRequest(e.to_string()))?;
+
+    if body.len() as u64 > max_upload {
+        return Err(HttpError::PayloadTooLarge(max_upload));
+    }
+
+    json::parse(&body).map_err(|e| HttpError::BadRequest(e.to_string()))
+}
+
+/// Open the base64 `document` of a request body in a temp directory and run `f` on it,
+/// returning the contents of the document afterwards.
+fn with_document(
+    body    : &JsonValue,
+    options : &OpenOptions,
+    f       : impl FnOnce(&mut LunacyDocument) -> Result<(), Error>
+)
+    -> Result<Vec<u8>, HttpError>
+{
+    let Some(document) = body["document"].as_str() else {
+        return Err(HttpError::BadRequest(String::from("expected a base64 `document`")));
+    };
+
+    let data = STANDARD.decode(document)
+        .map_err(|e| HttpError::BadRequest(format!("invalid document, {e}")))?;
+
+    let mut options = options.clone();
+    if let Some(password) = body["password"].as_str() {
+        options.password = Some(password.to_owned());
+    }
+
+    let run = || -> Result<Vec<u8>, Error> {
+        let dir     = tempdir::TempDir::new("lunacy-tools-serve")?;
+        let path    = dir.path().join("document.free");
+        std::fs::write(&path, data)?;
+
+        let mut doc = LunacyDocument::open(&path, &options)?;
+        f(&mut doc)?;
+        drop(doc);
+
+        Ok(std::fs::read(&path)?)
+    };
+
+    run().map_err(|e| HttpError::BadRequest(e.to_string()))
+}
+
+/// An error responded to a request with.
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum HttpError {
+    /// The request was malformed, or its document or scheme couldn't be used.
+    BadRequest(String),
+    /// The request is for a path with no endpoint.
+    NotFound(String),
+    /// The endpoint doesn't accept the method of the request.
+    MethodNotAllowed(String),
+    /// The request body is larger than the limit, in bytes.
+    PayloadTooLarge(u64),
+}
+
+impl HttpError {
+    /// Get the http status code of the error.
+    fn status(&self) -> u16 {
+        match self {
+            Self::BadRequest(_)         => 400,
+            Self::NotFound(_)           => 404,
+            Self::MethodNotAllowed(_)   => 405,
+            Self::PayloadTooLarge(_)    => 413,
+        }
+    }
+}
+
+impl std::fmt::Display for HttpError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Self::BadRequest(x)         => write!(f, "{x}"),
+            Self::NotFound(x)           => write!(f, "no endpoint at {x}"),
+            Self::MethodNotAllowed(x)   => write!(f, "method {x} not allowed"),
+            Self::PayloadTooLarge(x)    => write!(f, "request larger than {x} bytes"),
+        }
+    }
+}
+
+impl std::error::Error for HttpError { }
@@ -0,0 +1,140 @@
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+
+use clap::{arg, value_parser, ArgMatches, Command};
+use lunacy_tools::schema::DocumentJson;
+
+use crate::commands::{assets, format_size};
+use crate::document::LunacyDocument;
+use crate::exit::{Exit, OrExit};
+use crate::format;
+use crate::model::{self, layer_type};
+use crate::Error;
+
+/// Create the `stats` subcommand.
+pub fn command() -> Command {
+    Command::new("stats")
+        .about("print a summary of the contents of a document")
+        .arg(
+            arg!([FILE] "the lunacy .free file to summarize")
+                .required(true)
+                .value_parser(value_parser!(PathBuf))
+        )
+}
+
+/// Run the `stats` subcommand.
+pub fn run(matches: &ArgMatches) {
+    let Some(path) = matches.get_one::<PathBuf>("FILE") else {
+        panic!("expected .free document as first argument");
+    };
+
+    let options = crate::load_open_options(matches, path);
+    let doc     = LunacyDocument::open(path, &options)
+        .or_exit(Exit::Document, "failed to open document");
+
+    let stats   = Stats::collect(&doc)
+        .expect("failed to read document");
+
+    print!("{stats}");
+}
+
+/// A summary of the contents of a document.
+#[derive(Clone, Debug, Default)]
+pub struct Stats {
+    /// The format version recorded in the document.
+    pub format_version  : Option<u32>,
+    /// The number of pages.
+    pub pages           : usize,
+    /// The number of artboards across all pages.
+    pub artboards       : usize,
+    /// The number of layers across all pages.
+    pub layers          : usize,
+    /// The number of color variables in each group.
+    pub variables       : BTreeMap<String, usize>,
+    /// The number of styles of each kind in each group.
+    pub styles          : BTreeMap<&'static str, BTreeMap<String, usize>>,
+    /// The number of embedded assets.
+    pub assets          : usize,
+    /// The decompressed size of the embedded assets in bytes.
+    pub assets_size     : u64,
+    /// The compressed size of the embedded assets in bytes.
+    pub assets_compressed_size: u64,
+}
+
+impl Stats {
+    /// Collect statistics on `doc`.
+    pub fn collect(doc: &LunacyDocument) -> Result<Self, Error> {
+        let mut stats = Stats {
+            format_version: format::read_version(doc)?,
+            ..Default::default()
+        };
+
+        // Count the layers on every page.
+        for (_, page) in model::pages(doc)? {
+            stats.pages += 1;
+            page.visit(&mut |layer| {
+                stats.layers += 1;
+                if layer.kind() == layer_type::ARTBOARD {
+                    stats.artboards += 1;
+                }
+            });
+        }
+
+        // Count the variables and styles in each group.
+        let document = doc.load::<DocumentJson>("document.json")?;
+        for variable in document.color_variables() {
+            let group = model::group_name(&variable.name);
+            *stats.variables.entry(group.to_owned()).or_default() += 1;
+        }
+
+        for (kind, styles) in document.styles() {
+            let groups = stats.styles.entry(kind).or_default();
+            for style in styles {
+                let group = model::group_name(style.name.as_deref().unwrap_or_default());
+                *groups.entry(group.to_owned()).or_default() += 1;
+            }
+        }
+
+        // Total up the embedded assets.
+        for entry in assets::asset_entries(doc) {
+            stats.assets                 += 1;
+            stats.assets_size            += entry.size;
+            stats.assets_compressed_size += entry.compressed_size;
+        }
+
+        Ok(stats)
+    }
+}
+
+impl std::fmt::Display for Stats {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self.format_version {
+            Some(x) => writeln!(f, "format version: {x}")?,
+            None    => writeln!(f, "format version: unknown")?,
+        }
+
+        writeln!(f, "pages: {}", self.pages)?;
+        writeln!(f, "artboards: {}", self.artboards)?;
+        writeln!(f, "layers: {}", self.layers)?;
+
+        writeln!(f, "color variables: {}", self.variables.values().sum::<usize>())?;
+        for (group, count) in self.variables.iter() {
+            writeln!(f, "  {group}: {count}")?;
+        }
+
+        for (kind, groups) in self.styles.iter() {
+            writeln!(f, "{kind} styles: {}", groups.values().sum::<usize>())?;
+            for (group, count) in groups.iter() {
+                writeln!(f, "  {group}: {count}")?;
+            }
+        }
+
+        writeln!(
+            f,
+            "assets: {} ({}, {} compressed)",
+            self.assets,
+            format_size(self.assets_size),
+            format_size(self.assets_compressed_size)
+        )
+    }
+}
@@ -0,0 +1,327 @@
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+
+use clap::{arg, value_parser, ArgAction, ArgMatches, Command};
+use json::JsonValue;
+
+use crate::document::LunacyDocument;
+use crate::exit::{Exit, OrExit};
+use crate::model::{self, LAYERS_KEY, NAME_SEPARATOR, OVERRIDE_KEYS, STYLE_KINDS};
+use crate::Error;
+use crate::output::say;
+
+/// The keys of a style or variable that identify it rather than describe its properties.
+pub const IDENTITY_KEYS: &[&str] = &["id", "name", "version", "_class", "do_objectID"];
+
+/// Create the `styles` subcommand.
+pub fn command() -> Command {
+    Command::new("styles")
+        .about("inspect how shared styles and color variables are used")
+        .subcommand_required(true)
+        .subcommand(
+            Command::new("overrides")
+                .about("find layers and instances that override a shared style or color variable locally")
+                .arg(
+                    arg!([FILE] "the lunacy .free file to inspect")
+                        .required(true)
+                        .value_parser(value_parser!(PathBuf))
+                )
+                .arg(
+                    arg!(--reset "reset overridden properties back to the shared style or variable")
+                        .action(ArgAction::SetTrue)
+                )
+        )
+}
+
+/// Run the `styles` subcommand.
+pub fn run(matches: &ArgMatches) {
+    match matches.subcommand() {
+        Some(("overrides", matches))    => overrides(matches),
+        _                               => unreachable!("a subcommand is required"),
+    }
+}
+
+/// A shared style or color variable that objects can refer to by id.
+struct Shared {
+    /// A description of the style or variable for reporting, e.g. `color variable theme / blue`.
+    label       : String,
+    /// The properties the style or variable defines, as objects referring to it would store them.
+    properties  : JsonValue,
+}
+
+/// Load every shared style and color variable in `document.json` by id.
+fn load_shared(doc: &LunacyDocument) -> Result<BTreeMap<String, Shared>, Error> {
+    let json = doc.load_json("document.json")?;
+
+    let kinds = std::iter::once(("color variable".to_owned(), "colorVariables"))
+        .chain(STYLE_KINDS.iter().map(|(kind, key)| (format!("{kind} style"), *key)));
+
+    let mut shared = BTreeMap::new();
+    for (kind, key) in kinds {
+        for style in json[key].members() {
+            let Some(id) = style["id"].as_str() else {
+                continue;
+            };
+
+            // Variables and color styles hold a single color, which fills and borders store under
+            // `color`.
+            let mut properties = JsonValue::new_object();
+            if style.has_key("value") {
+                properties["color"] = style["value"].clone();
+            }
+            else {
+                for (key, value) in style.entries().filter(|(key, _)| !IDENTITY_KEYS.contains(key)) {
+                    properties[key] = value.clone();
+                }
+            }
+
+            shared.insert(id.to_owned(), Shared {
+                label       : format!("{kind} {}", style["name"].as_str().unwrap_or(id)),
+                properties,
+            });
+        }
+    }
+
+    Ok(shared)
+}
+
+/// Report the layers and instances that override shared styles or color variables, optionally
+/// resetting them.
+fn overrides(matches: &ArgMatches) {
+    let Some(path) = matches.get_one::<PathBuf>("FILE") else {
+        panic!("expected .free document as first argument");
+    };
+
+    let reset = matches.get_flag("reset");
+
+    let options = crate::load_edit_options(matches, path);
+    let mut doc = LunacyDocument::open(path, &options)
+        .or_exit(Exit::Document, "failed to open document");
+    doc.set_zero_timestamps(matches.get_flag("ZERO_TIMESTAMPS"));
+
+    let shared = load_shared(&doc)
+        .expect("failed to read document");
+    let pages = model::load_pages(&doc)
+        .expect("failed to read pages");
+
+    // Instance overrides are checked against the styled properties of the component layer they
+    // apply to.
+    let mut styled = BTreeMap::new();
+    for (_, page) in &pages {
+        model::visit_layers(page, &mut |layer| {
+            if let Some(id) = model::layer_id(layer) {
+                let properties = styled_properties(layer, &shared);
+                if !properties.is_empty() {
+                    styled.insert(id.to_owned(), properties);
+                }
+            }
+        });
+    }
+
+    let mut found = 0;
+    for (entry, mut page) in pages {
+        let page_name   = page["name"].as_str().unwrap_or(&entry).to_owned();
+        let changed     = visit_layer_paths(&mut page, &page_name, &mut |path, layer| {
+            let mut layer_found = 0;
+            for (label, keys) in check_layer(layer, &shared, reset) {
+                println!("{path}: overrides {label} ({})", keys.join(", "));
+                layer_found += 1;
+            }
+
+            if model::is_instance(layer) {
+                layer_found += check_instance(layer, &styled, reset, &mut |layer, label, property| {
+                    println!("{path}: overrides {label} ({property}) of {layer}");
+                });
+            }
+
+            found += layer_found;
+            layer_found > 0
+        });
+
+        if reset && changed {
+            doc.save_json(&entry, &page)
+                .expect("failed to save page");
+        }
+    }
+
+    if reset {
+        doc.commit()
+            .or_exit(Exit::Document, "failed to commit changes to document");
+        say!("reset {found} overrides");
+    }
+    else {
+        say!("{found} overrides");
+    }
+}
+
+/// Visit every layer below `json` with its path, returning whether `f` returned true for any.
+fn visit_layer_paths(json: &mut JsonValue, parent: &str, f: &mut impl FnMut(&str, &mut JsonValue) -> bool) -> bool {
+    if !json.has_key(LAYERS_KEY) {
+        return false;
+    }
+
+    let mut any = false;
+    for layer in json[LAYERS_KEY].members_mut() {
+        let path = format!("{parent} {NAME_SEPARATOR} {}", layer["name"].as_str().unwrap_or_default());
+        any |= f(&path, layer);
+        any |= visit_layer_paths(layer, &path, f);
+    }
+
+    any
+}
+
+/// Find the objects within `layer` that refer to a shared style or variable, excluding child
+/// layers, calling `f` with each object and the shared style or variable it refers to.
+fn visit_styled<'a>(json: &mut JsonValue, shared: &'a BTreeMap<String, Shared>, f: &mut impl FnMut(&mut JsonValue, &'a Shared)) {
+    let style = json.entries()
+        .filter(|(key, _)| key.ends_with("Id"))
+        .find_map(|(_, value)| value.as_str().and_then(|x| shared.get(x)));
+
+    if let Some(style) = style {
+        f(json, style);
+    }
+
+    match json {
+        JsonValue::Object(object) => {
+            for (_, value) in object.iter_mut().filter(|(key, _)| *key != LAYERS_KEY) {
+                visit_styled(value, shared, f);
+            }
+        }
+        JsonValue::Array(values) => {
+            for value in values {
+                visit_styled(value, shared, f);
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Get the properties of `layer` that come from a shared style or variable, and the label of
+/// the style or variable each comes from.
+fn styled_properties(layer: &JsonValue, shared: &BTreeMap<String, Shared>) -> BTreeMap<String, String> {
+    let mut properties  = BTreeMap::new();
+    let mut layer       = layer.clone();
+    for key in ["fills", "borders"] {
+        let mut found = None;
+        visit_styled(&mut layer[key], shared, &mut |_, style| {
+            found.get_or_insert_with(|| style.label.clone());
+        });
+
+        if let Some(label) = found {
+            properties.insert(key.to_owned(), label);
+        }
+    }
+
+    visit_styled(&mut layer, shared, &mut |_, style| {
+        for (key, _) in style.properties.entries() {
+            properties.entry(key.to_owned()).or_insert_with(|| style.label.clone());
+        }
+    });
+
+    properties
+}
+
+/// Find the properties of `layer` that differ from the shared style or variable they come
+/// from, resetting them if `reset` is set. Returns the label of each style or variable with
+/// the properties that differ from it.
+fn check_layer(layer: &mut JsonValue, shared: &BTreeMap<String, Shared>, reset: bool) -> Vec<(String, Vec<String>)> {
+    let mut found = vec![];
+    visit_styled(layer, shared, &mut |json, style| {
+        let keys = style.properties.entries()
+            .filter(|(key, value)| json.has_key(key) && !same_value(key, &json[*key], value))
+            .map(|(key, _)| key.to_owned())
+            .collect::<Vec<_>>();
+
+        if keys.is_empty() {
+            return;
+        }
+
+        if reset {
+            for key in &keys {
+                json[key.as_str()] = reset_value(&json[key.as_str()], &style.properties[key.as_str()]);
+            }
+        }
+
+        found.push((style.label.clone(), keys));
+    });
+
+    found
+}
+
+/// Find the overrides of the instance `layer` that replace a styled property of a component
+/// layer, removing them if `reset` is set. Calls `f` with the name of the component layer, the
+/// label of the style and the property for each, and returns the number found.
+fn check_instance(
+    layer   : &mut JsonValue,
+    styled  : &BTreeMap<String, BTreeMap<String, String>>,
+    reset   : bool,
+    f       : &mut impl FnMut(&str, &str, &str)
+)
+    -> usize
+{
+    let mut found = 0;
+    for key in OVERRIDE_KEYS {
+        if !layer.has_key(key) {
+            continue;
+        }
+
+        let mut retained = JsonValue::new_array();
+        for value in layer[*key].members() {
+            let style = model::override_target(value).and_then(|(layers, property)| {
+                let target = layers.last()?;
+                let label  = styled.get(*target)?.get(property)?;
+                Some((*target, label, property))
+            });
+
+            match style {
+                Some((target, label, property)) => {
+                    f(target, label, property);
+                    found += 1;
+
+                    if !reset {
+                        retained.push(value.clone()).expect("failed to keep override");
+                    }
+                }
+                None                            => {
+                    retained.push(value.clone()).expect("failed to keep override");
+                }
+            }
+        }
+
+        layer[*key] = retained;
+    }
+
+    found
+}
+
+/// Check whether `value` is the same as the shared `expected` value of the property `key`.
+fn same_value(key: &str, value: &JsonValue, expected: &JsonValue) -> bool {
+    match (key, value.as_str(), expected.as_str()) {
+        ("color", Some(value), Some(expected)) => normalize_color(value) == normalize_color(expected),
+        _                                      => value == expected,
+    }
+}
+
+/// Get the value to reset a property to, keeping colors in the format they were written in.
+fn reset_value(value: &JsonValue, expected: &JsonValue) -> JsonValue {
+    match (value.as_str(), expected.as_str()) {
+        (Some(value), Some(expected)) if value.starts_with('#') && !expected.starts_with('#') => {
+            format!("#{expected}").into()
+        }
+        (Some(value), Some(expected)) if !value.starts_with('#') && expected.starts_with('#') => {
+            expected[1..].into()
+        }
+        _ => expected.clone(),
+    }
+}
+
+/// Normalize a hex color for comparison, making alpha explicit.
+fn normalize_color(color: &str) -> String {
+    let mut color = color.trim_start_matches('#').to_uppercase();
+    if color.len() == 6 {
+        color.push_str("FF");
+    }
+
+    color
+}
+
@@ -0,0 +1,430 @@
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+use clap::{arg, value_parser, ArgAction, ArgMatches, Command};
+use json::JsonValue;
+use regex::{NoExpand, Regex, RegexBuilder};
+
+use crate::commands::DocumentCopy;
+use crate::document::{LunacyDocument, OpenOptions};
+use crate::exit::{Exit, OrExit};
+use crate::model::{self, layer_type, LAYERS_KEY, NAME_SEPARATOR, OVERRIDE_KEYS, TEXT_OVERRIDE_PROPERTIES};
+use crate::Error;
+use crate::output::say;
+
+/// Create the `text` subcommand.
+pub fn command() -> Command {
+    Command::new("text")
+        .about("edit the content of text layers")
+        .subcommand_required(true)
+        .subcommand(
+            Command::new("replace")
+                .about("find and replace text across every text layer and text override")
+                .arg(
+                    arg!([FILE] "the lunacy .free file to modify")
+                        .required(true)
+                        .value_parser(value_parser!(PathBuf))
+                )
+                .arg(
+                    arg!([FIND] "the text to find, e.g. 'Acme Corp'")
+                        .required(true)
+                )
+                .arg(
+                    arg!([REPLACE] "the text to replace it with")
+                        .required(true)
+                )
+                .arg(
+                    arg!(-i --ignore_case "match regardless of case")
+                        .id("IGNORE_CASE")
+                        .action(ArgAction::SetTrue)
+                )
+                .arg(
+                    arg!(--whole_word "only match whole words")
+                        .id("WHOLE_WORD")
+                        .action(ArgAction::SetTrue)
+                )
+                .arg(
+                    arg!(--dry_run "print the changes without modifying the document")
+                        .id("DRY_RUN")
+                        .action(ArgAction::SetTrue)
+                )
+        )
+        .subcommand(
+            Command::new("export")
+                .about("write the text of every text layer and text override to a strings file for translation")
+                .arg(
+                    arg!([FILE] "the lunacy .free file to export text from")
+                        .required(true)
+                        .value_parser(value_parser!(PathBuf))
+                )
+                .arg(
+                    arg!([STRINGS] "the .json or .csv file to write the strings to")
+                        .required(true)
+                        .value_parser(value_parser!(PathBuf))
+                )
+        )
+        .subcommand(
+            Command::new("import")
+                .about("write translated strings into a copy of a document")
+                .arg(
+                    arg!([FILE] "the lunacy .free file to translate")
+                        .required(true)
+                        .value_parser(value_parser!(PathBuf))
+                )
+                .arg(
+                    arg!([STRINGS] "the .json or .csv file of translated strings, as written by `text export`")
+                        .required(true)
+                        .value_parser(value_parser!(PathBuf))
+                )
+                .arg(
+                    arg!(--out <FILE> "the path to write the translated copy of the document to")
+                        .required(true)
+                        .value_parser(value_parser!(PathBuf))
+                )
+        )
+}
+
+/// Run the `text` subcommand.
+pub fn run(matches: &ArgMatches) {
+    match matches.subcommand() {
+        Some(("replace", matches))  => replace(matches),
+        Some(("export", matches))   => export(matches),
+        Some(("import", matches))   => import(matches),
+        _                           => unreachable!("a subcommand is required"),
+    }
+}
+
+/// Replace text across every text layer and text override in a document.
+fn replace(matches: &ArgMatches) {
+    let Some(path) = matches.get_one::<PathBuf>("FILE") else {
+        panic!("expected .free document as first argument");
+    };
+
+    let (Some(find), Some(replacement)) = (matches.get_one::<String>("FIND"), matches.get_one::<String>("REPLACE")) else {
+        panic!("expected text to find and replace");
+    };
+
+    let dry_run = matches.get_flag("DRY_RUN");
+
+    let mut pattern = regex::escape(find);
+    if matches.get_flag("WHOLE_WORD") {
+        pattern = format!(r"\b{pattern}\b");
+    }
+
+    let regex = RegexBuilder::new(&pattern)
+        .case_insensitive(matches.get_flag("IGNORE_CASE"))
+        .build()
+        .expect("failed to build pattern");
+
+    let options = crate::load_edit_options(matches, path);
+    let mut doc = LunacyDocument::open(path, &options)
+        .or_exit(Exit::Document, "failed to open document");
+    doc.set_zero_timestamps(matches.get_flag("ZERO_TIMESTAMPS"));
+
+    let mut layers          = 0;
+    let mut occurrences     = 0;
+    for (entry, mut page) in model::load_pages(&doc).expect("failed to read pages") {
+        let page_name = page["name"].as_str().unwrap_or(&entry).to_owned();
+
+        let mut changed = false;
+        model::visit_layers_mut(&mut page, &mut |layer| {
+            let name = layer["name"].as_str().unwrap_or_default().to_owned();
+
+            let mut count = 0;
+            if model::layer_type(layer) == layer_type::TEXT {
+                if let Some(key) = model::text_key(layer) {
+                    count += replace_value(&mut layer[key], &regex, replacement);
+                }
+            }
+
+            // Text overridden within instances would otherwise keep the old text.
+            if model::is_instance(layer) {
+                for key in OVERRIDE_KEYS {
+                    if !layer.has_key(key) {
+                        continue;
+                    }
+
+                    for value in layer[*key].members_mut() {
+                        let is_text = model::override_target(value)
+                            .is_some_and(|(_, property)| TEXT_OVERRIDE_PROPERTIES.contains(&property));
+
+                        if is_text {
+                            count += replace_value(&mut value["value"], &regex, replacement);
+                        }
+                    }
+                }
+            }
+
+            if count > 0 {
+                say!("{page_name}: {name}: {count} replaced");
+                layers      += 1;
+                occurrences += count;
+                changed      = true;
+            }
+        });
+
+        if changed && !dry_run {
+            doc.save_json(&entry, &page)
+                .expect("failed to save page");
+        }
+    }
+
+    if dry_run {
+        say!("would replace {occurrences} occurrences in {layers} layers");
+        return;
+    }
+
+    doc.commit()
+        .or_exit(Exit::Document, "failed to commit changes to document");
+
+    say!("replaced {occurrences} occurrences in {layers} layers");
+}
+
+/// Replace every match of `regex` in the string `value` with `replacement`, returning the number
+/// of matches.
+fn replace_value(value: &mut JsonValue, regex: &Regex, replacement: &str) -> usize {
+    let Some(text) = value.as_str() else {
+        return 0;
+    };
+
+    let count = regex.find_iter(text).count();
+    if count > 0 {
+        *value = regex.replace_all(text, NoExpand(replacement)).into_owned().into();
+    }
+
+    count
+}
+
+/// Write the text of every text layer and text override in a document to a strings file.
+fn export(matches: &ArgMatches) {
+    let Some(path) = matches.get_one::<PathBuf>("FILE") else {
+        panic!("expected .free document as first argument");
+    };
+
+    let Some(out) = matches.get_one::<PathBuf>("STRINGS") else {
+        panic!("expected a strings file as second argument");
+    };
+
+    let options = crate::load_open_options(matches, path);
+    let doc     = LunacyDocument::open(path, &options)
+        .or_exit(Exit::Document, "failed to open document");
+
+    let mut strings = vec![];
+    let mut keys    = Keys::default();
+    for (entry, mut page) in model::load_pages(&doc).expect("failed to read pages") {
+        let page_name = page["name"].as_str().unwrap_or(&entry).to_owned();
+        visit_strings(&mut page, &page_name, &mut keys, &mut |key, value| {
+            if let Some(text) = value.as_str() {
+                strings.push((key.to_owned(), text.to_owned()));
+            }
+        });
+    }
+
+    write_strings(out, &strings)
+        .expect("failed to write strings");
+
+    say!("exported {} strings to {}", strings.len(), out.display());
+}
+
+/// Write the translated strings in a strings file into a copy of a document.
+fn import(matches: &ArgMatches) {
+    let Some(path) = matches.get_one::<PathBuf>("FILE") else {
+        panic!("expected .free document as first argument");
+    };
+
+    let Some(strings_path) = matches.get_one::<PathBuf>("STRINGS") else {
+        panic!("expected a strings file as second argument");
+    };
+
+    let Some(out) = matches.get_one::<PathBuf>("out") else {
+        panic!("expected a path to write the translated document to");
+    };
+
+    let mut strings = read_strings(strings_path)
+        .expect("failed to read strings");
+
+    // The original is left untouched so it can be reviewed side by side with the translation.
+    let mut options = crate::load_edit_options(matches, path);
+    let copy        = DocumentCopy::new(path, out, &mut options)
+        .or_exit(Exit::Document, "failed to copy document");
+
+    // The copy is dropped before exiting so it isn't left next to the output.
+    let (updated, untranslated) = match translate(matches, copy.path(), &options, &mut strings) {
+        Ok(x)   => x,
+        Err(e)  => {
+            drop(copy);
+            Exit::Document.fail(format!("failed to translate document, {e}"));
+        }
+    };
+
+    copy.finish()
+        .or_exit(Exit::Failure, "failed to write document");
+
+    for key in strings.keys() {
+        say!("not found: {key}");
+    }
+
+    say!("updated {updated} strings, {untranslated} without a translation, {} not found", strings.len());
+}
+
+/// Write the translated `strings` into the document at `path`, removing each string found.
+/// Returns the number of strings updated and the number of strings without a translation.
+fn translate(
+    matches : &ArgMatches,
+    path    : &Path,
+    options : &OpenOptions,
+    strings : &mut BTreeMap<String, String>
+)
+    -> Result<(usize, usize), Error>
+{
+    let mut doc = LunacyDocument::open(path, options)?;
+    doc.set_zero_timestamps(matches.get_flag("ZERO_TIMESTAMPS"));
+
+    let mut updated         = 0;
+    let mut untranslated    = 0;
+    let mut keys            = Keys::default();
+    for (entry, mut page) in model::load_pages(&doc)? {
+        let page_name   = page["name"].as_str().unwrap_or(&entry).to_owned();
+        let mut changed = false;
+        visit_strings(&mut page, &page_name, &mut keys, &mut |key, value| {
+            let Some(text) = strings.remove(key) else {
+                untranslated += 1;
+                return;
+            };
+
+            if value.as_str() != Some(text.as_str()) {
+                *value   = text.into();
+                updated += 1;
+                changed  = true;
+            }
+        });
+
+        if changed {
+            doc.save_json(&entry, &page)?;
+        }
+    }
+
+    doc.commit()?;
+
+    Ok((updated, untranslated))
+}
+
+/// Tracks the keys given to strings so that layers with the same path still get unique keys.
+#[derive(Default)]
+struct Keys {
+    /// The number of times each path has been seen.
+    seen: BTreeMap<String, usize>,
+}
+
+impl Keys {
+    /// Get a unique key for `path`, numbering repeated paths in the order they're seen.
+    fn key(&mut self, path: String) -> String {
+        let count = self.seen.entry(path.clone()).or_default();
+        *count += 1;
+
+        match *count {
+            1 => path,
+            n => format!("{path} ({n})"),
+        }
+    }
+}
+
+/// Visit the text of every text layer and text override below `json` with its key, which is the
+/// path to the layer and, for overrides, the ids of the layers the override applies to.
+fn visit_strings(json: &mut JsonValue, parent: &str, keys: &mut Keys, f: &mut impl FnMut(&str, &mut JsonValue)) {
+    if !json.has_key(LAYERS_KEY) {
+        return;
+    }
+
+    for layer in json[LAYERS_KEY].members_mut() {
+        let path = format!("{parent} {NAME_SEPARATOR} {}", layer["name"].as_str().unwrap_or_default());
+
+        if model::layer_type(layer) == layer_type::TEXT {
+            if let Some(key) = model::text_key(layer) {
+                f(&keys.key(path.clone()), &mut layer[key]);
+            }
+        }
+
+        if model::is_instance(layer) {
+            for key in OVERRIDE_KEYS {
+                if !layer.has_key(key) {
+                    continue;
+                }
+
+                for value in layer[*key].members_mut() {
+                    let target = model::override_target(value)
+                        .filter(|(_, property)| TEXT_OVERRIDE_PROPERTIES.contains(property))
+                        .map(|(layers, _)| layers.join("/"));
+
+                    if let Some(target) = target {
+                        f(&keys.key(format!("{path} [{target}]")), &mut value["value"]);
+                    }
+                }
+            }
+        }
+
+        visit_strings(layer, &path, keys, f);
+    }
+}
+
+/// Check whether the strings file at `path` is a CSV file rather than json.
+fn is_csv(path: &Path) -> bool {
+    path.extension().is_some_and(|x| x.eq_ignore_ascii_case("csv"))
+}
+
+/// Write `strings` to `path`, as CSV with `key` and `text` columns if it has a `.csv` extension,
+/// and as a json object otherwise.
+fn write_strings(path: &Path, strings: &[(String, String)]) -> Result<(), Error> {
+    if is_csv(path) {
+        let mut writer = csv::Writer::from_path(path)?;
+        writer.write_record(["key", "text"])?;
+        for (key, text) in strings {
+            writer.write_record([key, text])?;
+        }
+
+        writer.flush()?;
+    }
+    else {
+        let mut json = JsonValue::new_object();
+        for (key, text) in strings {
+            json[key.as_str()] = text.as_str().into();
+        }
+
+        std::fs::write(path, json.pretty(4))?;
+    }
+
+    Ok(())
+}
+
+/// Read the strings written by `write_strings`, keyed by their key.
+fn read_strings(path: &Path) -> Result<BTreeMap<String, String>, Error> {
+    let mut strings = BTreeMap::new();
+    if is_csv(path) {
+        let mut reader  = csv::Reader::from_path(path)?;
+        let headers     = reader.headers()?.clone();
+        let column      = |name: &str| headers.iter()
+            .position(|x| x == name)
+            .ok_or_else(|| format!("missing `{name}` column"));
+
+        let key_column  = column("key")?;
+        let text_column = column("text")?;
+        for record in reader.records() {
+            let record = record?;
+            if let (Some(key), Some(text)) = (record.get(key_column), record.get(text_column)) {
+                strings.insert(key.to_owned(), text.to_owned());
+            }
+        }
+    }
+    else {
+        let json = json::parse(&std::fs::read_to_string(path)?)?;
+        for (key, text) in json.entries() {
+            let Some(text) = text.as_str() else {
+                return Err(format!("expected a string for `{key}`").into());
+            };
+
+            strings.insert(key.to_owned(), text.to_owned());
+        }
+    }
+
+    Ok(strings)
+}
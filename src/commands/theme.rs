@@ -0,0 +1,184 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::{Path, PathBuf};
+
+use clap::{arg, value_parser, ArgAction, ArgMatches, Command};
+use clap_complete::ArgValueCompleter;
+use json::{object, JsonValue};
+use lunacy_tools::palette;
+use lunacy_tools::schema::DocumentJson;
+
+use crate::compose::SchemeComposer;
+use crate::exit::{Exit, OrExit};
+use crate::output::say;
+
+/// The group tokens are generated into for the report when none is configured.
+const REPORT_GROUP: &str = "theme";
+
+/// Create the `theme` subcommand.
+pub fn command() -> Command {
+    Command::new("theme")
+        .about("manage themes built from shared color schemes")
+        .subcommand_required(true)
+        .subcommand(
+            Command::new("brand")
+                .about("layer a per-customer override onto a shared base scheme and apply the result, reporting the tokens the brand overrides")
+                .arg(
+                    arg!([FILE] "the lunacy .free file to apply the brand to")
+                        .required(true)
+                        .value_parser(value_parser!(PathBuf))
+                )
+                .arg(
+                    arg!(--base <SCHEME> "the json file containing the shared base color scheme")
+                        .required(true)
+                        .value_parser(value_parser!(PathBuf))
+                )
+                .arg(
+                    arg!(--overrides <SCHEME> "the json file containing the brand's overrides of the base scheme")
+                        .required(true)
+                        .value_parser(value_parser!(PathBuf))
+                )
+                .arg(
+                    arg!(--group <GROUP> "the group of colors to apply the brand to, defaults to the configured group, or one chosen from the document when run interactively, or 'theme'")
+                        .value_parser(value_parser!(String))
+                        .add(ArgValueCompleter::new(crate::complete_group))
+                )
+                .arg(
+                    arg!(--on_conflict <POLICY> "how an entry defined by more than one of the color schemes or the schemes they compose is resolved, last_wins keeping the one merged last or error failing, defaults to last_wins")
+                        .id("ON_CONFLICT")
+                        .value_parser(["last_wins", "error"])
+                )
+                .arg(
+                    arg!(--json "print the report as json")
+                        .action(ArgAction::SetTrue)
+                )
+        )
+}
+
+/// Run the `theme` subcommand.
+pub fn run(matches: &ArgMatches) {
+    match matches.subcommand() {
+        Some(("brand", matches))    => brand(matches),
+        _                           => unreachable!("a subcommand is required"),
+    }
+}
+
+/// Apply a base scheme with a brand's overrides layered onto it, reporting the tokens the
+/// overrides change.
+fn brand(matches: &ArgMatches) {
+    let Some(path) = matches.get_one::<PathBuf>("FILE") else {
+        panic!("expected .free document as first argument");
+    };
+    let Some(base) = matches.get_one::<PathBuf>("base") else {
+        panic!("expected a base color scheme");
+    };
+    let Some(overrides) = matches.get_one::<PathBuf>("overrides") else {
+        panic!("expected a color scheme of overrides");
+    };
+
+    let base        = read_scheme(matches, base);
+    let overrides   = read_scheme(matches, overrides);
+    let branded     = layer(&base, &overrides);
+
+    // The report compares the tokens each scheme generates on its own, whatever the document
+    // holds already.
+    let group   = matches.get_one::<String>("group")
+        .or(crate::config().group.as_ref())
+        .map_or(REPORT_GROUP, |x| x.as_str());
+    let changes = compare(&tokens(&base, group), &tokens(&branded, group));
+
+    if super::json_output(matches) {
+        let json = changes.iter()
+            .map(|(token, from, to)| object! {
+                "token" : token.as_str(),
+                "base"  : from.as_deref(),
+                "brand" : to.as_deref(),
+            })
+            .collect::<Vec<_>>();
+
+        println!("{}", JsonValue::from(json).pretty(4));
+    }
+    else {
+        let rows = changes.iter()
+            .map(|(token, from, to)| [token.clone(), from.clone().unwrap_or_else(|| String::from("-")), to.clone().unwrap_or_else(|| String::from("-"))])
+            .collect::<Vec<_>>();
+
+        super::print_table(&["token", "base", "brand"], &rows);
+        say!("the brand overrides {} tokens", changes.len());
+    }
+
+    let mut scheme = crate::color_scheme(None, None, None, None)
+        .or_exit(Exit::InvalidArgs, "invalid stops");
+    scheme.add_colors(&branded);
+    if scheme.is_empty() {
+        Exit::NothingToDo.fail("no colors to apply, the base scheme and overrides are empty");
+    }
+
+    crate::apply_color_scheme(matches, path, &scheme, false);
+}
+
+/// Read and check the color scheme at `path`, along with the schemes it composes, exiting if
+/// it's invalid.
+fn read_scheme(matches: &ArgMatches, path: &Path) -> JsonValue {
+    let mut schemes = SchemeComposer::new(crate::conflict_policy(matches), true);
+    schemes.add(path)
+        .or_exit(Exit::Scheme, "invalid color scheme");
+
+    schemes.finish()
+}
+
+/// Layer `overrides` onto the `base` scheme. The keys of an entry in both replace those of the
+/// base entry, keeping the rest, so an override only needs what differs, and entries only in the
+/// overrides are added.
+fn layer(base: &JsonValue, overrides: &JsonValue) -> JsonValue {
+    let mut layered = base.clone();
+    for (name, entry) in overrides.entries() {
+        if !layered[name].is_object() {
+            layered[name] = entry.clone();
+            continue;
+        }
+
+        // A value replaces a link and the other way around.
+        for (key, other) in [("value", "link"), ("link", "value")] {
+            if entry.has_key(key) {
+                layered[name].remove(other);
+            }
+        }
+
+        for (key, value) in entry.entries() {
+            layered[name][key] = value.clone();
+        }
+    }
+
+    layered
+}
+
+/// Generate the tokens of the scheme `json` into an empty document in `group`, by name within the
+/// group.
+fn tokens(json: &JsonValue, group: &str) -> BTreeMap<String, String> {
+    let mut scheme = crate::color_scheme(None, None, None, None)
+        .or_exit(Exit::InvalidArgs, "invalid stops");
+    scheme.add_colors(json);
+
+    let mut document = DocumentJson::default();
+    palette::apply(&mut document, group, &scheme)
+        .or_exit(Exit::Scheme, "failed to generate colors");
+
+    let prefix = format!("{group}{}", crate::separator());
+    document.color_variables()
+        .iter()
+        .map(|x| (x.name.strip_prefix(&prefix).unwrap_or(&x.name).to_owned(), format!("#{}", x.value.to_lowercase())))
+        .collect()
+}
+
+/// Compare the tokens of the base scheme to those of the branded scheme, returning each token the
+/// brand adds, changes or removes with its base and branded values, if any, in order of name.
+fn compare(base: &BTreeMap<String, String>, branded: &BTreeMap<String, String>) -> Vec<(String, Option<String>, Option<String>)> {
+    let tokens = base.keys()
+        .chain(branded.keys())
+        .collect::<BTreeSet<_>>();
+
+    tokens.into_iter()
+        .filter(|x| base.get(*x) != branded.get(*x))
+        .map(|x| (x.clone(), base.get(x).cloned(), branded.get(x).cloned()))
+        .collect()
+}
@@ -0,0 +1,53 @@
+use std::path::PathBuf;
+
+use clap::{arg, value_parser, ArgMatches, Command};
+
+use crate::document::LunacyDocument;
+use crate::exit::{Exit, OrExit};
+use crate::history;
+use crate::output::say;
+
+/// Create the `undo` subcommand.
+pub fn command() -> Command {
+    Command::new("undo")
+        .about("roll back the most recent changes the tool made to a document")
+        .arg(
+            arg!([FILE] "the lunacy .free file to modify")
+                .required(true)
+                .value_parser(value_parser!(PathBuf))
+        )
+        .arg(
+            arg!(-n --count <COUNT> "the number of changes to undo")
+                .default_value("1")
+                .value_parser(value_parser!(usize))
+        )
+}
+
+/// Run the `undo` subcommand.
+pub fn run(matches: &ArgMatches) {
+    let Some(path) = matches.get_one::<PathBuf>("FILE") else {
+        panic!("expected .free document as first argument");
+    };
+
+    let count = matches.get_one::<usize>("count")
+        .copied()
+        .unwrap_or(1);
+
+    // Undoing removes changes from the history rather than recording another.
+    let mut options = crate::load_edit_options(matches, path);
+    options.operation = None;
+
+    let mut doc = LunacyDocument::open(path, &options)
+        .or_exit(Exit::Document, "failed to open document");
+    doc.set_zero_timestamps(matches.get_flag("ZERO_TIMESTAMPS"));
+
+    let undone = history::undo(&mut doc, count)
+        .expect("failed to undo changes");
+
+    doc.commit()
+        .or_exit(Exit::Document, "failed to commit changes to document");
+
+    for record in undone {
+        say!("undid {record}");
+    }
+}
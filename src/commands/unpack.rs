@@ -0,0 +1,118 @@
+use std::path::{Component, Path, PathBuf};
+
+use clap::{arg, value_parser, ArgAction, ArgMatches, Command};
+use json::{object, JsonValue};
+
+use crate::canonical;
+use crate::document::{LunacyDocument, ORDER_FILE};
+use crate::Error;
+use crate::exit::{Exit, OrExit};
+use crate::output::say;
+
+/// Create the `unpack` subcommand.
+pub fn command() -> Command {
+    Command::new("unpack")
+        .about("explode a document into a directory of pretty printed json and assets")
+        .arg(
+            arg!([FILE] "the lunacy .free file to unpack")
+                .required(true)
+                .value_parser(value_parser!(PathBuf))
+        )
+        .arg(
+            arg!([DIR] "the directory to unpack the document into")
+                .required(true)
+                .value_parser(value_parser!(PathBuf))
+        )
+        .arg(
+            arg!(--canonical "write json in canonical form, with sorted keys and consistent numbers")
+                .action(ArgAction::SetTrue)
+        )
+}
+
+/// Run the `unpack` subcommand.
+pub fn run(matches: &ArgMatches) {
+    let Some(path) = matches.get_one::<PathBuf>("FILE") else {
+        panic!("expected .free document as first argument");
+    };
+
+    let Some(out) = matches.get_one::<PathBuf>("DIR") else {
+        panic!("expected a directory as second argument");
+    };
+
+    let options = crate::load_open_options(matches, path);
+    let doc     = LunacyDocument::open(path, &options)
+        .or_exit(Exit::Document, "failed to open document");
+
+    let sort = matches.get_flag("canonical");
+
+    clean(out)
+        .expect("failed to clean directory");
+
+    let mut entries = vec![];
+    for entry in doc.entries() {
+        let file = out.join(&entry.name);
+        if let Some(parent) = file.parent() {
+            std::fs::create_dir_all(parent)
+                .expect("failed to create directory");
+        }
+
+        // Json is pretty printed so it diffs line by line, everything else is written as is.
+        let data = match entry.name.ends_with(".json") {
+            true    => {
+                let mut json = doc.load_json(&entry.name).expect("failed to read entry");
+                if sort {
+                    json = canonical::canonicalize(&entry.name, &json);
+                }
+
+                json.pretty(2).into_bytes()
+            }
+            false   => doc.load_bytes(&entry.name).expect("failed to read entry"),
+        };
+
+        std::fs::write(&file, data)
+            .expect("failed to write entry");
+        entries.push(entry.name.as_str());
+    }
+
+    // Changes committed to the directory later are written back in the same form.
+    let mut order = object! {
+        "entries": entries.as_slice(),
+    };
+    if sort {
+        order["canonical"] = true.into();
+    }
+
+    std::fs::write(out.join(ORDER_FILE), order.pretty(2))
+        .expect("failed to write entry order");
+
+    say!("unpacked {} entries to {}", entries.len(), out.display());
+}
+
+/// Prepare `dir` to unpack a document into, removing the entries of a document unpacked there
+/// before so entries deleted since don't linger.
+///
+/// Directories holding anything other than an unpacked document are refused rather than cleared.
+fn clean(dir: &Path) -> Result<(), Error> {
+    if !dir.exists() {
+        return Ok(());
+    }
+
+    let order = dir.join(ORDER_FILE);
+    if !order.is_file() {
+        if std::fs::read_dir(dir)?.next().is_some() {
+            return Err(format!("{} isn't empty or an unpacked document", dir.display()).into());
+        }
+
+        return Ok(());
+    }
+
+    let json = json::parse(&std::fs::read_to_string(&order)?)?;
+    for entry in json["entries"].members().filter_map(JsonValue::as_str) {
+        let file = dir.join(entry);
+        if Path::new(entry).components().all(|x| matches!(x, Component::Normal(_))) && file.is_file() {
+            std::fs::remove_file(file)?;
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,184 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs::File;
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+
+use clap::{arg, value_parser, ArgMatches, Command};
+use json::JsonValue;
+use zip::read::ZipFile;
+use zip::ZipArchive;
+
+use crate::document::{self, ArchiveError, OpenOptions};
+use crate::exit::Exit;
+use crate::output::say;
+use crate::references::{self, Reference};
+use crate::Error;
+
+/// Create the `verify` subcommand.
+pub fn command() -> Command {
+    Command::new("verify")
+        .about("check a document for corrupt entries, invalid JSON and dangling references")
+        .arg(
+            arg!([FILE] "the lunacy .free file to verify")
+                .required(true)
+                .value_parser(value_parser!(PathBuf))
+        )
+}
+
+/// Run the `verify` subcommand.
+pub fn run(matches: &ArgMatches) {
+    let Some(path) = matches.get_one::<PathBuf>("FILE") else {
+        panic!("expected .free document as first argument");
+    };
+
+    let options = crate::load_open_options(matches, path);
+    let issues  = verify(path, &options)
+        .expect("failed to read document");
+
+    for issue in issues.iter() {
+        say!("{issue}");
+    }
+
+    if !issues.is_empty() {
+        Exit::Failure.quit();
+    }
+}
+
+/// A problem found while verifying a document.
+#[derive(Clone, Debug)]
+pub enum Issue {
+    /// An entry couldn't be read, e.g. because it failed its checksum.
+    Unreadable {
+        /// The name of the entry.
+        entry   : String,
+        /// The error raised while reading the entry.
+        error   : String,
+    },
+    /// A JSON entry couldn't be parsed.
+    InvalidJson {
+        /// The name of the entry.
+        entry   : String,
+        /// The error raised while parsing the entry.
+        error   : String,
+    },
+    /// A reference to an object or asset that doesn't exist in the document.
+    Dangling {
+        /// The name of the entry containing the reference.
+        entry   : String,
+        /// The path to the reference within the entry.
+        path    : String,
+        /// The dangling reference.
+        reference: Reference,
+    },
+}
+
+impl std::fmt::Display for Issue {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Self::Unreadable { entry, error }           => write!(f, "{entry}: unreadable - {error}"),
+            Self::InvalidJson { entry, error }          => write!(f, "{entry}: invalid json - {error}"),
+            Self::Dangling { entry, path, reference }   => write!(f, "{entry}: {path} references missing {reference}"),
+        }
+    }
+}
+
+/// Verify the document at `path`, returning every issue found.
+pub fn verify(path: &Path, options: &OpenOptions) -> Result<Vec<Issue>, Error> {
+    let mut archive = ZipArchive::new(File::open(path)?)?;
+    if archive.len() > options.limits.max_entries {
+        return Err(Box::new(ArchiveError::TooManyEntries(archive.len())));
+    }
+
+    let mut issues      = vec![];
+    let mut entries     = BTreeSet::new();
+    let mut documents   = BTreeMap::new();
+    let mut remaining   = options.limits.max_size;
+
+    for i in 0..archive.len() {
+        let name = archive.by_index_raw(i)?.name().to_owned();
+        entries.insert(name.clone());
+
+        // Only JSON entries are kept to be parsed, the rest are streamed through to the end, which
+        // validates their checksum without holding them in memory.
+        let keep    = name.ends_with(".json");
+        let read    = document::open_entry(&mut archive, i, options.password.as_deref())
+            .and_then(|x| check_entry(x, keep, remaining));
+
+        let (size, data) = match read {
+            Ok(x)   => x,
+            Err(x)  => {
+                issues.push(Issue::Unreadable { entry: name, error: x.to_string() });
+                continue;
+            }
+        };
+
+        // The limit covers the whole document, so an archive of many entries can't get around it.
+        if size > remaining {
+            return Err(Box::new(ArchiveError::TooLarge(options.limits.max_size)));
+        }
+        remaining -= size;
+
+        if !keep {
+            continue;
+        }
+
+        let parsed = String::from_utf8(data)
+            .map_err(|x| x.to_string())
+            .and_then(|x| json::parse(&x).map_err(|x| x.to_string()));
+
+        match parsed {
+            Ok(x)   => { documents.insert(name, x); },
+            Err(x)  => issues.push(Issue::InvalidJson { entry: name, error: x }),
+        }
+    }
+
+    issues.extend(dangling_references(&documents, &entries));
+
+    Ok(issues)
+}
+
+/// Read `entry` through to the end, reading at most one byte past `limit`. Returns the number of
+/// bytes read, along with the data itself when `keep` is set.
+fn check_entry(entry: ZipFile<File>, keep: bool, limit: u64) -> Result<(u64, Vec<u8>), Error> {
+    let mut entry   = entry.take(limit + 1);
+    let mut data    = Vec::new();
+    let size = match keep {
+        true    => entry.read_to_end(&mut data)? as u64,
+        false   => io::copy(&mut entry, &mut io::sink())?,
+    };
+
+    Ok((size, data))
+}
+
+/// Find every reference in `documents` to an object or asset that doesn't exist.
+pub fn dangling_references(
+    documents   : &BTreeMap<String, JsonValue>,
+    entries     : &BTreeSet<String>
+)
+    -> Vec<Issue>
+{
+    let mut ids = BTreeSet::new();
+    for json in documents.values() {
+        references::collect_ids(json, &mut ids);
+    }
+
+    let mut issues = vec![];
+    for (entry, json) in documents.iter() {
+        references::visit_references(json, &mut |path, reference| {
+            let exists = match &reference {
+                Reference::Id(x)    => ids.contains(x),
+                Reference::Asset(x) => entries.contains(x),
+            };
+
+            if !exists {
+                issues.push(Issue::Dangling {
+                    entry   : entry.clone(),
+                    path    : path.to_owned(),
+                    reference,
+                });
+            }
+        });
+    }
+
+    issues
+}
@@ -0,0 +1,171 @@
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+use json::JsonValue;
+use lunacy_tools::palette;
+
+use crate::Error;
+
+/// The key of a color scheme listing the scheme files it's composed of.
+pub const COMPOSES_KEY: &str = "composes";
+
+/// How an entry defined by more than one of the schemes being merged is resolved.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ConflictPolicy {
+    /// The entry of the scheme merged last replaces the others.
+    #[default]
+    LastWins,
+    /// Fail, naming the entry and the schemes defining it.
+    Error,
+}
+
+impl std::str::FromStr for ConflictPolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "last_wins" => Ok(Self::LastWins),
+            "error"     => Ok(Self::Error),
+            _           => Err(format!("unknown conflict policy `{s}`")),
+        }
+    }
+}
+
+/// Color scheme files merged in order, each after the schemes it composes.
+///
+/// A scheme lists the files it's composed of under `composes`, relative to itself, so a large set
+/// of colors can be split across files:
+/// ```json
+/// {
+///     "composes"  : ["brand.json", "neutrals.json"],
+///     "primary"   : { "link": "brand / blue" }
+/// }
+/// ```
+/// The composed schemes are merged first, in order and along with the schemes they compose in
+/// turn, and the entries of the scheme itself last, so an entry defined more than once is the one
+/// merged last unless the conflict policy is [`ConflictPolicy::Error`].
+pub struct SchemeComposer {
+    /// How entries defined by more than one scheme are resolved.
+    policy  : ConflictPolicy,
+    /// Whether schemes are checked before they're merged, and unreadable ones fail rather than
+    /// being skipped.
+    strict  : bool,
+    /// The entries merged so far.
+    merged  : JsonValue,
+    /// The scheme file each merged entry was last defined by.
+    sources : BTreeMap<String, PathBuf>,
+    /// The canonical paths of the schemes whose composed schemes are being merged, to find
+    /// schemes composing themselves.
+    loading : Vec<PathBuf>,
+}
+
+impl SchemeComposer {
+    /// Create a composer resolving conflicts with `policy`, checking each scheme when `strict`.
+    pub fn new(policy: ConflictPolicy, strict: bool) -> Self {
+        Self {
+            policy,
+            strict,
+            merged  : JsonValue::new_object(),
+            sources : BTreeMap::new(),
+            loading : Vec::new(),
+        }
+    }
+
+    /// Merge the scheme at `path` after the schemes it composes.
+    pub fn add(&mut self, path: &Path) -> Result<(), Error> {
+        let mut json = match (read(path), self.strict) {
+            (Ok(json), _)       => json,
+            (Err(e), true)      => return Err(Box::new(ComposeError::Read { path: path.to_owned(), error: e })),
+            (Err(_), false)     => return Ok(()),
+        };
+
+        let canonical = path.canonicalize()?;
+        if self.loading.contains(&canonical) {
+            return Err(Box::new(ComposeError::Cycle(path.to_owned())));
+        }
+
+        let composes = json.remove(COMPOSES_KEY);
+        let valid = composes.is_null() || composes.is_array() && composes.members().all(|x| x.is_string());
+        if !valid {
+            return Err(Box::new(ComposeError::InvalidComposes(path.to_owned())));
+        }
+        if self.strict {
+            palette::check_scheme(&json)
+                .map_err(|e| ComposeError::Read { path: path.to_owned(), error: Box::new(e) })?;
+        }
+
+        // Composed schemes are relative to the scheme composing them.
+        let dir = path.parent().unwrap_or(Path::new(""));
+        self.loading.push(canonical.clone());
+        let result = composes.members().try_for_each(|x| self.add(&dir.join(x.as_str().unwrap_or_default())));
+        self.loading.pop();
+        result?;
+
+        for (name, entry) in json.entries() {
+            if self.policy == ConflictPolicy::Error {
+                // A scheme composed twice, e.g. by two schemes sharing it, doesn't conflict with
+                // itself.
+                if let Some(first) = self.sources.get(name).filter(|x| x.canonicalize().ok().as_ref() != Some(&canonical)) {
+                    return Err(Box::new(ComposeError::Conflict {
+                        entry   : name.to_owned(),
+                        first   : first.clone(),
+                        second  : path.to_owned(),
+                    }));
+                }
+            }
+
+            self.sources.insert(name.to_owned(), path.to_owned());
+            self.merged[name] = entry.clone();
+        }
+
+        Ok(())
+    }
+
+    /// Get the merged entries.
+    pub fn finish(self) -> JsonValue {
+        self.merged
+    }
+}
+
+/// Read and parse the scheme file at `path`.
+fn read(path: &Path) -> Result<JsonValue, Error> {
+    Ok(json::parse(&std::fs::read_to_string(path)?)?)
+}
+
+/// An error composing color schemes.
+#[derive(Debug)]
+pub enum ComposeError {
+    /// A scheme couldn't be read, parsed or checked.
+    Read {
+        /// The scheme file.
+        path    : PathBuf,
+        /// The problem with it.
+        error   : Error,
+    },
+    /// A scheme composes itself, directly or through the schemes it composes.
+    Cycle(PathBuf),
+    /// A scheme's `composes` isn't an array of file names.
+    InvalidComposes(PathBuf),
+    /// An entry is defined by two schemes and the conflict policy is to fail.
+    Conflict {
+        /// The name of the entry.
+        entry   : String,
+        /// The scheme merged first defining it.
+        first   : PathBuf,
+        /// The scheme merged next defining it.
+        second  : PathBuf,
+    },
+}
+
+impl std::fmt::Display for ComposeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Self::Read { path, error }              => write!(f, "{}: {error}", path.display()),
+            Self::Cycle(x)                          => write!(f, "{} composes itself", x.display()),
+            Self::InvalidComposes(x)                => write!(f, "expected `{COMPOSES_KEY}` in {} to be an array of scheme files", x.display()),
+            Self::Conflict { entry, first, second } => write!(f, "entry `{entry}` is defined by both {} and {}", first.display(), second.display()),
+        }
+    }
+}
+
+impl std::error::Error for ComposeError { }
@@ -0,0 +1,334 @@
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+use lunacy_tools::palette::{RampKind, RampSpace};
+use toml::{Table, Value};
+
+use crate::cloud::CloudOptions;
+use crate::commands::export::Artifact;
+use crate::commands::OutputFormat;
+use crate::compose::ConflictPolicy;
+use crate::document::BackupPolicy;
+use crate::hooks::Hooks;
+use crate::Error;
+
+/// The name of the configuration file a project keeps in its root directory.
+const PROJECT_CONFIG: &str = ".lunacy-tools.toml";
+
+/// The keys only the user configuration can set, as a project configuration comes with the files
+/// it sits next to and shouldn't get to run commands or receive documents on its own.
+const USER_ONLY_KEYS: &[&str] = &["pre_open", "post_commit", "webhook", "cloud_api"];
+
+/// Defaults for the program arguments, read from a configuration file. Arguments given on the
+/// command line take precedence over anything set here.
+///
+/// An example configuration:
+/// ```toml
+/// group           = "brand"
+/// stops           = [50, 100, 200, 300, 400, 500, 600, 700, 800, 900]
+/// ramp            = "tints"
+/// ramp_space      = "linear"
+/// max_lightness   = 0.95
+/// output_format   = "json"
+/// backup          = "first"
+/// on_conflict     = "error"
+/// pre_open        = "git pull --ff-only"
+/// post_commit     = ["git add"]
+/// webhook         = "https://hooks.slack.com/services/..."
+/// cloud_api       = "https://cloud.example.com/api"
+///
+/// [[profiles.web]]
+/// target          = "json"
+/// output          = "dist/theme.json"
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct Config {
+    /// The group containing the colors to modify.
+    pub group           : Option<String>,
+    /// The stops to generate for each color.
+    pub stops           : Option<Vec<u32>>,
+    /// The names of the tints at each stop.
+    pub stop_labels     : Option<Vec<String>>,
+    /// The format generated colors are named with.
+    pub name_format     : Option<String>,
+    /// The separator between the levels of variable names.
+    pub separator       : Option<String>,
+    /// How the ramp of each color is generated.
+    pub ramp            : Option<RampKind>,
+    /// The color space tints are interpolated in.
+    pub ramp_space      : Option<RampSpace>,
+    /// The furthest tints move towards white.
+    pub max_lightness   : Option<f64>,
+    /// The furthest tints move towards black.
+    pub max_darkness    : Option<f64>,
+    /// The format commands print their output in.
+    pub output_format   : Option<OutputFormat>,
+    /// When to back up a document before changing it.
+    pub backup          : Option<BackupPolicy>,
+    /// How entries defined by more than one color scheme are resolved.
+    pub on_conflict     : Option<ConflictPolicy>,
+    /// The shell commands run before a document is extracted and after changes are committed.
+    pub hooks           : Hooks,
+    /// The api and token used to fetch and push cloud documents.
+    pub cloud           : CloudOptions,
+    /// The files each export profile writes, by name of profile.
+    pub profiles        : BTreeMap<String, Vec<Artifact>>,
+}
+
+impl Config {
+    /// Load the user configuration from `~/.config/lunacy-tools/config.toml`, with the settings
+    /// of the nearest project configuration, a `.lunacy-tools.toml` file in the current
+    /// directory or any above it, taking precedence.
+    pub fn load() -> Result<Self, Error> {
+        let mut config = match user_config_path() {
+            Some(path) if path.is_file()    => Self::read(&path, false)?,
+            _                               => Self::default(),
+        };
+
+        if let Some(path) = project_config_path(&std::env::current_dir()?) {
+            config = config.overlay(Self::read(&path, true)?);
+        }
+
+        Ok(config)
+    }
+
+    /// Read the configuration file at `path`, refusing the keys only the user configuration can
+    /// set if it's a `project` configuration.
+    pub fn read(path: &Path, project: bool) -> Result<Self, Error> {
+        let table = std::fs::read_to_string(path)?
+            .parse::<Table>()?;
+
+        // The files of export profiles are relative to the configuration file.
+        let dir = path.parent().unwrap_or(Path::new(""));
+
+        let mut config = Self::default();
+        for (key, value) in &table {
+            if project && USER_ONLY_KEYS.contains(&key.as_str()) {
+                return Err(Box::new(ConfigError::UserOnly(key.to_owned())));
+            }
+
+            match key.as_str() {
+                "group"         => config.group         = Some(string(key, value)?.to_owned()),
+                "stops"         => config.stops         = Some(stops(key, value)?),
+                "stop_labels"   => config.stop_labels   = Some(labels(key, value)?),
+                "name_format"   => config.name_format   = Some(string(key, value)?.to_owned()),
+                "separator"     => config.separator     = Some(string(key, value)?.to_owned()),
+                "ramp"          => config.ramp          = Some(parse(key, value)?),
+                "ramp_space"    => config.ramp_space    = Some(parse(key, value)?),
+                "max_lightness" => config.max_lightness = Some(fraction(key, value)?),
+                "max_darkness"  => config.max_darkness  = Some(fraction(key, value)?),
+                "output_format" => config.output_format = Some(parse(key, value)?),
+                "backup"        => config.backup        = Some(parse(key, value)?),
+                "on_conflict"   => config.on_conflict   = Some(parse(key, value)?),
+                "pre_open"      => config.hooks.pre_open    = commands(key, value)?,
+                "post_commit"   => config.hooks.post_commit = commands(key, value)?,
+                "webhook"       => config.hooks.webhook     = Some(string(key, value)?.to_owned()),
+                "cloud_api"     => config.cloud.api         = Some(string(key, value)?.to_owned()),
+                "cloud_token"   => config.cloud.token       = Some(string(key, value)?.to_owned()),
+                "profiles"      => config.profiles          = profiles(key, value, dir)?,
+                _               => return Err(Box::new(ConfigError::UnknownKey(key.to_owned()))),
+            }
+        }
+
+        Ok(config)
+    }
+
+    /// Combine the configuration with `other`, with the settings of `other` taking precedence.
+    /// The hooks, webhook and cloud api are only taken from the configuration itself, and the
+    /// export profiles of `other` replace those of the same name.
+    fn overlay(self, other: Self) -> Self {
+        let mut profiles = self.profiles;
+        profiles.extend(other.profiles);
+
+        Self {
+            group           : other.group.or(self.group),
+            stops           : other.stops.or(self.stops),
+            stop_labels     : other.stop_labels.or(self.stop_labels),
+            name_format     : other.name_format.or(self.name_format),
+            separator       : other.separator.or(self.separator),
+            ramp            : other.ramp.or(self.ramp),
+            ramp_space      : other.ramp_space.or(self.ramp_space),
+            max_lightness   : other.max_lightness.or(self.max_lightness),
+            max_darkness    : other.max_darkness.or(self.max_darkness),
+            output_format   : other.output_format.or(self.output_format),
+            backup          : other.backup.or(self.backup),
+            on_conflict     : other.on_conflict.or(self.on_conflict),
+            hooks           : Hooks {
+                pre_open    : self.hooks.pre_open,
+                post_commit : self.hooks.post_commit,
+                webhook     : self.hooks.webhook,
+            },
+            cloud           : CloudOptions {
+                api         : self.cloud.api,
+                token       : other.cloud.token.or(self.cloud.token),
+            },
+            profiles,
+        }
+    }
+}
+
+/// Find the project configuration file nearest to `dir`, searching it and then each directory
+/// above it.
+fn project_config_path(dir: &Path) -> Option<PathBuf> {
+    dir.ancestors()
+        .map(|x| x.join(PROJECT_CONFIG))
+        .find(|x| x.is_file())
+}
+
+/// Get the path of the user configuration file, if the home directory is known.
+fn user_config_path() -> Option<PathBuf> {
+    let config_dir = std::env::var_os("XDG_CONFIG_HOME")
+        .filter(|x| !x.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| {
+            std::env::var_os("HOME")
+                .or_else(|| std::env::var_os("USERPROFILE"))
+                .map(|x| PathBuf::from(x).join(".config"))
+        })?;
+
+    Some(config_dir.join("lunacy-tools").join("config.toml"))
+}
+
+/// Read the string value of `key`.
+fn string<'a>(key: &str, value: &'a Value) -> Result<&'a str, ConfigError> {
+    value.as_str()
+        .ok_or_else(|| ConfigError::InvalidValue { key: key.to_owned(), value: value.to_string() })
+}
+
+/// Read the value of `key` from its string form.
+fn parse<T: std::str::FromStr>(key: &str, value: &Value) -> Result<T, ConfigError> {
+    string(key, value)?
+        .parse()
+        .map_err(|_| ConfigError::InvalidValue { key: key.to_owned(), value: value.to_string() })
+}
+
+/// Read a fraction from 0 to 1 from `key`.
+fn fraction(key: &str, value: &Value) -> Result<f64, ConfigError> {
+    value.as_float()
+        .or_else(|| value.as_integer().map(|x| x as f64))
+        .filter(|x| (0.0..=1.0).contains(x))
+        .ok_or_else(|| ConfigError::InvalidValue { key: key.to_owned(), value: value.to_string() })
+}
+
+/// Read a command, or list of commands, from `key`.
+fn commands(key: &str, value: &Value) -> Result<Vec<String>, ConfigError> {
+    if let Some(command) = value.as_str() {
+        return Ok(vec![command.to_owned()]);
+    }
+
+    value.as_array()
+        .and_then(|x| x.iter().map(|x| x.as_str().map(|x| x.to_owned())).collect())
+        .ok_or_else(|| ConfigError::InvalidValue { key: key.to_owned(), value: value.to_string() })
+}
+
+/// Read a list of stops from `key`.
+fn stops(key: &str, value: &Value) -> Result<Vec<u32>, ConfigError> {
+    let invalid = || ConfigError::InvalidValue { key: key.to_owned(), value: value.to_string() };
+
+    let stops = value.as_array()
+        .ok_or_else(invalid)?
+        .iter()
+        .map(|x| x.as_integer().and_then(|x| u32::try_from(x).ok()))
+        .collect::<Option<Vec<_>>>()
+        .ok_or_else(invalid)?;
+
+    match stops.is_empty() {
+        true    => Err(invalid()),
+        false   => Ok(stops),
+    }
+}
+
+/// Read the export profiles from `key`, a table of the files each profile writes, with their
+/// outputs relative to the directory `dir`.
+fn profiles(key: &str, value: &Value, dir: &Path) -> Result<BTreeMap<String, Vec<Artifact>>, ConfigError> {
+    let Some(table) = value.as_table() else {
+        return Err(ConfigError::InvalidValue { key: key.to_owned(), value: value.to_string() });
+    };
+
+    let mut profiles = BTreeMap::new();
+    for (name, artifacts) in table {
+        let invalid = || ConfigError::InvalidValue { key: format!("{key}.{name}"), value: artifacts.to_string() };
+
+        let artifacts = artifacts.as_array()
+            .filter(|x| !x.is_empty())
+            .ok_or_else(invalid)?
+            .iter()
+            .map(|x| artifact(x, dir))
+            .collect::<Option<Vec<_>>>()
+            .ok_or_else(invalid)?;
+
+        profiles.insert(name.to_owned(), artifacts);
+    }
+
+    Ok(profiles)
+}
+
+/// Read a file of an export profile, a table of its `target`, `output` and optionally `prefix`
+/// and `shape`, with its output relative to the directory `dir`.
+fn artifact(value: &Value, dir: &Path) -> Option<Artifact> {
+    let table = value.as_table()?;
+    if table.keys().any(|x| !["target", "output", "prefix", "shape"].contains(&x.as_str())) {
+        return None;
+    }
+
+    let prefix = match table.get("prefix") {
+        Some(prefix)    => Some(prefix.as_str()?.to_owned()),
+        None            => None,
+    };
+    let shape = match table.get("shape") {
+        Some(shape)     => Some(shape.as_str()?.parse().ok()?),
+        None            => None,
+    };
+
+    Some(Artifact {
+        target  : table.get("target")?.as_str()?.parse().ok()?,
+        output  : dir.join(table.get("output")?.as_str()?),
+        prefix,
+        shape,
+    })
+}
+
+/// Read a list of stop labels from `key`.
+fn labels(key: &str, value: &Value) -> Result<Vec<String>, ConfigError> {
+    let invalid = || ConfigError::InvalidValue { key: key.to_owned(), value: value.to_string() };
+
+    let labels = value.as_array()
+        .ok_or_else(invalid)?
+        .iter()
+        .map(|x| x.as_str().map(|x| x.to_owned()))
+        .collect::<Option<Vec<_>>>()
+        .ok_or_else(invalid)?;
+
+    match labels.is_empty() {
+        true    => Err(invalid()),
+        false   => Ok(labels),
+    }
+}
+
+/// An error raised when a configuration file can't be understood.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ConfigError {
+    /// The configuration sets a key the tool doesn't know.
+    UnknownKey(String),
+    /// A project configuration sets a key only the user configuration can.
+    UserOnly(String),
+    /// A key is set to a value it can't take.
+    InvalidValue {
+        /// The key being set.
+        key     : String,
+        /// The value it was set to.
+        value   : String,
+    },
+}
+
+impl std::fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Self::UnknownKey(x)                 => write!(f, "unknown configuration key `{x}`"),
+            Self::UserOnly(x)                   => write!(f, "`{x}` can only be set in the user configuration"),
+            Self::InvalidValue { key, value }   => write!(f, "invalid value {value} for configuration key `{key}`"),
+        }
+    }
+}
+
+impl std::error::Error for ConfigError { }
@@ -0,0 +1,697 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::Path;
+
+use json::{array, object, JsonValue};
+use lunacy_tools::schema::{ColorVariable, DocumentJson};
+use lunacy_tools::sketch::{self, Swatch, SwatchContainer};
+
+use crate::commands::pages;
+use crate::document::LunacyDocument;
+use crate::model::{self, layer_type, FontRef, Frame, LAYERS_KEY, PAGES_DIR};
+use crate::render::{self, Paint};
+use crate::Error;
+
+/// The Sketch file format version converted documents are written in.
+const SKETCH_VERSION: u32 = 146;
+
+/// The oldest Sketch file format version that can open converted documents.
+const SKETCH_COMPATIBILITY_VERSION: u32 = 99;
+
+/// The key the fills and borders of converted Lunacy layers refer to color variables by.
+const VARIABLE_KEY: &str = "colorVariableId";
+
+/// The group layer type, which both formats share.
+const GROUP: &str = "group";
+
+/// The layer types both formats share, the only ones converted.
+const SHARED_TYPES: &[&str] = &[layer_type::ARTBOARD, GROUP, layer_type::RECTANGLE, layer_type::OVAL, layer_type::TEXT];
+
+/// The font weights by the name they're given in PostScript font names, which Sketch refers to
+/// fonts by.
+const WEIGHTS: &[(&str, u32)] = &[
+    ("Thin",        100),
+    ("ExtraLight",  200),
+    ("Light",       300),
+    ("Regular",     400),
+    ("Medium",      500),
+    ("SemiBold",    600),
+    ("Bold",        700),
+    ("ExtraBold",   800),
+    ("Black",       900),
+];
+
+/// The format of a document.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Format {
+    /// A Lunacy `.free` document.
+    Lunacy,
+    /// A Sketch `.sketch` document.
+    Sketch,
+}
+
+impl Format {
+    /// Get the format of the document at `path` from its extension.
+    pub fn from_path(path: &Path) -> Option<Self> {
+        match path.extension().and_then(|x| x.to_str()) {
+            Some("free")    => Some(Self::Lunacy),
+            Some("sketch")  => Some(Self::Sketch),
+            _               => None,
+        }
+    }
+
+    /// Get the format of the opened document `doc`.
+    pub fn of(doc: &LunacyDocument) -> Self {
+        match doc.is_sketch() {
+            true    => Self::Sketch,
+            false   => Self::Lunacy,
+        }
+    }
+}
+
+impl std::fmt::Display for Format {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Self::Lunacy    => write!(f, "Lunacy"),
+            Self::Sketch    => write!(f, "Sketch"),
+        }
+    }
+}
+
+/// A document converted to another format.
+#[derive(Debug, Default)]
+pub struct Conversion {
+    /// The json entries of the converted document, in archive order.
+    pub entries : Vec<(String, JsonValue)>,
+    /// The number of pages converted.
+    pub pages   : usize,
+    /// The number of layers converted.
+    pub layers  : usize,
+    /// The number of color variables converted.
+    pub colors  : usize,
+    /// The number of layers of each type left out because the formats don't share them.
+    pub skipped : BTreeMap<String, usize>,
+}
+
+/// Convert `doc` to `format`.
+///
+/// Only the features both formats share are converted, the color variables of a Lunacy
+/// document and the shared swatches of a Sketch document, and pages of artboards, groups,
+/// rectangles, ovals and text with their frames, solid fills and borders, opacity and fonts.
+/// Anything else is left out, with the layers left out counted by type.
+pub fn convert(doc: &LunacyDocument, format: Format) -> Result<Conversion, Error> {
+    let variables = doc.load_color_variables()?;
+    let document  = doc.load::<DocumentJson>("document.json")?;
+
+    let mut converter = Converter {
+        conversion  : Conversion { colors: variables.len(), ..Default::default() },
+        variables   : variables.iter().map(|x| x.id.clone()).collect(),
+    };
+
+    let mut pages = vec![];
+    for entry in pages::ordered_entries(doc, &document) {
+        let page = doc.load_json(&entry)?;
+        let id   = Path::new(&entry).file_stem().and_then(|x| x.to_str()).unwrap_or(&entry).to_owned();
+        pages.push((id, page));
+    }
+
+    converter.conversion.pages = pages.len();
+    match format {
+        Format::Sketch => {
+            let swatches = variables.iter()
+                .map(Swatch::from_variable)
+                .collect::<Result<Vec<_>, _>>()?;
+
+            converter.write_sketch(pages, swatches)?;
+        }
+        Format::Lunacy => {
+            converter.write_lunacy(pages, &variables)?;
+        }
+    }
+
+    Ok(converter.conversion)
+}
+
+/// Converts the pages of a document between formats, counting what is converted.
+struct Converter {
+    /// The document converted so far.
+    conversion  : Conversion,
+    /// The ids of the color variables in the document, as Lunacy writes them.
+    variables   : BTreeSet<String>,
+}
+
+impl Converter {
+    /// Convert the Lunacy `pages`, by page id, and `swatches` to the entries of a Sketch
+    /// document.
+    fn write_sketch(&mut self, pages: Vec<(String, JsonValue)>, swatches: Vec<Swatch>) -> Result<(), Error> {
+        let mut refs        = array![];
+        let mut meta_pages  = JsonValue::new_object();
+        for (id, page) in pages {
+            let id      = sketch::sketch_id(&id);
+            let name    = page["name"].as_str().unwrap_or(&id).to_owned();
+
+            let mut json = sketch_layer("page", &id, &name, Frame::default(), true, self.sketch_style(&page, &id));
+            json["hasClickThrough"]     = true.into();
+            json["horizontalRulerData"] = object! { "_class": "rulerData", "base": 0, "guides": [] };
+            json["verticalRulerData"]   = object! { "_class": "rulerData", "base": 0, "guides": [] };
+            json[LAYERS_KEY]            = self.sketch_layers(&page, &id).into();
+
+            let mut artboards = JsonValue::new_object();
+            for layer in json[LAYERS_KEY].members().filter(|x| x["_class"] == "artboard") {
+                artboards[layer["do_objectID"].as_str().unwrap_or_default()] = object! { "name": layer["name"].clone() };
+            }
+
+            meta_pages[id.as_str()] = object! { "name": name.as_str(), "artboards": artboards };
+            refs.push(object! {
+                "_class"    : "MSJSONFileReference",
+                "_ref_class": "MSImmutablePage",
+                "_ref"      : format!("{PAGES_DIR}{id}"),
+            })?;
+
+            self.conversion.entries.push((format!("{PAGES_DIR}{id}.json"), json));
+        }
+
+        let swatches = SwatchContainer { objects: swatches, ..Default::default() };
+        let document = object! {
+            "_class"            : "document",
+            "do_objectID"       : sketch::sketch_id("document"),
+            "assets"            : object! {
+                "_class"        : "assetCollection",
+                "do_objectID"   : sketch::sketch_id("assets"),
+                "colorAssets"   : [],
+                "gradientAssets": [],
+                "images"        : [],
+                "colors"        : [],
+                "gradients"     : [],
+                "exportPresets" : [],
+            },
+            "colorSpace"        : 0,
+            "currentPageIndex"  : 0,
+            "foreignLayerStyles": [],
+            "foreignSymbols"    : [],
+            "foreignTextStyles" : [],
+            "foreignSwatches"   : [],
+            "layerStyles"       : object! { "_class": "sharedStyleContainer", "do_objectID": sketch::sketch_id("layerStyles"), "objects": [] },
+            "layerSymbols"      : object! { "_class": "symbolContainer", "do_objectID": sketch::sketch_id("layerSymbols"), "objects": [] },
+            "layerTextStyles"   : object! { "_class": "sharedTextStyleContainer", "do_objectID": sketch::sketch_id("layerTextStyles"), "objects": [] },
+            "sharedSwatches"    : json::parse(&serde_json::to_string(&swatches)?)?,
+            "pages"             : refs,
+        };
+
+        let app     = "com.bohemiancoding.sketch3";
+        let created = object! {
+            "app"                   : app,
+            "appVersion"            : "98",
+            "build"                 : 0,
+            "commit"                : "",
+            "compatibilityVersion"  : SKETCH_COMPATIBILITY_VERSION,
+            "variant"               : "NONAPPSTORE",
+            "version"               : SKETCH_VERSION,
+        };
+
+        let mut meta = created.clone();
+        meta["autosaved"]           = 0.into();
+        meta["created"]             = created;
+        meta["pagesAndArtboards"]   = meta_pages;
+        meta["saveHistory"]         = array![];
+
+        self.conversion.entries.insert(0, ("document.json".to_owned(), document));
+        self.conversion.entries.push(("meta.json".to_owned(), meta));
+        self.conversion.entries.push(("user.json".to_owned(), JsonValue::new_object()));
+
+        Ok(())
+    }
+
+    /// Convert the Sketch `pages`, by page id, and `variables` to the entries of a Lunacy
+    /// document.
+    fn write_lunacy(&mut self, pages: Vec<(String, JsonValue)>, variables: &[ColorVariable]) -> Result<(), Error> {
+        let mut refs = array![];
+        for (id, page) in pages {
+            let id      = sketch::lunacy_id(model::layer_id(&page).unwrap_or(&id));
+            let json    = object! {
+                "name"      : page["name"].as_str().unwrap_or(&id),
+                "layers"    : self.lunacy_layers(&page),
+            };
+
+            refs.push(id.as_str())?;
+            self.conversion.entries.push((format!("{PAGES_DIR}{id}.json"), json));
+        }
+
+        let document = object! {
+            "colorVariables"    : json::parse(&serde_json::to_string(variables)?)?,
+            "pages"             : refs,
+        };
+
+        self.conversion.entries.insert(0, ("document.json".to_owned(), document));
+
+        Ok(())
+    }
+
+    /// Convert the child layers of the Lunacy layer or page `parent`, whose Sketch id is
+    /// `parent_id`, to Sketch layers.
+    fn sketch_layers(&mut self, parent: &JsonValue, parent_id: &str) -> Vec<JsonValue> {
+        let mut layers = vec![];
+        for (i, layer) in parent[LAYERS_KEY].members().enumerate() {
+            let kind = model::layer_type(layer);
+            if !SHARED_TYPES.contains(&kind.as_str()) {
+                *self.conversion.skipped.entry(kind).or_default() += 1;
+                continue;
+            }
+
+            // Layers without an id are given one derived from their place in the document.
+            let id = match model::layer_id(layer) {
+                Some(id)    => sketch::sketch_id(id),
+                None        => sketch::sketch_id(&format!("{parent_id} / {i}")),
+            };
+
+            let name        = layer["name"].as_str().unwrap_or(&kind).to_owned();
+            let frame       = Frame::from_layer(layer);
+            let mut json    = sketch_layer(&kind, &id, &name, frame, model::is_visible(layer), self.sketch_style(layer, &id));
+
+            match kind.as_str() {
+                layer_type::ARTBOARD => {
+                    let background = render::first_enabled(layer, "fills").and_then(render::parse_color);
+
+                    json["style"]["fills"]          = array![];
+                    json["hasBackgroundColor"]      = background.is_some().into();
+                    json["backgroundColor"]         = sketch_color(background.unwrap_or(Paint { rgb: 0xffffff, opacity: 1.0 }), None);
+                    json["includeBackgroundColorInExport"] = true.into();
+                    json["hasClickThrough"]         = false.into();
+                    json["resizesContent"]          = false.into();
+                    json["horizontalRulerData"]     = object! { "_class": "rulerData", "base": 0, "guides": [] };
+                    json["verticalRulerData"]       = object! { "_class": "rulerData", "base": 0, "guides": [] };
+                }
+                GROUP => {
+                    json["hasClickThrough"]         = false.into();
+                    json["groupLayout"]             = object! { "_class": "MSImmutableFreeformGroupLayout" };
+                }
+                layer_type::RECTANGLE => {
+                    let radius = layer["cornerRadius"].as_f64()
+                        .or(layer["radius"].as_f64())
+                        .unwrap_or_default();
+
+                    json["fixedRadius"]             = radius.into();
+                    json["hasConvertedToNewRoundCorners"] = true.into();
+                    json["needsConvertionToNewRoundCorners"] = false.into();
+                    json["edited"]                  = false.into();
+                    json["isClosed"]                = true.into();
+                    json["pointRadiusBehaviour"]    = 1.into();
+                    json["points"]                  = rectangle_points(radius);
+                }
+                layer_type::OVAL => {
+                    json["edited"]                  = false.into();
+                    json["isClosed"]                = true.into();
+                    json["pointRadiusBehaviour"]    = 1.into();
+                    json["points"]                  = oval_points();
+                }
+                _ => {
+                    let text = model::text_key(layer)
+                        .and_then(|x| layer[x].as_str())
+                        .unwrap_or_default();
+
+                    let mut font = None;
+                    model::visit_fonts(layer, &mut |x| { font.get_or_insert(x); });
+
+                    let size    = layer["fontSize"].as_f64().or(layer["style"]["fontSize"].as_f64()).unwrap_or(14.0);
+                    let color   = render::parse_color(&layer["textColor"])
+                        .or_else(|| render::first_enabled(layer, "fills").and_then(render::parse_color))
+                        .unwrap_or(Paint { rgb: 0, opacity: 1.0 });
+
+                    let attributes = object! {
+                        "MSAttributedStringFontAttribute"   : object! {
+                            "_class"        : "fontDescriptor",
+                            "attributes"    : object! {
+                                "name"      : font.map_or("Helvetica".to_owned(), |x| postscript_name(&x)),
+                                "size"      : size,
+                            },
+                        },
+                        "MSAttributedStringColorAttribute"  : sketch_color(color, None),
+                        "paragraphStyle"                    : object! { "_class": "paragraphStyle", "alignment": 0 },
+                    };
+
+                    json["style"]["fills"]          = array![];
+                    json["style"]["textStyle"]      = object! {
+                        "_class"            : "textStyle",
+                        "encodedAttributes" : attributes.clone(),
+                        "verticalAlignment" : 0,
+                    };
+                    let range = object! {
+                        "_class"        : "stringAttribute",
+                        "location"      : 0,
+                        "length"        : text.encode_utf16().count(),
+                        "attributes"    : attributes,
+                    };
+
+                    json["attributedString"]        = object! {
+                        "_class"        : "attributedString",
+                        "string"        : text,
+                        "attributes"    : array![range],
+                    };
+                    json["automaticallyDrawOnUnderlyingPath"] = false.into();
+                    json["dontSynchroniseWithSymbol"] = false.into();
+                    json["lineSpacingBehaviour"]    = 2.into();
+                    json["textBehaviour"]           = 2.into();
+                    json["glyphBounds"]             = format!("{{{{0, 0}}, {{{}, {}}}}}", frame.width, frame.height).into();
+                }
+            }
+
+            if matches!(kind.as_str(), layer_type::ARTBOARD | GROUP) {
+                json[LAYERS_KEY] = self.sketch_layers(layer, &id).into();
+            }
+
+            self.conversion.layers += 1;
+            layers.push(json);
+        }
+
+        layers
+    }
+
+    /// Convert the style of the Lunacy `layer`, whose Sketch id is `id`, to a Sketch style.
+    fn sketch_style(&self, layer: &JsonValue, id: &str) -> JsonValue {
+        let opacity = layer["opacity"].as_f64()
+            .or(layer["style"]["opacity"].as_f64())
+            .unwrap_or(1.0);
+
+        let mut style = object! {
+            "_class"            : "style",
+            "do_objectID"       : sketch::sketch_id(&format!("{id} / style")),
+            "borders"           : [],
+            "fills"             : [],
+            "endMarkerType"     : 0,
+            "miterLimit"        : 10,
+            "startMarkerType"   : 0,
+            "windingRule"       : 1,
+            "contextSettings"   : object! { "_class": "graphicsContextSettings", "blendMode": 0, "opacity": opacity },
+        };
+
+        for (key, class) in [("fills", "fill"), ("borders", "border")] {
+            let list = match layer[key].is_array() {
+                true    => &layer[key],
+                false   => &layer["style"][key],
+            };
+
+            for paint in list.members() {
+                let Some(color) = render::parse_color(&paint["color"]) else {
+                    continue;
+                };
+
+                let color   = Paint { opacity: color.opacity * paint["opacity"].as_f64().unwrap_or(1.0), ..color };
+                let enabled = paint["enabled"].as_bool().or(paint["isEnabled"].as_bool()).unwrap_or(true);
+                let swatch  = paint.entries()
+                    .filter(|(key, _)| key.ends_with("Id"))
+                    .find_map(|(_, value)| value.as_str().filter(|x| self.variables.contains(*x)));
+
+                let mut json = object! {
+                    "_class"            : class,
+                    "isEnabled"         : enabled,
+                    "fillType"          : 0,
+                    "color"             : sketch_color(color, swatch),
+                    "contextSettings"   : object! { "_class": "graphicsContextSettings", "blendMode": 0, "opacity": 1 },
+                };
+
+                match class {
+                    "fill" => {
+                        json["noiseIndex"]          = 0.into();
+                        json["noiseIntensity"]      = 0.into();
+                        json["patternFillType"]     = 1.into();
+                        json["patternTileScale"]    = 1.into();
+                    }
+                    _ => {
+                        json["position"]            = 1.into();
+                        json["thickness"]           = paint["thickness"].as_f64().or(paint["width"].as_f64()).unwrap_or(1.0).into();
+                    }
+                }
+
+                let _ = style[key].push(json);
+            }
+        }
+
+        style
+    }
+
+    /// Convert the child layers of the Sketch layer or page `parent` to Lunacy layers.
+    fn lunacy_layers(&mut self, parent: &JsonValue) -> Vec<JsonValue> {
+        let mut layers = vec![];
+        for layer in parent[LAYERS_KEY].members() {
+            // Shape groups hold the shapes of a boolean operation, which are kept as a group.
+            let kind = match model::layer_type(layer).as_str() {
+                "shapegroup"    => GROUP.to_owned(),
+                kind            => kind.to_owned(),
+            };
+
+            if !SHARED_TYPES.contains(&kind.as_str()) {
+                *self.conversion.skipped.entry(kind).or_default() += 1;
+                continue;
+            }
+
+            let frame       = Frame::from_layer(layer);
+            let mut json    = object! {
+                "id"        : sketch::lunacy_id(model::layer_id(layer).unwrap_or_default()),
+                "name"      : layer["name"].as_str().unwrap_or(&kind),
+                "type"      : kind.as_str(),
+                "x"         : frame.x,
+                "y"         : frame.y,
+                "width"     : frame.width,
+                "height"    : frame.height,
+            };
+
+            if !model::is_visible(layer) {
+                json["visible"] = false.into();
+            }
+
+            let opacity = layer["style"]["contextSettings"]["opacity"].as_f64().unwrap_or(1.0);
+            if opacity < 1.0 {
+                json["opacity"] = opacity.into();
+            }
+
+            for key in ["fills", "borders"] {
+                let paints = layer["style"][key].members()
+                    .filter(|x| x["fillType"].as_u32().unwrap_or_default() == 0)
+                    .filter_map(|x| {
+                        let mut json = lunacy_paint(&x["color"])?;
+                        if x["isEnabled"].as_bool() == Some(false) {
+                            json["enabled"] = false.into();
+                        }
+                        if key == "borders" {
+                            json["thickness"] = x["thickness"].as_f64().unwrap_or(1.0).into();
+                        }
+
+                        Some(json)
+                    })
+                    .collect::<Vec<_>>();
+
+                if !paints.is_empty() {
+                    json[key] = paints.into();
+                }
+            }
+
+            match kind.as_str() {
+                layer_type::ARTBOARD if layer["hasBackgroundColor"].as_bool() == Some(true) => {
+                    if let Some(paint) = lunacy_paint(&layer["backgroundColor"]) {
+                        json["fills"] = array![paint];
+                    }
+                }
+                layer_type::RECTANGLE => {
+                    let radius = layer["fixedRadius"].as_f64().unwrap_or_default();
+                    if radius > 0.0 {
+                        json["cornerRadius"] = radius.into();
+                    }
+                }
+                layer_type::TEXT => {
+                    let string      = &layer["attributedString"];
+                    let attributes  = match string["attributes"][0]["attributes"].is_object() {
+                        true    => &string["attributes"][0]["attributes"],
+                        false   => &layer["style"]["textStyle"]["encodedAttributes"],
+                    };
+
+                    let descriptor = &attributes["MSAttributedStringFontAttribute"]["attributes"];
+                    json["text"] = string["string"].as_str().unwrap_or_default().into();
+                    if let Some(name) = descriptor["name"].as_str() {
+                        font_from_postscript_name(name).write_json(&mut json);
+                    }
+                    if let Some(size) = descriptor["size"].as_f64() {
+                        json["fontSize"] = size.into();
+                    }
+                    if let Some(color) = sketch_paint(&attributes["MSAttributedStringColorAttribute"]) {
+                        json["textColor"] = hex(color, true).into();
+                    }
+                }
+                _ => {}
+            }
+
+            if kind == GROUP || kind == layer_type::ARTBOARD {
+                json[LAYERS_KEY] = self.lunacy_layers(layer).into();
+            }
+
+            self.conversion.layers += 1;
+            layers.push(json);
+        }
+
+        layers
+    }
+}
+
+/// Create a Sketch layer of `class` with the fields every layer has.
+fn sketch_layer(class: &str, id: &str, name: &str, frame: Frame, visible: bool, style: JsonValue) -> JsonValue {
+    object! {
+        "_class"                : class,
+        "do_objectID"           : id,
+        "booleanOperation"      : -1,
+        "exportOptions"         : object! {
+            "_class"            : "exportOptions",
+            "exportFormats"     : [],
+            "includedLayerIds"  : [],
+            "layerOptions"      : 0,
+            "shouldTrim"        : false,
+        },
+        "frame"                 : object! {
+            "_class"                : "rect",
+            "constrainProportions"  : false,
+            "height"                : frame.height,
+            "width"                 : frame.width,
+            "x"                     : frame.x,
+            "y"                     : frame.y,
+        },
+        "isFixedToViewport"     : false,
+        "isFlippedHorizontal"   : false,
+        "isFlippedVertical"     : false,
+        "isLocked"              : false,
+        "isVisible"             : visible,
+        "layerListExpandedType" : 0,
+        "name"                  : name,
+        "nameIsFixed"           : false,
+        "resizingConstraint"    : 63,
+        "resizingType"          : 0,
+        "rotation"              : 0,
+        "shouldBreakMaskChain"  : false,
+        "clippingMaskMode"      : 0,
+        "hasClippingMask"       : false,
+        "style"                 : style,
+    }
+}
+
+/// Get the points of a Sketch rectangle with corners rounded by `radius`.
+fn rectangle_points(radius: f64) -> JsonValue {
+    ["{0, 0}", "{1, 0}", "{1, 1}", "{0, 1}"]
+        .into_iter()
+        .map(|point| object! {
+            "_class"        : "curvePoint",
+            "cornerRadius"  : radius,
+            "cornerStyle"   : 0,
+            "curveFrom"     : point,
+            "curveMode"     : 1,
+            "curveTo"       : point,
+            "hasCurveFrom"  : false,
+            "hasCurveTo"    : false,
+            "point"         : point,
+        })
+        .collect::<Vec<_>>()
+        .into()
+}
+
+/// Get the points of a Sketch oval, the four points on its frame with the bezier handles that
+/// approximate an ellipse between them.
+fn oval_points() -> JsonValue {
+    const HANDLE: &str = "0.22385762510000001";
+    const OPPOSITE: &str = "0.77614237490000004";
+
+    [
+        ("{0.5, 0}", format!("{{{OPPOSITE}, 0}}"), format!("{{{HANDLE}, 0}}")),
+        ("{1, 0.5}", format!("{{1, {OPPOSITE}}}"), format!("{{1, {HANDLE}}}")),
+        ("{0.5, 1}", format!("{{{HANDLE}, 1}}"), format!("{{{OPPOSITE}, 1}}")),
+        ("{0, 0.5}", format!("{{0, {HANDLE}}}"), format!("{{0, {OPPOSITE}}}")),
+    ]
+        .into_iter()
+        .map(|(point, from, to)| object! {
+            "_class"        : "curvePoint",
+            "cornerRadius"  : 0,
+            "cornerStyle"   : 0,
+            "curveFrom"     : from,
+            "curveMode"     : 2,
+            "curveTo"       : to,
+            "hasCurveFrom"  : true,
+            "hasCurveTo"    : true,
+            "point"         : point,
+        })
+        .collect::<Vec<_>>()
+        .into()
+}
+
+/// Convert `paint` to a Sketch color, referring to the color variable `swatch` by its Lunacy id
+/// if it comes from one.
+fn sketch_color(paint: Paint, swatch: Option<&str>) -> JsonValue {
+    let channel     = |shift: u32| ((paint.rgb >> shift) & 0xff) as f64 / 255.0;
+    let mut json    = object! {
+        "_class"    : "color",
+        "alpha"     : paint.opacity,
+        "blue"      : channel(0),
+        "green"     : channel(8),
+        "red"       : channel(16),
+    };
+
+    if let Some(swatch) = swatch {
+        json["swatchID"] = sketch::sketch_id(swatch).into();
+    }
+
+    json
+}
+
+/// Read the Sketch color `json`.
+fn sketch_paint(json: &JsonValue) -> Option<Paint> {
+    let channel = |key: &str| json[key].as_f64().map(|x| (x.clamp(0.0, 1.0) * 255.0).round() as u32);
+    Some(Paint {
+        rgb     : channel("red")? << 16 | channel("green")? << 8 | channel("blue")?,
+        opacity : json["alpha"].as_f64().unwrap_or(1.0),
+    })
+}
+
+/// Convert the Sketch color `json` to a Lunacy fill or border, referring to the color variable
+/// its swatch was converted to if it comes from one.
+fn lunacy_paint(color: &JsonValue) -> Option<JsonValue> {
+    let paint       = sketch_paint(color)?;
+    let mut json    = object! { "color": hex(paint, false) };
+    if paint.opacity < 1.0 {
+        json["opacity"] = paint.opacity.into();
+    }
+    if let Some(swatch) = color["swatchID"].as_str() {
+        json[VARIABLE_KEY] = sketch::lunacy_id(swatch).into();
+    }
+
+    Some(json)
+}
+
+/// Format `paint` as a Lunacy hex color, with the opacity as alpha if `alpha` is set and it
+/// isn't opaque.
+fn hex(paint: Paint, alpha: bool) -> String {
+    match alpha && paint.opacity < 1.0 {
+        true    => format!("#{:06X}{:02X}", paint.rgb, (paint.opacity.clamp(0.0, 1.0) * 255.0).round() as u32),
+        false   => format!("#{:06X}", paint.rgb),
+    }
+}
+
+/// Get the PostScript name of `font`, e.g. `Inter-SemiBoldItalic`, which Sketch refers to fonts
+/// by.
+fn postscript_name(font: &FontRef) -> String {
+    let weight = WEIGHTS.iter()
+        .min_by_key(|(_, x)| x.abs_diff(font.weight))
+        .map_or("Regular", |(name, _)| name);
+
+    let family = font.family.replace(' ', "");
+    match (weight, font.italic) {
+        ("Regular", true)   => format!("{family}-Italic"),
+        (weight, true)      => format!("{family}-{weight}Italic"),
+        (weight, false)     => format!("{family}-{weight}"),
+    }
+}
+
+/// Get the font a PostScript font `name` refers to, see `postscript_name`.
+fn font_from_postscript_name(name: &str) -> FontRef {
+    let (family, style) = name.rsplit_once('-').unwrap_or((name, "Regular"));
+    let italic          = style.ends_with("Italic");
+    let style           = style.trim_end_matches("Italic");
+
+    FontRef {
+        family  : family.to_owned(),
+        weight  : WEIGHTS.iter().find(|(x, _)| *x == style).map_or(model::DEFAULT_FONT_WEIGHT, |(_, x)| *x),
+        italic,
+    }
+}
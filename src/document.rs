@@ -0,0 +1,1183 @@
+use std::cell::{Cell, RefCell};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs::{self, File};
+use std::io::{self, Read};
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+
+use json::{object, JsonValue};
+use lunacy_tools::palette::{self, ColorScheme};
+use lunacy_tools::rewrite;
+use lunacy_tools::schema::{self, ColorVariable, DocumentJson};
+use lunacy_tools::sketch;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use tempdir::TempDir;
+use uuid::Uuid;
+use zip::write::{FileOptions, SimpleFileOptions};
+use zip::read::ZipFile;
+use zip::{AesMode, CompressionMethod, DateTime, ZipArchive, ZipWriter};
+
+use crate::cache::{self, Checksum};
+use crate::canonical;
+use crate::cloud::{self, CloudOptions, Remote};
+use crate::format;
+use crate::history;
+use crate::hooks::{self, Hooks};
+use crate::output::warning;
+use crate::timings::{self, Phase};
+use crate::Error;
+
+pub use lunacy_tools::archive::{ArchiveError, ExtractLimits};
+
+/// The entry size at which Zip64 extensions are required.
+pub const ZIP64_THRESHOLD: u64 = u32::MAX as u64;
+
+/// The file recording the entries of an unpacked document in archive order.
+pub const ORDER_FILE: &str = ".lunacy-tools.json";
+
+/// Information about a file stored in a document archive.
+#[derive(Clone, Debug)]
+pub struct EntryInfo {
+    /// The name of the entry.
+    pub name            : String,
+    /// The decompressed size of the entry in bytes.
+    pub size            : u64,
+    /// The compressed size of the entry in bytes.
+    pub compressed_size : u64,
+}
+
+/// Options controlling how a document is opened.
+#[derive(Clone, Debug, Default)]
+pub struct OpenOptions {
+    /// The limits enforced when extracting the document.
+    pub limits  : ExtractLimits,
+    /// The password used to decrypt and re-encrypt the document.
+    pub password: Option<String>,
+    /// Whether to refuse documents in a newer format than the tool supports.
+    pub strict  : bool,
+    /// The operation opening the document, recorded in its history when changes are committed.
+    pub operation: Option<String>,
+    /// A message describing the changes, recorded in the document history.
+    pub message : Option<String>,
+    /// When to back up the document before changes are committed to it.
+    pub backup  : BackupPolicy,
+    /// The shell commands run before the document is extracted and after changes are committed.
+    pub hooks   : Hooks,
+    /// The api and token used to fetch and push cloud documents.
+    pub cloud   : CloudOptions,
+    /// Whether the document is opened to commit changes to, rather than only to read. The
+    /// `pre_open` hooks are only run for documents opened for changes, and documents opened
+    /// only to read can't be committed.
+    pub writable: bool,
+}
+
+/// When to back up a document to a `.free.bak` file next to it before changing it.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum BackupPolicy {
+    /// Never back up the document.
+    #[default]
+    Never,
+    /// Back up the document the first time it's changed, keeping the backup of the original
+    /// through later changes.
+    First,
+    /// Back up the document before every change, replacing the previous backup.
+    Always,
+}
+
+impl std::str::FromStr for BackupPolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "never"     => Ok(Self::Never),
+            "first"     => Ok(Self::First),
+            "always"    => Ok(Self::Always),
+            _           => Err(format!("unknown backup policy `{s}`")),
+        }
+    }
+}
+
+/// A lunacy document opened for edit.
+pub struct LunacyDocument {
+    /// The path to the document we're editing.
+    doc_path: PathBuf,
+    /// Whether the document is a directory it was unpacked to rather than an archive.
+    unpacked: bool,
+    /// Whether the document is a Sketch document, with shared swatches instead of color
+    /// variables.
+    sketch  : bool,
+    /// The directory entries of the document are extracted to as they're used, and changed
+    /// entries of an unpacked document are written to until they're committed.
+    doc_dir : TempDir,
+    /// The archive of the document, opened when the first entry is extracted.
+    archive : RefCell<Option<ZipArchive<File>>>,
+    /// The entries of the document not extracted yet.
+    unextracted: RefCell<BTreeSet<String>>,
+    /// The limits enforced when extracting entries.
+    limits  : ExtractLimits,
+    /// The number of bytes that can still be extracted before exceeding the size limit.
+    remaining: Cell<u64>,
+    /// The archive entries modified since the document was opened.
+    modified: BTreeSet<String>,
+    /// The archive entries removed since the document was opened.
+    removed : BTreeSet<String>,
+    /// The deflate level every entry is recompressed with, if the document is being repacked.
+    repack_level: Option<i64>,
+    /// Whether entry timestamps are zeroed on commit.
+    zero_timestamps: bool,
+    /// The password the document was decrypted with.
+    password: Option<String>,
+    /// The files in the document as it was opened.
+    entries : Vec<EntryInfo>,
+    /// The encryption mode of each encrypted entry.
+    encrypted: BTreeMap<String, AesMode>,
+    /// The operation recorded in the document history when changes are committed.
+    operation: Option<String>,
+    /// The message recorded in the document history when changes are committed.
+    message : Option<String>,
+    /// When the document is backed up before changes are committed.
+    backup  : BackupPolicy,
+    /// The shell commands run after changes are committed.
+    post_commit: Vec<String>,
+    /// The url a report of the changes is posted to after they're committed.
+    webhook : Option<String>,
+    /// Whether the document was opened to commit changes to.
+    writable: bool,
+    /// The cloud document the document was downloaded from, uploaded to when changes are
+    /// committed.
+    remote  : Option<Remote>,
+    /// The lock held on the document while it's open for changes.
+    _lock   : Option<File>,
+}
+
+impl LunacyDocument {
+    /// Check whether the document at `path` contains any encrypted entries.
+    pub fn is_encrypted(path: impl AsRef<Path>) -> Result<bool, Error> {
+        let mut archive = ZipArchive::new(File::open(path)?)?;
+        for i in 0..archive.len() {
+            if archive.by_index_raw(i)?.encrypted() {
+                return Ok(true);
+            }
+        }
+
+        Ok(false)
+    }
+
+    /// Open the document at `path` for edit.
+    ///
+    /// The document is either a `.free` or `.sketch` file or a directory holding an unpacked
+    /// document, such as one created by `unpack`, which is read and changed in place without any
+    /// zip handling. Cloud urls, see `cloud::is_url`, are downloaded to a local copy first, which
+    /// is uploaded as a new revision when changes are committed.
+    pub fn open(path: impl AsRef<Path>, options: &OpenOptions) -> Result<Self, Error> {
+        let path    = path.as_ref();
+        let remote  = match cloud::is_url(path) {
+            true    => Some(Remote::fetch(&path.to_string_lossy(), &options.cloud, options.limits.max_size)?),
+            false   => None,
+        };
+        let local   = remote.as_ref().map(|x| x.path().to_owned());
+
+        // Ensure the file is a .free or .sketch file or an unpacked document.
+        let path        = local.as_deref().unwrap_or(path);
+        let unpacked    = path.is_dir() && path.join("document.json").is_file();
+        let extension   = path.extension().and_then(|x| x.to_str());
+        if !unpacked && extension != Some("free") && extension != Some("sketch") {
+            return Err(Box::new(io::Error::new(io::ErrorKind::Unsupported, "only `.free` and `.sketch` files and unpacked document directories are supported")));
+        }
+
+        // Hold a lock on a document opened for changes until we're done with it so concurrent
+        // runs can't interleave their changes. Documents only read don't need one.
+        let lock = match options.writable {
+            true    => Some(lock(path)?),
+            false   => None,
+        };
+
+        if options.writable {
+            hooks::run(&options.hooks.pre_open, path)?;
+        }
+
+        // Open a temp directory to hold the document contents, only reading the index up front.
+        // Entries are extracted to it when they're first used, so commands that only look at a
+        // few entries don't pay for extracting every image in the document.
+        let dir         = tempdir::TempDir::new("lunacy-tools")?;
+        let (archive, entries, encrypted) = timings::time(Phase::Extract, || -> Result<_, Error> {
+            if unpacked {
+                return Ok((None, read_directory(path, options)?, BTreeMap::new()));
+            }
+
+            let mut archive = ZipArchive::new(File::open(path)?)?;
+            let (entries, encrypted) = read_index(&mut archive, options)?;
+
+            Ok((Some(archive), entries, encrypted))
+        })?;
+
+        // Unpacked documents have no extension to tell a Sketch document by.
+        let sketch = match unpacked {
+            true    => sketch::is_sketch(&std::fs::read_to_string(path.join("document.json"))?),
+            false   => extension == Some("sketch"),
+        };
+
+        let doc = Self {
+            doc_path: path.to_owned(),
+            unpacked,
+            sketch,
+            doc_dir : dir,
+            unextracted: RefCell::new(entries.iter().map(|x| x.name.clone()).collect()),
+            archive : RefCell::new(archive),
+            limits  : options.limits,
+            remaining: Cell::new(options.limits.max_size),
+            modified: BTreeSet::new(),
+            removed : BTreeSet::new(),
+            repack_level: None,
+            zero_timestamps: false,
+            password: options.password.clone(),
+            entries,
+            encrypted,
+            operation: options.operation.clone(),
+            message : options.message.clone(),
+            // The local copy of a cloud document is thrown away, the cloud keeps its revisions.
+            backup  : match remote {
+                Some(_) => BackupPolicy::Never,
+                None    => options.backup,
+            },
+            post_commit: options.hooks.post_commit.clone(),
+            webhook : options.hooks.webhook.clone(),
+            writable: options.writable,
+            remote,
+            _lock   : lock,
+        };
+
+        // Make sure we understand the document before we go changing it.
+        format::check_version(format::read_version(&doc)?, options.strict)?;
+
+        Ok(doc)
+    }
+
+    /// Zero the timestamp of every entry on commit so the output doesn't depend on when the
+    /// tool was run.
+    pub fn set_zero_timestamps(&mut self, zero_timestamps: bool) {
+        self.zero_timestamps = zero_timestamps;
+    }
+
+    /// Commit changes to the document.
+    ///
+    /// Entries that were not modified are copied byte-for-byte from the original archive, only
+    /// the modified entries are compressed again. Entry order, compression methods and
+    /// timestamps are preserved so the same input always produces the same output. Unpacked
+    /// documents only have the files of the modified and removed entries replaced or deleted,
+    /// and are never backed up.
+    ///
+    /// The changes are recorded in the document history if the document was opened by an
+    /// operation, and reported to the webhook if one is configured.
+    pub fn commit(&mut self) -> Result<(), Error> {
+        if self.modified.is_empty() && self.removed.is_empty() {
+            return Ok(());
+        }
+        if !self.writable {
+            return Err(Box::new(io::Error::new(io::ErrorKind::PermissionDenied, "the document was opened read-only")));
+        }
+
+        // The report only covers the changes made to the design, not the tool's own entries.
+        let report = self.change_report();
+
+        if let Some(operation) = self.operation.clone() {
+            let message = self.message.clone();
+            timings::time(Phase::History, || history::record(self, &operation, message.as_deref(), !self.zero_timestamps))?;
+        }
+
+        match self.unpacked {
+            true    => self.write_directory()?,
+            false   => self.replace_archive()?,
+        }
+
+        self.modified.clear();
+        self.removed.clear();
+
+        if let Some(remote) = &mut self.remote {
+            remote.push()?;
+        }
+
+        hooks::run(&self.post_commit, &self.doc_path)?;
+
+        // The changes are already saved, so a webhook that can't be reached is only a warning.
+        if let Some(url) = &self.webhook {
+            if let Err(e) = hooks::notify(url, &report) {
+                warning!("warning: failed to notify webhook, {e}");
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Create a json report of the changes to be committed, for posting to a webhook.
+    fn change_report(&self) -> JsonValue {
+        let document    = match &self.remote {
+            Some(remote)    => remote.url().to_owned(),
+            None            => self.doc_path.display().to_string(),
+        };
+        let text        = match &self.operation {
+            Some(operation) => format!("lunacy-tools {operation} changed {document}"),
+            None            => format!("lunacy-tools changed {document}"),
+        };
+        let entries     = self.changed_entries()
+            .into_iter()
+            .filter(|x| !x.starts_with(history::TOOL_DIR))
+            .collect::<Vec<_>>();
+
+        object! {
+            "text"      : text,
+            "document"  : document.as_str(),
+            "operation" : self.operation.as_deref(),
+            "message"   : self.message.as_deref(),
+            "version"   : env!("CARGO_PKG_VERSION"),
+            "entries"   : entries,
+        }
+    }
+
+    /// Get the entries modified or removed since the document was opened or last committed.
+    pub fn changed_entries(&self) -> Vec<String> {
+        self.modified.iter()
+            .chain(&self.removed)
+            .cloned()
+            .collect()
+    }
+
+    /// Load the raw contents of an entry as it was when the document was opened or last
+    /// committed, or `None` if it didn't exist.
+    pub fn load_original(&self, name: &str) -> Result<Option<Vec<u8>>, Error> {
+        if self.unpacked {
+            let path = self.doc_path.join(name);
+            return match name != ORDER_FILE && path.is_file() {
+                true    => Ok(Some(std::fs::read(path)?)),
+                false   => Ok(None),
+            };
+        }
+
+        let mut archive = ZipArchive::new(File::open(&self.doc_path)?)?;
+        let Some(index) = archive.index_for_name(name) else {
+            return Ok(None);
+        };
+
+        // The entry was checked against the extraction limits when the document was opened.
+        let size = archive.by_index_raw(index)?.size();
+        Ok(Some(read_entry(&mut archive, index, self.password.as_deref(), size)?))
+    }
+
+    /// Check whether the contents of the entry `name` differ from how they were when the document
+    /// was opened or last committed. Entries only marked modified to be recompressed are unchanged.
+    pub fn is_changed(&self, name: &str) -> Result<bool, Error> {
+        // Entries that were never extracted can't have been changed.
+        if self.unextracted.borrow().contains(name) {
+            return Ok(false);
+        }
+
+        match (self.load_original(name)?, self.has_entry(name)) {
+            (Some(original), true)  => Ok(self.load_bytes(name)? != original),
+            (None, false)           => Ok(false),
+            _                       => Ok(true),
+        }
+    }
+
+    /// Check whether the document is a Sketch document.
+    pub fn is_sketch(&self) -> bool {
+        self.sketch
+    }
+
+    /// Get the files in the document as it was opened, in archive order.
+    pub fn entries(&self) -> &[EntryInfo] {
+        &self.entries
+    }
+
+    /// Check whether the opened document contains the entry at `path`.
+    pub fn has_entry(&self, path: impl AsRef<Path>) -> bool {
+        let path = path.as_ref();
+        self.unextracted.borrow().contains(&entry_name(path)) || self.doc_dir.path().join(path).is_file()
+    }
+
+    /// Load the raw contents of an entry in the opened lunacy document.
+    pub fn load_bytes(&self, path: impl AsRef<Path>) -> Result<Vec<u8>, Error> {
+        Ok(std::fs::read(self.extracted(path)?)?)
+    }
+
+    /// Save the raw contents of an entry back to the opened lunacy document.
+    pub fn save_bytes(&mut self, path: impl AsRef<Path>, data: &[u8]) -> Result<(), Error> {
+        let path        = path.as_ref();
+        let document    = self.doc_dir.path().join(path);
+        if let Some(parent) = document.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+
+        std::fs::write(document, data)?;
+        self.unextracted.get_mut().remove(&entry_name(path));
+        self.mark_modified(entry_name(path));
+
+        Ok(())
+    }
+
+    /// Remove an entry from the opened lunacy document.
+    pub fn remove_entry(&mut self, path: impl AsRef<Path>) -> Result<(), Error> {
+        let path = path.as_ref();
+        let name = entry_name(path);
+
+        if !self.unextracted.get_mut().remove(&name) {
+            std::fs::remove_file(self.doc_dir.path().join(path))?;
+        }
+
+        self.entries.retain(|x| x.name != name);
+        self.modified.remove(&name);
+        self.removed.insert(name);
+
+        Ok(())
+    }
+
+    /// Recompress every entry with deflate at `level` when the document is committed.
+    pub fn repack(&mut self, level: i64) {
+        self.repack_level = Some(level);
+        self.modified.extend(self.entries.iter().map(|x| x.name.clone()));
+    }
+
+    /// Load a JSON document from the opened lunacy document.
+    pub fn load_json(&self, path: impl AsRef<Path>) -> Result<JsonValue, Error> {
+        let document    = self.extracted(path)?;
+        let data        = std::fs::read_to_string(&document)?;
+        let json        = timings::time(Phase::Parse, || json::parse(&data))?;
+
+        Ok(json)
+    }
+
+    /// Load a JSON document from an opened lunacy document into the typed model `T`.
+    ///
+    /// When the cache of parsed entries is enabled, entries with the same contents as one parsed
+    /// before are taken from it rather than parsed again. The contents are read, and decrypted,
+    /// first, so a wrong password never gets a model from the cache.
+    pub fn load<T: DeserializeOwned + Clone + Send + 'static>(&self, path: impl AsRef<Path>) -> Result<T, Error> {
+        let document    = self.extracted(path)?;
+        let mut data    = std::fs::read(&document)?;
+
+        let checksum = cache::enabled().then(|| Checksum::of(&data));
+        if let Some(value) = checksum.and_then(cache::get) {
+            return Ok(value);
+        }
+
+        let value = timings::time(Phase::Parse, || schema::from_slice(&mut data))?;
+        if let Some(checksum) = checksum {
+            cache::insert(checksum, &value);
+        }
+
+        Ok(value)
+    }
+
+    /// Load the color variables of the opened lunacy document, without parsing the rest of
+    /// `document.json`. The shared swatches of a Sketch document are loaded as color variables.
+    pub fn load_color_variables(&self) -> Result<Vec<ColorVariable>, Error> {
+        let json = std::fs::read_to_string(self.extracted("document.json")?)?;
+        timings::time(Phase::Parse, || match self.sketch {
+            true    => sketch::color_variables(&json),
+            false   => schema::color_variables(&json),
+        })
+    }
+
+    /// Load the names of the groups of color variables in the opened lunacy document, with the
+    /// levels of their names split by `separator`, in alphabetical order.
+    pub fn load_color_groups(&self, separator: &str) -> Result<Vec<String>, Error> {
+        let document = DocumentJson {
+            color_variables : Some(self.load_color_variables()?),
+            ..Default::default()
+        };
+
+        Ok(palette::groups(&document, separator))
+    }
+
+    /// Save a typed JSON document back to an opened lunacy document, keeping the text of any
+    /// values that didn't change.
+    pub fn save<T: Serialize>(&mut self, path: impl AsRef<Path>, value: &T) -> Result<(), Error> {
+        let path        = path.as_ref();
+        let original    = std::fs::read_to_string(self.extracted(path)?).ok();
+        let json        = timings::time(Phase::Serialize, || match &original {
+            Some(original)  => rewrite::to_string(original, value),
+            None            => Ok(serde_json::to_string(value)?),
+        })?;
+
+        self.save_bytes(path, json.as_bytes())
+    }
+
+    /// Save a JSON document back to an opened lunacy document, keeping the text of any values
+    /// that didn't change.
+    pub fn save_json(&mut self, path: impl AsRef<Path>, json: &JsonValue) -> Result<(), Error> {
+        let value = timings::time(Phase::Serialize, || serde_json::from_str::<serde_json::Value>(&json.dump()))?;
+        self.save(path, &value)
+    }
+
+    /// Mark the entry `name` as modified so it's written on commit.
+    fn mark_modified(&mut self, name: String) {
+        self.removed.remove(&name);
+        self.modified.insert(name);
+    }
+
+    /// Apply the encryption of entry `name` to `options`.
+    ///
+    /// Entries keep the AES mode they were read with. Legacy ZipCrypto entries can't be written
+    /// so are upgraded to AES-256, as are new entries in an encrypted document.
+    fn encrypt<'a>(&'a self, name: &str, options: SimpleFileOptions) -> FileOptions<'a, ()> {
+        let Some(password) = self.password.as_deref() else {
+            return options;
+        };
+
+        match self.encrypted.get(name) {
+            Some(mode)                              => options.with_aes_encryption(*mode, password),
+            None if !self.encrypted.is_empty()      => options.with_aes_encryption(AesMode::Aes256, password),
+            None                                    => options,
+        }
+    }
+
+    /// Update colors in the document with the provided color scheme.
+    ///
+    /// Only the color variables and color styles in `document.json` are parsed and written again
+    /// where it has them, the rest is copied as it is, so updating a large document costs little
+    /// more than updating a small one. Sketch documents have their shared swatches updated
+    /// instead.
+    pub fn update_colors(&mut self, group: &str, scheme: &ColorScheme) -> Result<(), Error> {
+        if self.sketch {
+            return self.update_swatches(group, scheme);
+        }
+
+        let json = std::fs::read_to_string(self.extracted("document.json")?)?;
+
+        // The whole document is parsed when it lacks a member the scheme adds.
+        let variables   = rewrite::find_member(&json, "colorVariables");
+        let styles      = rewrite::find_member(&json, "colorStyles");
+        let partial     = variables.is_some() && (styles.is_some() || scheme.gradients.is_empty());
+
+        let mut document = timings::time(Phase::Parse, || -> Result<_, Error> {
+            if !partial {
+                return schema::from_slice::<DocumentJson>(&mut json.as_bytes().to_vec());
+            }
+
+            Ok(DocumentJson {
+                color_variables : parse_member(&json, variables.clone())?,
+                color_styles    : parse_member(&json, styles.clone())?,
+                ..Default::default()
+            })
+        })?;
+
+        let palette = timings::time(Phase::Generate, || palette::generate(&document, group, scheme))?;
+        timings::time(Phase::Apply, || {
+            palette::apply_palette(&mut document, &palette);
+            palette::apply_gradients(&mut document, &scheme.gradients, &palette);
+        });
+
+        let json = timings::time(Phase::Serialize, || -> Result<_, Error> {
+            if !partial {
+                return rewrite::to_string(&json, &document);
+            }
+
+            let json = rewrite::splice(&json, variables.unwrap(), &document.color_variables)?;
+            match rewrite::find_member(&json, "colorStyles") {
+                Some(range) => rewrite::splice(&json, range, &document.color_styles),
+                None        => Ok(json),
+            }
+        })?;
+
+        self.save_bytes("document.json", json.as_bytes())
+    }
+
+    /// Update the shared swatches of a Sketch document with the provided color scheme, along
+    /// with the shared styles using them.
+    fn update_swatches(&mut self, group: &str, scheme: &ColorScheme) -> Result<(), Error> {
+        let json = std::fs::read_to_string(self.extracted("document.json")?)?;
+
+        let mut document = DocumentJson {
+            color_variables : Some(timings::time(Phase::Parse, || sketch::color_variables(&json))?),
+            ..Default::default()
+        };
+
+        let palette = timings::time(Phase::Generate, || palette::generate(&document, group, scheme))?;
+        timings::time(Phase::Apply, || palette::apply_palette(&mut document, &palette));
+
+        let json = timings::time(Phase::Serialize, || sketch::write_color_variables(&json, document.color_variables()))?;
+        self.save_bytes("document.json", json.as_bytes())
+    }
+}
+
+impl LunacyDocument {
+    /// Get the path of the entry at `path` in the extraction directory, extracting it from the
+    /// document first if it hasn't been yet.
+    ///
+    /// Unchanged entries of an unpacked document are read where they are.
+    fn extracted(&self, path: impl AsRef<Path>) -> Result<PathBuf, Error> {
+        let path = path.as_ref();
+        let name = entry_name(path);
+        if self.unextracted.borrow().contains(&name) {
+            if self.unpacked {
+                return Ok(self.doc_path.join(path));
+            }
+
+            timings::time(Phase::Extract, || self.extract(&name))?;
+        }
+
+        Ok(self.doc_dir.path().join(path))
+    }
+
+    /// Extract the entry `name` from the document.
+    fn extract(&self, name: &str) -> Result<(), Error> {
+        let mut archive = self.archive.borrow_mut();
+        let archive     = match archive.as_mut() {
+            Some(archive)   => archive,
+            None            => archive.insert(ZipArchive::new(File::open(&self.doc_path)?)?),
+        };
+
+        let Some(index) = archive.index_for_name(name) else {
+            return Err(Box::new(io::Error::new(io::ErrorKind::NotFound, format!("entry `{name}` not found"))));
+        };
+
+        let mut entry = match (self.encrypted.contains_key(name), self.password.as_deref()) {
+            (true, Some(password))  => archive.by_index_decrypt(index, password.as_bytes())?,
+            (true, None)            => return Err(Box::new(ArchiveError::PasswordRequired)),
+            (false, _)              => archive.by_index(index)?,
+        };
+
+        let mut remaining = self.remaining.get();
+        extract_entry(&mut entry, self.doc_dir.path(), self.limits, &mut remaining)?;
+        self.remaining.set(remaining);
+        self.unextracted.borrow_mut().remove(name);
+
+        Ok(())
+    }
+
+    /// Replace the document archive with one holding the changes made to it, backing up the
+    /// original first if the backup policy calls for it.
+    fn replace_archive(&mut self) -> Result<(), Error> {
+        // Write to a sibling file first so a failed commit never leaves a half written document.
+        let extension = self.doc_path.extension().and_then(|x| x.to_str()).unwrap_or("free");
+        let temp_path = self.doc_path.with_extension(format!("{extension}.tmp"));
+        timings::time(Phase::Zip, || self.write_archive(&temp_path))?;
+
+        // Entries not extracted yet are read from the new archive from here on.
+        self.archive.get_mut().take();
+
+        let backup_path = self.doc_path.with_extension(format!("{extension}.bak"));
+        let backup      = match self.backup {
+            BackupPolicy::Never     => false,
+            BackupPolicy::First     => !backup_path.exists(),
+            BackupPolicy::Always    => true,
+        };
+
+        if backup {
+            std::fs::copy(&self.doc_path, &backup_path)?;
+        }
+
+        std::fs::rename(&temp_path, &self.doc_path)?;
+
+        Ok(())
+    }
+
+    /// Write the changes made to an unpacked document to its directory.
+    ///
+    /// Each changed file is written to a sibling file first and renamed over the original, so a
+    /// failed commit never leaves a half written entry. Json is written back as `unpack` writes
+    /// it, pretty printed and in canonical form if the document was unpacked so, and entries added
+    /// are listed in the entry order file after the rest. Entries marked modified without being
+    /// written, as when repacking, have nothing to compress and are left as they are.
+    fn write_directory(&self) -> Result<(), Error> {
+        let order_path  = self.doc_path.join(ORDER_FILE);
+        let mut order   = match order_path.is_file() {
+            true    => json::parse(&std::fs::read_to_string(&order_path)?)?,
+            false   => JsonValue::new_object(),
+        };
+        let canonical   = order["canonical"].as_bool() == Some(true);
+
+        for name in &self.removed {
+            let path = self.doc_path.join(name);
+            if path.is_file() {
+                std::fs::remove_file(path)?;
+            }
+        }
+
+        for name in &self.modified {
+            let source = self.doc_dir.path().join(name);
+            if !source.is_file() {
+                continue;
+            }
+
+            let path = self.doc_path.join(name);
+            if let Some(parent) = path.parent() {
+                std::fs::create_dir_all(parent)?;
+            }
+
+            let mut temp_path = path.as_os_str().to_owned();
+            temp_path.push(".tmp");
+
+            match name.ends_with(".json") {
+                true    => {
+                    let mut json = json::parse(&std::fs::read_to_string(&source)?)?;
+                    if canonical {
+                        json = canonical::canonicalize(name, &json);
+                    }
+
+                    std::fs::write(&temp_path, json.pretty(2))?;
+                }
+                false   => { std::fs::copy(&source, &temp_path)?; }
+            }
+
+            std::fs::rename(&temp_path, &path)?;
+        }
+
+        let mut entries = order["entries"].members()
+            .filter_map(JsonValue::as_str)
+            .filter(|x| !self.removed.contains(*x))
+            .map(|x| x.to_owned())
+            .collect::<Vec<_>>();
+        for name in &self.modified {
+            if !entries.contains(name) && self.doc_path.join(name).is_file() {
+                entries.push(name.clone());
+            }
+        }
+
+        order["entries"] = entries.into();
+        std::fs::write(&order_path, order.pretty(2))?;
+
+        Ok(())
+    }
+
+    /// Write the document with the changes made to it to `temp_path`.
+    fn write_archive(&self, temp_path: &Path) -> Result<(), Error> {
+        let mut source  = ZipArchive::new(File::open(&self.doc_path)?)?;
+        let mut writer  = ZipWriter::new(File::create(temp_path)?);
+        writer.set_raw_comment(source.comment().into());
+
+        let mut written = BTreeSet::new();
+        for i in 0..source.len() {
+            let entry       = source.by_index_raw(i)?;
+            let name        = entry.name().to_owned();
+            let unix_mode   = entry.unix_mode();
+
+            let mut options = entry.options();
+            if self.zero_timestamps {
+                options = options.last_modified_time(DateTime::default());
+            }
+            if let Some(level) = self.repack_level {
+                options = options
+                    .compression_method(CompressionMethod::Deflated)
+                    .compression_level(Some(level));
+            }
+
+            // Raw copies lose their encryption, so encrypted entries are always written again.
+            if self.removed.contains(&name) {
+                continue;
+            }
+            else if self.modified.contains(&name) || self.encrypted.contains_key(&name) {
+                drop(entry);
+                self.write_entry(&mut writer, &name, self.encrypt(&name, options))?;
+            }
+            else if self.zero_timestamps {
+                writer.raw_copy_file_touch(entry, DateTime::default(), unix_mode)?;
+            }
+            else {
+                writer.raw_copy_file(entry)?;
+            }
+
+            written.insert(name);
+        }
+
+        // Append any entries created since the document was opened. These have no original
+        // timestamp to preserve, so they always use the zip epoch.
+        for name in self.modified.difference(&written) {
+            let options = SimpleFileOptions::default()
+                .last_modified_time(DateTime::default());
+
+            self.write_entry(&mut writer, name, self.encrypt(name, options))?;
+        }
+
+        writer.finish()?;
+
+        Ok(())
+    }
+
+    /// Write the extracted entry `name` into `writer`.
+    fn write_entry(
+        &self,
+        writer  : &mut ZipWriter<File>,
+        name    : &str,
+        options : FileOptions<'_, ()>
+    )
+        -> Result<(), Error>
+    {
+        let mut file    = File::open(self.extracted(name)?)?;
+        let size        = file.metadata()?.len();
+
+        // Entries may have grown past the point where Zip64 extensions are required.
+        writer.start_file(name, options.large_file(size >= ZIP64_THRESHOLD))?;
+        io::copy(&mut file, writer)?;
+
+        Ok(())
+    }
+}
+
+/// Open the entry at `index` of `archive` for reading, decrypting it with `password` if needed.
+pub fn open_entry<'a>(
+    archive : &'a mut ZipArchive<File>,
+    index   : usize,
+    password: Option<&str>
+)
+    -> Result<ZipFile<'a, File>, Error>
+{
+    if archive.by_index_raw(index)?.encrypted() {
+        let Some(password) = password else {
+            return Err(Box::new(ArchiveError::PasswordRequired));
+        };
+
+        return Ok(archive.by_index_decrypt(index, password.as_bytes())?);
+    }
+
+    Ok(archive.by_index(index)?)
+}
+
+/// Read the entry at `index` of `archive` into memory, decrypting it with `password` if needed.
+///
+/// At most `limit` bytes are decompressed, guarding against entries that expand without bound.
+pub fn read_entry(
+    archive : &mut ZipArchive<File>,
+    index   : usize,
+    password: Option<&str>,
+    limit   : u64
+)
+    -> Result<Vec<u8>, Error>
+{
+    let entry = open_entry(archive, index, password)?;
+
+    let mut data = Vec::new();
+    entry.take(limit + 1).read_to_end(&mut data)?;
+    if data.len() as u64 > limit {
+        return Err(Box::new(ArchiveError::TooLarge(limit)));
+    }
+
+    Ok(data)
+}
+
+/// Get the entries of the document unpacked to `dir`, in the order they were unpacked, followed
+/// by any files added since in name order.
+pub fn unpacked_entries(dir: &Path) -> Result<Vec<String>, Error> {
+    let mut files = vec![];
+    find_files(dir, dir, &mut files)?;
+    files.sort();
+
+    let order = match dir.join(ORDER_FILE).is_file() {
+        true    => json::parse(&std::fs::read_to_string(dir.join(ORDER_FILE))?)?,
+        false   => JsonValue::new_object(),
+    };
+
+    // Entries deleted since the document was unpacked are left out.
+    let mut entries = order["entries"].members()
+        .filter_map(JsonValue::as_str)
+        .filter(|x| files.iter().any(|file| file == x))
+        .map(|x| x.to_owned())
+        .collect::<Vec<_>>();
+
+    for file in files {
+        if !entries.contains(&file) {
+            entries.push(file);
+        }
+    }
+
+    Ok(entries)
+}
+
+/// Find the files below `path` as entry names relative to `dir`, skipping the entry order file.
+fn find_files(dir: &Path, path: &Path, files: &mut Vec<String>) -> Result<(), Error> {
+    for entry in std::fs::read_dir(path)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            find_files(dir, &path, files)?;
+            continue;
+        }
+
+        let name = path.strip_prefix(dir)?
+            .components()
+            .map(|x| x.as_os_str().to_string_lossy())
+            .collect::<Vec<_>>()
+            .join("/");
+
+        if name != ORDER_FILE {
+            files.push(name);
+        }
+    }
+
+    Ok(())
+}
+
+/// Acquire an exclusive advisory lock on the document at `path`, waiting for other processes
+/// to release it.
+///
+/// The lock is taken on a `.lock` file named for the canonical path of the document rather than
+/// the document itself, since commits replace the document file. It's kept in the runtime
+/// directory, or the temp directory where there is none, so nothing is left next to documents.
+/// The lock file is left in place as removing it would race with processes waiting on it.
+fn lock(path: &Path) -> Result<File, Error> {
+    let canonical   = fs::canonicalize(path)?;
+    let id          = Uuid::new_v5(&Uuid::NAMESPACE_URL, canonical.as_os_str().as_encoded_bytes());
+
+    let dir = std::env::var_os("XDG_RUNTIME_DIR")
+        .filter(|x| !x.is_empty())
+        .map(PathBuf::from)
+        .unwrap_or_else(std::env::temp_dir);
+    let lock_path = dir.join(format!("lunacy-tools-{id}.lock"));
+
+    let file = File::options()
+        .create(true)
+        .truncate(false)
+        .write(true)
+        .open(&lock_path)?;
+
+    match file.try_lock() {
+        Ok(())                                  => {},
+        Err(fs::TryLockError::Error(x))         => return Err(Box::new(x)),
+        Err(fs::TryLockError::WouldBlock)       => {
+            warning!("waiting for lock on {}", path.display());
+            file.lock()?;
+        }
+    }
+
+    Ok(file)
+}
+
+/// Read the index of `archive`, validating entry paths and enforcing the entry limit so unsafe
+/// archives are refused before anything is extracted.
+///
+/// Returns the files in the archive and the encryption mode of each encrypted entry.
+fn read_index(
+    archive : &mut ZipArchive<File>,
+    options : &OpenOptions
+)
+    -> Result<(Vec<EntryInfo>, BTreeMap<String, AesMode>), Error>
+{
+    if archive.len() > options.limits.max_entries {
+        return Err(Box::new(ArchiveError::TooManyEntries(archive.len())));
+    }
+
+    let mut entries   = vec![];
+    let mut encrypted = BTreeMap::new();
+    for i in 0..archive.len() {
+        let entry = archive.by_index_raw(i)?;
+
+        // Reject entries that would escape the extraction directory.
+        if entry.enclosed_name().is_none() || entry.is_symlink() {
+            return Err(Box::new(ArchiveError::UnsafePath(entry.name().to_owned())));
+        }
+
+        if entry.is_dir() {
+            continue;
+        }
+
+        let name = entry.name().to_owned();
+        entries.push(EntryInfo {
+            name            : name.clone(),
+            size            : entry.size(),
+            compressed_size : entry.compressed_size(),
+        });
+
+        // Encrypted entries require a password to read.
+        if entry.encrypted() {
+            drop(entry);
+
+            if options.password.is_none() {
+                return Err(Box::new(ArchiveError::PasswordRequired));
+            }
+
+            let mode = archive.get_aes_verification_key_and_salt(i)?
+                .map(|x| x.aes_mode)
+                .unwrap_or(AesMode::Aes256);
+
+            encrypted.insert(name, mode);
+        }
+    }
+
+    Ok((entries, encrypted))
+}
+
+/// Read the files of the document unpacked to `dir`, enforcing the entry limit.
+fn read_directory(dir: &Path, options: &OpenOptions) -> Result<Vec<EntryInfo>, Error> {
+    let names = unpacked_entries(dir)?;
+    if names.len() > options.limits.max_entries {
+        return Err(Box::new(ArchiveError::TooManyEntries(names.len())));
+    }
+
+    let mut entries = vec![];
+    for name in names {
+        let size = dir.join(&name).metadata()?.len();
+        entries.push(EntryInfo { name, size, compressed_size: size });
+    }
+
+    Ok(entries)
+}
+
+/// Extract a single `entry` into `dir`, deducting the decompressed size from `remaining`.
+fn extract_entry(
+    entry       : &mut ZipFile<File>,
+    dir         : &Path,
+    limits      : ExtractLimits,
+    remaining   : &mut u64
+)
+    -> Result<(), Error>
+{
+    // Reject entries that would escape the extraction directory.
+    let Some(relative) = entry.enclosed_name() else {
+        return Err(Box::new(ArchiveError::UnsafePath(entry.name().to_owned())));
+    };
+    if entry.is_symlink() {
+        return Err(Box::new(ArchiveError::UnsafePath(entry.name().to_owned())));
+    }
+
+    let path = dir.join(relative);
+    if entry.is_dir() {
+        std::fs::create_dir_all(&path)?;
+        return Ok(());
+    }
+
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+
+    // Count the bytes actually decompressed rather than trusting the sizes recorded in the
+    // archive, which are trivial to forge.
+    let mut file    = File::create(&path)?;
+    let written     = io::copy(&mut entry.take(*remaining + 1), &mut file)?;
+    if written > *remaining {
+        return Err(Box::new(ArchiveError::TooLarge(limits.max_size)));
+    }
+
+    *remaining -= written;
+
+    Ok(())
+}
+
+/// Convert a path relative to the document root into an archive entry name.
+fn entry_name(path: &Path) -> String {
+    path.components()
+        .map(|x| x.as_os_str().to_string_lossy())
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+/// Parse the member of the json text `json` at `range`, or `None` if it doesn't have it.
+fn parse_member<T: DeserializeOwned>(json: &str, range: Option<Range<usize>>) -> Result<Option<T>, Error> {
+    match range {
+        Some(range) => schema::from_slice(&mut json[range].as_bytes().to_vec()),
+        None        => Ok(None),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use zip::write::SimpleFileOptions;
+    use zip::{CompressionMethod, ZipWriter};
+
+    use super::*;
+
+    /// Write a document to `path` holding `document.json` and `count` small images, each written
+    /// with Zip64 records when `large_file` is set as they would be for entries over 4 GiB.
+    fn write_document(path: &Path, count: usize, large_file: bool) {
+        let options = SimpleFileOptions::default()
+            .compression_method(CompressionMethod::Stored)
+            .large_file(large_file);
+
+        let mut writer = ZipWriter::new(File::create(path).unwrap());
+        writer.start_file("document.json", options).unwrap();
+        writer.write_all(b"{}").unwrap();
+
+        for i in 0..count {
+            writer.start_file(format!("images/{i}.png"), options).unwrap();
+            writer.write_all(i.to_string().as_bytes()).unwrap();
+        }
+
+        writer.finish().unwrap();
+    }
+
+    /// Open the document at `path` for changes, change its last image and commit it, then check
+    /// every entry survives the round trip with the change made.
+    fn check_commit(path: &Path, count: usize) {
+        let options = OpenOptions { writable: true, ..Default::default() };
+
+        let mut doc = LunacyDocument::open(path, &options).unwrap();
+        assert_eq!(doc.entries().len(), count + 1);
+
+        let last = format!("images/{}.png", count - 1);
+        assert_eq!(doc.load_bytes(&last).unwrap(), (count - 1).to_string().as_bytes());
+
+        doc.save_bytes(&last, b"changed").unwrap();
+        doc.commit().unwrap();
+        drop(doc);
+
+        let doc = LunacyDocument::open(path, &OpenOptions::default()).unwrap();
+        assert_eq!(doc.entries().len(), count + 1);
+        assert_eq!(doc.load_bytes("images/0.png").unwrap(), b"0");
+        assert_eq!(doc.load_bytes(&last).unwrap(), b"changed");
+    }
+
+    #[test]
+    fn commits_document_with_more_than_65535_entries() {
+        let dir     = TempDir::new("lunacy-tools-test").unwrap();
+        let path    = dir.path().join("many.free");
+        write_document(&path, 70_000, false);
+
+        check_commit(&path, 70_000);
+    }
+
+    #[test]
+    fn repacking_does_not_grow_document() {
+        let dir     = TempDir::new("lunacy-tools-test").unwrap();
+        let path    = dir.path().join("repack.free");
+
+        // Half of each image compresses away and half doesn't, so any copy of the images kept to
+        // undo the repack would outweigh what it saves.
+        let mut state = 0x2545f491u32;
+        let mut image = vec![0; 16 * 1024];
+        image.extend((0..16 * 1024).map(|_| {
+            state ^= state << 13;
+            state ^= state >> 17;
+            state ^= state << 5;
+            state as u8
+        }));
+
+        let options     = SimpleFileOptions::default().compression_method(CompressionMethod::Stored);
+        let mut writer  = ZipWriter::new(File::create(&path).unwrap());
+        writer.start_file("document.json", options).unwrap();
+        writer.write_all(b"{}").unwrap();
+        for i in 0..4 {
+            writer.start_file(format!("images/{i}.png"), options).unwrap();
+            writer.write_all(&image).unwrap();
+        }
+        writer.finish().unwrap();
+
+        let before  = std::fs::metadata(&path).unwrap().len();
+        let options = OpenOptions { writable: true, operation: Some(String::from("optimize")), ..Default::default() };
+
+        // Images are read to be optimized before the document is repacked.
+        let mut doc = LunacyDocument::open(&path, &options).unwrap();
+        for i in 0..4 {
+            doc.load_bytes(format!("images/{i}.png")).unwrap();
+        }
+        doc.repack(9);
+        doc.commit().unwrap();
+        drop(doc);
+
+        let after = std::fs::metadata(&path).unwrap().len();
+        assert!(after < before, "repacking grew the document from {before} to {after} bytes");
+
+        let doc     = LunacyDocument::open(&path, &OpenOptions::default()).unwrap();
+        let records = history::load(&doc).unwrap();
+        assert_eq!(records.len(), 1);
+        assert!(records[0].entries.is_empty());
+    }
+
+    #[test]
+    fn commits_document_with_zip64_entries() {
+        let dir     = TempDir::new("lunacy-tools-test").unwrap();
+        let path    = dir.path().join("large.free");
+        write_document(&path, 3, true);
+
+        check_commit(&path, 3);
+    }
+}
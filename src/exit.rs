@@ -0,0 +1,56 @@
+/// The code the tool exits with, so shell scripts can tell why a run failed.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Exit {
+    /// A check found problems, or the run failed for any other reason.
+    Failure         = 1,
+    /// The program arguments were invalid.
+    InvalidArgs     = 2,
+    /// A color scheme couldn't be read or was malformed.
+    Scheme          = 3,
+    /// A document couldn't be opened, read or written.
+    Document        = 4,
+    /// There was nothing to do, e.g. no colors were given to apply.
+    NothingToDo     = 5,
+    /// Colors failed a contrast check.
+    Contrast        = 6,
+}
+
+impl Exit {
+    /// Print `message` as an error and exit with the code.
+    ///
+    /// The failure unwinds back to `main`, which exits once everything along the way is dropped,
+    /// so temp directories and files are still cleaned up.
+    pub fn fail(self, message: impl std::fmt::Display) -> ! {
+        std::panic::resume_unwind(Box::new(Failure { code: self, message: Some(message.to_string()) }))
+    }
+
+    /// Exit with the code without printing an error, e.g. once a check has printed the problems
+    /// it found.
+    pub fn quit(self) -> ! {
+        std::panic::resume_unwind(Box::new(Failure { code: self, message: None }))
+    }
+}
+
+/// A failure unwinding back to `main` to exit with its code.
+#[derive(Clone, Debug)]
+pub struct Failure {
+    /// The code to exit with.
+    pub code    : Exit,
+    /// The error to print before exiting, if any.
+    pub message : Option<String>,
+}
+
+/// Exit with a code when a result is an error, in place of `expect`.
+pub trait OrExit<T> {
+    /// Get the value of the result, or print `message` with the error and exit with `code`.
+    fn or_exit(self, code: Exit, message: &str) -> T;
+}
+
+impl<T, E: std::fmt::Display> OrExit<T> for Result<T, E> {
+    fn or_exit(self, code: Exit, message: &str) -> T {
+        match self {
+            Ok(value)   => value,
+            Err(e)      => code.fail(format!("{message}, {e}")),
+        }
+    }
+}
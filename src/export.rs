@@ -0,0 +1,285 @@
+use std::fmt::Write;
+
+use json::JsonValue;
+
+use crate::palette;
+use crate::schema::DocumentJson;
+
+/// The names of the export targets, as accepted by `Target::from_str`.
+pub const TARGETS: &[&str] = &["css-gradients", "env", "json", "chakra", "mui"];
+
+/// The names of the shapes of json exports, as accepted by `Shape::from_str`.
+pub const SHAPES: &[&str] = &["nested", "flat"];
+
+/// The key a color takes in a nested json export when other colors are nested under its name,
+/// as Tailwind and Chakra name it.
+const DEFAULT_KEY: &str = "DEFAULT";
+
+/// The contrast ratio from which MUI puts white text on a color rather than dark text, its
+/// default `contrastThreshold`.
+const MUI_CONTRAST_THRESHOLD: f64 = 3.0;
+
+/// The text color MUI puts on light colors, its default `text.primary`.
+const MUI_DARK_TEXT: &str = "rgba(0, 0, 0, 0.87)";
+
+/// A format the colors of a document can be exported to.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Target {
+    /// CSS custom properties holding a `linear-gradient()` spanning each ramp of tints.
+    CssGradients,
+    /// Shell variable assignments, one per color, that can be sourced by scripts.
+    Env,
+    /// A json theme object, for Storybook theming and JavaScript apps.
+    Json,
+    /// The `colors` object of a Chakra UI theme.
+    Chakra,
+    /// The `palette` object of a MUI theme, with the `main`, `light`, `dark` and `contrastText`
+    /// of each color.
+    Mui,
+}
+
+/// The shape of a json export.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Shape {
+    /// Objects nested by the levels of color names, e.g. `{ "blue": { "500": "#3714ae" } }`.
+    #[default]
+    Nested,
+    /// A single object keyed by the full names of colors, e.g. `{ "blue-500": "#3714ae" }`.
+    Flat,
+}
+
+impl std::str::FromStr for Shape {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "nested"    => Ok(Self::Nested),
+            "flat"      => Ok(Self::Flat),
+            _           => Err(format!("unknown json shape `{s}`")),
+        }
+    }
+}
+
+/// Options shaping an export.
+#[derive(Clone, Debug)]
+pub struct ExportOptions {
+    /// The separator between the levels of color names.
+    pub separator   : String,
+    /// The prefix of every exported name, e.g. `THEME_`, where the target names colors freely.
+    pub prefix      : String,
+    /// The shape of json exports.
+    pub shape       : Shape,
+}
+
+impl Default for ExportOptions {
+    fn default() -> Self {
+        Self {
+            separator   : palette::SEPARATOR.to_owned(),
+            prefix      : String::new(),
+            shape       : Shape::default(),
+        }
+    }
+}
+
+impl std::str::FromStr for Target {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "css-gradients" => Ok(Self::CssGradients),
+            "env"           => Ok(Self::Env),
+            "json"          => Ok(Self::Json),
+            "chakra"        => Ok(Self::Chakra),
+            "mui"           => Ok(Self::Mui),
+            _               => Err(format!("unknown export target `{s}`")),
+        }
+    }
+}
+
+/// Export the colors in `group` of a `document.json` to `target`.
+pub fn export(document: &DocumentJson, group: &str, target: Target, options: &ExportOptions) -> String {
+    match target {
+        Target::CssGradients    => css_gradients(document, group, &options.separator),
+        Target::Env             => env(document, group, options),
+        Target::Json            => json(document, group, options),
+        Target::Chakra          => chakra(document, group, options),
+        Target::Mui             => mui(document, group, options),
+    }
+}
+
+/// Export each ramp of tints in `group` as a CSS custom property holding a `linear-gradient()`
+/// from its lightest to its darkest tint, with the tints spaced evenly, e.g.
+/// `--blue-gradient: linear-gradient(to right, #d7d0ef 0%, ..., #14073f 100%);`.
+fn css_gradients(document: &DocumentJson, group: &str, separator: &str) -> String {
+    let mut css = String::from(":root {\n");
+    for ramp in palette::ramps(document, group, separator) {
+        let last    = (ramp.tints.len() - 1) as f64;
+        let stops   = ramp.tints.iter()
+            .enumerate()
+            .map(|(i, (_, value))| format!("#{} {}%", value.to_lowercase(), (i as f64 / last * 10000.0).round() / 100.0))
+            .collect::<Vec<_>>()
+            .join(", ");
+
+        // Writing to a string can't fail.
+        let _ = writeln!(css, "    --{}-gradient: linear-gradient(to right, {stops});", css_name(&ramp.name));
+    }
+
+    css.push_str("}\n");
+    css
+}
+
+/// Export each color in `group` as a shell variable assignment named after the color in upper
+/// case, e.g. `THEME_BLUE_500=#3b82f6` with the `THEME_` prefix.
+fn env(document: &DocumentJson, group: &str, options: &ExportOptions) -> String {
+    let mut env = String::new();
+    for (path, value) in colors(document, group, &options.separator) {
+        let name = path.iter()
+            .flat_map(|x| x.split(|x: char| !x.is_ascii_alphanumeric()))
+            .filter(|x| !x.is_empty())
+            .collect::<Vec<_>>()
+            .join("_")
+            .to_uppercase();
+
+        // Writing to a string can't fail.
+        let _ = writeln!(env, "{}{name}={value}", options.prefix);
+    }
+
+    env
+}
+
+/// Export the colors in `group` as a json theme object, either nested by the levels of their
+/// names or flat and keyed by their full names with the prefix.
+fn json(document: &DocumentJson, group: &str, options: &ExportOptions) -> String {
+    let colors = colors(document, group, &options.separator);
+    if options.shape == Shape::Nested {
+        return nested(colors).pretty(2) + "\n";
+    }
+
+    let mut theme = JsonValue::new_object();
+    for (path, value) in colors {
+        theme[format!("{}{}", options.prefix, css_name(&path.join(" ")))] = value.into();
+    }
+
+    theme.pretty(2) + "\n"
+}
+
+/// Export the colors in `group` as the `colors` object of a Chakra UI theme, each ramp a scale of
+/// its stops, e.g. `{ "colors": { "blue": { "100": "#d7d0ef", ... } } }`.
+fn chakra(document: &DocumentJson, group: &str, options: &ExportOptions) -> String {
+    let mut theme = JsonValue::new_object();
+    theme["colors"] = nested(colors(document, group, &options.separator));
+
+    theme.pretty(2) + "\n"
+}
+
+/// Export the colors in `group` as the `palette` object of a MUI theme.
+///
+/// Each ramp of tints takes its tint at stop 500, or its middle tint, as `main`, and the tints two
+/// stops lighter and darker as `light` and `dark`. Other colors only give `main`, leaving MUI to
+/// derive the rest. `contrastText` is white or dark text as MUI would choose it.
+fn mui(document: &DocumentJson, group: &str, options: &ExportOptions) -> String {
+    let ramps = palette::ramps(document, group, &options.separator);
+
+    let mut palette = JsonValue::new_object();
+    for ramp in &ramps {
+        let tint = |i: usize| format!("#{}", ramp.tints[i].1.to_lowercase());
+        let main = ramp.tints.iter()
+            .position(|(stop, _)| *stop == 500)
+            .unwrap_or(ramp.tints.len() / 2);
+
+        palette[&ramp.name] = json::object! {
+            main            : tint(main),
+            light           : tint(main.saturating_sub(2)),
+            dark            : tint((main + 2).min(ramp.tints.len() - 1)),
+            contrastText    : contrast_text(&tint(main)),
+        };
+    }
+
+    for (path, value) in colors(document, group, &options.separator) {
+        if path.len() > 1 || ramps.iter().any(|x| x.name == path[0]) {
+            continue;
+        }
+
+        palette[&path[0]] = json::object! {
+            main            : value.as_str(),
+            contrastText    : contrast_text(&value),
+        };
+    }
+
+    let mut theme = JsonValue::new_object();
+    theme["palette"] = palette;
+
+    theme.pretty(2) + "\n"
+}
+
+/// Get the text color MUI puts on the hex color `value`, white where it contrasts enough and
+/// dark text otherwise.
+fn contrast_text(value: &str) -> &'static str {
+    match palette::contrast_ratio(value, "#ffffff").unwrap_or_default() >= MUI_CONTRAST_THRESHOLD {
+        true    => "#fff",
+        false   => MUI_DARK_TEXT,
+    }
+}
+
+/// Nest `colors` in objects by the levels of their names, a color with colors nested under its
+/// name moving to the default key of their object.
+fn nested(colors: Vec<(Vec<String>, String)>) -> JsonValue {
+    let mut theme = JsonValue::new_object();
+    for (path, value) in colors {
+        let mut object = &mut theme;
+        for level in &path[..path.len() - 1] {
+            if object[level].is_string() {
+                let value = object[level].take();
+                object[level][DEFAULT_KEY] = value;
+            }
+            object = &mut object[level];
+        }
+
+        let last = &path[path.len() - 1];
+        match object[last].is_object() {
+            true    => object[last][DEFAULT_KEY] = value.into(),
+            false   => object[last] = value.into(),
+        }
+    }
+
+    theme
+}
+
+/// Get the colors in `group` of a `document.json` in order, each with the levels of its name
+/// within the group and its value, e.g. `#3b82f6`.
+///
+/// A tint named after the color it's a tint of drops the level repeating it, so
+/// `blue / blue.500` is at `["blue", "500"]`.
+fn colors(document: &DocumentJson, group: &str, separator: &str) -> Vec<(Vec<String>, String)> {
+    let prefix = format!("{group}{separator}");
+
+    let mut colors = vec![];
+    for variable in document.color_variables() {
+        let Some(name) = variable.name.strip_prefix(&prefix) else {
+            continue;
+        };
+
+        let mut path = name.split(separator.trim())
+            .map(|x| x.trim().to_owned())
+            .collect::<Vec<_>>();
+
+        if let [.., parent, last] = path.as_mut_slice() {
+            if let Some(stop) = last.strip_prefix(parent.as_str()).and_then(|x| x.strip_prefix('.')) {
+                *last = stop.to_owned();
+            }
+        }
+
+        colors.push((path, format!("#{}", variable.value.to_lowercase())));
+    }
+
+    colors
+}
+
+/// Convert a color name to a CSS identifier, e.g. `brand / Blue` to `brand-blue`.
+fn css_name(name: &str) -> String {
+    name.to_lowercase()
+        .split(|x: char| !x.is_ascii_alphanumeric())
+        .filter(|x| !x.is_empty())
+        .collect::<Vec<_>>()
+        .join("-")
+}
@@ -0,0 +1,182 @@
+//! A C interface to the document engine, so plugins and tools written in other languages can
+//! theme documents. Functions returning `int` return `0` on success and `-1` on failure, with a
+//! description of the failure available from `lunacy_last_error`.
+
+use std::cell::RefCell;
+use std::ffi::{c_char, c_int, CStr, CString};
+use std::panic::AssertUnwindSafe;
+
+use crate::archive::{Document, ExtractLimits};
+use crate::palette::{ColorScheme, RampSpace, STOPS};
+use crate::Error;
+
+thread_local! {
+    /// The error raised by the last failed call on this thread.
+    static LAST_ERROR: RefCell<Option<CString>> = const { RefCell::new(None) };
+}
+
+/// A document opened from a buffer, with the changes made to it since.
+pub type LunacyDocument = Document;
+
+/// Open the `.free` document in the `len` bytes at `data`, returning a handle to it, or null if
+/// it can't be read. The buffer is copied, so can be freed once the call returns.
+///
+/// # Safety
+/// `data` must point to at least `len` readable bytes.
+#[no_mangle]
+pub unsafe extern "C" fn lunacy_open(data: *const u8, len: usize) -> *mut LunacyDocument {
+    if data.is_null() {
+        set_last_error("expected a document");
+        return std::ptr::null_mut();
+    }
+
+    let data = std::slice::from_raw_parts(data, len).to_vec();
+    let doc  = guard(|| Document::open(data, ExtractLimits::default()));
+
+    match doc {
+        Some(doc)   => Box::into_raw(Box::new(doc)),
+        None        => std::ptr::null_mut(),
+    }
+}
+
+/// Apply a color scheme to the colors in `group` of a document. `scheme` is a json object in the
+/// same form as a `--color_scheme` file, with tints generated at the default stops in sRGB.
+///
+/// # Safety
+/// `doc` must be a handle returned by `lunacy_open` that hasn't been closed, and `group` and
+/// `scheme` nul terminated utf-8 strings.
+#[no_mangle]
+pub unsafe extern "C" fn lunacy_update_colors(
+    doc     : *mut LunacyDocument,
+    group   : *const c_char,
+    scheme  : *const c_char
+)
+    -> c_int
+{
+    let (Some(doc), Some(group), Some(scheme)) = (doc.as_mut(), string(group), string(scheme)) else {
+        set_last_error("expected a document, group and scheme");
+        return -1;
+    };
+
+    let result = guard(|| {
+        let mut color_scheme = ColorScheme::new(STOPS.to_vec(), RampSpace::default());
+        color_scheme.add_colors(&json::parse(scheme)?);
+
+        doc.update_colors(group, &color_scheme)
+    });
+
+    status(result)
+}
+
+/// Commit the changes made to a document, writing the updated document to a new buffer. The
+/// buffer is stored in `out` and its length in `out_len`, and must be freed with
+/// `lunacy_free_buffer`.
+///
+/// # Safety
+/// `doc` must be a handle returned by `lunacy_open` that hasn't been closed, and `out` and
+/// `out_len` valid for writes.
+#[no_mangle]
+pub unsafe extern "C" fn lunacy_commit(doc: *mut LunacyDocument, out: *mut *mut u8, out_len: *mut usize) -> c_int {
+    let Some(doc) = doc.as_mut() else {
+        set_last_error("expected a document");
+        return -1;
+    };
+    if out.is_null() || out_len.is_null() {
+        set_last_error("expected an output buffer");
+        return -1;
+    }
+
+    let result = guard(|| Ok(Box::<[u8]>::from(doc.commit()?)));
+
+    let Some(buffer) = result else {
+        return -1;
+    };
+
+    *out_len    = buffer.len();
+    *out        = Box::into_raw(buffer) as *mut u8;
+
+    0
+}
+
+/// Free a buffer returned by `lunacy_commit`.
+///
+/// # Safety
+/// `data` and `len` must be a buffer and length returned by `lunacy_commit` that hasn't been
+/// freed, or null.
+#[no_mangle]
+pub unsafe extern "C" fn lunacy_free_buffer(data: *mut u8, len: usize) {
+    if !data.is_null() {
+        drop(Box::from_raw(std::ptr::slice_from_raw_parts_mut(data, len)));
+    }
+}
+
+/// Close a document, discarding any uncommitted changes.
+///
+/// # Safety
+/// `doc` must be a handle returned by `lunacy_open` that hasn't been closed, or null.
+#[no_mangle]
+pub unsafe extern "C" fn lunacy_close(doc: *mut LunacyDocument) {
+    if !doc.is_null() {
+        drop(Box::from_raw(doc));
+    }
+}
+
+/// Get a description of the last failure on the calling thread, or null if nothing has failed.
+/// The string is owned by the library and valid until the next failing call on the thread.
+#[no_mangle]
+pub extern "C" fn lunacy_last_error() -> *const c_char {
+    LAST_ERROR.with(|x| {
+        x.borrow()
+            .as_ref()
+            .map_or(std::ptr::null(), |x| x.as_ptr())
+    })
+}
+
+/// Run `f`, recording its error, or a panic raised by it, as the last error.
+///
+/// Panics can't unwind into foreign code, so are caught here.
+fn guard<T>(f: impl FnOnce() -> Result<T, Error>) -> Option<T> {
+    let result = std::panic::catch_unwind(AssertUnwindSafe(f))
+        .unwrap_or_else(|x| {
+            let message = x.downcast_ref::<&str>().map(|x| x.to_string())
+                .or_else(|| x.downcast_ref::<String>().cloned())
+                .unwrap_or_else(|| String::from("unknown error"));
+
+            Err(Box::from(message))
+        });
+
+    match result {
+        Ok(x)   => Some(x),
+        Err(e)  => {
+            set_last_error(&e.to_string());
+            None
+        }
+    }
+}
+
+/// Convert the result of a call to its status code.
+fn status(result: Option<()>) -> c_int {
+    match result {
+        Some(_) => 0,
+        None    => -1,
+    }
+}
+
+/// Record `message` as the last error on the calling thread.
+fn set_last_error(message: &str) {
+    let message = CString::new(message.replace('\0', ""))
+        .unwrap_or_default();
+
+    LAST_ERROR.with(|x| *x.borrow_mut() = Some(message));
+}
+
+/// Borrow a nul terminated utf-8 string, or `None` if it's null or not utf-8.
+///
+/// # Safety
+/// `value` must be null or a nul terminated string that outlives the returned borrow.
+unsafe fn string<'a>(value: *const c_char) -> Option<&'a str> {
+    match value.is_null() {
+        true    => None,
+        false   => CStr::from_ptr(value).to_str().ok(),
+    }
+}
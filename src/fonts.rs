@@ -0,0 +1,192 @@
+use json::JsonValue;
+use ttf_parser::{name_id, Permissions};
+
+use crate::model::FontRef;
+
+/// The file extensions of the font files that can be identified.
+pub const FONT_EXTENSIONS: &[&str] = &["ttf", "otf", "ttc"];
+
+/// Identify the family, weight and style of an encoded font file.
+pub fn identify(data: &[u8]) -> Option<FontRef> {
+    let face = ttf_parser::Face::parse(data, 0).ok()?;
+
+    // Prefer the typographic family, which groups every weight under a single name.
+    let family = find_name(&face, &[name_id::TYPOGRAPHIC_FAMILY, name_id::FAMILY])?;
+
+    Some(FontRef {
+        family,
+        weight  : face.weight().to_number() as u32,
+        italic  : face.is_italic(),
+    })
+}
+
+/// The licensing information recorded in a font file.
+pub struct FontLicense {
+    /// Whether the font's embedding permissions allow it to be installed.
+    pub installable : bool,
+    /// The license description.
+    pub description : Option<String>,
+    /// The URL of the full license.
+    pub url         : Option<String>,
+}
+
+/// Read the licensing information recorded in an encoded font file.
+pub fn license(data: &[u8]) -> Option<FontLicense> {
+    let face = ttf_parser::Face::parse(data, 0).ok()?;
+
+    // Fonts without embedding permissions recorded have no restrictions on installation.
+    Some(FontLicense {
+        installable : face.permissions().is_none_or(|x| x == Permissions::Installable),
+        description : find_name(&face, &[name_id::LICENSE]),
+        url         : find_name(&face, &[name_id::LICENSE_URL]),
+    })
+}
+
+/// Find the first of the names `ids` recorded in `face`.
+fn find_name(face: &ttf_parser::Face, ids: &[u16]) -> Option<String> {
+    ids.iter().find_map(|id| {
+        face.names()
+            .into_iter()
+            .filter(|x| x.name_id == *id && x.is_unicode())
+            .find_map(|x| x.to_string())
+    })
+}
+
+/// A font family with an optional weight and style, e.g. `Helvetica Neue 700 italic`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct FontPattern {
+    /// The font family.
+    pub family  : String,
+    /// The numeric font weight, matching any weight if unspecified.
+    pub weight  : Option<u32>,
+    /// Whether the italic style is used, matching either style if unspecified.
+    pub italic  : Option<bool>,
+}
+
+impl FontPattern {
+    /// Check whether `font` matches the pattern.
+    fn matches(&self, font: &FontRef) -> bool {
+        self.family.eq_ignore_ascii_case(&font.family)
+            && self.weight.is_none_or(|x| x == font.weight)
+            && self.italic.is_none_or(|x| x == font.italic)
+    }
+
+    /// The number of properties the pattern specifies beyond the family.
+    fn specificity(&self) -> usize {
+        self.weight.is_some() as usize + self.italic.is_some() as usize
+    }
+}
+
+impl std::str::FromStr for FontPattern {
+    type Err = FontMappingError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut words   = s.split_whitespace().collect::<Vec<_>>();
+        let mut italic  = None;
+        let mut weight  = None;
+
+        // The style and weight are optional trailing words, in either order.
+        for _ in 0..2 {
+            match words.last() {
+                Some(x) if x.eq_ignore_ascii_case("italic")                 => italic = Some(true),
+                Some(x) if x.eq_ignore_ascii_case("normal")                 => italic = Some(false),
+                Some(x) if weight.is_none() && x.parse::<u32>().is_ok()     => weight = x.parse().ok(),
+                _                                                           => break,
+            }
+            words.pop();
+        }
+
+        if words.is_empty() {
+            return Err(FontMappingError::Pattern(s.to_owned()));
+        }
+
+        Ok(Self {
+            family  : words.join(" "),
+            weight,
+            italic,
+        })
+    }
+}
+
+/// A mapping from the fonts used by a document to their replacements.
+///
+/// Each rule maps a pattern to a replacement, with the weight and style of the original font kept
+/// unless the replacement specifies them. Where several rules match a font the most specific one
+/// wins, so `Helvetica Neue=Inter` can be combined with `Helvetica Neue 500=Inter 600`.
+#[derive(Debug, Default)]
+pub struct FontMapping {
+    rules: Vec<(FontPattern, FontPattern)>,
+}
+
+impl FontMapping {
+    /// Add the rule `from=to` to the mapping.
+    pub fn add_rule(&mut self, rule: &str) -> Result<(), FontMappingError> {
+        let Some((from, to)) = rule.split_once('=') else {
+            return Err(FontMappingError::Rule(rule.to_owned()));
+        };
+
+        self.rules.push((from.parse()?, to.parse()?));
+        Ok(())
+    }
+
+    /// Add the rules in a JSON object mapping each pattern to its replacement.
+    pub fn add_json(&mut self, json: &JsonValue) -> Result<(), FontMappingError> {
+        if !json.is_object() {
+            return Err(FontMappingError::NotAnObject);
+        }
+
+        for (from, to) in json.entries() {
+            let Some(to) = to.as_str() else {
+                return Err(FontMappingError::Rule(from.to_owned()));
+            };
+
+            self.rules.push((from.parse()?, to.parse()?));
+        }
+
+        Ok(())
+    }
+
+    /// Check whether the mapping has no rules.
+    pub fn is_empty(&self) -> bool {
+        self.rules.is_empty()
+    }
+
+    /// Get the replacement for `font`, if any rule matches it and changes it.
+    pub fn apply(&self, font: &FontRef) -> Option<FontRef> {
+        let (_, to) = self.rules
+            .iter()
+            .filter(|(from, _)| from.matches(font))
+            .max_by_key(|(from, _)| from.specificity())?;
+
+        let replaced = FontRef {
+            family  : to.family.clone(),
+            weight  : to.weight.unwrap_or(font.weight),
+            italic  : to.italic.unwrap_or(font.italic),
+        };
+
+        (replaced != *font).then_some(replaced)
+    }
+}
+
+/// An error encountered while parsing a font mapping.
+#[derive(Debug)]
+pub enum FontMappingError {
+    /// A rule wasn't of the form `from=to`.
+    Rule(String),
+    /// A font pattern didn't name a font family.
+    Pattern(String),
+    /// A mapping file wasn't a JSON object.
+    NotAnObject,
+}
+
+impl std::fmt::Display for FontMappingError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Rule(x)    => write!(f, "invalid font mapping `{x}`, expected `from=to`"),
+            Self::Pattern(x) => write!(f, "invalid font `{x}`, expected a family with an optional weight and style"),
+            Self::NotAnObject       => write!(f, "font mapping file must be a JSON object"),
+        }
+    }
+}
+
+impl std::error::Error for FontMappingError { }
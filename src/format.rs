@@ -0,0 +1,198 @@
+use serde::Deserialize;
+use serde_json::Value;
+
+use crate::document::LunacyDocument;
+use crate::model;
+use crate::Error;
+use crate::output::warning;
+
+/// The newest document format version the tool understands.
+pub const SUPPORTED_VERSION: u32 = 2;
+
+/// The entries that may hold the document format version, in order of preference.
+const VERSION_ENTRIES: &[&str] = &["meta.json", "document.json"];
+
+/// The key the document format version is stored under.
+pub const VERSION_KEY: &str = "formatVersion";
+
+/// The part of an entry recording the format version, so the rest is skipped when it's parsed.
+#[derive(Clone, Deserialize)]
+struct Versioned {
+    /// The format version, see `VERSION_KEY`.
+    #[serde(rename = "formatVersion", default)]
+    format_version: Option<Value>,
+}
+
+/// Read the format version of `doc`, if it records one.
+pub fn read_version(doc: &LunacyDocument) -> Result<Option<u32>, Error> {
+    for entry in VERSION_ENTRIES {
+        if !doc.has_entry(entry) {
+            continue;
+        }
+
+        let json = doc.load::<Versioned>(entry)?;
+        if let Some(version) = json.format_version.and_then(|x| u32::try_from(x.as_u64()?).ok()) {
+            return Ok(Some(version));
+        }
+    }
+
+    Ok(None)
+}
+
+/// Check the document format `version` is one the tool understands.
+///
+/// Newer versions are refused when `strict`, otherwise a warning is printed since the tool may
+/// write data the newer format considers invalid.
+pub fn check_version(version: Option<u32>, strict: bool) -> Result<(), FormatError> {
+    let Some(version) = version else {
+        return Ok(());
+    };
+
+    if version <= SUPPORTED_VERSION {
+        return Ok(());
+    }
+
+    if strict {
+        return Err(FormatError::Unsupported(version));
+    }
+
+    warning!("warning: {}, changes may not be valid", FormatError::Unsupported(version));
+
+    Ok(())
+}
+
+/// An error raised when a document is in a format the tool doesn't understand.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum FormatError {
+    /// The document is in a newer format than the tool supports.
+    Unsupported(u32),
+    /// A document can't be migrated to an older format.
+    Downgrade {
+        /// The version of the document.
+        from: u32,
+        /// The version the document was to be migrated to.
+        to  : u32,
+    },
+    /// There is no migration from a format version.
+    NoMigration(u32),
+}
+
+impl std::fmt::Display for FormatError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Self::Unsupported(x)            => write!(f, "document format version {x} is newer than the supported version {SUPPORTED_VERSION}"),
+            Self::Downgrade { from, to }    => write!(f, "can't migrate document format version {from} down to {to}"),
+            Self::NoMigration(x)            => write!(f, "no migration exists from document format version {x}"),
+        }
+    }
+}
+
+impl std::error::Error for FormatError { }
+
+/// Record `version` as the format version of `doc`.
+pub fn write_version(doc: &mut LunacyDocument, version: u32) -> Result<(), Error> {
+    // Update the version where it's already recorded, falling back to the document itself.
+    let mut entry = VERSION_ENTRIES[VERSION_ENTRIES.len() - 1];
+    for x in VERSION_ENTRIES {
+        if doc.has_entry(x) && doc.load_json(x)?.has_key(VERSION_KEY) {
+            entry = x;
+            break;
+        }
+    }
+
+    let mut json = doc.load_json(entry)?;
+    json[VERSION_KEY] = version.into();
+
+    doc.save_json(entry, &json)
+}
+
+/// A migration upgrading a document from one format version to the next.
+pub struct Migration {
+    /// The format version the migration upgrades from.
+    pub from        : u32,
+    /// A short description of the changes made by the migration.
+    pub description : &'static str,
+    /// Apply the migration to a document.
+    pub apply       : fn(&mut LunacyDocument) -> Result<(), Error>,
+}
+
+/// Every known migration, in order of the version they upgrade from.
+pub const MIGRATIONS: &[Migration] = &[
+    Migration {
+        from        : 1,
+        description : "store color variable values as uppercase hex without a leading `#`, and text layer contents under `text`",
+        apply       : migrate_v1,
+    },
+];
+
+/// Upgrade a document from format version 1, which stored color variable values as `#rrggbb` or
+/// shorthand `#rgb` hex and the contents of text layers under `string`.
+fn migrate_v1(doc: &mut LunacyDocument) -> Result<(), Error> {
+    let mut document    = doc.load_json("document.json")?;
+    let mut changed     = false;
+    for variable in document["colorVariables"].members_mut() {
+        let Some(value) = variable["value"].as_str() else {
+            continue;
+        };
+
+        let hex = value.strip_prefix('#').unwrap_or(value);
+        let hex = match hex.len() {
+            3 | 4   => hex.chars().flat_map(|x| [x, x]).collect(),
+            _       => hex.to_owned(),
+        };
+
+        let hex = hex.to_uppercase();
+        if hex != value {
+            variable["value"]   = hex.into();
+            changed             = true;
+        }
+    }
+
+    if changed {
+        doc.save_json("document.json", &document)?;
+    }
+
+    // Documents imported from Sketch store the contents of text layers in their own way, and are
+    // left as they are.
+    for (entry, mut page) in model::load_pages(doc)? {
+        let mut changed = false;
+        model::visit_layers_mut(&mut page, &mut |layer| {
+            if !layer.has_key("type") || layer.has_key("text") || !layer["string"].is_string() {
+                return;
+            }
+
+            layer["text"]   = layer.remove("string");
+            changed         = true;
+        });
+
+        if changed {
+            doc.save_json(&entry, &page)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Migrate `doc` from format version `from` to `to`, returning the migrations applied.
+pub fn migrate(doc: &mut LunacyDocument, from: u32, to: u32) -> Result<Vec<&'static Migration>, Error> {
+    if to > SUPPORTED_VERSION {
+        return Err(Box::new(FormatError::Unsupported(to)));
+    }
+    if from > to {
+        return Err(Box::new(FormatError::Downgrade { from, to }));
+    }
+
+    let mut applied = vec![];
+    for version in from..to {
+        let Some(migration) = MIGRATIONS.iter().find(|x| x.from == version) else {
+            return Err(Box::new(FormatError::NoMigration(version)));
+        };
+
+        (migration.apply)(doc)?;
+        applied.push(migration);
+    }
+
+    write_version(doc, to)?;
+
+    Ok(applied)
+}
@@ -0,0 +1,335 @@
+//! The HCT color space of Material Design 3, describing colors by the hue and chroma of the CAM16
+//! color appearance model and the tone of CIE L*, so tones of the same hue and chroma keep their
+//! contrast with each other whatever the hue. Used to generate tonal palettes as Material 3
+//! dynamic color does.
+
+use crate::palette::RampSpace;
+
+/// The white point of sRGB, D65, in XYZ from 0 to 100.
+const WHITE_POINT : [f64; 3]
+    = [95.047, 100.0, 108.883];
+
+/// The steps chroma is reduced by when searching for the chroma of a color that fits in the
+/// sRGB gamut.
+const CHROMA_STEPS : usize
+    = 100;
+
+/// How far the tone of a color clipped to the sRGB gamut may be from the tone asked for.
+const TONE_TOLERANCE : f64
+    = 0.2;
+
+/// The matrix converting linear sRGB, from 0 to 100, to XYZ.
+const SRGB_TO_XYZ : [[f64; 3]; 3] = [
+    [0.41233895, 0.35762064, 0.18051042],
+    [0.2126, 0.7152, 0.0722],
+    [0.01932141, 0.11916382, 0.95034478],
+];
+
+/// The matrix converting XYZ to linear sRGB, from 0 to 100.
+const XYZ_TO_SRGB : [[f64; 3]; 3] = [
+    [3.2413774792388685, -1.5376652402851851, -0.49885366846268053],
+    [-0.9691452513005321, 1.8758853451067872, 0.04156585616912061],
+    [0.05562093689691305, -0.20395524564742123, 1.0571799111220335],
+];
+
+/// The matrix converting XYZ to the cone responses of CAM16.
+const XYZ_TO_CAM16 : [[f64; 3]; 3] = [
+    [0.401288, 0.650173, -0.051461],
+    [-0.250268, 1.204414, 0.045854],
+    [-0.002079, 0.048952, 0.953127],
+];
+
+/// The matrix converting the cone responses of CAM16 back to XYZ.
+const CAM16_TO_XYZ : [[f64; 3]; 3] = [
+    [1.8620678, -1.0112547, 0.14918678],
+    [0.38752654, 0.62144744, -0.00897398],
+    [-0.0158415, -0.03412294, 1.0499644],
+];
+
+/// A color in the HCT color space.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Hct {
+    /// The hue, in degrees from 0 to 360.
+    pub hue     : f64,
+    /// The colorfulness, from 0 for grays up to around 150 depending on the hue and tone.
+    pub chroma  : f64,
+    /// The lightness, from 0 for black to 100 for white.
+    pub tone    : f64,
+}
+
+impl Hct {
+    /// Get the HCT components of the gamma encoded sRGB color `rgb`.
+    pub fn from_rgb(rgb: (f64, f64, f64)) -> Self {
+        let (r, g, b)   = RampSpace::Linear.decode(rgb);
+        let xyz         = multiply(&SRGB_TO_XYZ, [r * 100.0, g * 100.0, b * 100.0]);
+        let view        = ViewingConditions::default();
+
+        let [r, g, b] = multiply(&XYZ_TO_CAM16, xyz);
+        let [r, g, b] = [r * view.rgb_d[0], g * view.rgb_d[1], b * view.rgb_d[2]].map(|x| {
+            let factor = (view.fl * x.abs() / 100.0).powf(0.42);
+            x.signum() * 400.0 * factor / (factor + 27.13)
+        });
+
+        let a   = (11.0 * r - 12.0 * g + b) / 11.0;
+        let b_  = (r + g - 2.0 * b) / 9.0;
+        let u   = (20.0 * r + 20.0 * g + 21.0 * b) / 20.0;
+        let p2  = (40.0 * r + 20.0 * g + b) / 20.0;
+
+        let hue = b_.atan2(a).to_degrees().rem_euclid(360.0);
+        let j   = 100.0 * (p2 * view.nbb / view.aw).powf(view.c * view.z);
+
+        let hue_prime   = match hue < 20.14 {
+            true    => hue + 360.0,
+            false   => hue,
+        };
+        let e_hue       = 0.25 * ((hue_prime.to_radians() + 2.0).cos() + 3.8);
+        let p1          = 50000.0 / 13.0 * e_hue * view.nc * view.ncb;
+        let t           = p1 * a.hypot(b_) / (u + 0.305);
+        let alpha       = t.powf(0.9) * (1.64 - 0.29_f64.powf(view.n)).powf(0.73);
+
+        Self {
+            hue,
+            chroma  : alpha * (j / 100.0).sqrt(),
+            tone    : lstar_from_y(xyz[1]),
+        }
+    }
+
+    /// Get the gamma encoded sRGB color closest to the HCT color, with the same hue and tone and
+    /// its chroma reduced as far as needed to fit in the sRGB gamut.
+    pub fn to_rgb(self) -> (f64, f64, f64) {
+        if self.tone <= 0.0 {
+            return (0.0, 0.0, 0.0);
+        }
+        if self.tone >= 100.0 {
+            return (1.0, 1.0, 1.0);
+        }
+
+        let y       = y_from_lstar(self.tone);
+        let view    = ViewingConditions::default();
+        let target  = solve(&view, self.hue, self.chroma, y);
+        if in_gamut(target) {
+            return encode(target);
+        }
+
+        // Search for the largest chroma that fits by stepping down from the target first.
+        let step    = self.chroma / CHROMA_STEPS as f64;
+        let Some(fits) = (0..CHROMA_STEPS).rev().find(|i| in_gamut(solve(&view, self.hue, *i as f64 * step, y))) else {
+            return clip(&view, self.hue, self.chroma, y);
+        };
+
+        let (mut low, mut high) = (fits as f64 * step, (fits + 1) as f64 * step);
+        for _ in 0..24 {
+            let chroma = (low + high) / 2.0;
+            match in_gamut(solve(&view, self.hue, chroma, y)) {
+                true    => low  = chroma,
+                false   => high = chroma,
+            }
+        }
+
+        encode(solve(&view, self.hue, low, y))
+    }
+}
+
+/// Find the color closest to the `hue` with up to `chroma` at the luminance `y` where no chroma of
+/// the hue fits in the sRGB gamut, as at the lightest and darkest tones of some hues, since CAM16
+/// sees sRGB grays as slightly colorful.
+///
+/// The color is clipped to the gamut at the largest chroma that keeps its tone within
+/// `TONE_TOLERANCE`, as the Material 3 solver allows, and is the sRGB gray of the tone where even
+/// the least chroma doesn't.
+fn clip(view: &ViewingConditions, hue: f64, chroma: f64, y: f64) -> (f64, f64, f64) {
+    let tone    = lstar_from_y(y);
+    let fits    = |chroma: f64| {
+        let (r, g, b)   = solve(view, hue, chroma, y);
+        let clipped     = multiply(&SRGB_TO_XYZ, [r, g, b].map(|x| x.clamp(0.0, 1.0) * 100.0))[1];
+        (lstar_from_y(clipped) - tone).abs() <= TONE_TOLERANCE
+    };
+
+    let (mut low, mut high) = (0.0, chroma);
+    for _ in 0..24 {
+        let chroma = (low + high) / 2.0;
+        match fits(chroma) {
+            true    => low  = chroma,
+            false   => high = chroma,
+        }
+    }
+
+    match low > 0.0 {
+        true    => encode(solve(view, hue, low, y)),
+        false   => {
+            let gray = y / 100.0;
+            encode((gray, gray, gray))
+        }
+    }
+}
+
+/// The conditions colors are viewed in, those of Material Design: an average surround with a
+/// background of L* 50 under the sRGB white point.
+struct ViewingConditions {
+    /// The exponent of the nonlinearity, from the surround.
+    c       : f64,
+    /// The chromatic induction factor, from the surround.
+    nc      : f64,
+    /// The luminance of the background relative to the white point.
+    n       : f64,
+    /// The brightness induction factor.
+    nbb     : f64,
+    /// The chromatic induction factor.
+    ncb     : f64,
+    /// The base exponential nonlinearity.
+    z       : f64,
+    /// The degree of adaptation applied to each cone response.
+    rgb_d   : [f64; 3],
+    /// The luminance level adaptation factor.
+    fl      : f64,
+    /// The achromatic response to the white point.
+    aw      : f64,
+}
+
+impl Default for ViewingConditions {
+    fn default() -> Self {
+        let adapting    = 200.0 / std::f64::consts::PI * y_from_lstar(50.0) / 100.0;
+        let f           = 1.0;
+        let c           = 0.69;
+
+        let white       = multiply(&XYZ_TO_CAM16, WHITE_POINT);
+        let d           = (f * (1.0 - (1.0 / 3.6) * ((-adapting - 42.0) / 92.0).exp())).clamp(0.0, 1.0);
+        let rgb_d       = white.map(|x| d * (100.0 / x) + 1.0 - d);
+
+        let k           = 1.0 / (5.0 * adapting + 1.0);
+        let k4          = k.powi(4);
+        let fl          = k4 * adapting + 0.1 * (1.0 - k4).powi(2) * (5.0 * adapting).cbrt();
+        let n           = y_from_lstar(50.0) / WHITE_POINT[1];
+        let z           = 1.48 + n.sqrt();
+        let nbb         = 0.725 / n.powf(0.2);
+
+        let [r, g, b]   = [0, 1, 2].map(|i| {
+            let factor = (fl * rgb_d[i] * white[i] / 100.0).powf(0.42);
+            400.0 * factor / (factor + 27.13)
+        });
+        let aw          = (2.0 * r + g + 0.05 * b) * nbb;
+
+        Self { c, nc: f, n, nbb, ncb: nbb, z, rgb_d, fl, aw }
+    }
+}
+
+/// Find the linear sRGB color, from 0 to 1, with the CAM16 `hue` and `chroma` and the luminance
+/// `y`, which may be outside the sRGB gamut.
+fn solve(view: &ViewingConditions, hue: f64, chroma: f64, y: f64) -> (f64, f64, f64) {
+    // Luminance rises with lightness at a given hue and chroma, so search for the lightness that
+    // reaches it.
+    let (mut low, mut high) = (0.0, 200.0);
+    for _ in 0..40 {
+        let j = (low + high) / 2.0;
+        match xyz_from_jch(view, j, chroma, hue)[1] < y {
+            true    => low  = j,
+            false   => high = j,
+        }
+    }
+
+    let [r, g, b] = multiply(&XYZ_TO_SRGB, xyz_from_jch(view, (low + high) / 2.0, chroma, hue))
+        .map(|x| x / 100.0);
+
+    (r, g, b)
+}
+
+/// Check whether linear sRGB components are in gamut, allowing for rounding.
+fn in_gamut((r, g, b): (f64, f64, f64)) -> bool {
+    [r, g, b].iter().all(|x| (-1e-4..=1.0 + 1e-4).contains(x))
+}
+
+/// Convert the CAM16 lightness `j`, `chroma` and `hue` to XYZ, from 0 to 100.
+fn xyz_from_jch(view: &ViewingConditions, j: f64, chroma: f64, hue: f64) -> [f64; 3] {
+    let alpha = match chroma == 0.0 || j == 0.0 {
+        true    => 0.0,
+        false   => chroma / (j / 100.0).sqrt(),
+    };
+
+    let t       = (alpha / (1.64 - 0.29_f64.powf(view.n)).powf(0.73)).powf(1.0 / 0.9);
+    let h       = hue.to_radians();
+    let e_hue   = 0.25 * ((h + 2.0).cos() + 3.8);
+    let ac      = view.aw * (j / 100.0).powf(1.0 / view.c / view.z);
+    let p1      = e_hue * (50000.0 / 13.0) * view.nc * view.ncb;
+    let p2      = ac / view.nbb;
+
+    let gamma   = 23.0 * (p2 + 0.305) * t / (23.0 * p1 + 11.0 * t * h.cos() + 108.0 * t * h.sin());
+    let a       = gamma * h.cos();
+    let b       = gamma * h.sin();
+
+    let responses = [
+        (460.0 * p2 + 451.0 * a + 288.0 * b) / 1403.0,
+        (460.0 * p2 - 891.0 * a - 261.0 * b) / 1403.0,
+        (460.0 * p2 - 220.0 * a - 6300.0 * b) / 1403.0,
+    ];
+
+    let mut cone = [0.0; 3];
+    for (i, x) in responses.iter().enumerate() {
+        let base = (27.13 * x.abs() / (400.0 - x.abs())).max(0.0);
+        cone[i] = x.signum() * (100.0 / view.fl) * base.powf(1.0 / 0.42) / view.rgb_d[i];
+    }
+
+    multiply(&CAM16_TO_XYZ, cone)
+}
+
+/// Convert linear sRGB components to gamma encoded ones, clamped to the gamut.
+fn encode((r, g, b): (f64, f64, f64)) -> (f64, f64, f64) {
+    RampSpace::Linear.encode((r.clamp(0.0, 1.0), g.clamp(0.0, 1.0), b.clamp(0.0, 1.0)))
+}
+
+/// Convert the luminance `y`, from 0 to 100, to CIE L*.
+fn lstar_from_y(y: f64) -> f64 {
+    let y = y / 100.0;
+    let f = match y > 216.0 / 24389.0 {
+        true    => y.cbrt(),
+        false   => (24389.0 / 27.0 * y + 16.0) / 116.0,
+    };
+
+    116.0 * f - 16.0
+}
+
+/// Convert CIE L* to the luminance, from 0 to 100.
+fn y_from_lstar(lstar: f64) -> f64 {
+    let f   = (lstar + 16.0) / 116.0;
+    let f3  = f.powi(3);
+    let y   = match f3 > 216.0 / 24389.0 {
+        true    => f3,
+        false   => (116.0 * f - 16.0) / (24389.0 / 27.0),
+    };
+
+    y * 100.0
+}
+
+/// Multiply the vector `v` by the matrix `m`.
+fn multiply(m: &[[f64; 3]; 3], v: [f64; 3]) -> [f64; 3] {
+    m.map(|row| row[0] * v[0] + row[1] * v[1] + row[2] * v[2])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::palette::{hex_to_rgb, rgb_to_hex};
+
+    /// The primary tonal palette of the Material 3 baseline scheme, generated from `#6750a4`.
+    const BASELINE : [(u32, &str); 13] = [
+        (0, "#000000"), (10, "#21005d"), (20, "#381e72"), (30, "#4f378b"), (40, "#6750a4"),
+        (50, "#7f67be"), (60, "#9a82db"), (70, "#b69df8"), (80, "#d0bcff"), (90, "#eaddff"),
+        (95, "#f6edff"), (99, "#fffbfe"), (100, "#ffffff"),
+    ];
+
+    #[test]
+    fn generates_material_baseline_palette() {
+        let hct = Hct::from_rgb(hex_to_rgb("#6750a4").unwrap());
+
+        // The baseline was generated by an earlier solver that accepted colors slightly off their
+        // hue and tone, so each channel may differ by a couple of steps.
+        for (tone, expected) in BASELINE {
+            let (r, g, b)   = Hct { tone: tone as f64, ..hct }.to_rgb();
+            let actual      = hex_to_rgb(&rgb_to_hex(r, g, b)).unwrap();
+            let expected    = hex_to_rgb(expected).unwrap();
+
+            let difference = [actual.0 - expected.0, actual.1 - expected.1, actual.2 - expected.2]
+                .map(|x| (x * 255.0).abs().round() as u32);
+            assert!(difference.iter().all(|x| *x <= 2), "tone {tone} is {}", rgb_to_hex(r, g, b));
+        }
+    }
+}
@@ -0,0 +1,325 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD;
+use json::{object, JsonValue};
+
+use crate::document::LunacyDocument;
+use crate::patch;
+use crate::Error;
+
+/// The directory the tool keeps its own entries in within a document.
+pub const TOOL_DIR: &str = "lunacy-tools/";
+
+/// The entry the change history of a document is kept in.
+pub const HISTORY_ENTRY: &str = "lunacy-tools/history.json";
+
+/// The directory holding the data needed to undo each change.
+pub const UNDO_DIR: &str = "lunacy-tools/undo/";
+
+/// The number of most recent changes that can be undone, older changes have their undo data
+/// dropped to keep the document small.
+pub const UNDO_LIMIT: usize = 20;
+
+/// A change made to a document by the tool.
+#[derive(Clone, Debug)]
+pub struct Record {
+    /// When the change was made, in seconds since the unix epoch, if recorded.
+    pub time        : Option<u64>,
+    /// The version of the tool that made the change.
+    pub version     : String,
+    /// The operation that made the change, e.g. `styles overrides`.
+    pub operation   : String,
+    /// The message given to describe the change.
+    pub message     : Option<String>,
+    /// The entries the change modified or removed.
+    pub entries     : Vec<String>,
+    /// The entry holding the data needed to undo the change, if it can still be undone.
+    pub undo        : Option<String>,
+}
+
+impl Record {
+    /// Create a record of `operation` changing `entries`, timestamped now if `timestamp` is set.
+    pub fn new(operation: &str, message: Option<&str>, entries: Vec<String>, timestamp: bool) -> Self {
+        let time = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .ok()
+            .filter(|_| timestamp)
+            .map(|x| x.as_secs());
+
+        Self {
+            time,
+            version     : env!("CARGO_PKG_VERSION").to_owned(),
+            operation   : operation.to_owned(),
+            message     : message.map(|x| x.to_owned()),
+            entries,
+            undo        : None,
+        }
+    }
+
+    /// Read a record from its json form.
+    pub fn from_json(json: &JsonValue) -> Self {
+        Self {
+            time        : json["time"].as_u64(),
+            version     : json["version"].as_str().unwrap_or_default().to_owned(),
+            operation   : json["operation"].as_str().unwrap_or_default().to_owned(),
+            message     : json["message"].as_str().map(|x| x.to_owned()),
+            entries     : json["entries"].members().filter_map(|x| x.as_str()).map(|x| x.to_owned()).collect(),
+            undo        : json["undo"].as_str().map(|x| x.to_owned()),
+        }
+    }
+
+    /// Convert the record to json for storage.
+    pub fn to_json(&self) -> JsonValue {
+        let mut json = object! {
+            "version"   : self.version.as_str(),
+            "operation" : self.operation.as_str(),
+            "entries"   : self.entries.iter().map(|x| x.as_str()).collect::<Vec<_>>(),
+        };
+
+        if let Some(time) = self.time {
+            json["time"] = time.into();
+        }
+        if let Some(message) = &self.message {
+            json["message"] = message.as_str().into();
+        }
+        if let Some(undo) = &self.undo {
+            json["undo"] = undo.as_str().into();
+        }
+
+        json
+    }
+}
+
+impl std::fmt::Display for Record {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let time = self.time.map(format_time).unwrap_or_else(|| String::from("-"));
+        write!(f, "{time}  {} ({})  {}", self.operation, self.version, self.entries.join(", "))?;
+
+        if let Some(message) = &self.message {
+            write!(f, "\n    {message}")?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Load the change history of `doc`, oldest first.
+pub fn load(doc: &LunacyDocument) -> Result<Vec<Record>, Error> {
+    if !doc.has_entry(HISTORY_ENTRY) {
+        return Ok(vec![]);
+    }
+
+    let json = doc.load_json(HISTORY_ENTRY)?;
+    Ok(json["records"].members().map(Record::from_json).collect())
+}
+
+/// Save `records` as the change history of `doc`.
+fn save(doc: &mut LunacyDocument, records: &[Record]) -> Result<(), Error> {
+    let json = object! {
+        "records": records.iter().map(|x| x.to_json()).collect::<Vec<_>>(),
+    };
+
+    doc.save_json(HISTORY_ENTRY, &json)
+}
+
+/// Record the changes made to `doc` by `operation` in its history, along with the data needed
+/// to undo them.
+pub fn record(doc: &mut LunacyDocument, operation: &str, message: Option<&str>, timestamp: bool) -> Result<(), Error> {
+    // Entries only marked modified, e.g. to be recompressed, weren't changed by the operation.
+    let mut entries = vec![];
+    let mut steps   = JsonValue::new_object();
+    for entry in doc.changed_entries().into_iter().filter(|x| !x.starts_with(TOOL_DIR)) {
+        if doc.is_changed(&entry)? {
+            steps[entry.as_str()] = undo_step(doc, &entry)?;
+            entries.push(entry);
+        }
+    }
+
+    let mut records = load(doc)?;
+    let mut record  = Record::new(operation, message, entries, timestamp);
+
+    let undo = format!("{UNDO_DIR}{}.json", records.len());
+    doc.save_json(&undo, &object! { "entries": steps })?;
+    record.undo = Some(undo);
+    records.push(record);
+
+    // Only the most recent changes are kept undoable.
+    let undoable = records.iter().filter(|x| x.undo.is_some()).count();
+    for record in records.iter_mut().filter(|x| x.undo.is_some()).take(undoable.saturating_sub(UNDO_LIMIT)) {
+        if let Some(undo) = record.undo.take().filter(|x| doc.has_entry(x)) {
+            doc.remove_entry(&undo)?;
+        }
+    }
+
+    save(doc, &records)
+}
+
+/// Merge the histories of two versions of a document, `ours` and `theirs`, with that of their
+/// common ancestor `base`, returning `None` if neither version has a history.
+///
+/// The records of both are kept, ours first and then those theirs added since the ancestor. Each change can only
+/// be undone in the version it was made in, so the undo data of every record is dropped.
+pub fn merge(base: Option<&JsonValue>, ours: Option<&JsonValue>, theirs: Option<&JsonValue>) -> Option<JsonValue> {
+    if ours.is_none() && theirs.is_none() {
+        return None;
+    }
+
+    let records = |json: Option<&JsonValue>| json
+        .map(|x| x["records"].members().map(Record::from_json).collect::<Vec<_>>())
+        .unwrap_or_default();
+
+    // Records theirs shares with the ancestor are already among ours, each counted once so the
+    // same change made again is still kept.
+    let key         = |record: &Record| Record { undo: None, ..record.clone() }.to_json().dump();
+    let mut base    = records(base).iter().map(key).collect::<Vec<_>>();
+    let mut merged  = records(ours);
+    for record in records(theirs) {
+        match base.iter().position(|x| *x == key(&record)) {
+            Some(index) => { base.remove(index); }
+            None        => merged.push(record),
+        }
+    }
+
+    for record in &mut merged {
+        record.undo = None;
+    }
+
+    Some(object! {
+        "records": merged.iter().map(|x| x.to_json()).collect::<Vec<_>>(),
+    })
+}
+
+/// Get the data needed to restore `entry` of `doc` to how it was before it was changed.
+///
+/// Json entries store a patch reversing the change, or their original contents if they were
+/// removed. Other entries aren't copied into the document, so changes to them can't be undone.
+fn undo_step(doc: &LunacyDocument, entry: &str) -> Result<JsonValue, Error> {
+    let Some(original) = doc.load_original(entry)? else {
+        return Ok(object! { "created": true });
+    };
+
+    let original = match entry.ends_with(".json") {
+        true    => json::parse(&String::from_utf8_lossy(&original)).ok(),
+        false   => None,
+    };
+
+    match original {
+        Some(json) if doc.has_entry(entry)  => Ok(object! { "patch": patch::diff(&doc.load_json(entry)?, &json) }),
+        Some(json)                          => Ok(object! { "removed": json }),
+        None                                => Ok(object! { "binary": true }),
+    }
+}
+
+/// Undo the last `count` changes recorded in the history of `doc`, returning the records of the
+/// changes undone, newest first.
+///
+/// The changes are undone by reversing them, so anything else changed since in the same places
+/// may keep the changes from being undone.
+pub fn undo(doc: &mut LunacyDocument, count: usize) -> Result<Vec<Record>, Error> {
+    let mut records = load(doc)?;
+    if count > records.len() {
+        return Err(Box::new(HistoryError::Unrecorded { requested: count, recorded: records.len() }));
+    }
+
+    let mut undone = vec![];
+    for _ in 0..count {
+        let Some(record) = records.pop() else {
+            break;
+        };
+
+        let Some(undo) = record.undo.as_deref().filter(|x| doc.has_entry(x)) else {
+            return Err(Box::new(HistoryError::Irreversible(record.operation)));
+        };
+
+        let steps = doc.load_json(undo)?;
+        if let Some((entry, _)) = steps["entries"].entries().find(|(_, x)| x["binary"].as_bool() == Some(true)) {
+            return Err(Box::new(HistoryError::Binary { operation: record.operation, entry: entry.to_owned() }));
+        }
+
+        for (entry, step) in steps["entries"].entries() {
+            if step["created"].as_bool() == Some(true) {
+                if doc.has_entry(entry) {
+                    doc.remove_entry(entry)?;
+                }
+            }
+            else if step.has_key("patch") {
+                let mut json = doc.load_json(entry)?;
+                patch::apply(&mut json, &step["patch"])?;
+                doc.save_json(entry, &json)?;
+            }
+            else if step.has_key("removed") {
+                doc.save_json(entry, &step["removed"])?;
+            }
+            // Changes recorded before binary entries stopped being copied hold their contents.
+            else if let Some(data) = step["data"].as_str() {
+                doc.save_bytes(entry, &STANDARD.decode(data)?)?;
+            }
+        }
+
+        doc.remove_entry(undo)?;
+        undone.push(record);
+    }
+
+    save(doc, &records)?;
+
+    Ok(undone)
+}
+
+/// An error raised when changes can't be undone.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum HistoryError {
+    /// More changes were to be undone than are recorded.
+    Unrecorded {
+        /// The number of changes to undo.
+        requested   : usize,
+        /// The number of changes recorded.
+        recorded    : usize,
+    },
+    /// A change no longer has the data needed to undo it.
+    Irreversible(String),
+    /// A change modified an entry that isn't json, which isn't recorded to be undone.
+    Binary {
+        /// The operation that made the change.
+        operation   : String,
+        /// The entry that isn't json.
+        entry       : String,
+    },
+}
+
+impl std::fmt::Display for HistoryError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Self::Unrecorded { requested, recorded }    => write!(f, "can't undo {requested} changes when only {recorded} are recorded"),
+            Self::Irreversible(x)                       => write!(f, "the `{x}` change can no longer be undone"),
+            Self::Binary { operation, entry }           => write!(f, "the `{operation}` change can't be undone as it changed `{entry}`, which isn't json"),
+        }
+    }
+}
+
+impl std::error::Error for HistoryError { }
+
+/// Format a time in seconds since the unix epoch as a UTC date and time, e.g.
+/// `2025-04-01 09:30:00`.
+pub fn format_time(time: u64) -> String {
+    let (days, seconds) = (time / 86400, time % 86400);
+
+    // Convert days since the epoch to a civil date, see
+    // http://howardhinnant.github.io/date_algorithms.html#civil_from_days
+    let z       = days as i64 + 719468;
+    let era     = z.div_euclid(146097);
+    let doe     = z.rem_euclid(146097);
+    let yoe     = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy     = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp      = (5 * doy + 2) / 153;
+    let day     = doy - (153 * mp + 2) / 5 + 1;
+    let month   = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year    = yoe + era * 400 + i64::from(month <= 2);
+
+    format!(
+        "{year:04}-{month:02}-{day:02} {:02}:{:02}:{:02}",
+        seconds / 3600,
+        seconds % 3600 / 60,
+        seconds % 60
+    )
+}
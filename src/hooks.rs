@@ -0,0 +1,94 @@
+use std::path::Path;
+use std::process::Command;
+use std::time::Duration;
+
+use json::JsonValue;
+
+use crate::Error;
+
+/// How long to wait for a webhook to respond.
+const WEBHOOK_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Shell commands run around changes to a document.
+#[derive(Clone, Debug, Default)]
+pub struct Hooks {
+    /// The commands run before a document is extracted.
+    pub pre_open    : Vec<String>,
+    /// The commands run after changes are successfully committed to a document.
+    pub post_commit : Vec<String>,
+    /// The url a report of the changes is posted to after they're committed to a document.
+    pub webhook     : Option<String>,
+}
+
+/// Run each of `commands` in the shell in turn with `path` as their argument, stopping at the
+/// first that fails.
+///
+/// The path is appended to the command, so `git add` runs as `git add <path>`, and is available
+/// as `$1` for commands that need it elsewhere.
+pub fn run(commands: &[String], path: &Path) -> Result<(), Error> {
+    for command in commands {
+        let status = shell(command, path).status()?;
+        if !status.success() {
+            return Err(Box::new(HookError { command: command.to_owned(), code: status.code() }));
+        }
+    }
+
+    Ok(())
+}
+
+/// Post the json `report` of changes made to a document to the webhook at `url`.
+pub fn notify(url: &str, report: &JsonValue) -> Result<(), Error> {
+    let agent = ureq::Agent::config_builder()
+        .timeout_global(Some(WEBHOOK_TIMEOUT))
+        .build()
+        .new_agent();
+
+    agent.post(url)
+        .header("Content-Type", "application/json")
+        .send(report.dump())?;
+
+    Ok(())
+}
+
+/// Create the shell process running `command` with `path` as its argument.
+#[cfg(not(windows))]
+fn shell(command: &str, path: &Path) -> Command {
+    let mut process = Command::new("sh");
+    process.arg("-c")
+        .arg(format!("{command} \"$@\""))
+        .arg("sh")
+        .arg(path);
+
+    process
+}
+
+/// Create the shell process running `command` with `path` as its argument.
+#[cfg(windows)]
+fn shell(command: &str, path: &Path) -> Command {
+    let mut process = Command::new("cmd");
+    process.arg("/C")
+        .arg(command)
+        .arg(path);
+
+    process
+}
+
+/// An error raised when a hook command fails.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct HookError {
+    /// The command that failed.
+    pub command : String,
+    /// The exit code of the command, if it exited rather than being killed.
+    pub code    : Option<i32>,
+}
+
+impl std::fmt::Display for HookError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self.code {
+            Some(code)  => write!(f, "hook `{}` failed with exit code {code}", self.command),
+            None        => write!(f, "hook `{}` was terminated", self.command),
+        }
+    }
+}
+
+impl std::error::Error for HookError { }
@@ -0,0 +1,125 @@
+use std::fmt::Write;
+
+use json::{array, object, JsonValue};
+use resvg::tiny_skia::{self, PathSegment, Transform};
+use resvg::usvg::{self, TreeParsing, TreePostProc};
+
+use crate::model::{self, layer_type};
+use crate::Error;
+
+/// Convert an SVG icon into path layers, scaled to fit a `size` by `size` square and centered
+/// within it. Layer ids are derived from `seed`.
+pub fn icon_layers(svg: &str, size: f64, seed: &str) -> Result<Vec<JsonValue>, Error> {
+    let mut tree = usvg::Tree::from_str(svg, &usvg::Options::default())?;
+    tree.postprocess(usvg::PostProcessingSteps::default(), &usvg::fontdb::Database::new());
+
+    // Map the view box onto the icon square, preserving the aspect ratio.
+    let view    = tree.view_box.rect;
+    let scale   = size as f32 / view.width().max(view.height());
+    let offset  = (
+        (size as f32 - view.width() * scale) / 2.0,
+        (size as f32 - view.height() * scale) / 2.0,
+    );
+    let normalize = Transform::from_translate(-view.x(), -view.y())
+        .post_scale(scale, scale)
+        .post_translate(offset.0, offset.1);
+
+    let mut layers = vec![];
+    collect_paths(&tree.root, normalize, scale, seed, &mut layers);
+
+    Ok(layers)
+}
+
+/// Collect the visible paths below `group` as layers.
+fn collect_paths(group: &usvg::Group, normalize: Transform, scale: f32, seed: &str, layers: &mut Vec<JsonValue>) {
+    for node in &group.children {
+        let path = match node {
+            usvg::Node::Group(group)    => {
+                collect_paths(group, normalize, scale, seed, layers);
+                continue;
+            }
+            usvg::Node::Path(path)      => path,
+            _                           => continue,
+        };
+
+        if path.visibility != usvg::Visibility::Visible {
+            continue;
+        }
+
+        // Position each path layer at the top left of its bounds, relative to the icon.
+        let transform = path.abs_transform.post_concat(normalize);
+        let Some(data) = path.data.as_ref().clone().transform(transform) else {
+            continue;
+        };
+
+        let bounds = data.bounds();
+        let Some(data) = data.transform(Transform::from_translate(-bounds.left(), -bounds.top())) else {
+            continue;
+        };
+
+        let index       = layers.len();
+        let mut layer   = object! {
+            "id"        : model::derive_id(&format!("{seed} / {index}")),
+            "name"      : format!("path {}", index + 1),
+            "type"      : layer_type::PATH,
+            "x"         : num(bounds.left()),
+            "y"         : num(bounds.top()),
+            "width"     : num(bounds.width()),
+            "height"    : num(bounds.height()),
+            "path"      : path_data(&data),
+            "fills"     : JsonValue::new_array(),
+        };
+
+        // Gradients and patterns aren't supported, so only solid colors are kept.
+        if let Some(fill) = &path.fill {
+            if let usvg::Paint::Color(color) = fill.paint {
+                layer["fills"] = array![object! {
+                    "color"     : hex(color),
+                    "opacity"   : num(fill.opacity.get()),
+                }];
+            }
+        }
+
+        if let Some(stroke) = &path.stroke {
+            if let usvg::Paint::Color(color) = stroke.paint {
+                layer["borders"] = array![object! {
+                    "color"     : hex(color),
+                    "opacity"   : num(stroke.opacity.get()),
+                    "thickness" : num(stroke.width.get() * scale),
+                }];
+            }
+        }
+
+        layers.push(layer);
+    }
+}
+
+/// Format `path` as SVG path data.
+fn path_data(path: &tiny_skia::Path) -> String {
+    let mut data = String::new();
+    for segment in path.segments() {
+        let _ = match segment {
+            PathSegment::MoveTo(p)          => write!(data, "M{} {}", num(p.x), num(p.y)),
+            PathSegment::LineTo(p)          => write!(data, "L{} {}", num(p.x), num(p.y)),
+            PathSegment::QuadTo(a, p)       => write!(data, "Q{} {} {} {}", num(a.x), num(a.y), num(p.x), num(p.y)),
+            PathSegment::CubicTo(a, b, p)   => write!(
+                data,
+                "C{} {} {} {} {} {}",
+                num(a.x), num(a.y), num(b.x), num(b.y), num(p.x), num(p.y)
+            ),
+            PathSegment::Close              => write!(data, "Z"),
+        };
+    }
+
+    data
+}
+
+/// Format a color as a Lunacy hex color.
+fn hex(color: usvg::Color) -> String {
+    format!("#{:02X}{:02X}{:02X}", color.red, color.green, color.blue)
+}
+
+/// Round a coordinate to a sensible precision.
+fn num(x: f32) -> f64 {
+    (x as f64 * 1000.0).round() / 1000.0
+}
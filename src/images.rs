@@ -0,0 +1,337 @@
+use std::io::Cursor;
+
+use image::codecs::jpeg::JpegEncoder;
+use image::codecs::png::{CompressionType, FilterType, PngEncoder};
+use image::{DynamicImage, ImageFormat};
+
+use crate::Error;
+
+/// The quality JPEG images are encoded with.
+const JPEG_QUALITY: u8 = 90;
+
+/// An image that was downscaled to fit within a pixel budget.
+pub struct Downscaled {
+    /// The encoded image.
+    pub data    : Vec<u8>,
+    /// The original width and height of the image.
+    pub from    : (u32, u32),
+    /// The new width and height of the image.
+    pub to      : (u32, u32),
+}
+
+/// Encode `image` in `format`.
+pub fn encode(image: &DynamicImage, format: ImageFormat) -> Result<Vec<u8>, Error> {
+    let mut data = vec![];
+    match format {
+        ImageFormat::Png    => {
+            let encoder = PngEncoder::new_with_quality(&mut data, CompressionType::Best, FilterType::Adaptive);
+            image.write_with_encoder(encoder)?;
+        }
+        ImageFormat::Jpeg   => return encode_jpeg(image, JPEG_QUALITY),
+        _                   => image.write_to(&mut Cursor::new(&mut data), format)?,
+    }
+
+    Ok(data)
+}
+
+/// Recompress a PNG image with the best compression, returning it if it came out smaller.
+pub fn recompress_png(data: &[u8]) -> Result<Option<Vec<u8>>, Error> {
+    let image       = image::load_from_memory_with_format(data, ImageFormat::Png)?;
+    let recompressed = encode(&image, ImageFormat::Png)?;
+
+    Ok((recompressed.len() < data.len()).then_some(recompressed))
+}
+
+/// Downscale an image so it has at most `max_pixels` pixels, preserving its format and aspect
+/// ratio. Returns `None` if the image is already within budget.
+pub fn downscale(data: &[u8], max_pixels: u64) -> Result<Option<Downscaled>, Error> {
+    let format  = image::guess_format(data)?;
+    let image   = image::load_from_memory_with_format(data, format)?;
+
+    let (width, height) = (image.width(), image.height());
+    let pixels          = width as u64 * height as u64;
+    if pixels <= max_pixels {
+        return Ok(None);
+    }
+
+    let scale   = (max_pixels as f64 / pixels as f64).sqrt();
+    let to      = (
+        ((width as f64 * scale).floor() as u32).max(1),
+        ((height as f64 * scale).floor() as u32).max(1),
+    );
+
+    let resized = image.resize_exact(to.0, to.1, image::imageops::FilterType::Lanczos3);
+
+    Ok(Some(Downscaled {
+        data    : encode(&resized, format)?,
+        from    : (width, height),
+        to,
+    }))
+}
+
+/// Encode `image` as a JPEG of `quality`, from 1 to 100.
+fn encode_jpeg(image: &DynamicImage, quality: u8) -> Result<Vec<u8>, Error> {
+    // JPEG has no alpha channel so flatten it away first.
+    let mut data    = vec![];
+    let encoder     = JpegEncoder::new_with_quality(&mut data, quality);
+    DynamicImage::ImageRgb8(image.to_rgb8()).write_with_encoder(encoder)?;
+
+    Ok(data)
+}
+
+/// Convert a PNG or JPEG image to a lossy JPEG of `quality`, from 1 to 100, returning it if it
+/// came out smaller. Images with any transparency are left as they are, since JPEG can't hold it.
+pub fn convert_to_jpeg(data: &[u8], quality: u8) -> Result<Option<Vec<u8>>, Error> {
+    let source = image::guess_format(data)?;
+    if !matches!(source, ImageFormat::Png | ImageFormat::Jpeg) {
+        return Ok(None);
+    }
+
+    let image = image::load_from_memory_with_format(data, source)?;
+    if image.color().has_alpha() && image.to_rgba8().pixels().any(|x| x[3] != u8::MAX) {
+        return Ok(None);
+    }
+
+    let converted = encode_jpeg(&image, quality)?;
+    Ok((converted.len() < data.len()).then_some(converted))
+}
+
+/// Get the width and height of an encoded image, if it can be decoded.
+pub fn dimensions(data: &[u8]) -> Option<(u32, u32)> {
+    image::ImageReader::new(Cursor::new(data))
+        .with_guessed_format()
+        .ok()?
+        .into_dimensions()
+        .ok()
+}
+
+/// The PNG chunks holding metadata, text and timestamps.
+const PNG_METADATA_CHUNKS: [&[u8; 4]; 5] = [b"eXIf", b"tEXt", b"zTXt", b"iTXt", b"tIME"];
+
+/// The WebP chunks holding metadata, along with their flag in the `VP8X` header chunk.
+const WEBP_METADATA_CHUNKS: [(&[u8; 4], u8); 2] = [(b"EXIF", 0x08), (b"XMP ", 0x04)];
+
+/// The header preceding the EXIF data in a JPEG `APP1` segment, and optionally a WebP chunk.
+const EXIF_HEADER: &[u8; 6] = b"Exif\0\0";
+
+/// The EXIF tag holding the orientation of an image.
+const ORIENTATION_TAG: u16 = 0x0112;
+
+/// Remove EXIF, XMP and other metadata from an image without re-encoding it, returning it if any
+/// metadata was removed. Color profiles are kept as they affect how the image is displayed, as is
+/// the EXIF orientation, written again as the only EXIF field, since without it photos taken
+/// sideways would turn.
+pub fn strip_metadata(data: &[u8]) -> Result<Option<Vec<u8>>, Error> {
+    let stripped = match image::guess_format(data)? {
+        ImageFormat::Jpeg   => strip_jpeg(data)?,
+        ImageFormat::Png    => strip_png(data)?,
+        ImageFormat::WebP   => strip_webp(data)?,
+        _                   => return Ok(None),
+    };
+
+    Ok((stripped.len() < data.len()).then_some(stripped))
+}
+
+/// Remove the `APP1` (EXIF and XMP), `APP13` (IPTC) and comment segments from a JPEG image,
+/// keeping the orientation in an `APP1` segment of its own where the EXIF segment was.
+fn strip_jpeg(data: &[u8]) -> Result<Vec<u8>, Error> {
+    let mut stripped    = data[..2].to_vec();
+    let mut offset      = 2;
+    let mut orientation = None;
+    while offset + 4 <= data.len() {
+        if data[offset] != 0xFF {
+            return Err(MetadataError::Malformed.into());
+        }
+
+        // Entropy coded data follows the start of scan segment, copy it and the rest verbatim.
+        let marker = data[offset + 1];
+        if marker == 0xDA {
+            break;
+        }
+
+        let length  = u16::from_be_bytes([data[offset + 2], data[offset + 3]]) as usize;
+        let end     = offset + 2 + length;
+        if length < 2 || end > data.len() {
+            return Err(MetadataError::Malformed.into());
+        }
+
+        let exif = data[offset + 4..end]
+            .strip_prefix(EXIF_HEADER.as_slice())
+            .filter(|_| marker == 0xE1);
+        match exif {
+            Some(exif)                                      => orientation = exif_orientation(exif).map(|x| (stripped.len(), x)),
+            None if matches!(marker, 0xE1 | 0xED | 0xFE)    => (),
+            None                                            => stripped.extend_from_slice(&data[offset..end]),
+        }
+        offset = end;
+    }
+
+    if let Some((position, orientation)) = orientation {
+        let exif    = [EXIF_HEADER.as_slice(), &orientation_exif(orientation)].concat();
+        let segment = [&[0xFF, 0xE1], &(exif.len() as u16 + 2).to_be_bytes(), exif.as_slice()].concat();
+        stripped.splice(position..position, segment);
+    }
+
+    stripped.extend_from_slice(&data[offset..]);
+    Ok(stripped)
+}
+
+/// Remove the metadata chunks from a PNG image, keeping the orientation in an `eXIf` chunk of its
+/// own.
+fn strip_png(data: &[u8]) -> Result<Vec<u8>, Error> {
+    let mut stripped    = data[..8].to_vec();
+    let mut offset      = 8;
+    while offset < data.len() {
+        if offset + 12 > data.len() {
+            return Err(MetadataError::Malformed.into());
+        }
+
+        // Each chunk is a length, type, data and CRC.
+        let length  = u32::from_be_bytes(data[offset..offset + 4].try_into()?) as usize;
+        let kind    = &data[offset + 4..offset + 8];
+        let end     = offset + 12 + length;
+        if end > data.len() {
+            return Err(MetadataError::Malformed.into());
+        }
+
+        if kind == b"eXIf" {
+            if let Some(orientation) = exif_orientation(&data[offset + 8..end - 4]) {
+                let exif = orientation_exif(orientation);
+                let mut crc = crc32fast::Hasher::new();
+                crc.update(b"eXIf");
+                crc.update(&exif);
+
+                stripped.extend_from_slice(&(exif.len() as u32).to_be_bytes());
+                stripped.extend_from_slice(b"eXIf");
+                stripped.extend_from_slice(&exif);
+                stripped.extend_from_slice(&crc.finalize().to_be_bytes());
+            }
+        }
+        else if !PNG_METADATA_CHUNKS.iter().any(|x| x.as_slice() == kind) {
+            stripped.extend_from_slice(&data[offset..end]);
+        }
+        offset = end;
+    }
+
+    Ok(stripped)
+}
+
+/// Remove the metadata chunks from a WebP image, clearing their flags in the header, and keeping
+/// the orientation in an `EXIF` chunk of its own.
+fn strip_webp(data: &[u8]) -> Result<Vec<u8>, Error> {
+    let mut stripped    = data[..12].to_vec();
+    let mut offset      = 12;
+    while offset < data.len() {
+        if offset + 8 > data.len() {
+            return Err(MetadataError::Malformed.into());
+        }
+
+        // Each chunk is a type, length and data padded to an even length.
+        let kind    = &data[offset..offset + 4];
+        let length  = u32::from_le_bytes(data[offset + 4..offset + 8].try_into()?) as usize;
+        let end     = offset + 8 + length + length % 2;
+        if end > data.len() {
+            return Err(MetadataError::Malformed.into());
+        }
+
+        let exif        = &data[offset + 8..offset + 8 + length];
+        let orientation = match kind == b"EXIF" {
+            true    => exif_orientation(exif.strip_prefix(EXIF_HEADER.as_slice()).unwrap_or(exif)),
+            false   => None,
+        };
+
+        match (WEBP_METADATA_CHUNKS.iter().find(|(x, _)| x.as_slice() == kind), orientation) {
+            (Some(_), Some(orientation))    => {
+                let exif = orientation_exif(orientation);
+                stripped.extend_from_slice(b"EXIF");
+                stripped.extend_from_slice(&(exif.len() as u32).to_le_bytes());
+                stripped.extend_from_slice(&exif);
+            }
+            (Some((_, flag)), None)         => {
+                // The extended header is always the first chunk and holds the feature flags.
+                if stripped.len() > 20 && &stripped[12..16] == b"VP8X" {
+                    stripped[20] &= !flag;
+                }
+            }
+            (None, _)                       => stripped.extend_from_slice(&data[offset..end]),
+        }
+        offset = end;
+    }
+
+    // The RIFF header holds the size of everything after it.
+    let size = (stripped.len() - 8) as u32;
+    stripped[4..8].copy_from_slice(&size.to_le_bytes());
+
+    Ok(stripped)
+}
+
+/// Read the orientation from the `exif` data of an image, a TIFF structure, if it has one other
+/// than the default of upright.
+fn exif_orientation(exif: &[u8]) -> Option<u16> {
+    let big_endian  = match exif.get(..2)? {
+        b"MM"   => true,
+        b"II"   => false,
+        _       => return None,
+    };
+
+    let u16_at = |offset: usize| -> Option<u16> {
+        let bytes = exif.get(offset..offset + 2)?.try_into().ok()?;
+        Some(match big_endian {
+            true    => u16::from_be_bytes(bytes),
+            false   => u16::from_le_bytes(bytes),
+        })
+    };
+    let u32_at = |offset: usize| -> Option<u32> {
+        let bytes = exif.get(offset..offset + 4)?.try_into().ok()?;
+        Some(match big_endian {
+            true    => u32::from_be_bytes(bytes),
+            false   => u32::from_le_bytes(bytes),
+        })
+    };
+
+    // The orientation is a single short in the first directory, stored in the entry itself.
+    let directory = u32_at(4)? as usize;
+    for i in 0..u16_at(directory)? as usize {
+        let entry = directory + 2 + i * 12;
+        if u16_at(entry)? == ORIENTATION_TAG && u16_at(entry + 2)? == 3 {
+            return u16_at(entry + 8).filter(|x| (2..=8).contains(x));
+        }
+    }
+
+    None
+}
+
+/// Write EXIF data, a TIFF structure, holding only `orientation`.
+fn orientation_exif(orientation: u16) -> Vec<u8> {
+    let mut exif = b"MM\0\x2A".to_vec();
+    exif.extend_from_slice(&8_u32.to_be_bytes());
+    exif.extend_from_slice(&1_u16.to_be_bytes());
+
+    // A short of type 3 with a count of 1, padded out to the 4 bytes of the value.
+    exif.extend_from_slice(&ORIENTATION_TAG.to_be_bytes());
+    exif.extend_from_slice(&3_u16.to_be_bytes());
+    exif.extend_from_slice(&1_u32.to_be_bytes());
+    exif.extend_from_slice(&orientation.to_be_bytes());
+    exif.extend_from_slice(&[0, 0]);
+
+    // There's no directory after the first.
+    exif.extend_from_slice(&0_u32.to_be_bytes());
+    exif
+}
+
+/// An error encountered while stripping metadata from an image.
+#[derive(Debug)]
+enum MetadataError {
+    /// The image is not structured the way its format requires.
+    Malformed,
+}
+
+impl std::fmt::Display for MetadataError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            MetadataError::Malformed => write!(f, "image is malformed"),
+        }
+    }
+}
+
+impl std::error::Error for MetadataError { }
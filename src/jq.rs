@@ -0,0 +1,1454 @@
+use std::cmp::Ordering;
+
+use json::JsonValue;
+use regex::Regex;
+
+/// A compiled jq filter.
+///
+/// A practical subset of jq is supported: paths, iteration, slices and recursion, pipes and
+/// commas, array and object construction, string interpolation, arithmetic, comparisons, `and`,
+/// `or`, `//`, `if`, `?` and the common builtin functions. Variables, user defined functions and
+/// assignment aren't.
+#[derive(Clone, Debug)]
+pub struct Filter {
+    /// The parsed filter expression.
+    expr: Expr,
+}
+
+impl Filter {
+    /// Run the filter against `input`, returning every output in order.
+    pub fn run(&self, input: &JsonValue) -> Result<Vec<JsonValue>, JqError> {
+        eval(&self.expr, input)
+    }
+}
+
+impl std::str::FromStr for Filter {
+    type Err = JqError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let tokens      = lex(s)?;
+        let mut parser  = Parser { tokens, position: 0 };
+        let expr        = parser.pipe()?;
+
+        match parser.peek() {
+            None        => Ok(Self { expr }),
+            Some(token) => Err(JqError::Syntax(format!("unexpected {token:?}"))),
+        }
+    }
+}
+
+/// An error parsing or running a jq filter.
+#[derive(Debug)]
+pub enum JqError {
+    /// The filter couldn't be parsed.
+    Syntax(String),
+    /// The filter failed while running, e.g. by indexing a number.
+    Runtime(String),
+    /// The filter called a function that doesn't exist.
+    UnknownFunction(String, usize),
+}
+
+impl std::fmt::Display for JqError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Syntax(x)                 => write!(f, "syntax error in filter: {x}"),
+            Self::Runtime(x)                => write!(f, "{x}"),
+            Self::UnknownFunction(x, arity) => write!(f, "{x}/{arity} is not defined"),
+        }
+    }
+}
+
+impl std::error::Error for JqError { }
+
+/// A parsed filter expression.
+#[derive(Clone, Debug)]
+enum Expr {
+    /// `.`
+    Identity,
+    /// `..`
+    Recurse,
+    /// A constant value.
+    Literal(JsonValue),
+    /// A string with interpolated expressions, e.g. `"\(.name): \(.value)"`.
+    Format(Vec<StringPart>),
+    /// `a[b]`, or `a.b` for field names.
+    Index(Box<Expr>, Box<Expr>),
+    /// `a[b:c]`
+    Slice(Box<Expr>, Option<Box<Expr>>, Option<Box<Expr>>),
+    /// `a[]`
+    Iterate(Box<Expr>),
+    /// `a?`, discarding errors.
+    Try(Box<Expr>),
+    /// `[a]`
+    Array(Option<Box<Expr>>),
+    /// `{a: b, ...}`
+    Object(Vec<(Expr, Expr)>),
+    /// `a | b`
+    Pipe(Box<Expr>, Box<Expr>),
+    /// `a, b`
+    Comma(Box<Expr>, Box<Expr>),
+    /// `-a`
+    Negate(Box<Expr>),
+    /// An arithmetic or comparison operator.
+    Binary(BinaryOp, Box<Expr>, Box<Expr>),
+    /// `a and b`
+    And(Box<Expr>, Box<Expr>),
+    /// `a or b`
+    Or(Box<Expr>, Box<Expr>),
+    /// `a // b`
+    Alternative(Box<Expr>, Box<Expr>),
+    /// `if a then b elif c then d else e end`
+    If(Vec<(Expr, Expr)>, Option<Box<Expr>>),
+    /// A call to a builtin function.
+    Call(String, Vec<Expr>),
+}
+
+/// A part of an interpolated string.
+#[derive(Clone, Debug)]
+enum StringPart {
+    /// Literal text.
+    Text(String),
+    /// An interpolated expression, `\(...)`.
+    Expr(Expr),
+}
+
+/// A binary arithmetic or comparison operator.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum BinaryOp {
+    /// `+`
+    Add,
+    /// `-`
+    Subtract,
+    /// `*`
+    Multiply,
+    /// `/`
+    Divide,
+    /// `%`
+    Modulo,
+    /// `==`
+    Equal,
+    /// `!=`
+    NotEqual,
+    /// `<`
+    Less,
+    /// `<=`
+    LessOrEqual,
+    /// `>`
+    Greater,
+    /// `>=`
+    GreaterOrEqual,
+}
+
+impl BinaryOp {
+    /// Get the symbol the operator is written as.
+    fn symbol(self) -> &'static str {
+        match self {
+            Self::Add               => "+",
+            Self::Subtract          => "-",
+            Self::Multiply          => "*",
+            Self::Divide            => "/",
+            Self::Modulo            => "%",
+            Self::Equal             => "==",
+            Self::NotEqual          => "!=",
+            Self::Less              => "<",
+            Self::LessOrEqual       => "<=",
+            Self::Greater           => ">",
+            Self::GreaterOrEqual    => ">=",
+        }
+    }
+}
+
+/// A lexical token of a filter.
+#[derive(Clone, Debug, PartialEq)]
+enum Token {
+    /// `.`
+    Dot,
+    /// `..`
+    DotDot,
+    /// `.name`
+    Field(String),
+    /// A function name or keyword.
+    Ident(String),
+    /// A string literal, with the source of each interpolated expression.
+    Str(Vec<(bool, String)>),
+    /// A number literal.
+    Number(f64),
+    /// Any other punctuation, e.g. `|` or `>=`.
+    Punct(&'static str),
+}
+
+/// The punctuation filters use, longest first so they're matched greedily.
+const PUNCTUATION: &[&str] = &[
+    "==", "!=", "<=", ">=", "//", "|", ",", "(", ")", "[", "]", "{", "}", ":", ";", "?", "<", ">",
+    "+", "-", "*", "/", "%",
+];
+
+/// Split `source` into tokens.
+fn lex(source: &str) -> Result<Vec<Token>, JqError> {
+    let chars       = source.chars().collect::<Vec<_>>();
+    let mut tokens  = vec![];
+    let mut i       = 0;
+
+    let is_ident = |c: char| c.is_alphanumeric() || c == '_';
+    while i < chars.len() {
+        let c = chars[i];
+        if c.is_whitespace() {
+            i += 1;
+        }
+        // Comments run to the end of the line.
+        else if c == '#' {
+            while i < chars.len() && chars[i] != '\n' {
+                i += 1;
+            }
+        }
+        else if c == '.' && chars.get(i + 1) == Some(&'.') {
+            tokens.push(Token::DotDot);
+            i += 2;
+        }
+        else if c == '.' && chars.get(i + 1).is_some_and(|x| is_ident(*x) && !x.is_ascii_digit()) {
+            let start = i + 1;
+            i += 1;
+            while i < chars.len() && is_ident(chars[i]) {
+                i += 1;
+            }
+            tokens.push(Token::Field(chars[start..i].iter().collect()));
+        }
+        else if c == '.' && !chars.get(i + 1).is_some_and(|x| x.is_ascii_digit()) {
+            tokens.push(Token::Dot);
+            i += 1;
+        }
+        else if c.is_ascii_digit() || c == '.' {
+            let start = i;
+            while i < chars.len() && (chars[i].is_ascii_digit() || chars[i] == '.' || chars[i] == 'e' || chars[i] == 'E') {
+                i += 1;
+            }
+
+            let text = chars[start..i].iter().collect::<String>();
+            let number = text.parse()
+                .map_err(|_| JqError::Syntax(format!("invalid number `{text}`")))?;
+            tokens.push(Token::Number(number));
+        }
+        else if is_ident(c) || c == '$' {
+            let start = i;
+            i += 1;
+            while i < chars.len() && (is_ident(chars[i]) || (chars[i] == ':' && chars.get(i + 1) == Some(&':'))) {
+                i += 1;
+            }
+            tokens.push(Token::Ident(chars[start..i].iter().collect()));
+        }
+        else if c == '"' {
+            let (parts, end) = lex_string(&chars, i + 1)?;
+            tokens.push(Token::Str(parts));
+            i = end;
+        }
+        else {
+            let rest = chars[i..].iter().take(2).collect::<String>();
+            let Some(punct) = PUNCTUATION.iter().find(|x| rest.starts_with(**x)) else {
+                return Err(JqError::Syntax(format!("unexpected `{c}`")));
+            };
+
+            // `|=`, `+=` and friends would otherwise fail confusingly on the `=`.
+            if chars.get(i + punct.chars().count()) == Some(&'=') && !punct.ends_with('=') && !matches!(*punct, "<" | ">" | "!") {
+                return Err(JqError::Syntax("assignment operators aren't supported".to_owned()));
+            }
+
+            tokens.push(Token::Punct(punct));
+            i += punct.chars().count();
+        }
+    }
+
+    Ok(tokens)
+}
+
+/// Lex a string starting after its opening quote, returning its parts, each flagged with
+/// whether it's the source of an interpolated expression, and the index after the closing quote.
+fn lex_string(chars: &[char], mut i: usize) -> Result<(Vec<(bool, String)>, usize), JqError> {
+    let mut parts   = vec![];
+    let mut text    = String::new();
+
+    loop {
+        let Some(&c) = chars.get(i) else {
+            return Err(JqError::Syntax("unterminated string".to_owned()));
+        };
+
+        i += 1;
+        match c {
+            '"'     => break,
+            '\\'    => {
+                let Some(&escaped) = chars.get(i) else {
+                    return Err(JqError::Syntax("unterminated string".to_owned()));
+                };
+
+                i += 1;
+                match escaped {
+                    'n' => text.push('\n'),
+                    't' => text.push('\t'),
+                    'r' => text.push('\r'),
+                    'u' => {
+                        let hex = chars.get(i..i + 4).map(|x| x.iter().collect::<String>()).unwrap_or_default();
+                        let c   = u32::from_str_radix(&hex, 16).ok().and_then(char::from_u32)
+                            .ok_or_else(|| JqError::Syntax(format!("invalid escape `\\u{hex}`")))?;
+                        text.push(c);
+                        i += 4;
+                    }
+                    '(' => {
+                        // Find the matching parenthesis, skipping over nested strings.
+                        let start       = i;
+                        let mut depth   = 1;
+                        let mut quoted  = false;
+                        while depth > 0 {
+                            let Some(&c) = chars.get(i) else {
+                                return Err(JqError::Syntax("unterminated interpolation".to_owned()));
+                            };
+
+                            match c {
+                                '\\' if quoted  => i += 1,
+                                '"'             => quoted = !quoted,
+                                '(' if !quoted  => depth += 1,
+                                ')' if !quoted  => depth -= 1,
+                                _               => {}
+                            }
+                            i += 1;
+                        }
+
+                        if !text.is_empty() {
+                            parts.push((false, std::mem::take(&mut text)));
+                        }
+                        parts.push((true, chars[start..i - 1].iter().collect()));
+                    }
+                    c   => text.push(c),
+                }
+            }
+            c       => text.push(c),
+        }
+    }
+
+    if !text.is_empty() || parts.is_empty() {
+        parts.push((false, text));
+    }
+
+    Ok((parts, i))
+}
+
+/// Parses filters from their tokens, lowest precedence first.
+struct Parser {
+    /// The tokens of the filter.
+    tokens      : Vec<Token>,
+    /// The index of the next token.
+    position    : usize,
+}
+
+impl Parser {
+    /// Peek at the next token.
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.position)
+    }
+
+    /// Take the next token.
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.position).cloned();
+        self.position += 1;
+        token
+    }
+
+    /// Take the next token if it's the punctuation `punct`.
+    fn eat(&mut self, punct: &str) -> bool {
+        match self.peek() {
+            Some(Token::Punct(x)) if *x == punct => {
+                self.position += 1;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Take the next token if it's the keyword `keyword`.
+    fn eat_keyword(&mut self, keyword: &str) -> bool {
+        match self.peek() {
+            Some(Token::Ident(x)) if x == keyword => {
+                self.position += 1;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Require the next token to be the punctuation or keyword `expected`.
+    fn expect(&mut self, expected: &str) -> Result<(), JqError> {
+        match self.eat(expected) || self.eat_keyword(expected) {
+            true    => Ok(()),
+            false   => Err(JqError::Syntax(format!("expected `{expected}`, found {:?}", self.peek()))),
+        }
+    }
+
+    /// `a | b`
+    fn pipe(&mut self) -> Result<Expr, JqError> {
+        let lhs = self.comma()?;
+        match self.eat("|") {
+            true    => Ok(Expr::Pipe(Box::new(lhs), Box::new(self.pipe()?))),
+            false   => Ok(lhs),
+        }
+    }
+
+    /// `a, b`
+    fn comma(&mut self) -> Result<Expr, JqError> {
+        let mut lhs = self.alternative()?;
+        while self.eat(",") {
+            lhs = Expr::Comma(Box::new(lhs), Box::new(self.alternative()?));
+        }
+
+        Ok(lhs)
+    }
+
+    /// `a // b`
+    fn alternative(&mut self) -> Result<Expr, JqError> {
+        let lhs = self.or()?;
+        match self.eat("//") {
+            true    => Ok(Expr::Alternative(Box::new(lhs), Box::new(self.alternative()?))),
+            false   => Ok(lhs),
+        }
+    }
+
+    /// `a or b`
+    fn or(&mut self) -> Result<Expr, JqError> {
+        let mut lhs = self.and()?;
+        while self.eat_keyword("or") {
+            lhs = Expr::Or(Box::new(lhs), Box::new(self.and()?));
+        }
+
+        Ok(lhs)
+    }
+
+    /// `a and b`
+    fn and(&mut self) -> Result<Expr, JqError> {
+        let mut lhs = self.comparison()?;
+        while self.eat_keyword("and") {
+            lhs = Expr::And(Box::new(lhs), Box::new(self.comparison()?));
+        }
+
+        Ok(lhs)
+    }
+
+    /// `a == b` and other comparisons, which don't chain.
+    fn comparison(&mut self) -> Result<Expr, JqError> {
+        let lhs = self.additive()?;
+        let operators = [
+            ("==", BinaryOp::Equal),
+            ("!=", BinaryOp::NotEqual),
+            ("<=", BinaryOp::LessOrEqual),
+            (">=", BinaryOp::GreaterOrEqual),
+            ("<",  BinaryOp::Less),
+            (">",  BinaryOp::Greater),
+        ];
+
+        for (punct, operator) in operators {
+            if self.eat(punct) {
+                return Ok(Expr::Binary(operator, Box::new(lhs), Box::new(self.additive()?)));
+            }
+        }
+
+        Ok(lhs)
+    }
+
+    /// `a + b` and `a - b`
+    fn additive(&mut self) -> Result<Expr, JqError> {
+        let mut lhs = self.multiplicative()?;
+        loop {
+            let operator = match () {
+                _ if self.eat("+")  => BinaryOp::Add,
+                _ if self.eat("-")  => BinaryOp::Subtract,
+                _                   => return Ok(lhs),
+            };
+            lhs = Expr::Binary(operator, Box::new(lhs), Box::new(self.multiplicative()?));
+        }
+    }
+
+    /// `a * b`, `a / b` and `a % b`
+    fn multiplicative(&mut self) -> Result<Expr, JqError> {
+        let mut lhs = self.unary()?;
+        loop {
+            let operator = match () {
+                _ if self.eat("*")  => BinaryOp::Multiply,
+                _ if self.eat("/")  => BinaryOp::Divide,
+                _ if self.eat("%")  => BinaryOp::Modulo,
+                _                   => return Ok(lhs),
+            };
+            lhs = Expr::Binary(operator, Box::new(lhs), Box::new(self.unary()?));
+        }
+    }
+
+    /// `-a`
+    fn unary(&mut self) -> Result<Expr, JqError> {
+        match self.eat("-") {
+            true    => Ok(Expr::Negate(Box::new(self.postfix()?))),
+            false   => self.postfix(),
+        }
+    }
+
+    /// A term followed by any number of field accesses, brackets and `?`.
+    fn postfix(&mut self) -> Result<Expr, JqError> {
+        let mut expr = self.term()?;
+        loop {
+            expr = match self.peek() {
+                Some(Token::Field(name))            => {
+                    let name = name.clone();
+                    self.position += 1;
+                    Expr::Index(Box::new(expr), Box::new(Expr::Literal(name.into())))
+                }
+                Some(Token::Dot)                    => {
+                    self.position += 1;
+                    match self.peek() {
+                        Some(Token::Str(_))         => {
+                            let key = self.term()?;
+                            Expr::Index(Box::new(expr), Box::new(key))
+                        }
+                        Some(Token::Punct("["))     => {
+                            self.position += 1;
+                            self.bracket(expr)?
+                        }
+                        _                           => return Err(JqError::Syntax("expected a field after `.`".to_owned())),
+                    }
+                }
+                Some(Token::Punct("["))             => {
+                    self.position += 1;
+                    self.bracket(expr)?
+                }
+                Some(Token::Punct("?"))             => {
+                    self.position += 1;
+                    Expr::Try(Box::new(expr))
+                }
+                _                                   => return Ok(expr),
+            };
+        }
+    }
+
+    /// The contents of brackets following `target`, after the opening bracket.
+    fn bracket(&mut self, target: Expr) -> Result<Expr, JqError> {
+        let target = Box::new(target);
+        if self.eat("]") {
+            return Ok(Expr::Iterate(target));
+        }
+
+        let start = match self.peek() {
+            Some(Token::Punct(":")) => None,
+            _                       => Some(Box::new(self.pipe()?)),
+        };
+
+        if self.eat(":") {
+            let end = match self.peek() {
+                Some(Token::Punct("]")) => None,
+                _                       => Some(Box::new(self.pipe()?)),
+            };
+            self.expect("]")?;
+            return Ok(Expr::Slice(target, start, end));
+        }
+
+        self.expect("]")?;
+        match start {
+            Some(index) => Ok(Expr::Index(target, index)),
+            None        => Err(JqError::Syntax("expected an index".to_owned())),
+        }
+    }
+
+    /// A single term: a path, literal, construction, parenthesised filter, `if` or function call.
+    fn term(&mut self) -> Result<Expr, JqError> {
+        let Some(token) = self.next() else {
+            return Err(JqError::Syntax("unexpected end of filter".to_owned()));
+        };
+
+        match token {
+            Token::Dot                  => match self.peek() {
+                Some(Token::Str(_))         => {
+                    let key = self.term()?;
+                    Ok(Expr::Index(Box::new(Expr::Identity), Box::new(key)))
+                }
+                Some(Token::Punct("["))     => {
+                    self.position += 1;
+                    self.bracket(Expr::Identity)
+                }
+                _                           => Ok(Expr::Identity),
+            },
+            Token::DotDot               => Ok(Expr::Recurse),
+            Token::Field(name)          => Ok(Expr::Index(Box::new(Expr::Identity), Box::new(Expr::Literal(name.into())))),
+            Token::Number(x)            => Ok(Expr::Literal(x.into())),
+            Token::Str(parts)           => {
+                let mut result = vec![];
+                for (is_expr, source) in parts {
+                    result.push(match is_expr {
+                        true    => StringPart::Expr(source.parse::<Filter>()?.expr),
+                        false   => StringPart::Text(source),
+                    });
+                }
+
+                match result.as_slice() {
+                    [StringPart::Text(x)]   => Ok(Expr::Literal(x.as_str().into())),
+                    _                       => Ok(Expr::Format(result)),
+                }
+            }
+            Token::Punct("(")           => {
+                let expr = self.pipe()?;
+                self.expect(")")?;
+                Ok(expr)
+            }
+            Token::Punct("[")           => {
+                if self.eat("]") {
+                    return Ok(Expr::Array(None));
+                }
+
+                let expr = self.pipe()?;
+                self.expect("]")?;
+                Ok(Expr::Array(Some(Box::new(expr))))
+            }
+            Token::Punct("{")           => self.object(),
+            Token::Ident(name)          => match name.as_str() {
+                "true"      => Ok(Expr::Literal(true.into())),
+                "false"     => Ok(Expr::Literal(false.into())),
+                "null"      => Ok(Expr::Literal(JsonValue::Null)),
+                "if"        => self.conditional(),
+                _ if name.starts_with('$')  => Err(JqError::Syntax(format!("variables such as `{name}` aren't supported"))),
+                _           => {
+                    let mut args = vec![];
+                    if self.eat("(") {
+                        loop {
+                            args.push(self.pipe()?);
+                            if !self.eat(";") {
+                                break;
+                            }
+                        }
+                        self.expect(")")?;
+                    }
+
+                    Ok(Expr::Call(name, args))
+                }
+            },
+            token                       => Err(JqError::Syntax(format!("unexpected {token:?}"))),
+        }
+    }
+
+    /// An object construction, after the opening brace.
+    fn object(&mut self) -> Result<Expr, JqError> {
+        let mut entries = vec![];
+        while !self.eat("}") {
+            let (key, shorthand) = match self.next() {
+                Some(Token::Ident(name))    => (Expr::Literal(name.as_str().into()), Some(name)),
+                Some(Token::Str(parts))     => {
+                    self.position -= 1;
+                    let key = self.term()?;
+                    let shorthand = match (&key, parts.as_slice()) {
+                        (Expr::Literal(_), [(false, text)]) => Some(text.clone()),
+                        _                                   => None,
+                    };
+                    (key, shorthand)
+                }
+                Some(Token::Punct("("))     => {
+                    let key = self.pipe()?;
+                    self.expect(")")?;
+                    (key, None)
+                }
+                token                       => return Err(JqError::Syntax(format!("unexpected {token:?} in object"))),
+            };
+
+            // `{name}` is shorthand for `{name: .name}`.
+            let value = match (self.eat(":"), shorthand) {
+                (true, _)           => self.object_value()?,
+                (false, Some(name)) => Expr::Index(Box::new(Expr::Identity), Box::new(Expr::Literal(name.into()))),
+                (false, None)       => return Err(JqError::Syntax("expected `:` in object".to_owned())),
+            };
+
+            entries.push((key, value));
+            if !self.eat(",") {
+                self.expect("}")?;
+                break;
+            }
+        }
+
+        Ok(Expr::Object(entries))
+    }
+
+    /// An object value, which can't contain unparenthesised commas.
+    fn object_value(&mut self) -> Result<Expr, JqError> {
+        let lhs = self.alternative()?;
+        match self.eat("|") {
+            true    => Ok(Expr::Pipe(Box::new(lhs), Box::new(self.object_value()?))),
+            false   => Ok(lhs),
+        }
+    }
+
+    /// An `if` expression, after the `if`.
+    fn conditional(&mut self) -> Result<Expr, JqError> {
+        let mut branches = vec![];
+        loop {
+            let condition = self.pipe()?;
+            self.expect("then")?;
+            branches.push((condition, self.pipe()?));
+
+            if !self.eat_keyword("elif") {
+                break;
+            }
+        }
+
+        let otherwise = match self.eat_keyword("else") {
+            true    => Some(Box::new(self.pipe()?)),
+            false   => None,
+        };
+        self.expect("end")?;
+
+        Ok(Expr::If(branches, otherwise))
+    }
+}
+
+/// Evaluate `expr` against `input`.
+fn eval(expr: &Expr, input: &JsonValue) -> Result<Vec<JsonValue>, JqError> {
+    match expr {
+        Expr::Identity                  => Ok(vec![input.clone()]),
+        Expr::Recurse                   => {
+            let mut values = vec![];
+            recurse(input, &mut values);
+            Ok(values)
+        }
+        Expr::Literal(x)                => Ok(vec![x.clone()]),
+        Expr::Format(parts)             => {
+            let mut results = vec![String::new()];
+            for part in parts {
+                let values = match part {
+                    StringPart::Text(x) => vec![x.clone()],
+                    StringPart::Expr(x) => eval(x, input)?.iter().map(to_text).collect(),
+                };
+
+                results = results.iter()
+                    .flat_map(|x| values.iter().map(move |value| format!("{x}{value}")))
+                    .collect();
+            }
+
+            Ok(results.into_iter().map(|x| x.into()).collect())
+        }
+        Expr::Index(target, index)      => {
+            let mut results = vec![];
+            for index in eval(index, input)? {
+                for target in eval(target, input)? {
+                    results.push(index_value(&target, &index)?);
+                }
+            }
+
+            Ok(results)
+        }
+        Expr::Slice(target, start, end) => {
+            let bound = |x: &Option<Box<Expr>>| match x {
+                Some(x) => eval(x, input).map(|x| x.into_iter().map(|x| x.as_f64()).collect::<Vec<_>>()),
+                None    => Ok(vec![None]),
+            };
+
+            let mut results = vec![];
+            for end in bound(end)? {
+                for start in bound(start)? {
+                    for target in eval(target, input)? {
+                        results.push(slice(&target, start, end)?);
+                    }
+                }
+            }
+
+            Ok(results)
+        }
+        Expr::Iterate(target)           => {
+            let mut results = vec![];
+            for target in eval(target, input)? {
+                match target {
+                    JsonValue::Array(values)    => results.extend(values),
+                    JsonValue::Object(object)   => results.extend(object.iter().map(|(_, x)| x.clone())),
+                    x                           => return Err(JqError::Runtime(format!("cannot iterate over {}", type_name(&x)))),
+                }
+            }
+
+            Ok(results)
+        }
+        Expr::Try(expr)                 => Ok(eval(expr, input).unwrap_or_default()),
+        Expr::Array(expr)               => {
+            let values = match expr {
+                Some(expr)  => eval(expr, input)?,
+                None        => vec![],
+            };
+
+            Ok(vec![JsonValue::Array(values)])
+        }
+        Expr::Object(entries)           => {
+            let mut results = vec![JsonValue::new_object()];
+            for (key, value) in entries {
+                let keys    = eval(key, input)?;
+                let values  = eval(value, input)?;
+
+                let mut next = vec![];
+                for object in &results {
+                    for key in &keys {
+                        let Some(key) = key.as_str() else {
+                            return Err(JqError::Runtime(format!("object keys must be strings, not {}", type_name(key))));
+                        };
+
+                        for value in &values {
+                            let mut object = object.clone();
+                            object[key] = value.clone();
+                            next.push(object);
+                        }
+                    }
+                }
+                results = next;
+            }
+
+            Ok(results)
+        }
+        Expr::Pipe(lhs, rhs)            => {
+            let mut results = vec![];
+            for value in eval(lhs, input)? {
+                results.extend(eval(rhs, &value)?);
+            }
+
+            Ok(results)
+        }
+        Expr::Comma(lhs, rhs)           => {
+            let mut results = eval(lhs, input)?;
+            results.extend(eval(rhs, input)?);
+            Ok(results)
+        }
+        Expr::Negate(expr)              => eval(expr, input)?
+            .iter()
+            .map(|x| match x.as_f64() {
+                Some(x) => Ok((-x).into()),
+                None    => Err(JqError::Runtime(format!("{} cannot be negated", type_name(x)))),
+            })
+            .collect(),
+        Expr::Binary(operator, lhs, rhs) => {
+            let mut results = vec![];
+            for rhs in eval(rhs, input)? {
+                for lhs in eval(lhs, input)? {
+                    results.push(binary(*operator, &lhs, &rhs)?);
+                }
+            }
+
+            Ok(results)
+        }
+        Expr::And(lhs, rhs)             => {
+            let mut results = vec![];
+            for lhs in eval(lhs, input)? {
+                if !truthy(&lhs) {
+                    results.push(false.into());
+                    continue;
+                }
+
+                for rhs in eval(rhs, input)? {
+                    results.push(truthy(&rhs).into());
+                }
+            }
+
+            Ok(results)
+        }
+        Expr::Or(lhs, rhs)              => {
+            let mut results = vec![];
+            for lhs in eval(lhs, input)? {
+                if truthy(&lhs) {
+                    results.push(true.into());
+                    continue;
+                }
+
+                for rhs in eval(rhs, input)? {
+                    results.push(truthy(&rhs).into());
+                }
+            }
+
+            Ok(results)
+        }
+        Expr::Alternative(lhs, rhs)     => {
+            let values = eval(lhs, input)
+                .unwrap_or_default()
+                .into_iter()
+                .filter(truthy)
+                .collect::<Vec<_>>();
+
+            match values.is_empty() {
+                true    => eval(rhs, input),
+                false   => Ok(values),
+            }
+        }
+        Expr::If(branches, otherwise)   => eval_if(branches, otherwise.as_deref(), input),
+        Expr::Call(name, args)          => call(name, args, input),
+    }
+}
+
+/// Evaluate the branches of an `if` expression against `input`.
+fn eval_if(branches: &[(Expr, Expr)], otherwise: Option<&Expr>, input: &JsonValue) -> Result<Vec<JsonValue>, JqError> {
+    let Some(((condition, then), rest)) = branches.split_first() else {
+        return match otherwise {
+            Some(otherwise) => eval(otherwise, input),
+            None            => Ok(vec![input.clone()]),
+        };
+    };
+
+    let mut results = vec![];
+    for value in eval(condition, input)? {
+        match truthy(&value) {
+            true    => results.extend(eval(then, input)?),
+            false   => results.extend(eval_if(rest, otherwise, input)?),
+        }
+    }
+
+    Ok(results)
+}
+
+/// Call the builtin function `name` with `args` against `input`.
+fn call(name: &str, args: &[Expr], input: &JsonValue) -> Result<Vec<JsonValue>, JqError> {
+    // Evaluate an argument to a single value, as most functions with arguments expect.
+    let arg = |i: usize| -> Result<JsonValue, JqError> {
+        eval(&args[i], input)?
+            .into_iter()
+            .next()
+            .ok_or_else(|| JqError::Runtime(format!("argument to {name} produced no value")))
+    };
+
+    let string = |value: &JsonValue| -> Result<String, JqError> {
+        value.as_str()
+            .map(|x| x.to_owned())
+            .ok_or_else(|| JqError::Runtime(format!("{name} requires a string, not {}", type_name(value))))
+    };
+
+    let one = |value: JsonValue| Ok(vec![value]);
+
+    match (name, args.len()) {
+        ("empty", 0)            => Ok(vec![]),
+        ("not", 0)              => one((!truthy(input)).into()),
+        ("length", 0)           => one(match input {
+            JsonValue::Null                         => 0.into(),
+            JsonValue::Array(x)                     => x.len().into(),
+            JsonValue::Object(x)                    => x.len().into(),
+            x if x.is_string()                      => x.as_str().unwrap_or_default().chars().count().into(),
+            x if x.is_number()                      => x.as_f64().unwrap_or_default().abs().into(),
+            x                                       => return Err(JqError::Runtime(format!("{} has no length", type_name(x)))),
+        }),
+        ("keys", 0) | ("keys_unsorted", 0) => {
+            let mut keys = match input {
+                JsonValue::Object(object)   => object.iter().map(|(key, _)| JsonValue::from(key)).collect::<Vec<_>>(),
+                JsonValue::Array(values)    => (0..values.len()).map(JsonValue::from).collect(),
+                x                           => return Err(JqError::Runtime(format!("{} has no keys", type_name(x)))),
+            };
+
+            if name == "keys" {
+                keys.sort_by(compare);
+            }
+            one(JsonValue::Array(keys))
+        }
+        ("values", 0)           => Ok(Some(input.clone()).filter(|x| !x.is_null()).into_iter().collect()),
+        ("type", 0)             => one(type_name(input).into()),
+        ("add", 0)              => {
+            let values = members(input, name)?;
+            let mut total = JsonValue::Null;
+            for value in values {
+                total = binary(BinaryOp::Add, &total, &value)?;
+            }
+            one(total)
+        }
+        ("sort", 0)             => {
+            let mut values = members(input, name)?;
+            values.sort_by(compare);
+            one(JsonValue::Array(values))
+        }
+        ("unique", 0)           => {
+            let mut values = members(input, name)?;
+            values.sort_by(compare);
+            values.dedup();
+            one(JsonValue::Array(values))
+        }
+        ("reverse", 0)          => {
+            let mut values = members(input, name)?;
+            values.reverse();
+            one(JsonValue::Array(values))
+        }
+        ("min", 0)              => one(members(input, name)?.into_iter().min_by(compare).unwrap_or(JsonValue::Null)),
+        ("max", 0)              => one(members(input, name)?.into_iter().max_by(compare).unwrap_or(JsonValue::Null)),
+        ("first", 0)            => one(input[0].clone()),
+        ("last", 0)             => one(members(input, name)?.pop().unwrap_or(JsonValue::Null)),
+        ("flatten", 0)          => {
+            let mut values = vec![];
+            flatten(input, &mut values);
+            one(JsonValue::Array(values))
+        }
+        ("any", 0)              => one(members(input, name)?.iter().any(truthy).into()),
+        ("all", 0)              => one(members(input, name)?.iter().all(truthy).into()),
+        ("to_entries", 0)       => {
+            let JsonValue::Object(object) = input else {
+                return Err(JqError::Runtime(format!("{} has no entries", type_name(input))));
+            };
+
+            let entries = object.iter()
+                .map(|(key, value)| json::object! { "key": key, "value": value.clone() })
+                .collect::<Vec<_>>();
+            one(JsonValue::Array(entries))
+        }
+        ("from_entries", 0)     => {
+            let mut object = JsonValue::new_object();
+            for entry in members(input, name)? {
+                let key = ["key", "k", "name", "Name", "Key", "K"].iter()
+                    .map(|x| &entry[*x])
+                    .find(|x| !x.is_null())
+                    .map(to_text)
+                    .unwrap_or_default();
+                object[key.as_str()] = entry["value"].clone();
+            }
+            one(object)
+        }
+        ("tostring", 0)         => one(to_text(input).into()),
+        ("tojson", 0)           => one(input.dump().into()),
+        ("fromjson", 0)         => one(json::parse(&string(input)?).map_err(|x| JqError::Runtime(x.to_string()))?),
+        ("tonumber", 0)         => match input.as_f64() {
+            Some(x) => one(x.into()),
+            None    => one(string(input)?.trim().parse::<f64>()
+                .map_err(|_| JqError::Runtime(format!("cannot parse {} as a number", input.dump())))?
+                .into()),
+        },
+        ("ascii_downcase", 0)   => one(string(input)?.to_ascii_lowercase().into()),
+        ("ascii_upcase", 0)     => one(string(input)?.to_ascii_uppercase().into()),
+        ("floor", 0)            => one(number(input, name)?.floor().into()),
+        ("ceil", 0)             => one(number(input, name)?.ceil().into()),
+        ("round", 0)            => one(number(input, name)?.round().into()),
+        ("recurse", 0)          => eval(&Expr::Recurse, input),
+        ("select", 1)           => {
+            let keep = eval(&args[0], input)?.iter().any(truthy);
+            Ok(Some(input.clone()).filter(|_| keep).into_iter().collect())
+        }
+        ("map", 1)              => {
+            let mut values = vec![];
+            for value in members(input, name)? {
+                values.extend(eval(&args[0], &value)?);
+            }
+            one(JsonValue::Array(values))
+        }
+        ("map_values", 1)       => {
+            let mut output = input.clone();
+            match &mut output {
+                JsonValue::Array(values)    => {
+                    for value in values.iter_mut() {
+                        *value = eval(&args[0], value)?.into_iter().next().unwrap_or(JsonValue::Null);
+                    }
+                }
+                JsonValue::Object(object)   => {
+                    for (_, value) in object.iter_mut() {
+                        *value = eval(&args[0], value)?.into_iter().next().unwrap_or(JsonValue::Null);
+                    }
+                }
+                x                           => return Err(JqError::Runtime(format!("cannot map over {}", type_name(x)))),
+            }
+            one(output)
+        }
+        ("with_entries", 1)     => {
+            let entries = call("to_entries", &[], input)?;
+            let mapped  = call("map", args, &entries[0])?;
+            call("from_entries", &[], &mapped[0])
+        }
+        ("has", 1)              => {
+            let key = arg(0)?;
+            match (input, key.as_str(), key.as_f64()) {
+                (JsonValue::Object(_), Some(key), _)    => one(input.has_key(key).into()),
+                (JsonValue::Array(x), _, Some(index))   => one((index >= 0.0 && (index as usize) < x.len()).into()),
+                _                                       => Err(JqError::Runtime(format!("cannot check whether {} has a key", type_name(input)))),
+            }
+        }
+        ("contains", 1)         => one(contains(input, &arg(0)?).into()),
+        ("startswith", 1)       => one(string(input)?.starts_with(&string(&arg(0)?)?).into()),
+        ("endswith", 1)         => one(string(input)?.ends_with(&string(&arg(0)?)?).into()),
+        ("ltrimstr", 1)         => {
+            let prefix = arg(0)?;
+            match (input.as_str(), prefix.as_str()) {
+                (Some(x), Some(prefix)) => one(x.strip_prefix(prefix).unwrap_or(x).into()),
+                _                       => one(input.clone()),
+            }
+        }
+        ("rtrimstr", 1)         => {
+            let suffix = arg(0)?;
+            match (input.as_str(), suffix.as_str()) {
+                (Some(x), Some(suffix)) => one(x.strip_suffix(suffix).unwrap_or(x).into()),
+                _                       => one(input.clone()),
+            }
+        }
+        ("split", 1)            => {
+            let input       = string(input)?;
+            let separator   = string(&arg(0)?)?;
+            one(JsonValue::Array(input.split(separator.as_str()).map(|x| x.into()).collect()))
+        }
+        ("join", 1)             => {
+            let separator = string(&arg(0)?)?;
+            let parts = members(input, name)?
+                .iter()
+                .map(|x| match x.is_null() {
+                    true    => String::new(),
+                    false   => to_text(x),
+                })
+                .collect::<Vec<_>>();
+            one(parts.join(&separator).into())
+        }
+        ("test", 1)             => {
+            let pattern = string(&arg(0)?)?;
+            let regex   = Regex::new(&pattern)
+                .map_err(|x| JqError::Runtime(x.to_string()))?;
+            one(regex.is_match(&string(input)?).into())
+        }
+        ("sort_by", 1) | ("group_by", 1) | ("unique_by", 1) | ("min_by", 1) | ("max_by", 1) => {
+            let mut keyed = vec![];
+            for value in members(input, name)? {
+                keyed.push((JsonValue::Array(eval(&args[0], &value)?), value));
+            }
+            keyed.sort_by(|a, b| compare(&a.0, &b.0));
+
+            match name {
+                "sort_by"   => one(JsonValue::Array(keyed.into_iter().map(|(_, x)| x).collect())),
+                "min_by"    => one(keyed.into_iter().next().map(|(_, x)| x).unwrap_or(JsonValue::Null)),
+                "max_by"    => one(keyed.into_iter().last().map(|(_, x)| x).unwrap_or(JsonValue::Null)),
+                _           => {
+                    let mut groups: Vec<(JsonValue, Vec<JsonValue>)> = vec![];
+                    for (key, value) in keyed {
+                        match groups.last_mut() {
+                            Some((last, group)) if *last == key => group.push(value),
+                            _                                   => groups.push((key, vec![value])),
+                        }
+                    }
+
+                    let groups = groups.into_iter().map(|(_, group)| match name {
+                        "group_by"  => JsonValue::Array(group),
+                        _           => group.into_iter().next().unwrap_or(JsonValue::Null),
+                    });
+                    one(JsonValue::Array(groups.collect()))
+                }
+            }
+        }
+        ("any", 1) | ("all", 1) => {
+            let mut results = vec![];
+            for value in members(input, name)? {
+                results.push(eval(&args[0], &value)?.iter().any(truthy));
+            }
+
+            match name {
+                "any"   => one(results.iter().any(|x| *x).into()),
+                _       => one(results.iter().all(|x| *x).into()),
+            }
+        }
+        ("first", 1)            => Ok(eval(&args[0], input)?.into_iter().take(1).collect()),
+        ("last", 1)             => Ok(eval(&args[0], input)?.pop().into_iter().collect()),
+        ("limit", 2)            => {
+            let count = number(&arg(0)?, name)?.max(0.0) as usize;
+            Ok(eval(&args[1], input)?.into_iter().take(count).collect())
+        }
+        ("range", 1) | ("range", 2) => {
+            let (start, end) = match args.len() {
+                1   => (0.0, number(&arg(0)?, name)?),
+                _   => (number(&arg(0)?, name)?, number(&arg(1)?, name)?),
+            };
+
+            let mut values  = vec![];
+            let mut x       = start;
+            while x < end {
+                values.push(x.into());
+                x += 1.0;
+            }
+            Ok(values)
+        }
+        ("recurse", 1)          => {
+            let mut results = vec![];
+            let mut pending = vec![input.clone()];
+            while let Some(value) = pending.pop() {
+                let mut children = eval(&args[0], &value).unwrap_or_default();
+                children.reverse();
+                results.push(value);
+                pending.extend(children);
+            }
+            Ok(results)
+        }
+        ("error", 0) | ("error", 1) => {
+            let message = match args.len() {
+                0   => input.clone(),
+                _   => arg(0)?,
+            };
+            Err(JqError::Runtime(to_text(&message)))
+        }
+        _                       => Err(JqError::UnknownFunction(name.to_owned(), args.len())),
+    }
+}
+
+/// Collect `value` and everything within it, depth first.
+fn recurse(value: &JsonValue, values: &mut Vec<JsonValue>) {
+    values.push(value.clone());
+    match value {
+        JsonValue::Array(children)  => children.iter().for_each(|x| recurse(x, values)),
+        JsonValue::Object(object)   => object.iter().for_each(|(_, x)| recurse(x, values)),
+        _                           => {}
+    }
+}
+
+/// Flatten nested arrays in `value` into `values`.
+fn flatten(value: &JsonValue, values: &mut Vec<JsonValue>) {
+    for member in value.members() {
+        match member.is_array() {
+            true    => flatten(member, values),
+            false   => values.push(member.clone()),
+        }
+    }
+}
+
+/// Get the members of the array `value`, for the function `name`.
+fn members(value: &JsonValue, name: &str) -> Result<Vec<JsonValue>, JqError> {
+    match value {
+        JsonValue::Array(values)    => Ok(values.clone()),
+        JsonValue::Object(object)   => Ok(object.iter().map(|(_, x)| x.clone()).collect()),
+        x                           => Err(JqError::Runtime(format!("{name} requires an array, not {}", type_name(x)))),
+    }
+}
+
+/// Get `value` as a number, for the function `name`.
+fn number(value: &JsonValue, name: &str) -> Result<f64, JqError> {
+    value.as_f64()
+        .ok_or_else(|| JqError::Runtime(format!("{name} requires a number, not {}", type_name(value))))
+}
+
+/// Index `target` by `index`.
+fn index_value(target: &JsonValue, index: &JsonValue) -> Result<JsonValue, JqError> {
+    match (target, index.as_str(), index.as_f64()) {
+        (JsonValue::Null, _, _)                         => Ok(JsonValue::Null),
+        (JsonValue::Object(_), Some(key), _)            => Ok(target[key].clone()),
+        (JsonValue::Array(values), _, Some(index))      => {
+            let index = match index < 0.0 {
+                true    => values.len() as f64 + index,
+                false   => index,
+            };
+
+            match index >= 0.0 {
+                true    => Ok(values.get(index as usize).cloned().unwrap_or(JsonValue::Null)),
+                false   => Ok(JsonValue::Null),
+            }
+        }
+        _                                               => Err(JqError::Runtime(format!(
+            "cannot index {} with {}", type_name(target), index.dump()
+        ))),
+    }
+}
+
+/// Slice the array or string `target` from `start` to `end`, counting negative bounds from the end.
+fn slice(target: &JsonValue, start: Option<f64>, end: Option<f64>) -> Result<JsonValue, JqError> {
+    let bounds = |len: usize| {
+        let resolve = |x: f64| match x < 0.0 {
+            true    => (len as f64 + x).max(0.0) as usize,
+            false   => (x as usize).min(len),
+        };
+
+        let start   = start.map_or(0, resolve);
+        let end     = end.map_or(len, resolve);
+        (start, end.max(start))
+    };
+
+    match target {
+        JsonValue::Null             => Ok(JsonValue::Null),
+        JsonValue::Array(values)    => {
+            let (start, end) = bounds(values.len());
+            Ok(JsonValue::Array(values[start..end].to_vec()))
+        }
+        x if x.is_string()          => {
+            let chars = x.as_str().unwrap_or_default().chars().collect::<Vec<_>>();
+            let (start, end) = bounds(chars.len());
+            Ok(chars[start..end].iter().collect::<String>().into())
+        }
+        x                           => Err(JqError::Runtime(format!("cannot slice {}", type_name(x)))),
+    }
+}
+
+/// Apply a binary operator.
+fn binary(operator: BinaryOp, lhs: &JsonValue, rhs: &JsonValue) -> Result<JsonValue, JqError> {
+    let error = || JqError::Runtime(format!(
+        "{} and {} cannot be combined with `{}`", type_name(lhs), type_name(rhs), operator.symbol()
+    ));
+
+    let numbers = lhs.as_f64().zip(rhs.as_f64());
+    match operator {
+        BinaryOp::Equal             => Ok((compare(lhs, rhs) == Ordering::Equal).into()),
+        BinaryOp::NotEqual          => Ok((compare(lhs, rhs) != Ordering::Equal).into()),
+        BinaryOp::Less              => Ok((compare(lhs, rhs) == Ordering::Less).into()),
+        BinaryOp::LessOrEqual       => Ok((compare(lhs, rhs) != Ordering::Greater).into()),
+        BinaryOp::Greater           => Ok((compare(lhs, rhs) == Ordering::Greater).into()),
+        BinaryOp::GreaterOrEqual    => Ok((compare(lhs, rhs) != Ordering::Less).into()),
+        BinaryOp::Add               => match (lhs, rhs) {
+            (JsonValue::Null, x) | (x, JsonValue::Null)     => Ok(x.clone()),
+            (JsonValue::Array(a), JsonValue::Array(b))      => Ok(JsonValue::Array(a.iter().chain(b).cloned().collect())),
+            (JsonValue::Object(_), JsonValue::Object(b))    => {
+                let mut merged = lhs.clone();
+                for (key, value) in b.iter() {
+                    merged[key] = value.clone();
+                }
+                Ok(merged)
+            }
+            _ if lhs.is_string() && rhs.is_string()         => {
+                Ok(format!("{}{}", lhs.as_str().unwrap_or_default(), rhs.as_str().unwrap_or_default()).into())
+            }
+            _                                               => numbers.map(|(a, b)| (a + b).into()).ok_or_else(error),
+        },
+        BinaryOp::Subtract          => match (lhs, rhs) {
+            (JsonValue::Array(a), JsonValue::Array(b))      => Ok(JsonValue::Array(a.iter().filter(|x| !b.contains(x)).cloned().collect())),
+            _                                               => numbers.map(|(a, b)| (a - b).into()).ok_or_else(error),
+        },
+        BinaryOp::Multiply          => numbers.map(|(a, b)| (a * b).into()).ok_or_else(error),
+        BinaryOp::Divide            => match (lhs.as_str(), rhs.as_str()) {
+            (Some(a), Some(b))  => Ok(JsonValue::Array(a.split(b).map(|x| x.into()).collect())),
+            _                   => match numbers {
+                Some((_, 0.0))              => Err(JqError::Runtime("cannot divide by zero".to_owned())),
+                Some((a, b))                => Ok((a / b).into()),
+                None                        => Err(error()),
+            },
+        },
+        BinaryOp::Modulo            => match numbers {
+            Some((_, b)) if b as i64 == 0   => Err(JqError::Runtime("cannot divide by zero".to_owned())),
+            Some((a, b))                    => Ok(((a as i64) % (b as i64)).into()),
+            None                            => Err(error()),
+        },
+    }
+}
+
+/// Check whether `a` contains `b`, recursively for arrays and objects.
+fn contains(a: &JsonValue, b: &JsonValue) -> bool {
+    match (a, b) {
+        (JsonValue::Object(a), JsonValue::Object(b))    => b.iter().all(|(key, b)| a.get(key).is_some_and(|a| contains(a, b))),
+        (JsonValue::Array(a), JsonValue::Array(b))      => b.iter().all(|b| a.iter().any(|a| contains(a, b))),
+        _ if a.is_string() && b.is_string()             => a.as_str().unwrap_or_default().contains(b.as_str().unwrap_or_default()),
+        _                                               => compare(a, b) == Ordering::Equal,
+    }
+}
+
+/// Order values the way jq does: null, false, true, numbers, strings, arrays, then objects.
+fn compare(a: &JsonValue, b: &JsonValue) -> Ordering {
+    let rank = |x: &JsonValue| match x {
+        JsonValue::Null                 => 0,
+        JsonValue::Boolean(false)       => 1,
+        JsonValue::Boolean(true)        => 2,
+        JsonValue::Number(_)            => 3,
+        JsonValue::Short(_)
+        | JsonValue::String(_)          => 4,
+        JsonValue::Array(_)             => 5,
+        JsonValue::Object(_)            => 6,
+    };
+
+    match (a, b) {
+        (JsonValue::Array(a), JsonValue::Array(b))      => {
+            a.iter().zip(b)
+                .map(|(a, b)| compare(a, b))
+                .find(|x| x.is_ne())
+                .unwrap_or(a.len().cmp(&b.len()))
+        }
+        (JsonValue::Object(a), JsonValue::Object(b))    => {
+            let mut keys_a = a.iter().map(|(key, _)| key).collect::<Vec<_>>();
+            let mut keys_b = b.iter().map(|(key, _)| key).collect::<Vec<_>>();
+            keys_a.sort();
+            keys_b.sort();
+
+            keys_a.cmp(&keys_b).then_with(|| {
+                keys_a.iter()
+                    .map(|key| compare(&a[*key], &b[*key]))
+                    .find(|x| x.is_ne())
+                    .unwrap_or(Ordering::Equal)
+            })
+        }
+        _ => match (a.as_f64(), b.as_f64(), a.as_str(), b.as_str()) {
+            (Some(a), Some(b), _, _)    => a.total_cmp(&b),
+            (_, _, Some(a), Some(b))    => a.cmp(b),
+            _                           => rank(a).cmp(&rank(b)),
+        },
+    }
+}
+
+/// Check whether `value` counts as true, which is anything but `false` and `null`.
+fn truthy(value: &JsonValue) -> bool {
+    !matches!(value, JsonValue::Null | JsonValue::Boolean(false))
+}
+
+/// Convert `value` to text, leaving strings as they are.
+fn to_text(value: &JsonValue) -> String {
+    value.as_str()
+        .map(|x| x.to_owned())
+        .unwrap_or_else(|| value.dump())
+}
+
+/// Get the jq name of the type of `value`.
+fn type_name(value: &JsonValue) -> &'static str {
+    match value {
+        JsonValue::Null                     => "null",
+        JsonValue::Boolean(_)               => "boolean",
+        JsonValue::Number(_)                => "number",
+        JsonValue::Short(_)
+        | JsonValue::String(_)              => "string",
+        JsonValue::Array(_)                 => "array",
+        JsonValue::Object(_)                => "object",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use json::object;
+
+    use super::*;
+
+    /// Run `filter` against `input`, returning its outputs as compact json.
+    fn run(filter: &str, input: &JsonValue) -> Result<Vec<String>, JqError> {
+        let filter = filter.parse::<Filter>()?;
+        Ok(filter.run(input)?.iter().map(|x| x.dump()).collect())
+    }
+
+    /// A document with a few color variables to run filters against.
+    fn document() -> JsonValue {
+        object! {
+            "colorVariables": [
+                { "name": "blue / blue.500", "value": "3B82F6" },
+                { "name": "blue / blue.700", "value": "1D4ED8" },
+                { "name": "gray / gray.500", "value": "6B7280" },
+            ],
+        }
+    }
+
+    #[test]
+    fn evaluates_paths_and_iteration() {
+        let json = document();
+        assert_eq!(run(".colorVariables[0].name", &json).unwrap(), [r#""blue / blue.500""#]);
+        assert_eq!(run(".colorVariables[-1].value", &json).unwrap(), [r#""6B7280""#]);
+        assert_eq!(run(".colorVariables[] | .value", &json).unwrap(), [r#""3B82F6""#, r#""1D4ED8""#, r#""6B7280""#]);
+        assert_eq!(run(".colorVariables[1:] | length", &json).unwrap(), ["2"]);
+        assert_eq!(run(r#"[.. | .value? // empty]"#, &json).unwrap(), [r#"["3B82F6","1D4ED8","6B7280"]"#]);
+        assert_eq!(run(".missing.path", &json).unwrap(), ["null"]);
+    }
+
+    #[test]
+    fn evaluates_construction_and_operators() {
+        let json = document();
+        assert_eq!(run(r#"{ count: (.colorVariables | length), first: .colorVariables[0].value }"#, &json).unwrap(), [r#"{"count":3,"first":"3B82F6"}"#]);
+        assert_eq!(run(r#".colorVariables[0] | "\(.name) is #\(.value)""#, &json).unwrap(), [r##""blue / blue.500 is #3B82F6""##]);
+        assert_eq!(run("1 + 2 * 3, 10 / 4, 7 % 3", &json).unwrap(), ["7", "2.5", "1"]);
+        assert_eq!(run("[1, 2] + [3], \"a\" + \"b\"", &json).unwrap(), ["[1,2,3]", r#""ab""#]);
+        assert_eq!(run("1 < 2 and 2 >= 3, null // 5, if . then 1 else 2 end", &json).unwrap(), ["false", "5", "1"]);
+    }
+
+    #[test]
+    fn evaluates_builtins() {
+        let json = document();
+        assert_eq!(run(r#"[.colorVariables[] | select(.name | startswith("blue")) | .value]"#, &json).unwrap(), [r#"["3B82F6","1D4ED8"]"#]);
+        assert_eq!(run(r#".colorVariables | map(.name | split(" / ")[0]) | unique"#, &json).unwrap(), [r#"["blue","gray"]"#]);
+        assert_eq!(run(r#".colorVariables | group_by(.name | split(" / ")[0]) | map(length)"#, &json).unwrap(), ["[2,1]"]);
+        assert_eq!(run(r#".colorVariables | sort_by(.value) | first | .value"#, &json).unwrap(), [r#""1D4ED8""#]);
+        assert_eq!(run(r#".colorVariables[0] | to_entries | map(.key) | join(",")"#, &json).unwrap(), [r#""name,value""#]);
+        assert_eq!(run(r#"[.colorVariables[].value | test("^[0-9A-F]{6}$")] | all"#, &json).unwrap(), ["true"]);
+        assert_eq!(run("[limit(2; range(10))], keys", &json).unwrap(), ["[0,1]", r#"["colorVariables"]"#]);
+    }
+
+    #[test]
+    fn reports_runtime_errors() {
+        let json = document();
+        assert!(matches!(run(".colorVariables[0].name[]", &json), Err(JqError::Runtime(_))));
+        assert!(matches!(run(".colorVariables - 1", &json), Err(JqError::Runtime(_))));
+        assert!(matches!(run(r#"error("bad")"#, &json), Err(JqError::Runtime(x)) if x == "bad"));
+        assert!(matches!(run("nope(1)", &json), Err(JqError::UnknownFunction(x, 1)) if x == "nope"));
+        assert_eq!(run(".colorVariables[0].name[]?", &json).unwrap(), Vec::<String>::new());
+    }
+
+    #[test]
+    fn rejects_unsupported_syntax() {
+        assert!(matches!(".a | (".parse::<Filter>(), Err(JqError::Syntax(_))));
+        assert!(matches!(". as $x | $x".parse::<Filter>(), Err(JqError::Syntax(_))));
+        assert!(matches!("def f: .; f".parse::<Filter>(), Err(JqError::Syntax(_))));
+    }
+}
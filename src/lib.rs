@@ -0,0 +1,19 @@
+//! The core of lunacy-tools, generating color palettes and applying them to Lunacy documents held
+//! in memory. It has no dependency on the file system, so builds for `wasm32` with
+//! `--no-default-features` to run in the browser. The `ffi` and `pyo3` features expose it over a
+//! C interface and to Python for use from other languages.
+
+pub mod archive;
+pub mod export;
+#[cfg(feature = "ffi")]
+pub mod ffi;
+pub mod hct;
+pub mod palette;
+#[cfg(feature = "pyo3")]
+pub mod python;
+pub mod rewrite;
+pub mod schema;
+pub mod sketch;
+
+/// A generic error type.
+pub type Error = Box<dyn std::error::Error>;
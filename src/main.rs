@@ -1,474 +1,708 @@
+mod cache;
+mod canonical;
+mod checkpoint;
+mod cloud;
+mod commands;
+mod compose;
+mod config;
+mod convert;
+mod document;
+mod exit;
+mod fonts;
+mod format;
+mod history;
+mod hooks;
+mod icons;
+mod images;
+mod jq;
+mod merge;
+mod model;
+mod output;
+mod patch;
+mod query;
+mod references;
+mod render;
+mod timings;
+
 use std::collections::BTreeMap;
-use std::io;
+use std::io::{IsTerminal, Write};
+use std::ffi::OsStr;
+use std::fs::File;
+use std::panic::AssertUnwindSafe;
 use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+
+use clap::{arg, command, value_parser, ArgAction, ArgMatches, Command};
+use clap_complete::{ArgValueCompleter, CompleteEnv, CompletionCandidate};
+use lunacy_tools::palette::{self, ColorScheme, RampKind, RampSpace};
+use lunacy_tools::schema::{self, DocumentJson};
+use lunacy_tools::sketch;
 
-use base64::Engine;
-use base64::engine::general_purpose::URL_SAFE_NO_PAD;
-use clap::{arg, command, value_parser, ArgMatches};
-use json::{JsonValue, object};
-use tempdir::TempDir;
-use uuid::Uuid;
+use compose::{ConflictPolicy, SchemeComposer};
+use config::Config;
+use document::{LunacyDocument, OpenOptions};
+use exit::{Exit, Failure, OrExit};
+use output::say;
 
 /// A generic error type.
 type Error = Box<dyn std::error::Error>;
 
+/// The subcommands being run, e.g. `styles overrides`, recorded in the history of documents they
+/// change.
+static OPERATION: OnceLock<String> = OnceLock::new();
+
+/// The configuration providing defaults for the program arguments.
+static CONFIG: OnceLock<Config> = OnceLock::new();
+
 fn main() {
+    // Answer shell completion requests, which are made by running the program with the `COMPLETE`
+    // environment variable set.
+    CompleteEnv::with_factory(cli).complete();
+
     // Parse the program matches.
-    let matches = command!()
+    let matches = cli().get_matches();
+    if matches.get_flag("TIMINGS") {
+        timings::enable();
+    }
+    if matches.get_flag("QUIET") {
+        output::enable_quiet();
+    }
+
+    OPERATION.get_or_init(|| operation_name(&matches));
+
+    // Commands fail by panicking, which exits with the general failure code rather than Rust's
+    // own, unless they fail with a more specific code themselves. Either way the process only
+    // exits here, once whatever the command was holding on to is dropped.
+    let run = std::panic::catch_unwind(AssertUnwindSafe(|| {
+        CONFIG.get_or_init(|| Config::load().or_exit(Exit::InvalidArgs, "failed to read configuration"));
+        run(&matches);
+    }));
+
+    if let Err(payload) = run {
+        let code = match payload.downcast::<Failure>() {
+            Ok(failure) => {
+                if let Some(message) = &failure.message {
+                    eprintln!("error: {message}");
+                }
+
+                failure.code
+            }
+            Err(_)      => Exit::Failure,
+        };
+
+        std::process::exit(code as i32);
+    }
+
+    timings::report();
+}
+
+/// Run the command given in the program `matches`.
+fn run(matches: &ArgMatches) {
+    match matches.subcommand() {
+        Some(("artboards", matches))    => commands::artboards::run(matches),
+        Some(("assets", matches))       => commands::assets::run(matches),
+        Some(("canonicalize", matches)) => commands::canonicalize::run(matches),
+        Some(("checkpoint", matches))   => commands::checkpoint::run(matches),
+        Some(("components", matches))   => commands::components::run(matches),
+        Some(("contrast", matches))     => commands::contrast::run(matches),
+        Some(("convert", matches))      => commands::convert::run(matches),
+        Some(("export", matches))       => commands::export::run(matches),
+        Some(("fonts", matches))        => commands::fonts::run(matches),
+        Some(("gitdiff", matches))      => commands::gitdiff::run(matches),
+        Some(("grep", matches))         => commands::grep::run(matches),
+        Some(("history", matches))      => commands::history::run(matches),
+        Some(("icons", matches))        => commands::icons::run(matches),
+        Some(("jq", matches))           => commands::jq::run(matches),
+        Some(("layers", matches))       => commands::layers::run(matches),
+        Some(("mcp", matches))          => commands::mcp::run(matches),
+        Some(("merge", matches))        => commands::merge::run(matches),
+        Some(("meta", matches))         => commands::meta::run(matches),
+        Some(("migrate", matches))      => commands::migrate::run(matches),
+        Some(("normalize", matches))    => commands::normalize::run(matches),
+        Some(("optimize", matches))     => commands::optimize::run(matches),
+        Some(("pack", matches))         => commands::pack::run(matches),
+        Some(("pages", matches))        => commands::pages::run(matches),
+        Some(("preview", matches))      => commands::preview::run(matches),
+        Some(("query", matches))        => commands::query::run(matches),
+        Some(("render", matches))       => commands::render::run(matches),
+        Some(("repair", matches))       => commands::repair::run(matches),
+        Some(("roundtrip", matches))    => commands::roundtrip::run(matches),
+        Some(("scheme", matches))       => commands::scheme::run(matches),
+        Some(("serve", matches))        => commands::serve::run(matches),
+        Some(("stats", matches))        => commands::stats::run(matches),
+        Some(("styles", matches))       => commands::styles::run(matches),
+        Some(("text", matches))         => commands::text::run(matches),
+        Some(("theme", matches))        => commands::theme::run(matches),
+        Some(("undo", matches))         => commands::undo::run(matches),
+        Some(("unpack", matches))       => commands::unpack::run(matches),
+        Some(("verify", matches))       => commands::verify::run(matches),
+        _                               => update_colors(matches),
+    }
+}
+
+/// Create the program command line interface.
+fn cli() -> Command {
+    command!()
+        .args_conflicts_with_subcommands(true)
+        .subcommand_negates_reqs(true)
         .arg(
-            arg!([FILE] "the lunacy .free file to process")
+            arg!([FILE] "the lunacy .free file, unpacked document directory or cloud document url to process")
                 .required(true)
                 .value_parser(value_parser!(PathBuf))
         )
         .arg(
-            arg!(--group <GROUP> "set the group containing the colors to modify, defaults to 'theme' if unspecified")
+            arg!(--group <GROUP> "set the group containing the colors to modify, defaults to the configured group, or one chosen from the document when run interactively, or 'theme'")
                 .required(false)
                 .value_parser(value_parser!(String))
+                .add(ArgValueCompleter::new(complete_group))
         )
         .arg(
-            arg!(--color_scheme <COLOR_SCHEME> "specify a json file containing a color scheme")
+            arg!(--color_scheme <COLOR_SCHEME> "specify a json file containing a color scheme, repeat to merge several with later files taking precedence")
                 .id("COLOR_SCHEME")
+                .action(ArgAction::Append)
                 .value_parser(value_parser!(PathBuf))
         )
-        .get_matches();
-    
+        .arg(
+            arg!(--on_conflict <POLICY> "how an entry defined by more than one of the color schemes or the schemes they compose is resolved, last_wins keeping the one merged last or error failing, defaults to last_wins")
+                .id("ON_CONFLICT")
+                .value_parser(["last_wins", "error"])
+        )
+        .arg(
+            arg!(--set <COLOR> "set a color as NAME=#VALUE, or link it to another as NAME=link:OTHER, taking precedence over the color scheme")
+                .id("SET")
+                .action(ArgAction::Append)
+                .value_parser(value_parser!(String))
+        )
+        .arg(
+            arg!(--stops <STOPS> "the comma separated stops to generate for each color, defaults to 100,200,...,900, or the Material 3 tones for material3 ramps")
+                .id("STOPS")
+                .value_delimiter(',')
+                .value_parser(value_parser!(u32))
+        )
+        .arg(
+            arg!(--stop_labels <LABELS> "the comma separated names of the tints at each stop, e.g. lightest,lighter,base,darker,darkest, defaults to the stops")
+                .id("STOP_LABELS")
+                .value_delimiter(',')
+                .value_parser(value_parser!(String))
+        )
+        .arg(
+            arg!(--name_format <FORMAT> "the format generated colors are named with, defaults to '{group}{sep}{name}{sep}{stem}.{stop}'")
+                .id("NAME_FORMAT")
+                .value_parser(value_parser!(String))
+        )
+        .arg(
+            arg!(--ramp <RAMP> "how the ramp of each color is generated, tints towards white and black or Material 3 tonal palettes whose stops are tones from 0 to 100, defaults to tints")
+                .id("RAMP")
+                .value_parser(["tints", "material3"])
+        )
+        .arg(
+            arg!(--ramp_space <SPACE> "the color space tints are interpolated in, defaults to srgb")
+                .id("RAMP_SPACE")
+                .value_parser(["srgb", "linear", "oklab"])
+        )
+        .arg(
+            arg!(--max_lightness <T> "the furthest tints move towards white, from 0 to 1, defaults to 0.8")
+                .id("MAX_LIGHTNESS")
+                .value_parser(parse_fraction)
+        )
+        .arg(
+            arg!(--max_darkness <T> "the furthest tints move towards black, from 0 to 1, defaults to 0.8")
+                .id("MAX_DARKNESS")
+                .value_parser(parse_fraction)
+        )
+        .arg(
+            arg!(--adopt "regenerate the ramps of the colors already in the group around their base stop, keeping its value")
+                .id("ADOPT")
+        )
+        .arg(
+            arg!(--update_preview "regenerate the document preview image after updating colors")
+                .id("UPDATE_PREVIEW")
+        )
+        .arg(
+            arg!(--max_entries <COUNT> "the maximum number of entries allowed in the document archive")
+                .id("MAX_ENTRIES")
+                .global(true)
+                .value_parser(value_parser!(usize))
+        )
+        .arg(
+            arg!(--max_size <MEGABYTES> "the maximum decompressed size of the document in megabytes")
+                .id("MAX_SIZE")
+                .global(true)
+                .value_parser(value_parser!(u64))
+        )
+        .arg(
+            arg!(--password <PASSWORD> "the password of an encrypted document, prompted for if required and unspecified")
+                .id("PASSWORD")
+                .global(true)
+                .value_parser(value_parser!(String))
+        )
+        .arg(
+            arg!(--backup <POLICY> "when to back up a document to a .free.bak file before changing it, defaults to never")
+                .id("BACKUP")
+                .global(true)
+                .value_parser(["never", "first", "always"])
+        )
+        .arg(
+            arg!(--no_hooks "skip the hook commands set in the configuration")
+                .id("NO_HOOKS")
+                .global(true)
+        )
+        .arg(
+            arg!(--output_format <FORMAT> "the format to print command output in, defaults to table")
+                .id("OUTPUT_FORMAT")
+                .global(true)
+                .value_parser(["table", "json"])
+        )
+        .arg(
+            arg!(--strict "refuse to modify documents in a newer format than the tool supports, and fail on any problem with a color scheme")
+                .id("STRICT")
+                .global(true)
+        )
+        .arg(
+            arg!(-m --message <MESSAGE> "a message describing the change, recorded in the document history, or naming a checkpoint")
+                .id("MESSAGE")
+                .global(true)
+                .value_parser(value_parser!(String))
+        )
+        .arg(
+            arg!(-y --yes "make destructive changes without asking for confirmation")
+                .id("YES")
+                .global(true)
+        )
+        .arg(
+            arg!(-q --quiet "print nothing but errors")
+                .id("QUIET")
+                .global(true)
+        )
+        .arg(
+            arg!(--timings "report the time spent in each phase of the run, e.g. parsing and writing the document")
+                .id("TIMINGS")
+                .global(true)
+        )
+        .arg(
+            arg!(--zero_timestamps "zero the timestamps of every entry in the document for reproducible output")
+                .id("ZERO_TIMESTAMPS")
+                .global(true)
+        )
+        .subcommand(commands::artboards::command())
+        .subcommand(commands::assets::command())
+        .subcommand(commands::canonicalize::command())
+        .subcommand(commands::checkpoint::command())
+        .subcommand(commands::components::command())
+        .subcommand(commands::contrast::command())
+        .subcommand(commands::convert::command())
+        .subcommand(commands::export::command())
+        .subcommand(commands::fonts::command())
+        .subcommand(commands::gitdiff::command())
+        .subcommand(commands::grep::command())
+        .subcommand(commands::history::command())
+        .subcommand(commands::icons::command())
+        .subcommand(commands::jq::command())
+        .subcommand(commands::layers::command())
+        .subcommand(commands::mcp::command())
+        .subcommand(commands::merge::command())
+        .subcommand(commands::meta::command())
+        .subcommand(commands::migrate::command())
+        .subcommand(commands::normalize::command())
+        .subcommand(commands::optimize::command())
+        .subcommand(commands::pack::command())
+        .subcommand(commands::pages::command())
+        .subcommand(commands::preview::command())
+        .subcommand(commands::query::command())
+        .subcommand(commands::render::command())
+        .subcommand(commands::repair::command())
+        .subcommand(commands::roundtrip::command())
+        .subcommand(commands::scheme::command())
+        .subcommand(commands::serve::command())
+        .subcommand(commands::stats::command())
+        .subcommand(commands::styles::command())
+        .subcommand(commands::text::command())
+        .subcommand(commands::theme::command())
+        .subcommand(commands::undo::command())
+        .subcommand(commands::unpack::command())
+        .subcommand(commands::verify::command())
+}
+
+/// Get the configuration providing defaults for the program arguments.
+fn config() -> &'static Config {
+    CONFIG.get_or_init(Config::default)
+}
+
+/// Get the separator between the levels of variable names, from the configuration.
+fn separator() -> &'static str {
+    config().separator.as_deref().unwrap_or(palette::SEPARATOR)
+}
+
+/// Update the colors of a document from the program arguments.
+fn update_colors(matches: &ArgMatches) {
     // Acquire the document to update from the program arguments.
     let Some(path) = matches.get_one::<PathBuf>("FILE") else {
         panic!("expected .free document as first argument");
     };
 
-    // Read out the group to modify, or default to 'theme'.
-    let group = matches.get_one::<String>("group")
-        .map(|x| x.to_owned())
-        .unwrap_or_else(|| String::from("theme"));
-
     // Parse the color scheme to modify.
-    let scheme = load_color_scheme(&matches);
+    let scheme = load_color_scheme(matches);
 
-    // Bail with no errors if there are no colors to update.
-    if scheme.colors.is_empty() {
-        return;
+    // Bail if there are no colors to update.
+    if scheme.is_empty() {
+        Exit::NothingToDo.fail("no colors to apply, pass --color_scheme or --set");
     }
 
-    // Update the document colors.
-    let mut doc = LunacyDocument::open(&path)
-        .expect("failed to open document");
-
-    doc.update_colors(&group, &scheme)
-        .expect("failed to update colors in document");
-    doc.commit()
-        .expect("failed to commit changes to document");
+    apply_color_scheme(matches, path, &scheme, matches.get_flag("UPDATE_PREVIEW"));
 }
 
-/// Load the color scheme from the program arguments.
-fn load_color_scheme(matches: &ArgMatches) -> ColorScheme {
-    let mut scheme = ColorScheme { colors: vec![] };
-
-    // Load the JSON schema first if provided.
-    if let Some(colors_json) = matches.get_one::<PathBuf>("COLOR_SCHEME") {
-        if let Ok(json_str) = std::fs::read_to_string(colors_json) {
-            if let Ok(json) = json::parse(&json_str) {
-                for (name, color) in json.entries() {
-                    // `value` or `link` are required.
-                    let value = color["value"].as_str()
-                        .or(color["link"].as_str())
-                        .expect("expected `link` or `value`");
-                    // `stop` is optional and defaults to 500 if not present.
-                    let stop  = color.has_key("stop")
-                        .then(|| color["stop"].as_u32().unwrap())
-                        .unwrap_or(500);
-
-                    scheme.colors.push(BaseColor {
-                        name    : name.to_owned(),
-                        value   : value.to_owned(),
-                        stop    : stop,
-                    })
-                }
-            }
-        }
-    }
-
-    scheme
-}
+/// Apply the color `scheme` to the group of the document at `path` given by the program
+/// arguments, confirming any colors removed or overwritten, and regenerate the document preview
+/// if `update_preview` is set.
+fn apply_color_scheme(matches: &ArgMatches, path: &Path, scheme: &ColorScheme, update_preview: bool) {
+    let options = load_edit_options(matches, path);
+    let mut doc = LunacyDocument::open(path, &options)
+        .or_exit(Exit::Document, "failed to open document");
+    doc.set_zero_timestamps(matches.get_flag("ZERO_TIMESTAMPS"));
+
+    // Read out the group to modify, or pick one from the document.
+    let group = match matches.get_one::<String>("group").or(config().group.as_ref()) {
+        Some(group) => group.to_owned(),
+        None        => pick_group(&doc).expect("failed to pick a group"),
+    };
 
-/// A set of colors defining a color scheme to apply to a Lunacy document.
-struct ColorScheme {
-    /// A set of base colors to generate a color palette from.
-    colors  : Vec<BaseColor>
-}
+    let before = group_colors(&doc, &group)
+        .expect("failed to read colors");
 
-#[derive(Clone)]
-struct BaseColor {
-    /// The name of the color.
-    name    : String,
-    /// The hexadecimal value of the color or the name of a color to link to.
-    value   : String,
-    /// The stop the color starts at.
-    stop    : u32,
-}
+    doc.update_colors(&group, scheme)
+        .or_exit(Exit::Scheme, "failed to apply color scheme");
 
-/// The stops to emit for the color.
-const STOPS : &'static [u32]
-    = &[100, 200, 300, 400, 500, 600, 700, 800, 900];
-
-impl BaseColor {
-    /// Create a color from a base color.
-    fn create_tints(&self) -> Result<Vec<Color>, Error> {
-        let (r, g, b)   = hex_to_rgb(&self.value)?;
-        let pivot       = STOPS.iter().position(|x| *x == self.stop).unwrap_or(500);
-        
-        let mut tints = Vec::with_capacity(STOPS.len());
-        for (i, stop) in STOPS.iter().enumerate() {
-            let hex;
-
-            if *stop == self.stop {
-                hex = self.value.to_owned();
-            }
-            else {
-                let t;
-                let dst;
-    
-                if i < pivot {
-                    t   = (pivot - i) as f64 / pivot as f64;
-                    dst = 1.0;
-                }
-                else {
-                    t   = (i - pivot) as f64 / (STOPS.len() - pivot) as f64;
-                    dst = 0.0;
-                };
-                
-                let new_r   = lerp(r, dst, t * 0.8);
-                let new_g   = lerp(g, dst, t * 0.8);
-                let new_b   = lerp(b, dst, t * 0.8);
-    
-                hex         = rgb_to_hex(new_r, new_g, new_b);
-            }
+    // Colors that are removed or replaced with a different value are confirmed first, since
+    // anything using them changes too.
+    let after = group_colors(&doc, &group)
+        .expect("failed to read colors");
 
-            let name_stem = self.name.split('/')
-                .last()
-                .unwrap()
-                .trim();
-
-            tints.push(Color {
-                id      : Uuid::new_v4(),
-                version : 1,
-                name    : format!("{} / {name_stem}.{stop}", self.name),
-                value   : hex
-            });
+    let mut changes = vec![];
+    for (name, value) in &before {
+        match after.get(name) {
+            None                        => changes.push(format!("delete {name} ({value})")),
+            Some(x) if x != value       => changes.push(format!("overwrite {name} ({value} -> {x})")),
+            Some(_)                     => {}
         }
-        
-        Ok(tints)
     }
-}
-
-/// A color palette from a lunacy document.
-#[derive(Default)]
-struct ColorPalette {
-    /// The set of colors in a color palette.
-    colors: BTreeMap<String, Color>
-}
 
-impl ColorPalette {
-    /// Update a color in the palette by name, updating the existing color or creating a new
-    /// one if missing.
-    fn update_by_name(&mut self, color: Color) {
-        if let Some(x) = self.colors.get_mut(&color.name) {
-            x.version  += 1;
-            x.value     = color.value.clone();
-        }
-        else {
-            self.colors.insert(color.name.to_owned(), color);
-        }
+    if !confirm(matches, &changes) {
+        return;
     }
 
-    /// Link in a color to an existing color by name.
-    fn link_by_name(&mut self, color: &BaseColor) {
-        match self.colors.get(&color.value) {
-            None        => panic!("color {} not found in palette", color.value),
-            Some(src)   => {
-                let color = Color {
-                    id      : Uuid::new_v4(),
-                    version : 1,
-                    name    : color.name.clone(),
-                    value   : src.value.clone()
-                };
-
-                self.update_by_name(color);
-            }
-        }
+    if update_preview {
+        commands::preview::update_preview(&mut doc)
+            .expect("failed to update preview");
     }
-}
 
-/// A tint in a sequence of color tints.
-#[derive(Debug)]
-struct Color {
-    /// The unique id of the color.
-    id      : Uuid,
-    /// The version of the color.
-    version : u32,
-    /// The name of the tint.
-    name    : String,
-    /// The hex value of the color.
-    value   : String,
+    doc.commit()
+        .or_exit(Exit::Document, "failed to commit changes to document");
 }
 
-impl Color {
-    // Create a new color from a json representation, filtering by prefix..
-    fn from_json(json: &JsonValue, prefix: &str) -> Option<Color> {
-        /// Decode a uuid from a lunacy id.
-        fn decode_id(id: &str) -> Uuid {
-            let bytes = URL_SAFE_NO_PAD.decode(id).unwrap();
-            let uuid  = Uuid::from_slice(&bytes)
-                .unwrap();
-
-            uuid
-        }
-
-        let name = json["name"].as_str()
-            .unwrap();
+/// Pick the group of colors to modify when none was given.
+///
+/// When the document has more than one group and the tool is run from a terminal, the user
+/// chooses from the groups found in it. Otherwise the group defaults to 'theme'.
+fn pick_group(doc: &LunacyDocument) -> Result<String, Error> {
+    const DEFAULT_GROUP: &str = "theme";
 
-        let Some(name) = name.strip_prefix(prefix) else {
-            return None;
-        };
+    let groups = doc.load_color_groups(separator())?;
 
-        Some(Color {
-            id      : decode_id(json["id"].as_str().unwrap()),
-            version : json["version"].as_u32().unwrap_or(1),
-            name    : name.trim().to_owned(),
-            value   : format!("#{}", json["value"].as_str().unwrap())
-        })
+    if groups.len() < 2 || !std::io::stdin().is_terminal() {
+        return Ok(String::from(DEFAULT_GROUP));
     }
 
-    /// Format the color as a JSON string.
-    fn to_json_obj(&self, group: &str) -> Result<JsonValue, Error> {
-        /// Encode a uuid to a lunacy id.
-        fn encode_id(id: &Uuid) -> String {
-            URL_SAFE_NO_PAD.encode(id.as_bytes())
-        }
-        
-        Ok(object! {
-            "id"        : encode_id(&self.id),
-            "version"   : self.version,
-            "name"      : format!("{group} / {}", self.name.as_str()),
-            "value"     : &self.value.as_str()[1..],
-        })
-    }
-}
+    let default = groups.iter()
+        .position(|x| x == DEFAULT_GROUP)
+        .unwrap_or(0);
 
-/// A lunacy document opened for edit.
-struct LunacyDocument {
-    /// The path to the document we're editing.
-    doc_path: PathBuf,
-    /// The directory containing the extracted document.
-    doc_dir : TempDir,
-}
+    let selection = dialoguer::Select::new()
+        .with_prompt("group to modify")
+        .items(&groups)
+        .default(default)
+        .interact()?;
 
-impl LunacyDocument {
-    /// Open the document at `path` for edit.
-    pub fn open(path: impl AsRef<Path>) -> Result<Self, Error> {
-        // Ensure the file is a .free file.
-        let path = path.as_ref();
-        if path.extension().map(|x| x.to_str()).flatten() != Some("free") {
-            return Err(Box::new(io::Error::new(io::ErrorKind::Unsupported, "only `.free` files are supported")));
-        }
+    Ok(groups[selection].clone())
+}
 
-        // Open a temp directory to hold the document contents.
-        let dir = tempdir::TempDir::new("lunacy-tools")?;
-        // Extract the document to the directory.
-        zip_extensions::zip_extract(&path.to_path_buf(), &dir.path().to_owned())?;
+/// Complete `--group` values with the groups in the document given on the command line.
+///
+/// Completion runs before the arguments are parsed, so the document is the first argument naming
+/// an existing `.free` or `.sketch` file or unpacked document. Encrypted and unreadable documents
+/// complete nothing.
+fn complete_group(current: &OsStr) -> Vec<CompletionCandidate> {
+    let current = current.to_string_lossy();
+
+    let Some(path) = std::env::args_os()
+        .skip(1)
+        .map(PathBuf::from)
+        .find(|x| (matches!(x.extension().and_then(|x| x.to_str()), Some("free" | "sketch")) && x.is_file()) || x.join("document.json").is_file())
+    else {
+        return vec![];
+    };
 
-        Ok(Self {
-            doc_path: path.to_owned(),
-            doc_dir : dir
-        })
-    }
+    let Ok(groups) = read_color_groups(&path) else {
+        return vec![];
+    };
 
-    /// Commit changes to the document.
-    pub fn commit(&mut self) -> Result<(), Error>{
-        zip_extensions::zip_create_from_directory(
-            &self.doc_path,
-            &self.doc_dir.path().to_owned(),
-        )?;
+    groups.into_iter()
+        .filter(|x| x.starts_with(current.as_ref()))
+        .map(CompletionCandidate::new)
+        .collect()
+}
 
-        Ok(())
-    }
+/// Read the groups of color variables from the document at `path` without extracting it.
+fn read_color_groups(path: &Path) -> Result<Vec<String>, Error> {
+    let data = match path.is_dir() {
+        true    => std::fs::read(path.join("document.json"))?,
+        false   => {
+            let mut archive = zip::ZipArchive::new(File::open(path)?)?;
+            let Some(index) = archive.index_for_name("document.json") else {
+                return Ok(vec![]);
+            };
 
-    /// Update colors in the document with the provided color scheme.
-    pub fn update_colors(&mut self, group: &str, scheme: &ColorScheme) -> Result<(), Error> {
-        // Load the document and resolve any existing colors.
-        let mut json    = self.load_json("document.json")?;
-        let mut palette = Self::parse_color_palette(&json, group);
-
-        // Modify or extend the color palette as requested by the user.
-        for base_color in scheme.colors.iter() {
-            // Values with a hashtag are generative colors.
-            if base_color.value.starts_with("#") {
-                for color in base_color.create_tints()? {
-                    palette.update_by_name(color);
-                }
-            }
-            // Otherwise they're link colors.
-            else {
-                palette.link_by_name(&base_color);
-            }
+            let limit = OpenOptions::default().limits.max_size;
+            document::read_entry(&mut archive, index, None, limit)?
         }
+    };
 
-        // Apply changes back to the JSON file.
-        Self::apply_color_palette(&mut json, &palette, group)?;
-        self.save_json("document.json", &json)?;
+    // Sketch documents keep their colors in shared swatches rather than color variables.
+    let json        = String::from_utf8(data)?;
+    let document    = DocumentJson {
+        color_variables : Some(match sketch::is_sketch(&json) {
+            true    => sketch::color_variables(&json)?,
+            false   => schema::color_variables(&json)?,
+        }),
+        ..Default::default()
+    };
 
-        Ok(())
-    }
+    Ok(palette::groups(&document, separator()))
 }
 
-impl LunacyDocument {
-    /// Load a JSON document from the opened lunacy document.
-    fn load_json(&self, path: impl AsRef<Path>) -> Result<JsonValue, Error> {
-        let document    = self.doc_dir.path().join(path);
-        let data        = std::fs::read_to_string(&document)?;
-        let json        = json::parse(&data)?;
+/// Get the value of every color variable in `group` of `doc` by name.
+fn group_colors(doc: &LunacyDocument, group: &str) -> Result<BTreeMap<String, String>, Error> {
+    let prefix = format!("{group}{}", separator().trim_end());
+    let colors = doc.load_color_variables()?
+        .into_iter()
+        .filter(|x| x.name.starts_with(&prefix))
+        .map(|x| (x.name, x.value))
+        .collect();
 
-        Ok(json)
+    Ok(colors)
+}
+
+/// Confirm the destructive `changes` with the user before making them, printing a summary and
+/// prompting unless `--yes` was passed. Returns whether to go ahead.
+///
+/// Nothing needs confirming if there are no changes, and changes can't be confirmed without a
+/// terminal, so `--yes` is required when running unattended.
+fn confirm(matches: &ArgMatches, changes: &[String]) -> bool {
+    if changes.is_empty() || matches.get_flag("YES") {
+        return true;
     }
 
-    /// Save a JSON document back to an opened lunacy document.
-    fn save_json(&self, path: impl AsRef<Path>, json: &JsonValue) -> Result<(), Error> {
-        let document    = self.doc_dir.path().join(path);
-        let json_str    = json.to_string();
-        
-        std::fs::write(document, json_str)?;
+    for change in changes {
+        say!("{change}");
+    }
 
-        Ok(())
+    if !std::io::stdin().is_terminal() {
+        panic!("expected --yes to confirm {} destructive changes when not running interactively", changes.len());
     }
 
-    /// Parse the color palette from a `document.json` file in the specified group.
-    fn parse_color_palette(json: &JsonValue, group: &str) -> ColorPalette {
-        let color_variables = &json["colorVariables"];
-        let mut palette     = ColorPalette::default();
+    print!("make {} destructive changes? [y/N] ", changes.len());
+    std::io::stdout().flush()
+        .expect("failed to write prompt");
 
-        let prefix          = format!("{group} /");
-        for i in 0..color_variables.len() {
-            let color_var   = &color_variables[i];
-            let Some(color) = Color::from_json(&color_var, &prefix) else {
-                continue;
-            };
+    let mut answer = String::new();
+    std::io::stdin().read_line(&mut answer)
+        .expect("failed to read answer");
 
-            // Add the color to the list.
-            palette.colors.insert(color.name.clone(), color);
-        }
+    matches!(answer.trim().to_lowercase().as_str(), "y" | "yes")
+}
 
-        palette
+/// Get the name of the operation being run from the chain of subcommands, e.g. `styles overrides`.
+fn operation_name(matches: &ArgMatches) -> String {
+    let mut names   = vec![];
+    let mut matches = matches;
+    while let Some((name, sub_matches)) = matches.subcommand() {
+        names.push(name);
+        matches = sub_matches;
     }
 
-    /// Apply `palette` to a `document.json` file.
-    fn apply_color_palette(
-        json    : &mut JsonValue,
-        palette : &ColorPalette,
-        group   : &str
-    )
-        -> Result<(), Error>
-    {
-        if !json.has_key("colorVariables") || !json["colorVariables"].is_array() {
-            json.insert("colorVariables", Vec::<JsonValue>::new())
-                .unwrap();
-        }
+    match names.is_empty() {
+        true    => String::from("update colors"),
+        false   => names.join(" "),
+    }
+}
 
-        let JsonValue::Array(color_variables) = &mut json["colorVariables"] else {
-            return Ok(());
-        };
+/// Load the options for opening the document at `path` from the program arguments.
+fn load_open_options(matches: &ArgMatches, path: &Path) -> OpenOptions {
+    let mut options = open_options(matches);
 
-        // Remove the old colors from the variable list.
-        for color in palette.colors.values() {
-            // Remove any variables that start with our colors.
-            let term    = color.name.split('/').next().unwrap().trim();
-            let prefix  = format!("{group} / {term}");
+    // Prompt for a password if the document is encrypted and none was given.
+    if options.password.is_none() && LunacyDocument::is_encrypted(path).unwrap_or(false) {
+        let password = rpassword::prompt_password("password: ")
+            .expect("failed to read password");
 
-            let mut i   = 0;
-            while i < color_variables.len() {
-                if color_variables[i]["name"].as_str().unwrap().starts_with(&prefix) {
-                    color_variables.remove(i);
-                }
-                else {
-                    i += 1;
-                }
-            }
-        }
+        options.password = Some(password);
+    }
 
-        // Now insert the updated colors.
-        for color in palette.colors.values() {
-            color_variables.push(color.to_json_obj(group)?);
-        }
+    options
+}
 
-        Ok(())
+/// Load the options for opening the document at `path` to commit changes to from the program
+/// arguments.
+fn load_edit_options(matches: &ArgMatches, path: &Path) -> OpenOptions {
+    OpenOptions {
+        writable    : true,
+        ..load_open_options(matches, path)
     }
 }
 
-/// Linearly interpolate from a -> b by `t`.
-fn lerp(a: f64, b: f64, t: f64) -> f64 {
-    a * (1.0 - t) + b * t
-}
+/// Load the options for opening documents from the program arguments, without prompting for a
+/// password.
+fn open_options(matches: &ArgMatches) -> OpenOptions {
+    let mut options = OpenOptions::default();
 
-/// Parse a hex value to an RGB tuple.
-fn hex_to_rgb(value: &str) -> Result<(f64, f64, f64), Error> {
-    // We expect either #RRGGBB format only.
-    if value.len() != 7 && value.len() != 9 {
-        return Err(Box::new(ColorParseError::InvalidFormat));
+    // Read out the limits to enforce when extracting the document.
+    if let Some(max_entries) = matches.get_one::<usize>("MAX_ENTRIES") {
+        options.limits.max_entries = *max_entries;
     }
-
-    // Make sure the leading hashtag is present.
-    if !value.starts_with("#") {
-        return Err(Box::new(ColorParseError::InvalidFormat));
+    if let Some(max_size) = matches.get_one::<u64>("MAX_SIZE") {
+        options.limits.max_size = match max_size.checked_mul(1024 * 1024) {
+            Some(x) => x,
+            None    => Exit::InvalidArgs.fail(format!("--max_size {max_size} is too large, expected at most {} megabytes", u64::MAX / (1024 * 1024))),
+        };
     }
 
-    // Parse the hex value.
-    let mut hex = u32::from_str_radix(&value[1..], 16)?;
+    options.strict      = matches.get_flag("STRICT");
+    options.operation   = OPERATION.get().cloned();
+    options.message     = matches.get_one::<String>("MESSAGE").cloned();
+    options.password    = matches.get_one::<String>("PASSWORD").cloned();
+    options.backup      = match matches.get_one::<String>("BACKUP") {
+        Some(backup)    => backup.parse().expect("expected a valid backup policy"),
+        None            => config().backup.unwrap_or_default(),
+    };
 
-    // Shift over by 8 and add FF to account for the alpha channel
-    // in #RRGGBB formats.
-    if value.len() != 9 {
-        hex <<= 8;
-        hex  |= 0xff;
+    if !matches.get_flag("NO_HOOKS") {
+        options.hooks = config().hooks.clone();
     }
 
-    let b = (hex >> 8  & 0xff)  as f64 / 255.0;
-    let g = (hex >> 16  & 0xff) as f64 / 255.0;
-    let r = (hex >> 24 & 0xff)  as f64 / 255.0;
+    // A token in the environment takes precedence so it needn't be kept in a configuration file.
+    options.cloud = config().cloud.clone();
+    if let Ok(token) = std::env::var(cloud::TOKEN_VAR) {
+        options.cloud.token = Some(token);
+    }
 
-    Ok((r, g, b))
+    options
 }
 
-/// Convert RGB to hex.
-fn rgb_to_hex(r: f64, g: f64, b: f64) -> String {
-    let r         = (r * 255.0).round() as u32;
-    let g         = (g * 255.0).round() as u32;
-    let b         = (b * 255.0).round() as u32;
-    let value     = (r << 16) | (g << 8) | (b << 0);
-    
-    format!("#{:06x}", value)
+/// Get how entries defined by more than one color scheme are resolved, from the arguments or the
+/// configuration.
+fn conflict_policy(matches: &ArgMatches) -> ConflictPolicy {
+    match matches.get_one::<String>("ON_CONFLICT") {
+        Some(policy)    => policy.parse().expect("expected a valid conflict policy"),
+        None            => config().on_conflict.unwrap_or_default(),
+    }
 }
 
-/// An error raised when parsing a color value from a string.
-#[derive(Clone, Debug, PartialEq, Eq)]
-pub enum ColorParseError {
-    /// The provided color text was in an unsupported format.
-    InvalidFormat,
-    /// The provided color value was invalid.
-    InvalidValue(std::num::ParseIntError)
+/// Create an empty color scheme generating `stops` named by `labels` in `space`, falling back to
+/// the configuration and then the defaults for each when not given.
+///
+/// Fails when there are labels and their number doesn't match the number of stops.
+fn color_scheme(stops: Option<Vec<u32>>, labels: Option<Vec<String>>, space: Option<RampSpace>, ramp: Option<RampKind>) -> Result<ColorScheme, String> {
+    let ramp    = ramp.or(config().ramp).unwrap_or_default();
+    let stops   = stops.or_else(|| config().stops.clone()).unwrap_or_else(|| ramp.default_stops().to_vec());
+    let labels  = labels.or_else(|| config().stop_labels.clone()).unwrap_or_default();
+    let space   = space.or(config().ramp_space).unwrap_or_default();
+
+    if !labels.is_empty() && labels.len() != stops.len() {
+        return Err(format!("expected a stop label for each of the {} stops, set --stops to as many stops as there are labels", stops.len()));
+    }
+
+    let mut scheme = ColorScheme::new(stops, space);
+    scheme.ramp = ramp;
+    scheme.labels = labels;
+    if let Some(name_format) = &config().name_format {
+        scheme.name_format = name_format.clone();
+    }
+    scheme.separator = separator().to_owned();
+    if let Some(max_lightness) = config().max_lightness {
+        scheme.max_lightness = max_lightness;
+    }
+    if let Some(max_darkness) = config().max_darkness {
+        scheme.max_darkness = max_darkness;
+    }
+
+    Ok(scheme)
 }
 
-impl From<std::num::ParseIntError> for ColorParseError {
-    fn from(x: std::num::ParseIntError) -> Self {
-        Self::InvalidValue(x)
+/// Parse a fraction from 0 to 1 from a program argument.
+fn parse_fraction(value: &str) -> Result<f64, String> {
+    match value.parse::<f64>() {
+        Ok(x) if (0.0..=1.0).contains(&x)   => Ok(x),
+        _                                   => Err(format!("expected a number from 0 to 1, got `{value}`")),
     }
 }
 
-impl std::fmt::Display for ColorParseError {
-    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
-        match self {
-            Self::InvalidFormat     => write!(f, "the provided color string was in an unsupported format"),
-            Self::InvalidValue(x)   => write!(f, "failed to parse color value - {x}")
+/// Load the color scheme from the program arguments.
+fn load_color_scheme(matches: &ArgMatches) -> ColorScheme {
+    let stops = matches.get_many::<u32>("STOPS")
+        .map(|x| x.copied().collect());
+    let labels = matches.get_many::<String>("STOP_LABELS")
+        .map(|x| x.cloned().collect());
+    let space = matches.get_one::<String>("RAMP_SPACE")
+        .map(|x| x.parse().expect("expected a valid ramp space"));
+    let ramp = matches.get_one::<String>("RAMP")
+        .map(|x| x.parse().expect("expected a valid ramp"));
+
+    let mut scheme = color_scheme(stops, labels, space, ramp)
+        .or_exit(Exit::InvalidArgs, "invalid stops");
+    if let Some(name_format) = matches.get_one::<String>("NAME_FORMAT") {
+        scheme.name_format = name_format.clone();
+    }
+    if let Some(max_lightness) = matches.get_one::<f64>("MAX_LIGHTNESS") {
+        scheme.max_lightness = *max_lightness;
+    }
+    if let Some(max_darkness) = matches.get_one::<f64>("MAX_DARKNESS") {
+        scheme.max_darkness = *max_darkness;
+    }
+    scheme.adopt = matches.get_flag("ADOPT");
+
+    // Load the JSON schemes first if provided, each after the schemes it composes, merged in
+    // order with the entries of later schemes replacing those of earlier ones unless conflicts
+    // are errors. Unreadable schemes are ignored unless strict.
+    let mut schemes = SchemeComposer::new(conflict_policy(matches), matches.get_flag("STRICT"));
+    for colors_json in matches.get_many::<PathBuf>("COLOR_SCHEME").into_iter().flatten() {
+        schemes.add(colors_json)
+            .or_exit(Exit::Scheme, "invalid color scheme");
+    }
+    let mut merged = schemes.finish();
+
+    // Colors set on the command line take precedence over the schemes.
+    for set in matches.get_many::<String>("SET").into_iter().flatten() {
+        let Some((name, value)) = set.split_once('=') else {
+            Exit::InvalidArgs.fail(format!("expected --set NAME=VALUE, got `{set}`"));
+        };
+
+        let mut color = json::JsonValue::new_object();
+        match value.strip_prefix("link:") {
+            Some(link)  => color["link"] = link.into(),
+            None        => color["value"] = value.into(),
+        }
+
+        if matches.get_flag("STRICT") {
+            let mut json = json::JsonValue::new_object();
+            json[name] = color.clone();
+            if let Err(e) = palette::check_scheme(&json) {
+                Exit::Scheme.fail(format!("invalid --set {set}: {e}"));
+            }
         }
+
+        merged[name] = color;
     }
-}
 
-impl std::error::Error for ColorParseError { }
\ No newline at end of file
+    scheme.add_colors(&merged);
+
+    scheme
+}
@@ -0,0 +1,184 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use json::JsonValue;
+
+use crate::model;
+
+/// Merge the changes made to `base` in `ours` and `theirs`, where `None` means the value doesn't
+/// exist on that side. Returns the merged value, or `None` if it was deleted.
+///
+/// Objects are merged key by key, and arrays of objects with ids, such as layers and color
+/// variables, item by item. Anything else changed differently on both sides is a conflict, which
+/// is recorded in `conflicts` by its path, and resolved in favour of `ours`.
+pub fn merge(
+    base        : Option<&JsonValue>,
+    ours        : Option<&JsonValue>,
+    theirs      : Option<&JsonValue>,
+    path        : &str,
+    conflicts   : &mut Vec<String>
+)
+    -> Option<JsonValue>
+{
+    if ours == theirs || theirs == base {
+        return ours.cloned();
+    }
+    if ours == base {
+        return theirs.cloned();
+    }
+
+    match (ours, theirs) {
+        (Some(ours @ JsonValue::Object(_)), Some(theirs @ JsonValue::Object(_))) => {
+            let base = base.filter(|x| x.is_object());
+            return Some(merge_objects(base, ours, theirs, path, conflicts));
+        }
+        (Some(JsonValue::Array(ours)), Some(JsonValue::Array(theirs))) => {
+            let base = match base {
+                Some(JsonValue::Array(base))    => base.as_slice(),
+                _                               => &[],
+            };
+
+            if let Some(merged) = merge_arrays(base, ours, theirs, path, conflicts) {
+                return Some(merged);
+            }
+
+            // Items without ids can still be matched up by position while none were added or
+            // removed.
+            if base.len() == ours.len() && ours.len() == theirs.len() {
+                let merged = (0..ours.len())
+                    .filter_map(|i| merge(Some(&base[i]), Some(&ours[i]), Some(&theirs[i]), &format!("{path}[{i}]"), conflicts))
+                    .collect::<Vec<_>>();
+
+                return Some(merged.into());
+            }
+        }
+        _ => {}
+    }
+
+    conflicts.push(path.to_owned());
+
+    // Content changed on one side and deleted on the other is kept rather than lost.
+    ours.or(theirs).cloned()
+}
+
+/// Merge two objects key by key, keeping the key order of `ours` with keys only `theirs` has
+/// after them.
+fn merge_objects(
+    base        : Option<&JsonValue>,
+    ours        : &JsonValue,
+    theirs      : &JsonValue,
+    path        : &str,
+    conflicts   : &mut Vec<String>
+)
+    -> JsonValue
+{
+    let keys = ours.entries()
+        .chain(theirs.entries())
+        .chain(base.into_iter().flat_map(|x| x.entries()))
+        .map(|(key, _)| key)
+        .fold(vec![], |mut keys, key| {
+            if !keys.contains(&key) {
+                keys.push(key);
+            }
+            keys
+        });
+
+    let mut merged = JsonValue::new_object();
+    for key in keys {
+        let path = match path.is_empty() {
+            true    => key.to_owned(),
+            false   => format!("{path}.{key}"),
+        };
+
+        let value = merge(base.and_then(|x| get(x, key)), get(ours, key), get(theirs, key), &path, conflicts);
+        if let Some(value) = value {
+            merged[key] = value;
+        }
+    }
+
+    merged
+}
+
+/// Merge two arrays of objects with ids item by item, returning `None` if the arrays can't be
+/// matched up by id.
+///
+/// Items keep the order of whichever side reordered them, with items added on the other side
+/// placed after the item they followed.
+fn merge_arrays(
+    base        : &[JsonValue],
+    ours        : &[JsonValue],
+    theirs      : &[JsonValue],
+    path        : &str,
+    conflicts   : &mut Vec<String>
+)
+    -> Option<JsonValue>
+{
+    let base_ids    = item_ids(base)?;
+    let our_ids     = item_ids(ours)?;
+    let their_ids   = item_ids(theirs)?;
+
+    let base_items  = items_by_id(base);
+    let our_items   = items_by_id(ours);
+    let their_items = items_by_id(theirs);
+
+    let (primary, secondary) = match our_ids == base_ids {
+        true    => (&their_ids, &our_ids),
+        false   => (&our_ids, &their_ids),
+    };
+
+    let mut order = primary.clone();
+    for (i, id) in secondary.iter().enumerate() {
+        if order.contains(id) {
+            continue;
+        }
+
+        let position = secondary[..i].iter()
+            .rev()
+            .find_map(|x| order.iter().position(|y| y == x))
+            .map(|x| x + 1)
+            .unwrap_or(0);
+
+        order.insert(position, id);
+    }
+
+    let mut merged = JsonValue::new_array();
+    for id in order {
+        let path    = format!("{path}[{id}]");
+        let value   = merge(base_items.get(id).copied(), our_items.get(id).copied(), their_items.get(id).copied(), &path, conflicts);
+        if let Some(value) = value {
+            merged.push(value).ok()?;
+        }
+    }
+
+    Some(merged)
+}
+
+/// Get the ids of `items` in order, or `None` if any item doesn't have a unique id.
+fn item_ids(items: &[JsonValue]) -> Option<Vec<&str>> {
+    let mut seen    = BTreeSet::new();
+    let mut ids     = vec![];
+    for item in items {
+        let id = model::layer_id(item)?;
+        if !seen.insert(id) {
+            return None;
+        }
+
+        ids.push(id);
+    }
+
+    Some(ids)
+}
+
+/// Get `items` by their id.
+fn items_by_id(items: &[JsonValue]) -> BTreeMap<&str, &JsonValue> {
+    items.iter()
+        .filter_map(|x| Some((model::layer_id(x)?, x)))
+        .collect()
+}
+
+/// Get the value of `key` in `json`, if it has one.
+fn get<'a>(json: &'a JsonValue, key: &str) -> Option<&'a JsonValue> {
+    match json.has_key(key) {
+        true    => Some(&json[key]),
+        false   => None,
+    }
+}
@@ -0,0 +1,392 @@
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use json::JsonValue;
+use uuid::Uuid;
+
+use crate::document::LunacyDocument;
+use crate::history;
+use crate::Error;
+
+use lunacy_tools::schema::{Layer, Page};
+
+pub use lunacy_tools::schema::PAGES_DIR;
+
+/// The key holding the child layers of a page or layer.
+pub const LAYERS_KEY: &str = "layers";
+
+/// The separator between the levels of a hierarchical name, e.g. `theme / blue`.
+pub const NAME_SEPARATOR: char = '/';
+
+/// The key text styles are stored under in `document.json`.
+pub const TEXT_STYLES_KEY: &str = "textStyles";
+
+/// The kinds of shared style stored in `document.json`, as a label and the key they're stored
+/// under.
+pub const STYLE_KINDS: &[(&str, &str)] = &[
+    ("color",   "colorStyles"),
+    ("text",    TEXT_STYLES_KEY),
+    ("effect",  "effectStyles"),
+];
+
+/// The keys a font is described by in text layers and styles.
+pub mod font_key {
+    /// The font family, e.g. `Inter`.
+    pub const FAMILY    : &str = "fontFamily";
+    /// The numeric font weight, e.g. `700`.
+    pub const WEIGHT    : &str = "fontWeight";
+    /// The font style, e.g. `italic`.
+    pub const STYLE     : &str = "fontStyle";
+}
+
+/// The weight assumed for fonts that don't specify one.
+pub const DEFAULT_FONT_WEIGHT: u32 = 400;
+
+/// A reference to a font from a text layer or style.
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct FontRef {
+    /// The font family.
+    pub family  : String,
+    /// The numeric font weight.
+    pub weight  : u32,
+    /// Whether the italic style is used.
+    pub italic  : bool,
+}
+
+impl FontRef {
+    /// Read the font described by `json`, if it describes one.
+    pub fn from_json(json: &JsonValue) -> Option<Self> {
+        Some(Self {
+            family  : json[font_key::FAMILY].as_str()?.to_owned(),
+            weight  : json[font_key::WEIGHT].as_u32().unwrap_or(DEFAULT_FONT_WEIGHT),
+            italic  : json[font_key::STYLE].as_str().is_some_and(|x| x.eq_ignore_ascii_case("italic")),
+        })
+    }
+
+    /// Write the font to `json`, only touching the keys describing properties that changed.
+    pub fn write_json(&self, json: &mut JsonValue) {
+        let current = Self::from_json(json);
+
+        if current.as_ref().map(|x| &x.family) != Some(&self.family) {
+            json[font_key::FAMILY] = self.family.as_str().into();
+        }
+
+        if current.as_ref().map_or(DEFAULT_FONT_WEIGHT, |x| x.weight) != self.weight {
+            json[font_key::WEIGHT] = self.weight.into();
+        }
+
+        if current.as_ref().is_some_and(|x| x.italic) != self.italic {
+            json[font_key::STYLE] = if self.italic { "italic" } else { "normal" }.into();
+        }
+    }
+}
+
+impl std::fmt::Display for FontRef {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{} {}", self.family, self.weight)?;
+        if self.italic {
+            write!(f, " italic")?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Common layer types.
+pub mod layer_type {
+    /// A top level frame on a page.
+    pub const ARTBOARD        : &str = "artboard";
+    /// A rectangle, optionally with rounded corners.
+    pub const RECTANGLE       : &str = "rectangle";
+    /// An ellipse filling the layer frame.
+    pub const OVAL            : &str = "oval";
+    /// An arbitrary vector shape described by SVG path data.
+    pub const PATH            : &str = "path";
+    /// A block of text.
+    pub const TEXT            : &str = "text";
+    /// An embedded raster image.
+    pub const IMAGE           : &str = "image";
+    /// A reusable component, instanced elsewhere in the document.
+    pub const COMPONENT       : &str = "component";
+    /// An instance of a component.
+    pub const INSTANCE        : &str = "instance";
+    /// A component in a document imported from Sketch.
+    pub const SYMBOL_MASTER   : &str = "symbolmaster";
+    /// An instance of a component in a document imported from Sketch.
+    pub const SYMBOL_INSTANCE : &str = "symbolinstance";
+}
+
+/// The keys the content of a text layer may be stored under, Lunacy uses `text` and older
+/// documents `string`.
+pub const TEXT_KEYS: &[&str] = &["text", "string"];
+
+/// The override properties that replace the content of a text layer within an instance.
+pub const TEXT_OVERRIDE_PROPERTIES: &[&str] = &["text", "stringValue"];
+
+/// The key an instance stores the id of its component under.
+pub const COMPONENT_ID_KEY: &str = "componentId";
+
+/// The key components and instances in documents imported from Sketch are linked by.
+pub const SYMBOL_ID_KEY: &str = "symbolID";
+
+/// The keys an instance stores its overrides under, Lunacy uses `overrides` and Sketch
+/// `overrideValues`.
+pub const OVERRIDE_KEYS: &[&str] = &["overrides", "overrideValues"];
+
+/// The position and size of a layer, relative to its parent.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Frame {
+    /// The horizontal offset from the parent layer.
+    pub x       : f64,
+    /// The vertical offset from the parent layer.
+    pub y       : f64,
+    /// The width of the layer.
+    pub width   : f64,
+    /// The height of the layer.
+    pub height  : f64,
+}
+
+impl Frame {
+    /// Read the frame of `layer`, stored either under `frame` or on the layer itself.
+    pub fn from_layer(layer: &JsonValue) -> Self {
+        let json = match layer["frame"].is_object() {
+            true    => &layer["frame"],
+            false   => layer,
+        };
+
+        Self {
+            x       : json["x"].as_f64().unwrap_or_default(),
+            y       : json["y"].as_f64().unwrap_or_default(),
+            width   : json["width"].as_f64().unwrap_or_default(),
+            height  : json["height"].as_f64().unwrap_or_default(),
+        }
+    }
+
+    /// Read the frame of the typed `layer`, stored either under `frame` or on the layer itself.
+    pub fn from_typed(layer: &Layer) -> Self {
+        let json = match layer.extra.get("frame").and_then(|x| x.as_object()) {
+            Some(frame) => frame,
+            None        => &layer.extra,
+        };
+
+        let number = |key: &str| json.get(key).and_then(|x| x.as_f64()).unwrap_or_default();
+        Self {
+            x       : number("x"),
+            y       : number("y"),
+            width   : number("width"),
+            height  : number("height"),
+        }
+    }
+}
+
+/// Derive a Lunacy id from `seed`, so objects created from the same input get the same id.
+pub fn derive_id(seed: &str) -> String {
+    let id = Uuid::new_v5(&Uuid::NAMESPACE_OID, seed.as_bytes());
+    URL_SAFE_NO_PAD.encode(id.as_bytes())
+}
+
+/// Get the names of the page entries in `doc`, in archive order.
+pub fn page_entries(doc: &LunacyDocument) -> Vec<String> {
+    doc.entries()
+        .iter()
+        .filter(|x| x.name.starts_with(PAGES_DIR) && x.name.ends_with(".json"))
+        .map(|x| x.name.clone())
+        .collect()
+}
+
+/// Get the names of the JSON entries of the design in `doc`, in archive order, leaving out the
+/// tool's own entries such as the history.
+pub fn json_entries(doc: &LunacyDocument) -> Vec<String> {
+    doc.entries()
+        .iter()
+        .filter(|x| x.name.ends_with(".json") && !x.name.starts_with(history::TOOL_DIR))
+        .map(|x| x.name.clone())
+        .collect()
+}
+
+/// Load every page in `doc` into the typed model along with the entry it was loaded from, for
+/// reading the names, types and structure of its layers.
+pub fn pages(doc: &LunacyDocument) -> Result<Vec<(String, Page)>, Error> {
+    page_entries(doc)
+        .into_iter()
+        .map(|entry| {
+            let page = doc.load(&entry)?;
+            Ok((entry, page))
+        })
+        .collect()
+}
+
+/// Load every page in `doc` as json along with the entry it was loaded from, for editing pages or
+/// reading the properties of layers the typed model doesn't describe.
+pub fn load_pages(doc: &LunacyDocument) -> Result<Vec<(String, JsonValue)>, Error> {
+    let mut pages = vec![];
+    for entry in page_entries(doc) {
+        let json = doc.load_json(&entry)?;
+        pages.push((entry, json));
+    }
+
+    Ok(pages)
+}
+
+/// Get the type of `layer`, lowercased for comparison.
+///
+/// Lunacy stores the type under `type`, documents imported from Sketch under `_class`.
+pub fn layer_type(layer: &JsonValue) -> String {
+    layer["type"].as_str()
+        .or(layer["_class"].as_str())
+        .unwrap_or_default()
+        .to_lowercase()
+}
+
+/// Get the id of `layer`, Lunacy stores this under `id` and Sketch under `do_objectID`.
+pub fn layer_id(layer: &JsonValue) -> Option<&str> {
+    layer["id"].as_str()
+        .or(layer["do_objectID"].as_str())
+}
+
+/// Get the key the content of the text layer `layer` is stored under, if it has any.
+pub fn text_key(layer: &JsonValue) -> Option<&'static str> {
+    TEXT_KEYS.iter()
+        .copied()
+        .find(|x| layer[*x].is_string())
+}
+
+/// Check whether `layer` is visible, Lunacy stores this under `visible` and Sketch under
+/// `isVisible`.
+pub fn is_visible(layer: &JsonValue) -> bool {
+    layer["visible"].as_bool()
+        .or(layer["isVisible"].as_bool())
+        .unwrap_or(true)
+}
+
+/// Check whether `layer` is a component.
+pub fn is_component(layer: &JsonValue) -> bool {
+    matches!(layer_type(layer).as_str(), layer_type::COMPONENT | layer_type::SYMBOL_MASTER)
+}
+
+/// Check whether `layer` is an instance of a component.
+pub fn is_instance(layer: &JsonValue) -> bool {
+    matches!(layer_type(layer).as_str(), layer_type::INSTANCE | layer_type::SYMBOL_INSTANCE)
+}
+
+/// Get the id instances of the component `layer` refer to it by.
+pub fn component_id(layer: &JsonValue) -> Option<&str> {
+    layer[SYMBOL_ID_KEY].as_str()
+        .or(layer_id(layer))
+}
+
+/// Get the id of the component the instance `layer` is an instance of.
+pub fn instance_component_id(layer: &JsonValue) -> Option<&str> {
+    layer[COMPONENT_ID_KEY].as_str()
+        .or(layer[SYMBOL_ID_KEY].as_str())
+}
+
+/// Visit every override of the instance `layer`.
+pub fn visit_overrides<'a>(layer: &'a JsonValue, f: &mut impl FnMut(&'a JsonValue)) {
+    for key in OVERRIDE_KEYS {
+        layer[*key].members().for_each(&mut *f);
+    }
+}
+
+/// Get the ids of the layers an override applies to, outermost first, and the property it
+/// overrides.
+///
+/// Lunacy stores these under `layerId` and `property`, Sketch encodes both in `overrideName`,
+/// e.g. `outer/inner_stringValue`.
+pub fn override_target(json: &JsonValue) -> Option<(Vec<&str>, &str)> {
+    if let (Some(layer), Some(property)) = (json["layerId"].as_str(), json["property"].as_str()) {
+        return Some((layer.split(NAME_SEPARATOR).collect(), property));
+    }
+
+    let (layers, property) = json["overrideName"].as_str()?.rsplit_once('_')?;
+    Some((layers.split(NAME_SEPARATOR).collect(), property))
+}
+
+/// Get every artboard in `doc` as json along with the page entry it is on, in document order, for
+/// rendering them.
+pub fn artboards(doc: &LunacyDocument) -> Result<Vec<(String, JsonValue)>, Error> {
+    let mut artboards = vec![];
+    for (entry, page) in load_pages(doc)? {
+        visit_layers(&page, &mut |layer| {
+            if layer_type(layer) == layer_type::ARTBOARD {
+                artboards.push((entry.clone(), layer.clone()));
+            }
+        });
+    }
+
+    Ok(artboards)
+}
+
+/// Visit every layer below `json` depth first, excluding `json` itself.
+pub fn visit_layers<'a>(json: &'a JsonValue, f: &mut impl FnMut(&'a JsonValue)) {
+    for layer in json[LAYERS_KEY].members() {
+        f(layer);
+        visit_layers(layer, f);
+    }
+}
+
+/// Visit every layer below `json` depth first, excluding `json` itself, allowing them to be
+/// modified.
+pub fn visit_layers_mut(json: &mut JsonValue, f: &mut impl FnMut(&mut JsonValue)) {
+    // Indexing mutably would add the key to layers without children.
+    if !json.has_key(LAYERS_KEY) {
+        return;
+    }
+
+    for layer in json[LAYERS_KEY].members_mut() {
+        f(layer);
+        visit_layers_mut(layer, f);
+    }
+}
+
+/// Update every font described within `json`, including in child layers, replacing those `f`
+/// returns a replacement for. Returns the number of fonts replaced.
+pub fn update_fonts(json: &mut JsonValue, f: &mut impl FnMut(&FontRef) -> Option<FontRef>) -> usize {
+    let mut count = 0;
+    if let Some(replaced) = FontRef::from_json(json).and_then(|x| f(&x)) {
+        replaced.write_json(json);
+        count += 1;
+    }
+
+    match json {
+        JsonValue::Object(object) => {
+            count + object.iter_mut()
+                .map(|(_, value)| update_fonts(value, f))
+                .sum::<usize>()
+        }
+        JsonValue::Array(values) => {
+            count + values.iter_mut()
+                .map(|value| update_fonts(value, f))
+                .sum::<usize>()
+        }
+        _ => count,
+    }
+}
+
+/// Visit every font described within `json`, without descending into child layers.
+pub fn visit_fonts(json: &JsonValue, f: &mut impl FnMut(FontRef)) {
+    if let Some(font) = FontRef::from_json(json) {
+        f(font);
+    }
+
+    match json {
+        JsonValue::Object(object) => {
+            for (_, value) in object.iter().filter(|(key, _)| *key != LAYERS_KEY) {
+                visit_fonts(value, f);
+            }
+        }
+        JsonValue::Array(values) => {
+            for value in values {
+                visit_fonts(value, f);
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Get the top level group of a hierarchical `name`, e.g. `theme` for `theme / blue / 500`.
+pub fn group_name(name: &str) -> &str {
+    name.split(NAME_SEPARATOR)
+        .next()
+        .unwrap_or_default()
+        .trim()
+}
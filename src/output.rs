@@ -0,0 +1,35 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+
+/// Whether output other than errors is suppressed, when `--quiet` is given.
+static QUIET: AtomicBool = AtomicBool::new(false);
+
+/// Suppress all output other than errors for the rest of the run.
+pub fn enable_quiet() {
+    QUIET.store(true, Ordering::Relaxed);
+}
+
+/// Check whether output other than errors is suppressed.
+pub fn quiet() -> bool {
+    QUIET.load(Ordering::Relaxed)
+}
+
+/// Print a line to stdout as `println!` does, unless `--quiet` was given.
+macro_rules! say {
+    ($($arg:tt)*) => {
+        if !crate::output::quiet() {
+            println!($($arg)*);
+        }
+    };
+}
+
+/// Print a warning line to stderr as `eprintln!` does, unless `--quiet` was given.
+macro_rules! warning {
+    ($($arg:tt)*) => {
+        if !crate::output::quiet() {
+            eprintln!($($arg)*);
+        }
+    };
+}
+
+pub(crate) use say;
+pub(crate) use warning;
@@ -0,0 +1,1630 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+
+use base64::Engine;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use json::JsonValue;
+use serde_json::{json, Value};
+use uuid::Uuid;
+
+use crate::hct::Hct;
+use crate::schema::{ColorVariable, DocumentJson, Extra, Style};
+use crate::Error;
+
+/// Apply the color `scheme` to the color variables in `group` of a `document.json`.
+///
+/// Colors are matched by name, existing colors are updated in place while new colors are added.
+pub fn apply(document: &mut DocumentJson, group: &str, scheme: &ColorScheme) -> Result<(), Error> {
+    let palette = generate(document, group, scheme)?;
+    apply_palette(document, &palette);
+    apply_gradients(document, &scheme.gradients, &palette);
+
+    Ok(())
+}
+
+/// Generate the palette of `group` with the color `scheme` applied to the colors already in it
+/// in a `document.json`, to apply to the document with `apply_palette`.
+pub fn generate(document: &DocumentJson, group: &str, scheme: &ColorScheme) -> Result<ColorPalette, Error> {
+    // Resolve any existing colors.
+    let mut palette = parse_color_palette(document, Names::new(scheme, group)?)?;
+
+    // Colors already in the document are adopted as the bases of their ramps, keeping the value
+    // picked for them, unless the scheme sets them itself.
+    let mut adopted = match scheme.adopt {
+        true    => palette.adopt(scheme),
+        false   => vec![],
+    };
+    adopted.retain(|x| !scheme.colors.iter().any(|y| y.name == x.name));
+
+    // Modify or extend the color palette as requested by the user.
+    for base_color in adopted.iter().chain(scheme.colors.iter()) {
+        // Colors with an overlay are elevated surfaces.
+        if let Some(overlay) = &base_color.overlay {
+            for color in base_color.create_elevations(&palette, overlay, base_color.ramp.space.unwrap_or(scheme.space))? {
+                palette.update_by_name(color);
+            }
+        }
+        // Colors with categories are categorical palettes anchored to them.
+        else if let Some(categories) = &base_color.categories {
+            for color in base_color.create_categories(&palette, categories)? {
+                palette.update_by_name(color);
+            }
+        }
+        // Colors with scrims are overlays of black, white and themselves.
+        else if !base_color.scrims.is_empty() {
+            for color in base_color.create_scrims(&palette)? {
+                palette.update_by_name(color);
+            }
+        }
+        // Neutral colors are ramps of grays tinted towards them.
+        else if let Some(chroma) = base_color.neutral {
+            for color in base_color.create_neutrals(&palette, scheme, chroma)? {
+                palette.update_by_name(color);
+            }
+        }
+        // Colors with a colormap are sampled from it.
+        else if !base_color.colormap.is_empty() {
+            for color in base_color.sample_colormap(&palette.names, scheme)? {
+                palette.update_by_name(color);
+            }
+        }
+        // Values with a hashtag are generative colors.
+        else if base_color.value.starts_with("#") {
+            for color in base_color.create_tints(&palette.names, scheme)? {
+                palette.update_by_name(color);
+            }
+        }
+        // Otherwise they're link colors.
+        else {
+            palette.link_by_name(base_color);
+        }
+
+        // Interaction states are derived from the color once it's in the palette.
+        for color in base_color.create_states(&palette)? {
+            palette.update_by_name(color);
+        }
+    }
+
+    Ok(palette)
+}
+
+/// Get the names of the groups of color variables in a `document.json`, the first level of their
+/// names split by `separator`, in alphabetical order.
+pub fn groups(document: &DocumentJson, separator: &str) -> Vec<String> {
+    document.color_variables()
+        .iter()
+        .filter_map(|x| x.name.split_once(separator.trim_end()))
+        .map(|(group, _)| group.trim().to_owned())
+        .collect::<BTreeSet<_>>()
+        .into_iter()
+        .collect()
+}
+
+/// Get the ramps of tints in `group` of a `document.json`, every color with more than one tint
+/// named after a numeric stop, e.g. `blue / blue.500` or `blue / 500` with the ` / ` `separator`,
+/// in the order they first appear.
+pub fn ramps(document: &DocumentJson, group: &str, separator: &str) -> Vec<Ramp> {
+    let prefix = format!("{group}{separator}");
+
+    let mut ramps = Vec::<Ramp>::new();
+    for variable in document.color_variables() {
+        let Some((name, tint)) = variable.name.strip_prefix(&prefix).and_then(|x| x.rsplit_once(separator)) else {
+            continue;
+        };
+        let Some(stop) = tint.rsplit('.').next().and_then(|x| x.parse::<u32>().ok()) else {
+            continue;
+        };
+
+        let tint = (stop, variable.value.clone());
+        match ramps.iter_mut().find(|x| x.name == name) {
+            Some(ramp)  => ramp.tints.push(tint),
+            None        => ramps.push(Ramp { name: name.to_owned(), tints: vec![tint] }),
+        }
+    }
+
+    ramps.retain(|x| x.tints.len() > 1);
+    for ramp in &mut ramps {
+        ramp.tints.sort_by_key(|(stop, _)| *stop);
+    }
+
+    ramps
+}
+
+/// Parse the color palette from a `document.json` file of the variables in the group `names`
+/// describes.
+fn parse_color_palette(document: &DocumentJson, names: Names) -> Result<ColorPalette, Error> {
+    let mut palette = ColorPalette { colors: BTreeMap::new(), names };
+
+    for variable in document.color_variables() {
+        let Some(color) = Color::from_variable(variable, palette.names.prefix.trim_end())? else {
+            continue;
+        };
+
+        // Add the color to the list.
+        palette.colors.insert(color.name.clone(), color);
+    }
+
+    Ok(palette)
+}
+
+/// Apply the `palette` generated for a group to a `document.json`.
+pub fn apply_palette(document: &mut DocumentJson, palette: &ColorPalette) {
+    let color_variables = document.color_variables_mut();
+
+    // Remove the old colors from the variable list.
+    for color in palette.colors.values() {
+        // Remove any variables that start with our colors.
+        let term    = palette.names.term(&color.name);
+        let prefix  = format!("{}{term}", palette.names.prefix);
+
+        color_variables.retain(|x| !x.name.starts_with(&prefix));
+    }
+
+    // Now insert the updated colors.
+    for color in palette.colors.values() {
+        color_variables.push(color.to_variable(&palette.names.prefix));
+    }
+}
+
+/// Apply the `gradients` to the color styles of a `document.json`, named and with stops referring
+/// to colors as in the group of `palette`, with stops taking the colors of the color variables
+/// they refer to.
+///
+/// Gradients are matched to styles by name, existing styles are updated in place while new ones
+/// are added. The stops of every other gradient style that refer to a color variable are updated
+/// with its current value too, so gradients follow the colors they're made of.
+pub fn apply_gradients(document: &mut DocumentJson, gradients: &[Gradient], palette: &ColorPalette) {
+    if gradients.is_empty() && document.color_styles.is_none() {
+        return;
+    }
+
+    let variables = document.color_variables()
+        .iter()
+        .map(|x| (x.name.clone(), (x.id.clone(), x.value.clone())))
+        .collect::<HashMap<_, _>>();
+
+    let styles = document.color_styles.get_or_insert_with(Vec::new);
+    for gradient in gradients {
+        let stops = gradient.stops.iter()
+            .map(|stop| {
+                if stop.color.starts_with("#") {
+                    return json!({ "position": stop.position, "color": stop.color.to_uppercase() });
+                }
+
+                match variables.get(&format!("{}{}", palette.names.prefix, stop.color)) {
+                    None            => panic!("color {} not found in palette", stop.color),
+                    Some((id, _))   => json!({ "position": stop.position, "colorVariableId": id }),
+                }
+            })
+            .collect::<Vec<_>>();
+
+        let value = json!({ "type": gradient.kind.to_string(), "angle": gradient.angle, "stops": stops });
+        let name  = format!("{}{}", palette.names.prefix, gradient.name);
+        match styles.iter_mut().find(|x| x.name.as_deref() == Some(name.as_str())) {
+            Some(style) => {
+                style.extra.insert("gradient".to_owned(), value);
+            }
+            None        => {
+                let id = color_id(&palette.names.group, &format!("gradient / {}", gradient.name));
+
+                let mut extra = Extra::new();
+                extra.insert("gradient".to_owned(), value);
+
+                styles.push(Style {
+                    id      : Some(URL_SAFE_NO_PAD.encode(id.as_bytes())),
+                    name    : Some(name),
+                    extra,
+                });
+            }
+        }
+    }
+
+    // Fill in the colors of every stop that refers to a color variable.
+    let values = variables.into_values().collect::<HashMap<_, _>>();
+    for style in styles.iter_mut() {
+        let Some(Value::Array(stops)) = style.extra.get_mut("gradient").and_then(|x| x.get_mut("stops")) else {
+            continue;
+        };
+
+        for stop in stops {
+            let value = stop.get("colorVariableId")
+                .and_then(|x| x.as_str())
+                .and_then(|x| values.get(x));
+
+            if let Some(value) = value {
+                stop["color"] = json!(format!("#{}", value.to_uppercase()));
+            }
+        }
+    }
+}
+
+/// A set of colors defining a color scheme to apply to a Lunacy document.
+pub struct ColorScheme {
+    /// A set of base colors to generate a color palette from.
+    pub colors  : Vec<BaseColor>,
+    /// The gradients made from the colors, applied as color styles.
+    pub gradients: Vec<Gradient>,
+    /// The stops to generate for each base color.
+    pub stops   : Vec<u32>,
+    /// The names of the tints at each stop, e.g. `lightest`, or empty to name tints by their
+    /// stop.
+    pub labels  : Vec<String>,
+    /// The format generated colors are named with, see `NAME_FORMAT`.
+    pub name_format: String,
+    /// The separator between the levels of variable names, e.g. the group and the color.
+    pub separator: String,
+    /// The color space tints are interpolated in.
+    pub space   : RampSpace,
+    /// How the ramp of each color is generated.
+    pub ramp    : RampKind,
+    /// The furthest tints move from the color towards white, from 0 to 1.
+    pub max_lightness: f64,
+    /// The furthest tints move from the color towards black, from 0 to 1.
+    pub max_darkness: f64,
+    /// Whether the colors already in the document at the base stop are adopted as base colors,
+    /// regenerating the rest of their ramps around them.
+    pub adopt   : bool,
+}
+
+impl ColorScheme {
+    /// Create an empty color scheme generating `stops` in `space`.
+    pub fn new(stops: Vec<u32>, space: RampSpace) -> Self {
+        Self { colors: vec![], gradients: vec![], stops, labels: vec![], name_format: NAME_FORMAT.to_owned(), separator: SEPARATOR.to_owned(), space, ramp: RampKind::default(), max_lightness: RAMP_RANGE, max_darkness: RAMP_RANGE, adopt: false }
+    }
+
+    /// Check whether the scheme has no colors or gradients to apply, and adopts none.
+    pub fn is_empty(&self) -> bool {
+        self.colors.is_empty() && self.gradients.is_empty() && !self.adopt
+    }
+
+    /// Add the colors described by a color scheme json object to the scheme.
+    pub fn add_colors(&mut self, json: &JsonValue) {
+        for (name, color) in json.entries() {
+            // Entries with a `gradient` type are gradients rather than colors.
+            if let Some(kind) = color["gradient"].as_str() {
+                self.gradients.push(Gradient::from_json(name, kind, color));
+                continue;
+            }
+
+            // `colormap` is optional and samples the color's ramp from a sequential colormap rather
+            // than generating tints, either the name of a built in colormap or a list of hex
+            // values, from its start to its end or the other way around if `reverse` is set.
+            let mut colormap = match &color["colormap"] {
+                JsonValue::Null                     => vec![],
+                x if x.is_array()                   => x.members().map(|x| x.as_str().expect("expected colormap colors to be hex values").to_owned()).collect(),
+                x                                   => {
+                    let name = x.as_str().expect("expected a colormap name or list of colors");
+                    COLORMAPS.iter()
+                        .find(|x| x.0 == name)
+                        .unwrap_or_else(|| panic!("unknown colormap `{name}`"))
+                        .1
+                        .iter()
+                        .map(|x| x.to_string())
+                        .collect()
+                }
+            };
+            if color["reverse"].as_bool() == Some(true) {
+                colormap.reverse();
+            }
+
+            // `categories` is optional and makes the color a categorical palette of that many
+            // distinct colors anchored to it, distinct to color blind eyes too if `cvd_safe` is set.
+            let categories = color["categories"].as_u32().map(|count| Categories {
+                count,
+                cvd_safe    : color["cvd_safe"].as_bool().unwrap_or(false),
+            });
+
+            // `neutral` is optional and makes the color a ramp of grays tinted towards its hue,
+            // either `true` for the default chroma or the chroma of the tint in Oklab.
+            let neutral = match &color["neutral"] {
+                JsonValue::Boolean(true)    => Some(NEUTRAL_CHROMA),
+                x                           => x.as_f64(),
+            };
+
+            // `scrims` is optional and makes the color a set of overlays of black, white and the
+            // color at each opacity, either `true` for the default opacities or a list of them
+            // in percent.
+            let scrims  = match &color["scrims"] {
+                JsonValue::Boolean(true)    => SCRIM_OPACITIES.to_vec(),
+                x                           => x.members().map(|x| x.as_u32().expect("expected scrim opacities to be numbers")).collect(),
+            };
+
+            // `value` or `link` are required, unless the color is sampled from a colormap.
+            let value = color["value"].as_str()
+                .or(color["link"].as_str())
+                .or(colormap.first().map(|x| x.as_str()))
+                .expect("expected `link` or `value`");
+            // `stop` is optional and defaults to 500 if not present.
+            let stop  = if color.has_key("stop") {
+                color["stop"].as_u32().unwrap()
+            }
+            else {
+                500
+            };
+
+            // `overlay` is optional and makes the color a set of elevated surfaces.
+            let overlay = color["overlay"].as_str().map(|x| x.to_owned());
+            // `states` is optional, either `true` for the default offsets or an object of the
+            // lightness offset of each state, overriding the defaults.
+            let states  = match &color["states"] {
+                JsonValue::Boolean(true)    => state_offsets(&JsonValue::Null),
+                x if x.is_object()          => state_offsets(x),
+                _                           => vec![],
+            };
+            // `stops`, `ramp`, `ramp_space`, `max_lightness` and `max_darkness` are optional and
+            // override the scheme's settings for this color's ramp.
+            let ramp    = RampOverrides {
+                stops           : match color["stops"].is_array() {
+                    true    => Some(color["stops"].members().map(|x| x.as_u32().expect("expected stops to be numbers")).collect()),
+                    false   => None,
+                },
+                kind            : color["ramp"].as_str().map(|x| x.parse().expect("expected a valid ramp")),
+                space           : color["ramp_space"].as_str().map(|x| x.parse().expect("expected a valid ramp space")),
+                max_lightness   : color["max_lightness"].as_f64(),
+                max_darkness    : color["max_darkness"].as_f64(),
+            };
+
+            self.colors.push(BaseColor {
+                name    : name.to_owned(),
+                value   : value.to_owned(),
+                stop,
+                overlay,
+                states,
+                ramp,
+                colormap,
+                categories,
+                neutral,
+                scrims,
+            })
+        }
+    }
+}
+
+/// The keys a color in a color scheme json object can have.
+const COLOR_KEYS : &[&str]
+    = &["value", "link", "stop", "overlay", "states", "stops", "ramp", "ramp_space", "max_lightness", "max_darkness", "colormap", "reverse", "categories", "cvd_safe", "neutral", "scrims"];
+
+/// The keys a gradient in a color scheme json object can have.
+const GRADIENT_KEYS : &[&str]
+    = &["gradient", "angle", "stops"];
+
+/// Check a color scheme json object for anything `ColorScheme::add_colors` would ignore or fail
+/// on, such as unknown keys or values of the wrong type, returning the first problem found.
+pub fn check_scheme(json: &JsonValue) -> Result<(), SchemeError> {
+    if !json.is_object() {
+        return Err(SchemeError::NotAnObject);
+    }
+
+    for (name, entry) in json.entries() {
+        let invalid = |key: &str| SchemeError::InvalidValue {
+            entry   : name.to_owned(),
+            key     : key.to_owned(),
+            value   : entry[key].dump(),
+        };
+
+        if !entry.is_object() {
+            return Err(SchemeError::InvalidEntry(name.to_owned()));
+        }
+
+        let keys = match entry.has_key("gradient") {
+            true    => GRADIENT_KEYS,
+            false   => COLOR_KEYS,
+        };
+        if let Some((key, _)) = entry.entries().find(|(key, _)| !keys.contains(key)) {
+            return Err(SchemeError::UnknownKey { entry: name.to_owned(), key: key.to_owned() });
+        }
+
+        // Gradients need a known kind and at least one stop naming a color.
+        if entry.has_key("gradient") {
+            if entry["gradient"].as_str().and_then(|x| x.parse::<GradientKind>().ok()).is_none() {
+                return Err(invalid("gradient"));
+            }
+            if entry.has_key("angle") && entry["angle"].as_f64().is_none() {
+                return Err(invalid("angle"));
+            }
+            let stop = |x: &JsonValue| x.is_string() || (x["color"].is_string() && (!x.has_key("position") || x["position"].as_f64().is_some()));
+            if entry["stops"].is_empty() || !entry["stops"].is_array() || !entry["stops"].members().all(stop) {
+                return Err(invalid("stops"));
+            }
+
+            continue;
+        }
+
+        // Colors sampled from a colormap need a known colormap or at least two hex values, and
+        // no value.
+        if entry.has_key("colormap") {
+            let known   = entry["colormap"].as_str().is_some_and(|x| COLORMAPS.iter().any(|y| y.0 == x));
+            let colors  = entry["colormap"].len() >= 2 && entry["colormap"].members().all(|x| x.as_str().is_some_and(|x| hex_to_rgb(x).is_ok()));
+            if !known && !colors {
+                return Err(invalid("colormap"));
+            }
+        }
+        if entry.has_key("reverse") && !entry["reverse"].is_boolean() {
+            return Err(invalid("reverse"));
+        }
+        if entry.has_key("categories") && entry["categories"].as_u32().is_none_or(|x| x == 0) {
+            return Err(invalid("categories"));
+        }
+        if let Some(count) = entry["categories"].as_u32().filter(|x| *x > MAX_CATEGORIES) {
+            return Err(SchemeError::TooManyCategories { entry: name.to_owned(), count });
+        }
+        if entry.has_key("cvd_safe") && !entry["cvd_safe"].is_boolean() {
+            return Err(invalid("cvd_safe"));
+        }
+        let opacities = |x: &JsonValue| x.is_array() && !x.is_empty() && x.members().all(|x| x.as_u32().is_some_and(|x| (1..=100).contains(&x)));
+        if entry.has_key("scrims") && entry["scrims"] != JsonValue::Boolean(true) && !opacities(&entry["scrims"]) {
+            return Err(invalid("scrims"));
+        }
+        if entry.has_key("neutral") && entry["neutral"] != JsonValue::Boolean(true) && entry["neutral"].as_f64().is_none_or(|x| x < 0.0) {
+            return Err(invalid("neutral"));
+        }
+
+        let key = match entry.has_key("value") {
+            true    => "value",
+            false   => "link",
+        };
+        match entry[key].as_str() {
+            None if entry.has_key("colormap")                       => { }
+            None                                                    => return Err(SchemeError::MissingValue(name.to_owned())),
+            Some(x) if x.starts_with('#') && hex_to_rgb(x).is_err() => return Err(invalid(key)),
+            Some(_)                                                 => { }
+        }
+
+        if entry.has_key("stop") && entry["stop"].as_u32().is_none() {
+            return Err(invalid("stop"));
+        }
+        if entry.has_key("overlay") && !entry["overlay"].is_string() {
+            return Err(invalid("overlay"));
+        }
+        let offsets = |x: &JsonValue| x.is_object() && x.entries().all(|(_, x)| x.as_f64().is_some());
+        if entry.has_key("states") && entry["states"] != JsonValue::Boolean(true) && !offsets(&entry["states"]) {
+            return Err(invalid("states"));
+        }
+        if entry.has_key("stops") && !(entry["stops"].is_array() && !entry["stops"].is_empty() && entry["stops"].members().all(|x| x.as_u32().is_some())) {
+            return Err(invalid("stops"));
+        }
+        if entry.has_key("ramp") && entry["ramp"].as_str().and_then(|x| x.parse::<RampKind>().ok()).is_none() {
+            return Err(invalid("ramp"));
+        }
+        if entry.has_key("ramp_space") && entry["ramp_space"].as_str().and_then(|x| x.parse::<RampSpace>().ok()).is_none() {
+            return Err(invalid("ramp_space"));
+        }
+        for key in ["max_lightness", "max_darkness"] {
+            if entry.has_key(key) && !entry[key].as_f64().is_some_and(|x| (0.0..=1.0).contains(&x)) {
+                return Err(invalid(key));
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Get the offsets of the interaction states from the default `STATE_OFFSETS`, with those in the
+/// json object `json` taking precedence and any other states in it added after them.
+fn state_offsets(json: &JsonValue) -> Vec<(String, f64)> {
+    let mut offsets = STATE_OFFSETS.iter()
+        .map(|(state, offset)| (state.to_string(), *offset))
+        .collect::<Vec<_>>();
+
+    for (state, offset) in json.entries() {
+        let offset = offset.as_f64().expect("expected a numeric state offset");
+        match offsets.iter_mut().find(|(x, _)| x == state) {
+            Some(x) => x.1 = offset,
+            None    => offsets.push((state.to_owned(), offset)),
+        }
+    }
+
+    offsets
+}
+
+/// The color space tints are interpolated towards white and black in.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum RampSpace {
+    /// Interpolate the gamma encoded sRGB components directly.
+    #[default]
+    Srgb,
+    /// Interpolate in linear light, keeping midtones from darkening.
+    Linear,
+    /// Interpolate in the Oklab perceptual color space, spacing tints evenly in perceived
+    /// lightness and keeping their hue.
+    Oklab,
+}
+
+impl std::str::FromStr for RampSpace {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "srgb"      => Ok(Self::Srgb),
+            "linear"    => Ok(Self::Linear),
+            "oklab"     => Ok(Self::Oklab),
+            _           => Err(format!("unknown ramp space `{s}`")),
+        }
+    }
+}
+
+/// How the ramp of a color is generated.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum RampKind {
+    /// Interpolate tints from the color towards white and black at each stop.
+    #[default]
+    Tints,
+    /// Generate a tonal palette as Material 3 dynamic color does, keeping the hue and chroma of
+    /// the color in the HCT color space with each stop as its tone, from 0 to 100.
+    Material3,
+}
+
+impl RampKind {
+    /// Get the stops generated for each color unless configured otherwise.
+    pub fn default_stops(self) -> &'static [u32] {
+        match self {
+            Self::Tints     => STOPS,
+            Self::Material3 => MATERIAL_TONES,
+        }
+    }
+}
+
+impl std::str::FromStr for RampKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "tints"     => Ok(Self::Tints),
+            "material3" => Ok(Self::Material3),
+            _           => Err(format!("unknown ramp `{s}`")),
+        }
+    }
+}
+
+/// A gradient in a color scheme, whose stops are colors of the palette or hex values.
+#[derive(Clone, Debug)]
+pub struct Gradient {
+    /// The name of the gradient.
+    pub name    : String,
+    /// The shape of the gradient.
+    pub kind    : GradientKind,
+    /// The direction of a linear gradient in degrees, clockwise from pointing up.
+    pub angle   : f64,
+    /// The stops of the gradient, in order.
+    pub stops   : Vec<GradientStop>,
+}
+
+impl Gradient {
+    /// Read a gradient from a color scheme json object, such as
+    /// `{ "gradient": "linear", "angle": 90, "stops": ["blue / blue.500", "pink / pink.500"] }`.
+    ///
+    /// Stops are either the name or hex value of a color, spread evenly, or objects giving the
+    /// `color` and its `position` from 0 to 1.
+    fn from_json(name: &str, kind: &str, json: &JsonValue) -> Self {
+        let count = json["stops"].len();
+        let stops = json["stops"].members()
+            .enumerate()
+            .map(|(i, stop)| GradientStop {
+                position: stop["position"].as_f64().unwrap_or(match count {
+                    1   => 0.0,
+                    _   => i as f64 / (count - 1) as f64,
+                }),
+                color   : stop.as_str()
+                    .or(stop["color"].as_str())
+                    .expect("expected the name or value of a color as a gradient stop")
+                    .to_owned(),
+            })
+            .collect::<Vec<_>>();
+
+        if stops.is_empty() {
+            panic!("expected gradient {name} to have `stops`");
+        }
+
+        Self {
+            name    : name.to_owned(),
+            kind    : kind.parse().unwrap_or_else(|e| panic!("{e}")),
+            angle   : json["angle"].as_f64().unwrap_or(180.0),
+            stops,
+        }
+    }
+}
+
+/// The shape of a gradient.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum GradientKind {
+    /// Colors change along a line.
+    #[default]
+    Linear,
+    /// Colors change outwards from a center.
+    Radial,
+}
+
+impl std::str::FromStr for GradientKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "linear"    => Ok(Self::Linear),
+            "radial"    => Ok(Self::Radial),
+            _           => Err(format!("unknown gradient type `{s}`")),
+        }
+    }
+}
+
+impl std::fmt::Display for GradientKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Self::Linear    => write!(f, "linear"),
+            Self::Radial    => write!(f, "radial"),
+        }
+    }
+}
+
+/// A stop of a gradient in a color scheme.
+#[derive(Clone, Debug)]
+pub struct GradientStop {
+    /// The position of the stop along the gradient, from 0 to 1.
+    pub position: f64,
+    /// The name of a color in the palette or a hex value.
+    pub color   : String,
+}
+
+/// A color in a color scheme, from which a ramp of tints is generated.
+#[derive(Clone)]
+pub struct BaseColor {
+    /// The name of the color.
+    pub name    : String,
+    /// The hexadecimal value of the color or the name of a color to link to.
+    pub value   : String,
+    /// The stop the color starts at.
+    pub stop    : u32,
+    /// The color blended into the surface `value` at each elevation level, as a hexadecimal
+    /// value or the name of a color, if the color is a set of elevated surfaces rather than tints.
+    pub overlay : Option<String>,
+    /// The interaction states to derive from the color, each with the offset added to its
+    /// perceptual lightness, from 0 to 1.
+    pub states  : Vec<(String, f64)>,
+    /// The settings of the scheme the color overrides for its own ramp.
+    pub ramp    : RampOverrides,
+    /// The hex values of the sequential colormap the color's ramp is sampled from, from its start
+    /// to its end, or empty if the color generates tints.
+    pub colormap: Vec<String>,
+    /// The categorical palette anchored to the color, if it's one rather than a ramp of tints.
+    pub categories: Option<Categories>,
+    /// The chroma, in Oklab, of a ramp of grays tinted towards the color's hue, if the color is
+    /// one rather than a ramp of tints.
+    pub neutral : Option<f64>,
+    /// The opacities, in percent, of the overlays of black, white and the color generated for
+    /// modals and hover layers, if the color is a set of scrims rather than a ramp of tints.
+    pub scrims  : Vec<u32>,
+}
+
+/// A categorical palette of distinct colors for data visualizations, anchored to a base color.
+#[derive(Clone, Copy, Debug)]
+pub struct Categories {
+    /// The number of colors in the palette.
+    pub count   : u32,
+    /// Whether the colors stay distinct to people with color vision deficiencies.
+    pub cvd_safe: bool,
+}
+
+/// The ramp settings of a color scheme a single color overrides, e.g. so neutrals can be tuned
+/// separately from brand hues. Settings left unset are taken from the scheme.
+#[derive(Clone, Debug, Default)]
+pub struct RampOverrides {
+    /// The stops to generate for the color, named by their stops rather than the scheme's labels.
+    pub stops           : Option<Vec<u32>>,
+    /// How the color's ramp is generated.
+    pub kind            : Option<RampKind>,
+    /// The color space the color's tints are interpolated in.
+    pub space           : Option<RampSpace>,
+    /// The furthest the color's tints move towards white, from 0 to 1.
+    pub max_lightness   : Option<f64>,
+    /// The furthest the color's tints move towards black, from 0 to 1.
+    pub max_darkness    : Option<f64>,
+}
+
+/// The format generated colors are named with unless configured otherwise. `{group}` is replaced
+/// with the group, `{name}` with the name of the color, `{stem}` with the last part of its name,
+/// `{stop}` with the stop, label, elevation level or state of the tint and `{sep}` with the
+/// separator.
+pub const NAME_FORMAT : &str
+    = "{group}{sep}{name}{sep}{stem}.{stop}";
+
+/// The separator between the levels of variable names unless configured otherwise, as Lunacy
+/// writes them.
+pub const SEPARATOR : &str
+    = " / ";
+
+/// The stops to emit for each color unless configured otherwise.
+pub const STOPS : &[u32]
+    = &[100, 200, 300, 400, 500, 600, 700, 800, 900];
+
+/// The tones of the tonal palettes of Material 3 dynamic color, emitted for each color of a
+/// `material3` ramp unless configured otherwise.
+pub const MATERIAL_TONES : &[u32]
+    = &[0, 10, 20, 30, 40, 50, 60, 70, 80, 90, 95, 99, 100];
+
+/// The opacities, in percent, of the scrims generated for a color unless configured otherwise.
+pub const SCRIM_OPACITIES : &[u32]
+    = &[4, 8, 12, 16, 24, 32, 48, 64];
+
+/// The chroma, in Oklab, of the grays of neutral ramps unless configured otherwise, enough to warm
+/// or cool them towards the brand without reading as a color.
+pub const NEUTRAL_CHROMA : f64
+    = 0.015;
+
+/// The lightness, in Oklab, of the lightest and darkest grays of neutral ramps.
+const NEUTRAL_LIGHTNESS : (f64, f64)
+    = (0.97, 0.20);
+
+/// The lightness, in Oklab, of the colors categorical palettes are chosen from, light enough to
+/// tell hues apart and dark enough to read against white.
+const CATEGORY_LIGHTNESS : &[f64]
+    = &[0.55, 0.65, 0.75, 0.85];
+
+/// The chroma, in Oklab, of the colors categorical palettes are chosen from.
+const CATEGORY_CHROMA : &[f64]
+    = &[0.10, 0.14, 0.18];
+
+/// The most colors a categorical palette can have, well past the number that can still be told
+/// apart in a chart.
+pub const MAX_CATEGORIES : u32
+    = 64;
+
+/// The matrices simulating protanopia, deuteranopia and tritanopia in linear sRGB, from Machado
+/// et al. (2009), that categorical colors are kept distinct under when CVD safe.
+const CVD_SIMULATIONS : &[[[f64; 3]; 3]] = &[
+    [[0.152286, 1.052583, -0.204868], [0.114503, 0.786281, 0.099216], [-0.003882, -0.048116, 1.051998]],
+    [[0.367322, 0.860646, -0.227968], [0.280085, 0.672501, 0.047413], [-0.011820, 0.042940, 0.968881]],
+    [[1.255528, -0.076749, -0.178779], [-0.078411, 0.930809, 0.147602], [0.004733, 0.691367, 0.303900]],
+];
+
+/// The built in sequential colormaps a color's ramp can be sampled from, each as hex values evenly
+/// spaced along it from its dark end to its light end. Each is perceptually uniform, so equal
+/// steps along it look equally far apart, and readable in grayscale.
+pub const COLORMAPS : &[(&str, &[&str])] = &[
+    ("viridis", &["#440154", "#482475", "#414487", "#355f8d", "#2a788e", "#21918c", "#22a884", "#44bf70", "#7ad151", "#bddf26", "#fde725"]),
+    ("cividis", &["#00204d", "#00336f", "#39486b", "#575c6d", "#707173", "#8a8779", "#a69d75", "#c4b56c", "#e4cf5b", "#ffea46"]),
+    ("magma",   &["#000004", "#140e36", "#3b0f70", "#641a80", "#8c2981", "#b73779", "#de4968", "#f7705c", "#fe9f6d", "#fecf92", "#fcfdbf"]),
+    ("inferno", &["#000004", "#160b39", "#420a68", "#6a176e", "#932667", "#bc3754", "#dd513a", "#f37819", "#fca50a", "#f6d746", "#fcffa4"]),
+    ("plasma",  &["#0d0887", "#41049d", "#6a00a8", "#8f0da4", "#b12a90", "#cc4778", "#e16462", "#f2844b", "#fca636", "#fcce25", "#f0f921"]),
+];
+
+/// The furthest tints move from a color towards white and black unless configured otherwise,
+/// short of either so ramps keep some of the color at their ends.
+pub const RAMP_RANGE : f64
+    = 0.8;
+
+/// The opacity of the overlay blended into a surface at each elevation level, from the surface
+/// itself at level 0 up to level 5, as in Material Design.
+pub const ELEVATION_OPACITIES : &[f64]
+    = &[0.0, 0.05, 0.08, 0.11, 0.12, 0.14];
+
+/// The interaction states derived from a color unless configured otherwise, with the offset added
+/// to its perceptual lightness for each.
+pub const STATE_OFFSETS : &[(&str, f64)]
+    = &[("hover", -0.05), ("pressed", -0.10), ("focus", -0.03), ("disabled", 0.20)];
+
+impl BaseColor {
+    /// Create a tint of the base color at each of the stops of `scheme`, interpolated in its space
+    /// up to its lightness and darkness range, or as a tonal palette for a `material3` ramp, unless
+    /// the color overrides them. Tints are named by the label at the same position in the scheme's
+    /// labels where there is one.
+    fn create_tints(&self, names: &Names, scheme: &ColorScheme) -> Result<Vec<Color>, Error> {
+        let kind            = self.ramp.kind.unwrap_or(scheme.ramp);
+        let space           = self.ramp.space.unwrap_or(scheme.space);
+        let max_lightness   = self.ramp.max_lightness.unwrap_or(scheme.max_lightness);
+        let max_darkness    = self.ramp.max_darkness.unwrap_or(scheme.max_darkness);
+
+        // A color generating a different kind of ramp than the scheme takes that kind's stops,
+        // and the scheme's labels name its own stops, not those a color overrides them with.
+        let (stops, labels) = match (&self.ramp.stops, kind == scheme.ramp) {
+            (Some(stops), _)    => (&stops[..], &[][..]),
+            (None, false)       => (kind.default_stops(), &[][..]),
+            (None, true)        => (&scheme.stops[..], &scheme.labels[..]),
+        };
+
+        if kind == RampKind::Material3 {
+            return self.create_tones(names, stops, labels);
+        }
+
+        let (r, g, b)   = space.decode(hex_to_rgb(&self.value)?);
+        let pivot       = stops.iter().position(|x| *x == self.stop).unwrap_or(500);
+
+        // Tints are interpolated towards white and black as the space represents them.
+        let white       = space.decode((1.0, 1.0, 1.0));
+        let black       = space.decode((0.0, 0.0, 0.0));
+        
+        let mut tints = Vec::with_capacity(stops.len());
+        for (i, stop) in stops.iter().enumerate() {
+            let hex;
+
+            if *stop == self.stop {
+                hex = self.value.to_owned();
+            }
+            else {
+                let t;
+                let dst;
+    
+                if i < pivot {
+                    t   = (pivot - i) as f64 / pivot as f64 * max_lightness;
+                    dst = white;
+                }
+                else {
+                    t   = (i - pivot) as f64 / (stops.len() - pivot) as f64 * max_darkness;
+                    dst = black;
+                };
+                
+                let new_r   = lerp(r, dst.0, t);
+                let new_g   = lerp(g, dst.1, t);
+                let new_b   = lerp(b, dst.2, t);
+                let (r, g, b) = space.encode((new_r, new_g, new_b));
+    
+                hex         = rgb_to_hex(r, g, b);
+            }
+
+            match labels.get(i) {
+                Some(label) => tints.push(self.create_color(names, label, hex)),
+                None        => tints.push(self.create_color(names, stop, hex)),
+            }
+        }
+        
+        Ok(tints)
+    }
+
+    /// Create the scrims of the base color, black, white and its value in `palette` at each of its
+    /// opacities as colors with an alpha channel. Scrims are named as ramps of their opacities,
+    /// e.g. `scrim / black / black.8` and `scrim / tint / tint.8` for the color's own.
+    fn create_scrims(&self, palette: &ColorPalette) -> Result<Vec<Color>, Error> {
+        let tint = palette.resolve(&self.value);
+        hex_to_rgb(tint)?;
+
+        let mut scrims = Vec::with_capacity(self.scrims.len() * 3);
+        for (base, value) in [("black", "#000000"), ("white", "#ffffff"), ("tint", tint)] {
+            let name = format!("{}{}{base}", self.name, palette.names.separator);
+            for opacity in &self.scrims {
+                let alpha   = (*opacity as f64 / 100.0 * 255.0).round() as u32;
+                let hex     = format!("{}{alpha:02x}", &value[..7].to_lowercase());
+                let name    = palette.names.generated(&name, opacity);
+                scrims.push(Color {
+                    id      : color_id(&palette.names.group, &name),
+                    version : Some(1),
+                    name,
+                    value   : hex,
+                    extra   : Extra::new(),
+                });
+            }
+        }
+
+        Ok(scrims)
+    }
+
+    /// Create a ramp of grays at each of the stops of `scheme`, unless the color overrides them,
+    /// evenly spaced in lightness in the Oklab color space from near white to near black, each
+    /// tinted towards the hue of the base color's value in `palette` by `chroma`. Grays are named
+    /// by the label at the same position in the scheme's labels where there is one.
+    fn create_neutrals(&self, palette: &ColorPalette, scheme: &ColorScheme, chroma: f64) -> Result<Vec<Color>, Error> {
+        let (stops, labels) = match &self.ramp.stops {
+            Some(stops) => (&stops[..], &[][..]),
+            None        => (&scheme.stops[..], &scheme.labels[..]),
+        };
+
+        let (_, a, b)   = to_oklab(hex_to_rgb(palette.resolve(&self.value))?);
+        let hue         = b.atan2(a);
+        let (light, dark) = NEUTRAL_LIGHTNESS;
+
+        // A gray has no hue to tint towards, so its ramp stays pure gray.
+        let chroma = match a.hypot(b) < 1e-3 {
+            true    => 0.0,
+            false   => chroma,
+        };
+
+        let mut grays = Vec::with_capacity(stops.len());
+        for (i, stop) in stops.iter().enumerate() {
+            let t = match stops.len() {
+                1   => 0.5,
+                n   => i as f64 / (n - 1) as f64,
+            };
+
+            let (r, g, b)   = from_oklab((lerp(light, dark, t), chroma * hue.cos(), chroma * hue.sin()));
+            let hex         = rgb_to_hex(r, g, b);
+            match labels.get(i) {
+                Some(label) => grays.push(self.create_color(&palette.names, label, hex)),
+                None        => grays.push(self.create_color(&palette.names, stop, hex)),
+            }
+        }
+
+        Ok(grays)
+    }
+
+    /// Create the categorical palette anchored to the base color, its value in `palette` followed
+    /// by the colors furthest apart from those before them in the Oklab color space, so the first
+    /// categories are the most distinct. CVD safe palettes are kept apart as protanopes,
+    /// deuteranopes and tritanopes see them too. Colors are named `category-1` to `category-N`
+    /// under the base color, e.g. `dataviz / category-1`.
+    fn create_categories(&self, palette: &ColorPalette, categories: &Categories) -> Result<Vec<Color>, Error> {
+        if categories.count > MAX_CATEGORIES {
+            return Err(Box::new(SchemeError::TooManyCategories { entry: self.name.clone(), count: categories.count }));
+        }
+
+        // How a color appears, as Oklab components, to each kind of vision considered.
+        let appearances = |rgb: (f64, f64, f64)| -> Vec<(f64, f64, f64)> {
+            let mut appearances = vec![to_oklab(rgb)];
+            if categories.cvd_safe {
+                let (r, g, b) = RampSpace::Linear.decode(rgb);
+                for m in CVD_SIMULATIONS {
+                    let [r, g, b] = m.map(|row| (row[0] * r + row[1] * g + row[2] * b).clamp(0.0, 1.0));
+                    appearances.push(to_oklab(RampSpace::Linear.encode((r, g, b))));
+                }
+            }
+
+            appearances
+        };
+
+        // The distance between two colors as the kind of vision that sees them closest.
+        let distance = |a: &[(f64, f64, f64)], b: &[(f64, f64, f64)]| a.iter()
+            .zip(b)
+            .map(|(a, b)| ((a.0 - b.0).powi(2) + (a.1 - b.1).powi(2) + (a.2 - b.2).powi(2)).sqrt())
+            .fold(f64::MAX, f64::min);
+
+        // Candidates are spread around every hue at a few levels of lightness and chroma.
+        let mut candidates = vec![];
+        for l in CATEGORY_LIGHTNESS {
+            for c in CATEGORY_CHROMA {
+                for hue in (0..360).step_by(5) {
+                    let h   = (hue as f64).to_radians();
+                    let rgb = from_oklab((*l, c * h.cos(), c * h.sin()));
+                    candidates.push((rgb, appearances(rgb)));
+                }
+            }
+        }
+
+        let anchor      = hex_to_rgb(palette.resolve(&self.value))?;
+        let mut chosen  = vec![(anchor, appearances(anchor))];
+        while chosen.len() < categories.count as usize {
+            let next = candidates.iter()
+                .enumerate()
+                .map(|(i, (_, x))| (i, chosen.iter().map(|(_, y)| distance(x, y)).fold(f64::MAX, f64::min)))
+                .max_by(|a, b| a.1.total_cmp(&b.1));
+
+            // Each candidate is only chosen once, so no color is repeated.
+            match next {
+                Some((i, _))    => chosen.push(candidates.swap_remove(i)),
+                None            => break,
+            }
+        }
+
+        let colors = chosen.iter()
+            .enumerate()
+            .map(|(i, ((r, g, b), _))| {
+                let name = format!("{}{}category-{}", self.name, palette.names.separator, i + 1);
+                Color {
+                    id      : color_id(&palette.names.group, &name),
+                    version : Some(1),
+                    name,
+                    value   : rgb_to_hex(*r, *g, *b),
+                    extra   : Extra::new(),
+                }
+            })
+            .collect();
+
+        Ok(colors)
+    }
+
+    /// Sample the base color's colormap at each of the stops of `scheme`, unless the color
+    /// overrides them, spaced evenly along its length in the Oklab color space so each step looks
+    /// the same distance from the last whatever the spacing of the colormap's colors. Samples are
+    /// named by the label at the same position in the scheme's labels where there is one.
+    fn sample_colormap(&self, names: &Names, scheme: &ColorScheme) -> Result<Vec<Color>, Error> {
+        let (stops, labels) = match &self.ramp.stops {
+            Some(stops) => (&stops[..], &[][..]),
+            None        => (&scheme.stops[..], &scheme.labels[..]),
+        };
+
+        let colors = self.colormap.iter()
+            .map(|x| Ok(to_oklab(hex_to_rgb(x)?)))
+            .collect::<Result<Vec<_>, Error>>()?;
+
+        // The distance along the colormap to each of its colors.
+        let mut lengths = vec![0.0];
+        for pair in colors.windows(2) {
+            let (a, b) = (pair[0], pair[1]);
+            lengths.push(lengths[lengths.len() - 1] + ((a.0 - b.0).powi(2) + (a.1 - b.1).powi(2) + (a.2 - b.2).powi(2)).sqrt());
+        }
+        let total = lengths[lengths.len() - 1];
+
+        let mut samples = Vec::with_capacity(stops.len());
+        for (i, stop) in stops.iter().enumerate() {
+            let distance = match stops.len() {
+                1   => 0.0,
+                n   => i as f64 / (n - 1) as f64 * total,
+            };
+
+            // Interpolate between the colors either side of the distance.
+            let next    = lengths.iter().position(|x| *x >= distance).unwrap_or(lengths.len() - 1);
+            let prev    = next.saturating_sub(1);
+            let span    = lengths[next] - lengths[prev];
+            let t       = match span > 0.0 {
+                true    => (distance - lengths[prev]) / span,
+                false   => 0.0,
+            };
+
+            let (a, b)      = (colors[prev], colors[next]);
+            let (r, g, b)   = from_oklab((lerp(a.0, b.0, t), lerp(a.1, b.1, t), lerp(a.2, b.2, t)));
+            let hex         = rgb_to_hex(r, g, b);
+            match labels.get(i) {
+                Some(label) => samples.push(self.create_color(names, label, hex)),
+                None        => samples.push(self.create_color(names, stop, hex)),
+            }
+        }
+
+        Ok(samples)
+    }
+
+    /// Create the tonal palette of the base color, the color in the HCT color space with its hue
+    /// and chroma at each of `stops` as its tone, as Material 3 dynamic color generates it.
+    fn create_tones(&self, names: &Names, stops: &[u32], labels: &[String]) -> Result<Vec<Color>, Error> {
+        let hct = Hct::from_rgb(hex_to_rgb(&self.value)?);
+
+        let mut tones = Vec::with_capacity(stops.len());
+        for (i, stop) in stops.iter().enumerate() {
+            if *stop > 100 {
+                return Err(Box::new(RampError::InvalidTone(*stop)));
+            }
+
+            let (r, g, b)   = Hct { tone: *stop as f64, ..hct }.to_rgb();
+            let hex         = rgb_to_hex(r, g, b);
+            match labels.get(i) {
+                Some(label) => tones.push(self.create_color(names, label, hex)),
+                None        => tones.push(self.create_color(names, stop, hex)),
+            }
+        }
+
+        Ok(tones)
+    }
+
+    /// Create the surface at each elevation level, the surface `value` with `overlay` blended
+    /// into it at the level's opacity in `space`. Either can name a color already in `palette`.
+    fn create_elevations(&self, palette: &ColorPalette, overlay: &str, space: RampSpace) -> Result<Vec<Color>, Error> {
+        let (r, g, b)       = space.decode(hex_to_rgb(palette.resolve(&self.value))?);
+        let (or, og, ob)    = space.decode(hex_to_rgb(palette.resolve(overlay))?);
+
+        let mut levels = Vec::with_capacity(ELEVATION_OPACITIES.len());
+        for (level, opacity) in ELEVATION_OPACITIES.iter().enumerate() {
+            let new_r   = lerp(r, or, *opacity);
+            let new_g   = lerp(g, og, *opacity);
+            let new_b   = lerp(b, ob, *opacity);
+            let (r, g, b) = space.encode((new_r, new_g, new_b));
+
+            levels.push(self.create_color(&palette.names, level as u32, rgb_to_hex(r, g, b)));
+        }
+
+        Ok(levels)
+    }
+
+    /// Create the interaction states of the base color, its value in `palette` with the offset of
+    /// each state added to its lightness in the Oklab color space, so every state is the same
+    /// perceived step from the color whatever its hue.
+    fn create_states(&self, palette: &ColorPalette) -> Result<Vec<Color>, Error> {
+        if self.states.is_empty() {
+            return Ok(vec![]);
+        }
+
+        let source = match self.value.starts_with("#") || self.overlay.is_some() {
+            true    => self.value.as_str(),
+            false   => self.name.as_str(),
+        };
+
+        let (l, a, b) = to_oklab(hex_to_rgb(palette.resolve(source))?);
+
+        let mut states = Vec::with_capacity(self.states.len());
+        for (state, offset) in &self.states {
+            let (r, g, b) = from_oklab(((l + offset).clamp(0.0, 1.0), a, b));
+            states.push(self.create_color(&palette.names, state, rgb_to_hex(r, g, b)));
+        }
+
+        Ok(states)
+    }
+
+    /// Create the color of the base color at `stop` with the hex `value`, named as `names`
+    /// describes, e.g. `blue / blue.500` or `blue / blue.hover`.
+    fn create_color(&self, names: &Names, stop: impl std::fmt::Display, value: String) -> Color {
+        let name = names.generated(&self.name, stop);
+        Color {
+            id      : color_id(&names.group, &name),
+            version : Some(1),
+            name,
+            value,
+            extra   : Extra::new(),
+        }
+    }
+}
+
+/// The tints of a color in a document, as generated by a color scheme.
+#[derive(Clone, Debug)]
+pub struct Ramp {
+    /// The name of the color within its group, e.g. `blue`.
+    pub name    : String,
+    /// The stop and hex value, without a leading `#`, of each tint in order of their stops.
+    pub tints   : Vec<(u32, String)>,
+}
+
+/// A color palette from a lunacy document.
+pub struct ColorPalette {
+    /// The set of colors in a color palette.
+    colors: BTreeMap<String, Color>,
+    /// How the variables of the palette's group are named.
+    names : Names,
+}
+
+/// How the variables of a group are named, from the name format and separator of a color scheme.
+struct Names {
+    /// The group the variables are in.
+    group       : String,
+    /// The start of the name of every variable in the group, e.g. `theme / `.
+    prefix      : String,
+    /// The format of the rest of the names of generated colors, e.g. `{name} / {stem}.{stop}`.
+    format      : String,
+    /// The separator between the levels of names, e.g. ` / `.
+    separator   : String,
+}
+
+impl Names {
+    /// Describe how the variables of `group` are named with the name format and separator of
+    /// `scheme`, splitting the format around `{name}`.
+    fn new(scheme: &ColorScheme, group: &str) -> Result<Self, Error> {
+        for placeholder in ["{name}", "{stop}"] {
+            if !scheme.name_format.contains(placeholder) {
+                return Err(Box::new(NameFormatError::Missing(placeholder)));
+            }
+        }
+
+        let format              = scheme.name_format.replace("{group}", group).replace("{sep}", &scheme.separator);
+        let (prefix, format)    = format.split_once("{name}").unwrap_or_default();
+
+        Ok(Self {
+            group       : group.to_owned(),
+            prefix      : prefix.to_owned(),
+            format      : format!("{{name}}{format}"),
+            separator   : scheme.separator.clone(),
+        })
+    }
+
+    /// Get the name within the group of the color generated from the color `name` at `stop`.
+    fn generated(&self, name: &str, stop: impl std::fmt::Display) -> String {
+        self.format.replace("{name}", name)
+            .replace("{stem}", self.levels(name).last().unwrap_or(&name))
+            .replace("{stop}", &stop.to_string())
+    }
+
+    /// Get the first level of the name within the group `name`, e.g. `blue` for `blue / blue.500`.
+    fn term<'a>(&self, name: &'a str) -> &'a str {
+        self.levels(name)[0]
+    }
+
+    /// Split the name within the group `name` into its levels.
+    fn levels<'a>(&self, name: &'a str) -> Vec<&'a str> {
+        name.split(self.separator.trim()).map(str::trim).collect()
+    }
+}
+
+impl ColorPalette {
+    /// Update a color in the palette by name, updating the existing color or creating a new
+    /// one if missing.
+    fn update_by_name(&mut self, color: Color) {
+        if let Some(x) = self.colors.get_mut(&color.name) {
+            x.version   = Some(x.version.unwrap_or(1) + 1);
+            x.value     = color.value.clone();
+        }
+        else {
+            self.colors.insert(color.name.to_owned(), color);
+        }
+    }
+
+    /// Find the base colors of the ramps already in the palette, the colors named as the tint of
+    /// a color at stop 500 with the labels of `scheme`, e.g. `blue / blue.500` for `blue`.
+    fn adopt(&self, scheme: &ColorScheme) -> Vec<BaseColor> {
+        // Base colors start at 500, named by its label where the scheme has labels.
+        let stop = scheme.stops.iter()
+            .position(|x| *x == 500)
+            .and_then(|i| scheme.labels.get(i))
+            .cloned()
+            .unwrap_or_else(|| 500.to_string());
+
+        let mut base_colors = vec![];
+        for color in self.colors.values() {
+            let name = color.name.char_indices()
+                .skip(1)
+                .map(|(i, _)| &color.name[..i])
+                .find(|x| self.names.generated(x, &stop) == color.name);
+
+            if let Some(name) = name {
+                base_colors.push(BaseColor {
+                    name    : name.to_owned(),
+                    value   : color.value.clone(),
+                    stop    : 500,
+                    overlay : None,
+                    states  : vec![],
+                    ramp    : RampOverrides::default(),
+                    colormap: vec![],
+                    categories: None,
+                    neutral : None,
+                    scrims  : vec![],
+                });
+            }
+        }
+
+        base_colors
+    }
+
+    /// Get the hex value of `value`, either a hexadecimal value itself or the name of a color in
+    /// the palette.
+    fn resolve<'a>(&'a self, value: &'a str) -> &'a str {
+        if value.starts_with("#") {
+            return value;
+        }
+
+        match self.colors.get(value) {
+            None        => panic!("color {value} not found in palette"),
+            Some(src)   => &src.value,
+        }
+    }
+
+    /// Link in a color to an existing color by name.
+    fn link_by_name(&mut self, color: &BaseColor) {
+        match self.colors.get(&color.value) {
+            None        => panic!("color {} not found in palette", color.value),
+            Some(src)   => {
+                let color = Color {
+                    id      : color_id(&self.names.group, &color.name),
+                    version : Some(1),
+                    name    : color.name.clone(),
+                    value   : src.value.clone(),
+                    extra   : Extra::new(),
+                };
+
+                self.update_by_name(color);
+            }
+        }
+    }
+}
+
+/// Derive the id of a newly created color from its name, so running the tool twice on the
+/// same input produces the same document.
+fn color_id(group: &str, name: &str) -> Uuid {
+    Uuid::new_v5(&Uuid::NAMESPACE_OID, format!("{group} / {name}").as_bytes())
+}
+
+/// A tint in a sequence of color tints.
+#[derive(Debug)]
+struct Color {
+    /// The unique id of the color.
+    id      : Uuid,
+    /// The version of the color, if recorded, which is taken as 1 where it isn't.
+    version : Option<u32>,
+    /// The name of the tint.
+    name    : String,
+    /// The hex value of the color.
+    value   : String,
+    /// The fields of the variable the color was read from that aren't described.
+    extra   : Extra,
+}
+
+impl Color {
+    /// Read a color from a color variable, if its name starts with `prefix`.
+    fn from_variable(variable: &ColorVariable, prefix: &str) -> Result<Option<Color>, Error> {
+        let Some(name) = variable.name.strip_prefix(prefix) else {
+            return Ok(None);
+        };
+
+        // Decode a uuid from a lunacy id.
+        let bytes = URL_SAFE_NO_PAD.decode(&variable.id)?;
+
+        Ok(Some(Color {
+            id      : Uuid::from_slice(&bytes)?,
+            version : variable.version,
+            name    : name.trim().to_owned(),
+            value   : format!("#{}", variable.value),
+            extra   : variable.extra.clone(),
+        }))
+    }
+
+    /// Convert the color to a color variable, its name starting with `prefix`.
+    fn to_variable(&self, prefix: &str) -> ColorVariable {
+        ColorVariable {
+            id      : URL_SAFE_NO_PAD.encode(self.id.as_bytes()),
+            version : self.version,
+            name    : format!("{prefix}{}", self.name),
+            value   : self.value[1..].to_owned(),
+            extra   : self.extra.clone(),
+        }
+    }
+}
+
+impl RampSpace {
+    /// Convert gamma encoded sRGB components into the space.
+    pub(crate) fn decode(self, (r, g, b): (f64, f64, f64)) -> (f64, f64, f64) {
+        /// Convert a gamma encoded sRGB component to linear light.
+        fn to_linear(x: f64) -> f64 {
+            match x <= 0.04045 {
+                true    => x / 12.92,
+                false   => ((x + 0.055) / 1.055).powf(2.4),
+            }
+        }
+
+        match self {
+            Self::Srgb      => (r, g, b),
+            Self::Linear    => (to_linear(r), to_linear(g), to_linear(b)),
+            Self::Oklab     => to_oklab((r, g, b)),
+        }
+    }
+
+    /// Convert components in the space back to gamma encoded sRGB.
+    pub(crate) fn encode(self, (r, g, b): (f64, f64, f64)) -> (f64, f64, f64) {
+        /// Convert a linear light component to gamma encoded sRGB.
+        fn to_srgb(x: f64) -> f64 {
+            match x <= 0.0031308 {
+                true    => x * 12.92,
+                false   => 1.055 * x.powf(1.0 / 2.4) - 0.055,
+            }
+        }
+
+        match self {
+            Self::Srgb      => (r, g, b),
+            Self::Linear    => (to_srgb(r), to_srgb(g), to_srgb(b)),
+            Self::Oklab     => from_oklab((r, g, b)),
+        }
+    }
+}
+
+/// Convert gamma encoded sRGB components to the Oklab color space, in which equal steps of
+/// lightness look equal.
+fn to_oklab(rgb: (f64, f64, f64)) -> (f64, f64, f64) {
+    let (r, g, b) = RampSpace::Linear.decode(rgb);
+
+    let l = (0.4122214708 * r + 0.5363325363 * g + 0.0514459929 * b).cbrt();
+    let m = (0.2119034982 * r + 0.6806995451 * g + 0.1073969566 * b).cbrt();
+    let s = (0.0883024619 * r + 0.2817188376 * g + 0.6299787005 * b).cbrt();
+
+    (
+        0.2104542553 * l + 0.7936177850 * m - 0.0040720468 * s,
+        1.9779984951 * l - 2.4285922050 * m + 0.4505937099 * s,
+        0.0259040371 * l + 0.7827717662 * m - 0.8086757660 * s,
+    )
+}
+
+/// Convert Oklab components back to gamma encoded sRGB. Colors outside the sRGB gamut have their
+/// chroma reduced until they fit, keeping their lightness and hue.
+fn from_oklab((l, a, b): (f64, f64, f64)) -> (f64, f64, f64) {
+    /// Convert Oklab components to linear sRGB components, which may be out of gamut.
+    fn to_linear((l, a, b): (f64, f64, f64)) -> (f64, f64, f64) {
+        let l_ = (l + 0.3963377774 * a + 0.2158037573 * b).powi(3);
+        let m_ = (l - 0.1055613458 * a - 0.0638541728 * b).powi(3);
+        let s_ = (l - 0.0894841775 * a - 1.2914855480 * b).powi(3);
+
+        (
+            4.0767416621 * l_ - 3.3077115913 * m_ + 0.2309699292 * s_,
+            -1.2684380046 * l_ + 2.6097574011 * m_ - 0.3413193965 * s_,
+            -0.0041960863 * l_ - 0.7034186147 * m_ + 1.7076147010 * s_,
+        )
+    }
+
+    /// Check whether linear sRGB components are in gamut, allowing for rounding.
+    fn in_gamut((r, g, b): (f64, f64, f64)) -> bool {
+        [r, g, b].iter().all(|x| (-1e-6..=1.0 + 1e-6).contains(x))
+    }
+
+    let mut rgb = to_linear((l, a, b));
+    if !in_gamut(rgb) {
+        // Search for the largest chroma that fits.
+        let (mut low, mut high) = (0.0, 1.0);
+        for _ in 0..24 {
+            let scale = (low + high) / 2.0;
+            match in_gamut(to_linear((l, a * scale, b * scale))) {
+                true    => low  = scale,
+                false   => high = scale,
+            }
+        }
+
+        rgb = to_linear((l, a * low, b * low));
+    }
+
+    let (r, g, b) = rgb;
+    RampSpace::Linear.encode((r.clamp(0.0, 1.0), g.clamp(0.0, 1.0), b.clamp(0.0, 1.0)))
+}
+
+/// Get the WCAG contrast ratio between the hex colors `a` and `b`, from 1 for the same
+/// luminance up to 21 for black on white.
+pub fn contrast_ratio(a: &str, b: &str) -> Result<f64, Error> {
+    let luminance = |value: &str| -> Result<f64, Error> {
+        let (r, g, b) = RampSpace::Linear.decode(hex_to_rgb(value)?);
+        Ok(0.2126 * r + 0.7152 * g + 0.0722 * b)
+    };
+
+    let (a, b) = (luminance(a)?, luminance(b)?);
+    Ok((a.max(b) + 0.05) / (a.min(b) + 0.05))
+}
+
+/// Linearly interpolate from a -> b by `t`.
+fn lerp(a: f64, b: f64, t: f64) -> f64 {
+    a * (1.0 - t) + b * t
+}
+
+/// Parse a hex value to an RGB tuple.
+pub(crate) fn hex_to_rgb(value: &str) -> Result<(f64, f64, f64), Error> {
+    // We expect either #RRGGBB format only.
+    if value.len() != 7 && value.len() != 9 {
+        return Err(Box::new(ColorParseError::InvalidFormat));
+    }
+
+    // Make sure the leading hashtag is present.
+    if !value.starts_with("#") {
+        return Err(Box::new(ColorParseError::InvalidFormat));
+    }
+
+    // Parse the hex value.
+    let mut hex = u32::from_str_radix(&value[1..], 16)?;
+
+    // Shift over by 8 and add FF to account for the alpha channel
+    // in #RRGGBB formats.
+    if value.len() != 9 {
+        hex <<= 8;
+        hex  |= 0xff;
+    }
+
+    let b = (hex >> 8  & 0xff)  as f64 / 255.0;
+    let g = (hex >> 16  & 0xff) as f64 / 255.0;
+    let r = (hex >> 24 & 0xff)  as f64 / 255.0;
+
+    Ok((r, g, b))
+}
+
+/// Convert RGB to hex.
+pub(crate) fn rgb_to_hex(r: f64, g: f64, b: f64) -> String {
+    let r         = (r * 255.0).round() as u32;
+    let g         = (g * 255.0).round() as u32;
+    let b         = (b * 255.0).round() as u32;
+    let value     = (r << 16) | (g << 8) | b;
+    
+    format!("#{:06x}", value)
+}
+
+/// An error raised when parsing a color value from a string.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ColorParseError {
+    /// The provided color text was in an unsupported format.
+    InvalidFormat,
+    /// The provided color value was invalid.
+    InvalidValue(std::num::ParseIntError)
+}
+
+impl From<std::num::ParseIntError> for ColorParseError {
+    fn from(x: std::num::ParseIntError) -> Self {
+        Self::InvalidValue(x)
+    }
+}
+
+impl std::fmt::Display for ColorParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Self::InvalidFormat     => write!(f, "the provided color string was in an unsupported format"),
+            Self::InvalidValue(x)   => write!(f, "failed to parse color value - {x}")
+        }
+    }
+}
+
+impl std::error::Error for ColorParseError { }
+
+/// An error raised when a color scheme's name format can't name generated colors.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum NameFormatError {
+    /// The format lacks a placeholder every name needs.
+    Missing(&'static str),
+}
+
+impl std::fmt::Display for NameFormatError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Self::Missing(x)    => write!(f, "the name format is missing the `{x}` placeholder"),
+        }
+    }
+}
+
+impl std::error::Error for NameFormatError { }
+
+/// An error raised when generating the ramp of a color.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum RampError {
+    /// A stop of a `material3` ramp is outside the tones from 0 to 100.
+    InvalidTone(u32),
+}
+
+impl std::fmt::Display for RampError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Self::InvalidTone(x)    => write!(f, "expected the stops of a material3 ramp to be tones from 0 to 100, got {x}"),
+        }
+    }
+}
+
+impl std::error::Error for RampError { }
+
+/// An error raised when checking a color scheme json object.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum SchemeError {
+    /// The scheme isn't a json object of colors.
+    NotAnObject,
+    /// An entry of the scheme isn't a json object.
+    InvalidEntry(String),
+    /// A color has neither a `value` nor a `link`.
+    MissingValue(String),
+    /// An entry has a key the scheme doesn't know.
+    UnknownKey {
+        /// The name of the entry.
+        entry   : String,
+        /// The unknown key.
+        key     : String,
+    },
+    /// An entry has a value of the wrong type or out of range.
+    InvalidValue {
+        /// The name of the entry.
+        entry   : String,
+        /// The key of the value.
+        key     : String,
+        /// The value, as json.
+        value   : String,
+    },
+    /// A categorical palette has more colors than `MAX_CATEGORIES`.
+    TooManyCategories {
+        /// The name of the entry.
+        entry   : String,
+        /// The number of colors asked for.
+        count   : u32,
+    },
+}
+
+impl std::fmt::Display for SchemeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Self::NotAnObject                           => write!(f, "expected the color scheme to be a json object of colors"),
+            Self::InvalidEntry(x)                       => write!(f, "`{x}`: expected a json object"),
+            Self::MissingValue(x)                       => write!(f, "`{x}`: expected a `value` or `link`"),
+            Self::UnknownKey { entry, key }             => write!(f, "`{entry}`: unknown key `{key}`"),
+            Self::InvalidValue { entry, key, value }    => write!(f, "`{entry}`: invalid `{key}` {value}"),
+            Self::TooManyCategories { entry, count }    => write!(f, "`{entry}`: {count} categories asked for, at most {MAX_CATEGORIES} can be generated"),
+        }
+    }
+}
+
+impl std::error::Error for SchemeError { }
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    /// Generate a categorical palette of `count` colors anchored to `#3b82f6`, by value.
+    fn categories(count: u32, cvd_safe: bool) -> Result<Vec<String>, Error> {
+        let mut scheme = ColorScheme::new(STOPS.to_vec(), RampSpace::default());
+        scheme.add_colors(&json::object! {
+            "dataviz": { "value": "#3b82f6", "categories": count, "cvd_safe": cvd_safe },
+        });
+
+        let mut document = DocumentJson::default();
+        apply(&mut document, "theme", &scheme)?;
+
+        Ok(document.color_variables().iter().map(|x| x.value.to_lowercase()).collect())
+    }
+
+    #[test]
+    fn generates_distinct_categories() {
+        for cvd_safe in [false, true] {
+            let colors = categories(MAX_CATEGORIES, cvd_safe).unwrap();
+            assert_eq!(colors.len(), MAX_CATEGORIES as usize);
+            assert_eq!(colors.iter().collect::<BTreeSet<_>>().len(), colors.len(), "repeated colors in {colors:?}");
+        }
+    }
+
+    #[test]
+    fn rejects_too_many_categories() {
+        assert!(categories(MAX_CATEGORIES + 1, false).is_err());
+
+        let json = json::object! { "dataviz": { "value": "#3b82f6", "categories": MAX_CATEGORIES + 1 } };
+        assert!(matches!(check_scheme(&json), Err(SchemeError::TooManyCategories { count, .. }) if count == MAX_CATEGORIES + 1));
+    }
+}
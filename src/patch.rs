@@ -0,0 +1,137 @@
+use json::{object, JsonValue};
+
+/// Create a json patch, in the form described by RFC 6902, that turns `from` into `to`.
+///
+/// Objects are diffed key by key and arrays item by item while their length is unchanged, any
+/// other difference replaces the value outright.
+pub fn diff(from: &JsonValue, to: &JsonValue) -> JsonValue {
+    let mut patch = JsonValue::new_array();
+    diff_value(from, to, &mut String::new(), &mut patch);
+    patch
+}
+
+/// Apply a json `patch` created by `diff` to `json`.
+pub fn apply(json: &mut JsonValue, patch: &JsonValue) -> Result<(), PatchError> {
+    for op in patch.members() {
+        let (Some(kind), Some(path)) = (op["op"].as_str(), op["path"].as_str()) else {
+            return Err(PatchError::Invalid(op.dump()));
+        };
+
+        let tokens = path.split('/')
+            .skip(1)
+            .map(|x| x.replace("~1", "/").replace("~0", "~"))
+            .collect::<Vec<_>>();
+
+        // The root itself can only be replaced.
+        let Some((last, parents)) = tokens.split_last() else {
+            match kind {
+                "replace"   => *json = op["value"].clone(),
+                _           => return Err(PatchError::Invalid(op.dump())),
+            }
+
+            continue;
+        };
+
+        let mut target = &mut *json;
+        for token in parents {
+            target = child(target, token).ok_or_else(|| PatchError::Missing(path.to_owned()))?;
+        }
+
+        match (kind, &mut *target) {
+            ("add", JsonValue::Object(object)) => {
+                object.insert(last, op["value"].clone());
+            }
+            ("remove", JsonValue::Object(object)) => {
+                object.remove(last).ok_or_else(|| PatchError::Missing(path.to_owned()))?;
+            }
+            ("replace", _) => {
+                *child(target, last).ok_or_else(|| PatchError::Missing(path.to_owned()))? = op["value"].clone();
+            }
+            _ => return Err(PatchError::Invalid(op.dump())),
+        }
+    }
+
+    Ok(())
+}
+
+/// Add the operations turning `from` into `to` at `path` to `patch`.
+fn diff_value(from: &JsonValue, to: &JsonValue, path: &mut String, patch: &mut JsonValue) {
+    if from == to {
+        return;
+    }
+
+    match (from, to) {
+        (JsonValue::Object(from), JsonValue::Object(to)) => {
+            for (key, value) in from.iter() {
+                let len = path.len();
+                path.push('/');
+                path.push_str(&escape(key));
+
+                match to.get(key) {
+                    Some(to)    => diff_value(value, to, path, patch),
+                    None        => push(patch, object! { "op": "remove", "path": path.as_str() }),
+                }
+
+                path.truncate(len);
+            }
+
+            for (key, value) in to.iter().filter(|(key, _)| from.get(key).is_none()) {
+                let path = format!("{path}/{}", escape(key));
+                push(patch, object! { "op": "add", "path": path, "value": value.clone() });
+            }
+        }
+        (JsonValue::Array(from), JsonValue::Array(to)) if from.len() == to.len() => {
+            for (i, (from, to)) in from.iter().zip(to).enumerate() {
+                let len = path.len();
+                path.push_str(&format!("/{i}"));
+
+                diff_value(from, to, path, patch);
+                path.truncate(len);
+            }
+        }
+        _ => {
+            push(patch, object! { "op": "replace", "path": path.as_str(), "value": to.clone() });
+        }
+    }
+}
+
+/// Add `op` to `patch`.
+fn push(patch: &mut JsonValue, op: JsonValue) {
+    if let JsonValue::Array(ops) = patch {
+        ops.push(op);
+    }
+}
+
+/// Get the child of `json` named by the pointer `token`.
+fn child<'a>(json: &'a mut JsonValue, token: &str) -> Option<&'a mut JsonValue> {
+    match json {
+        JsonValue::Object(object)   => object.get_mut(token),
+        JsonValue::Array(values)    => values.get_mut(token.parse::<usize>().ok()?),
+        _                           => None,
+    }
+}
+
+/// Escape `key` for use in a json pointer.
+fn escape(key: &str) -> String {
+    key.replace('~', "~0").replace('/', "~1")
+}
+
+/// An error raised when a patch can't be applied.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum PatchError {
+    /// An operation in the patch isn't one `diff` creates.
+    Invalid(String),
+    /// An operation refers to a value that doesn't exist.
+    Missing(String),
+}
+
+impl std::fmt::Display for PatchError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Self::Invalid(x) => write!(f, "invalid patch operation {x}"),
+            Self::Missing(x) => write!(f, "patch refers to missing value {x}"),
+        }
+    }
+}
+
+impl std::error::Error for PatchError { }
@@ -0,0 +1,112 @@
+//! Python bindings to the document engine, importable as `lunacy_tools` once built with
+//! `maturin`.
+
+use std::path::PathBuf;
+
+use pyo3::exceptions::PyValueError;
+use pyo3::prelude::*;
+use pyo3::types::PyString;
+
+use crate::archive::{Document, ExtractLimits};
+use crate::palette::{self, ColorScheme, RampSpace, STOPS};
+use crate::Error;
+
+/// The `lunacy_tools` python module.
+#[pymodule]
+fn lunacy_tools(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    m.add_class::<LunacyDocument>()
+}
+
+/// A Lunacy `.free` document held in memory.
+#[pyclass(module = "lunacy_tools")]
+pub struct LunacyDocument {
+    /// The document, with the changes made to it since it was last committed.
+    doc: Document,
+}
+
+#[pymethods]
+impl LunacyDocument {
+    /// Open the document in the bytes `data`.
+    #[new]
+    fn new(data: &[u8]) -> PyResult<Self> {
+        let doc = Document::open(data.to_vec(), ExtractLimits::default()).map_err(error)?;
+        Ok(Self { doc })
+    }
+
+    /// Open the document at `path`.
+    #[staticmethod]
+    fn open(path: PathBuf) -> PyResult<Self> {
+        let doc = Document::open(std::fs::read(path)?, ExtractLimits::default()).map_err(error)?;
+        Ok(Self { doc })
+    }
+
+    /// Get the names of the groups of color variables in the document.
+    fn groups(&self) -> Vec<String> {
+        palette::groups(self.doc.json(), palette::SEPARATOR)
+    }
+
+    /// Get the names and hex values of the color variables in the document, optionally only those
+    /// in `group`.
+    #[pyo3(signature = (group = None))]
+    fn colors(&self, group: Option<&str>) -> Vec<(String, String)> {
+        let prefix = group.map(|x| format!("{x}{}", palette::SEPARATOR));
+
+        self.doc.json()
+            .color_variables()
+            .iter()
+            .filter(|x| prefix.as_ref().is_none_or(|prefix| x.name.starts_with(prefix.as_str())))
+            .map(|x| (x.name.clone(), x.value.clone()))
+            .collect()
+    }
+
+    /// Apply a color scheme to the colors in `group`. `scheme` is a dict, or json string, in the
+    /// same form as a `--color_scheme` file, e.g. `{"blue": {"value": "#3714AE"}}`.
+    #[pyo3(signature = (scheme, group = "theme", stops = None, ramp_space = None))]
+    fn update_colors(
+        &mut self,
+        scheme      : &Bound<'_, PyAny>,
+        group       : &str,
+        stops       : Option<Vec<u32>>,
+        ramp_space  : Option<&str>
+    )
+        -> PyResult<()>
+    {
+        let scheme = match scheme.cast::<PyString>() {
+            Ok(scheme)  => scheme.to_string(),
+            Err(_)      => scheme.py()
+                .import("json")?
+                .call_method1("dumps", (scheme,))?
+                .extract()?,
+        };
+        let space = ramp_space
+            .map(|x| x.parse::<RampSpace>().map_err(PyValueError::new_err))
+            .transpose()?;
+
+        // Malformed schemes are raised as a `ValueError`, as adding them to the scheme would panic.
+        let scheme = json::parse(&scheme).map_err(|e| error(Box::new(e)))?;
+        palette::check_scheme(&scheme).map_err(|e| error(Box::new(e)))?;
+
+        let mut color_scheme = ColorScheme::new(stops.unwrap_or(STOPS.to_vec()), space.unwrap_or_default());
+        color_scheme.add_colors(&scheme);
+
+        self.doc.update_colors(group, &color_scheme).map_err(error)
+    }
+
+    /// Commit the changes made to the document, returning its updated contents as bytes.
+    fn commit(&mut self) -> PyResult<Vec<u8>> {
+        Ok(self.doc.commit().map_err(error)?.to_vec())
+    }
+
+    /// Commit the changes made to the document and write it to `path`.
+    fn save(&mut self, path: PathBuf) -> PyResult<()> {
+        let data = self.doc.commit().map_err(error)?;
+        std::fs::write(path, data)?;
+
+        Ok(())
+    }
+}
+
+/// Raise an error from the engine as a python `ValueError`.
+fn error(e: Error) -> PyErr {
+    PyValueError::new_err(e.to_string())
+}
@@ -0,0 +1,401 @@
+use json::JsonValue;
+
+use crate::model::{self, NAME_SEPARATOR};
+
+/// A CSS-like selector matching layers by type, name and properties, e.g.
+/// `page["Home"] artboard[name^="Mobile"] text[fontSize<12]`.
+#[derive(Clone, Debug)]
+pub struct Selector {
+    /// The compound selectors making up the selector, each with how it relates to the previous.
+    parts: Vec<(Combinator, Compound)>,
+}
+
+/// How a compound selector relates to the one before it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Combinator {
+    /// Any ancestor matches the previous selector, written as whitespace.
+    Descendant,
+    /// The parent matches the previous selector, written as `>`.
+    Child,
+}
+
+/// A layer type with the filters a single layer must match.
+#[derive(Clone, Debug, Default)]
+struct Compound {
+    /// The layer type to match, or any type if unspecified or `*`.
+    layer_type  : Option<String>,
+    /// The filters the layer must pass.
+    filters     : Vec<Filter>,
+}
+
+/// A filter on a layer property, e.g. `[name^="Mobile"]`.
+#[derive(Clone, Debug)]
+struct Filter {
+    /// The property to test, which may be a dotted path, e.g. `frame.width`.
+    key     : String,
+    /// The test to apply, or `None` to only require the property exists.
+    test    : Option<(Operator, String)>,
+}
+
+/// A comparison between a property and a value.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Operator {
+    /// `=`, comparing numerically when both sides are numbers.
+    Equal,
+    /// `!=`, also matching layers without the property.
+    NotEqual,
+    /// `^=`.
+    StartsWith,
+    /// `$=`.
+    EndsWith,
+    /// `*=`.
+    Contains,
+    /// `<`, only matching numbers.
+    Less,
+    /// `<=`, only matching numbers.
+    LessOrEqual,
+    /// `>`, only matching numbers.
+    Greater,
+    /// `>=`, only matching numbers.
+    GreaterOrEqual,
+}
+
+/// The operators filters support, longest first so they're matched greedily.
+const OPERATORS: &[(&str, Operator)] = &[
+    ("!=",  Operator::NotEqual),
+    ("^=",  Operator::StartsWith),
+    ("$=",  Operator::EndsWith),
+    ("*=",  Operator::Contains),
+    ("<=",  Operator::LessOrEqual),
+    (">=",  Operator::GreaterOrEqual),
+    ("=",   Operator::Equal),
+    ("<",   Operator::Less),
+    (">",   Operator::Greater),
+];
+
+/// A layer matched by a selector.
+pub struct Match<'a> {
+    /// The name of the page the layer is on.
+    pub page    : &'a str,
+    /// The names of the layers from the page to the matched layer, joined by the name separator.
+    pub path    : String,
+    /// The matched layer.
+    pub layer   : &'a JsonValue,
+}
+
+impl Selector {
+    /// Find the layers on `page` matched by the selector, in document order.
+    pub fn select<'a>(&self, page: &'a JsonValue, page_name: &'a str) -> Vec<Match<'a>> {
+        let mut matches = vec![];
+        let mut stack   = vec![page];
+        self.select_within(page_name, &mut stack, &mut String::new(), &mut matches);
+        matches
+    }
+
+    /// Check whether the layer at the top of `stack`, below its ancestors, is matched by the
+    /// selector. The page is at the bottom of the stack.
+    pub fn matches(&self, stack: &[&JsonValue]) -> bool {
+        stack.len() > 1 && self.matches_at(self.parts.len() - 1, stack, stack.len() - 1)
+    }
+
+    /// Recursively find matches below the layer at the top of `stack`.
+    fn select_within<'a>(
+        &self,
+        page_name   : &'a str,
+        stack       : &mut Vec<&'a JsonValue>,
+        path        : &mut String,
+        matches     : &mut Vec<Match<'a>>
+    ) {
+        let parent = stack[stack.len() - 1];
+        for layer in parent[model::LAYERS_KEY].members() {
+            let len = path.len();
+            if !path.is_empty() {
+                path.push_str(&format!(" {NAME_SEPARATOR} "));
+            }
+            path.push_str(layer["name"].as_str().unwrap_or_default());
+
+            stack.push(layer);
+            if self.matches(stack) {
+                matches.push(Match {
+                    page    : page_name,
+                    path    : path.clone(),
+                    layer,
+                });
+            }
+
+            self.select_within(page_name, stack, path, matches);
+            stack.pop();
+            path.truncate(len);
+        }
+    }
+
+    /// Check whether `parts[..=part]` matches with `parts[part]` matching `stack[index]`.
+    fn matches_at(&self, part: usize, stack: &[&JsonValue], index: usize) -> bool {
+        let (combinator, compound) = &self.parts[part];
+        if !compound.matches(stack[index], index == 0) {
+            return false;
+        }
+
+        if part == 0 {
+            return true;
+        }
+
+        match combinator {
+            Combinator::Child       => index > 0 && self.matches_at(part - 1, stack, index - 1),
+            Combinator::Descendant  => (0..index).rev().any(|x| self.matches_at(part - 1, stack, x)),
+        }
+    }
+}
+
+impl Compound {
+    /// Check whether `layer` matches, where `is_page` is set for the page itself.
+    fn matches(&self, layer: &JsonValue, is_page: bool) -> bool {
+        let type_matches = match self.layer_type.as_deref() {
+            None | Some("*")    => true,
+            Some("page")        => is_page,
+            Some(_) if is_page  => false,
+            Some("component")   => model::is_component(layer),
+            Some("instance")    => model::is_instance(layer),
+            Some(x)             => model::layer_type(layer) == x,
+        };
+
+        type_matches && self.filters.iter().all(|x| x.matches(layer))
+    }
+}
+
+impl Filter {
+    /// Check whether `layer` passes the filter.
+    fn matches(&self, layer: &JsonValue) -> bool {
+        let value = lookup(layer, &self.key);
+        let Some((operator, expected)) = &self.test else {
+            return !value.is_null();
+        };
+
+        if value.is_null() {
+            return *operator == Operator::NotEqual;
+        }
+
+        let text    = value.as_str().map(|x| x.to_owned()).unwrap_or_else(|| value.dump());
+        let numbers = value.as_f64().zip(expected.parse::<f64>().ok());
+
+        match operator {
+            Operator::Equal             => numbers.map_or(text == *expected, |(a, b)| a == b),
+            Operator::NotEqual          => numbers.map_or(text != *expected, |(a, b)| a != b),
+            Operator::StartsWith        => text.starts_with(expected.as_str()),
+            Operator::EndsWith          => text.ends_with(expected.as_str()),
+            Operator::Contains          => text.contains(expected.as_str()),
+            Operator::Less              => numbers.is_some_and(|(a, b)| a < b),
+            Operator::LessOrEqual       => numbers.is_some_and(|(a, b)| a <= b),
+            Operator::Greater           => numbers.is_some_and(|(a, b)| a > b),
+            Operator::GreaterOrEqual    => numbers.is_some_and(|(a, b)| a >= b),
+        }
+    }
+}
+
+/// Look up the dotted property `key` of `layer`, falling back to its `frame` so sizes and
+/// positions can be filtered on directly.
+fn lookup<'a>(layer: &'a JsonValue, key: &str) -> &'a JsonValue {
+    let find = |json: &'a JsonValue| key.split('.').fold(json, |json, key| &json[key]);
+
+    let value = find(layer);
+    match value.is_null() {
+        true    => find(&layer["frame"]),
+        false   => value,
+    }
+}
+
+/// An error parsing a selector.
+#[derive(Debug)]
+pub enum SelectorError {
+    /// The selector had no layer types or filters.
+    Empty,
+    /// A character appeared where it isn't allowed, at the given byte offset.
+    Unexpected(char, usize),
+    /// The selector ended part way through a filter or string.
+    Unterminated,
+}
+
+impl std::fmt::Display for SelectorError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Empty             => write!(f, "selector is empty"),
+            Self::Unexpected(c, i)  => write!(f, "unexpected `{c}` at offset {i} in selector"),
+            Self::Unterminated      => write!(f, "selector ended part way through a filter"),
+        }
+    }
+}
+
+impl std::error::Error for SelectorError { }
+
+impl std::str::FromStr for Selector {
+    type Err = SelectorError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parser  = Parser { chars: s.char_indices().peekable() };
+        let mut parts   = vec![];
+
+        loop {
+            parser.skip_whitespace();
+            let combinator = match parser.peek() {
+                None                    => break,
+                Some(('>', _))          => {
+                    parser.next();
+                    parser.skip_whitespace();
+                    Combinator::Child
+                }
+                Some(_)                 => Combinator::Descendant,
+            };
+
+            parts.push((combinator, parser.compound()?));
+        }
+
+        if parts.is_empty() {
+            return Err(SelectorError::Empty);
+        }
+
+        Ok(Self {
+            parts,
+        })
+    }
+}
+
+/// Parses selectors from their text.
+struct Parser<'a> {
+    /// The remaining characters with their byte offsets.
+    chars: std::iter::Peekable<std::str::CharIndices<'a>>,
+}
+
+impl Parser<'_> {
+    /// Peek at the next character and its offset.
+    fn peek(&mut self) -> Option<(char, usize)> {
+        self.chars.peek().map(|(i, c)| (*c, *i))
+    }
+
+    /// Take the next character.
+    fn next(&mut self) -> Option<char> {
+        self.chars.next().map(|(_, c)| c)
+    }
+
+    /// Skip whitespace, returning whether there was any.
+    fn skip_whitespace(&mut self) -> bool {
+        let mut skipped = false;
+        while self.peek().is_some_and(|(c, _)| c.is_whitespace()) {
+            self.next();
+            skipped = true;
+        }
+
+        skipped
+    }
+
+    /// Take characters while `f` returns true for them.
+    fn take_while(&mut self, f: impl Fn(char) -> bool) -> String {
+        let mut taken = String::new();
+        while let Some((c, _)) = self.peek().filter(|(c, _)| f(*c)) {
+            taken.push(c);
+            self.next();
+        }
+
+        taken
+    }
+
+    /// Parse a layer type followed by any number of filters.
+    fn compound(&mut self) -> Result<Compound, SelectorError> {
+        let name = self.take_while(|c| c.is_alphanumeric() || c == '_' || c == '-' || c == '*');
+        let mut compound = Compound {
+            layer_type  : (!name.is_empty()).then(|| name.to_lowercase()),
+            filters     : vec![],
+        };
+
+        while let Some(('[', _)) = self.peek() {
+            self.next();
+            compound.filters.push(self.filter()?);
+        }
+
+        match self.peek() {
+            _ if compound.layer_type.is_none() && compound.filters.is_empty() => {
+                let (c, i) = self.peek().ok_or(SelectorError::Unterminated)?;
+                Err(SelectorError::Unexpected(c, i))
+            }
+            Some((c, i)) if !c.is_whitespace() && c != '>' => Err(SelectorError::Unexpected(c, i)),
+            _ => Ok(compound),
+        }
+    }
+
+    /// Parse a filter after its opening bracket, where a lone string is shorthand for a name.
+    fn filter(&mut self) -> Result<Filter, SelectorError> {
+        self.skip_whitespace();
+
+        let filter = match self.peek() {
+            Some(('"' | '\'', _))   => Filter {
+                key     : "name".to_owned(),
+                test    : Some((Operator::Equal, self.string()?)),
+            },
+            Some(_)                 => {
+                let key = self.take_while(|c| c.is_alphanumeric() || c == '_' || c == '.' || c == '-');
+                self.skip_whitespace();
+
+                let mut test = None;
+                for (symbol, operator) in OPERATORS {
+                    if self.eat(symbol) {
+                        self.skip_whitespace();
+                        let value = match self.peek() {
+                            Some(('"' | '\'', _))   => self.string()?,
+                            _                       => self.take_while(|c| c != ']').trim().to_owned(),
+                        };
+
+                        test = Some((*operator, value));
+                        break;
+                    }
+                }
+
+                if key.is_empty() {
+                    let (c, i) = self.peek().ok_or(SelectorError::Unterminated)?;
+                    return Err(SelectorError::Unexpected(c, i));
+                }
+
+                Filter {
+                    key,
+                    test,
+                }
+            }
+            None                    => return Err(SelectorError::Unterminated),
+        };
+
+        self.skip_whitespace();
+        match self.peek() {
+            Some((']', _))  => {
+                self.next();
+                Ok(filter)
+            }
+            Some((c, i))    => Err(SelectorError::Unexpected(c, i)),
+            None            => Err(SelectorError::Unterminated),
+        }
+    }
+
+    /// Take `symbol` if the remaining text starts with it.
+    fn eat(&mut self, symbol: &str) -> bool {
+        let mut lookahead = self.chars.clone();
+        for expected in symbol.chars() {
+            if lookahead.next().map(|(_, c)| c) != Some(expected) {
+                return false;
+            }
+        }
+
+        self.chars = lookahead;
+        true
+    }
+
+    /// Parse a quoted string, allowing quotes to be escaped with a backslash.
+    fn string(&mut self) -> Result<String, SelectorError> {
+        let quote = self.next().ok_or(SelectorError::Unterminated)?;
+
+        let mut value = String::new();
+        loop {
+            match self.next().ok_or(SelectorError::Unterminated)? {
+                '\\'                => value.push(self.next().ok_or(SelectorError::Unterminated)?),
+                c if c == quote     => return Ok(value),
+                c                   => value.push(c),
+            }
+        }
+    }
+}
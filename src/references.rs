@@ -0,0 +1,257 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::Path;
+
+use json::JsonValue;
+
+/// The directory images are stored in within a document archive.
+pub const IMAGES_DIR: &str = "images/";
+
+/// The directory fonts are stored in within a document archive.
+pub const FONTS_DIR: &str = "fonts/";
+
+/// The directories assets are stored in within a document archive.
+pub const ASSET_DIRS: &[&str] = &[IMAGES_DIR, FONTS_DIR];
+
+/// A reference from one part of a document to another.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Reference {
+    /// A reference to an object by id, such as a color variable or a style.
+    Id(String),
+    /// A reference to an asset stored in the document archive.
+    Asset(String),
+}
+
+impl std::fmt::Display for Reference {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Self::Id(x)     => write!(f, "id `{x}`"),
+            Self::Asset(x)  => write!(f, "asset `{x}`"),
+        }
+    }
+}
+
+/// Get the strings that refer to the asset `name`: its entry name, file name and file name
+/// without extension.
+pub fn asset_keys(name: &str) -> [&str; 3] {
+    let path = Path::new(name);
+    let file = path.file_name().and_then(|x| x.to_str()).unwrap_or_default();
+    let stem = path.file_stem().and_then(|x| x.to_str()).unwrap_or_default();
+
+    [name, file, stem]
+}
+
+/// Collect the id of every object in `json` into `ids`.
+pub fn collect_ids(json: &JsonValue, ids: &mut BTreeSet<String>) {
+    match json {
+        JsonValue::Object(object) => {
+            if let Some(id) = object.get("id").and_then(|x| x.as_str()) {
+                ids.insert(id.to_owned());
+            }
+
+            for (_, value) in object.iter() {
+                collect_ids(value, ids);
+            }
+        }
+        JsonValue::Array(values) => {
+            for value in values {
+                collect_ids(value, ids);
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Visit every reference in `json` along with the path to the value holding it.
+///
+/// Ids are referenced by keys ending in `Id` or `Ids`, while assets are referenced by any string
+/// naming a file in one of the [`ASSET_DIRS`].
+pub fn visit_references(json: &JsonValue, f: &mut impl FnMut(&str, Reference)) {
+    visit(json, &mut String::new(), None, f);
+}
+
+/// Recursively visit references in `json` found at `path` under `key`.
+fn visit(
+    json    : &JsonValue,
+    path    : &mut String,
+    key     : Option<&str>,
+    f       : &mut impl FnMut(&str, Reference)
+) {
+    match json {
+        JsonValue::Object(object) => {
+            for (name, value) in object.iter() {
+                let len = path.len();
+                if !path.is_empty() {
+                    path.push('.');
+                }
+                path.push_str(name);
+
+                visit(value, path, Some(name), f);
+                path.truncate(len);
+            }
+        }
+        JsonValue::Array(values) => {
+            for (i, value) in values.iter().enumerate() {
+                let len = path.len();
+                path.push_str(&format!("[{i}]"));
+
+                visit(value, path, key, f);
+                path.truncate(len);
+            }
+        }
+        _ => {
+            let Some(value) = json.as_str() else {
+                return;
+            };
+
+            if let Some(reference) = classify(key, value) {
+                f(path, reference);
+            }
+        }
+    }
+}
+
+/// Classify the string `value` stored under `key` as a reference, if it is one.
+fn classify(key: Option<&str>, value: &str) -> Option<Reference> {
+    if ASSET_DIRS.iter().any(|x| value.starts_with(x)) {
+        return Some(Reference::Asset(value.to_owned()));
+    }
+
+    match key {
+        Some(key) if key.ends_with("Id") || key.ends_with("Ids") => {
+            Some(Reference::Id(value.to_owned()))
+        }
+        _ => None,
+    }
+}
+
+/// Remove every reference in `json` that `keep` returns false for, returning the path of each
+/// removed reference.
+///
+/// References held by an object key are removed along with the key, while references held in an
+/// array are removed from the array.
+pub fn retain_references(
+    json    : &mut JsonValue,
+    keep    : &mut impl FnMut(&Reference) -> bool
+)
+    -> Vec<(String, Reference)>
+{
+    let mut removed = vec![];
+    retain(json, &mut String::new(), None, keep, &mut removed);
+
+    removed
+}
+
+/// Recursively remove references from `json` found at `path` under `key`.
+fn retain(
+    json    : &mut JsonValue,
+    path    : &mut String,
+    key     : Option<&str>,
+    keep    : &mut impl FnMut(&Reference) -> bool,
+    removed : &mut Vec<(String, Reference)>
+) {
+    match json {
+        JsonValue::Object(object) => {
+            let names = object.iter()
+                .map(|(x, _)| x.to_owned())
+                .collect::<Vec<_>>();
+
+            for name in names {
+                let len = path.len();
+                if !path.is_empty() {
+                    path.push('.');
+                }
+                path.push_str(&name);
+
+                let reference = object.get(&name)
+                    .and_then(|x| x.as_str())
+                    .and_then(|x| classify(Some(&name), x));
+
+                match reference {
+                    Some(x) if !keep(&x)    => {
+                        object.remove(&name);
+                        removed.push((path.clone(), x));
+                    }
+                    Some(_)                 => {},
+                    None                    => {
+                        if let Some(value) = object.get_mut(&name) {
+                            retain(value, path, Some(&name), keep, removed);
+                        }
+                    }
+                }
+
+                path.truncate(len);
+            }
+        }
+        JsonValue::Array(values) => {
+            let mut i = 0;
+            for index in 0..values.len() {
+                let len = path.len();
+                path.push_str(&format!("[{index}]"));
+
+                let reference = values[i].as_str()
+                    .and_then(|x| classify(key, x));
+
+                match reference {
+                    Some(x) if !keep(&x)    => {
+                        values.remove(i);
+                        removed.push((path.clone(), x));
+                    }
+                    Some(_)                 => i += 1,
+                    None                    => {
+                        retain(&mut values[i], path, key, keep, removed);
+                        i += 1;
+                    }
+                }
+
+                path.truncate(len);
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Collect every string value in `json` into `strings`.
+pub fn collect_strings(json: &JsonValue, strings: &mut BTreeSet<String>) {
+    match json {
+        JsonValue::Object(object) => {
+            for (_, value) in object.iter() {
+                collect_strings(value, strings);
+            }
+        }
+        JsonValue::Array(values) => {
+            for value in values {
+                collect_strings(value, strings);
+            }
+        }
+        _ => {
+            if let Some(value) = json.as_str() {
+                strings.insert(value.to_owned());
+            }
+        }
+    }
+}
+
+/// Replace every string value in `json` that matches a key of `renames` with its value,
+/// returning the number of strings replaced.
+pub fn rename_strings(json: &mut JsonValue, renames: &BTreeMap<String, String>) -> usize {
+    match json {
+        JsonValue::Object(object) => {
+            object.iter_mut()
+                .map(|(_, value)| rename_strings(value, renames))
+                .sum()
+        }
+        JsonValue::Array(values) => {
+            values.iter_mut()
+                .map(|value| rename_strings(value, renames))
+                .sum()
+        }
+        _ => {
+            let Some(renamed) = json.as_str().and_then(|x| renames.get(x)) else {
+                return 0;
+            };
+
+            *json = renamed.as_str().into();
+            1
+        }
+    }
+}
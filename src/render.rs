@@ -0,0 +1,428 @@
+use std::collections::BTreeMap;
+use std::fmt::Write;
+
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use json::JsonValue;
+use lunacy_tools::schema::DocumentJson;
+use pdf_writer::{Content, Finish, Name, Pdf, Rect, Ref};
+use resvg::tiny_skia;
+use resvg::usvg::{self, fontdb, TreeParsing, TreePostProc};
+
+use crate::document::LunacyDocument;
+use crate::model::{self, layer_type, FontRef, Frame, LAYERS_KEY};
+use crate::references::{self, FONTS_DIR, IMAGES_DIR};
+use crate::Error;
+
+/// The font size used for text layers that don't specify one.
+const DEFAULT_FONT_SIZE: f64 = 14.0;
+
+/// The height of a line of text relative to its font size.
+const LINE_HEIGHT: f64 = 1.2;
+
+/// A color along with its opacity.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Paint {
+    /// The color as `0xRRGGBB`.
+    pub rgb     : u32,
+    /// The opacity from 0 to 1.
+    pub opacity : f64,
+}
+
+/// Renders the artboards of a document to standalone SVG.
+///
+/// Rectangles, ovals, paths, text and images are rendered with their first enabled fill and
+/// border. Fills referring to a color variable use the current value of the variable, and images
+/// are embedded in the SVG so it can be used on its own.
+pub struct SvgRenderer<'a> {
+    /// The document being rendered.
+    doc         : &'a LunacyDocument,
+    /// The value of each color variable, by id.
+    variables   : BTreeMap<String, Paint>,
+    /// The entry name of each image, by every string that may refer to it.
+    images      : BTreeMap<String, String>,
+}
+
+impl<'a> SvgRenderer<'a> {
+    /// Create a renderer for the artboards of `doc`.
+    pub fn new(doc: &'a LunacyDocument) -> Result<Self, Error> {
+        let document        = doc.load::<DocumentJson>("document.json")?;
+        let mut variables   = BTreeMap::new();
+        for variable in document.color_variables() {
+            let Some(paint) = parse_color(&variable.value.as_str().into()) else {
+                continue;
+            };
+
+            variables.insert(variable.id.clone(), paint);
+        }
+
+        let mut images = BTreeMap::new();
+        for entry in doc.entries().iter().filter(|x| x.name.starts_with(IMAGES_DIR)) {
+            for key in references::asset_keys(&entry.name) {
+                images.entry(key.to_owned()).or_insert_with(|| entry.name.clone());
+            }
+        }
+
+        Ok(Self {
+            doc,
+            variables,
+            images,
+        })
+    }
+
+    /// Render `artboard` to an SVG document.
+    pub fn render(&self, artboard: &JsonValue) -> Result<String, Error> {
+        let frame   = Frame::from_layer(artboard);
+        let mut svg = String::new();
+
+        writeln!(
+            svg,
+            r#"<svg xmlns="http://www.w3.org/2000/svg" width="{0}" height="{1}" viewBox="0 0 {0} {1}">"#,
+            num(frame.width),
+            num(frame.height)
+        )?;
+
+        // The artboard is the origin of the SVG, so only its contents are positioned.
+        let background = parse_color(&artboard["backgroundColor"])
+            .or_else(|| self.fill(artboard));
+        if let Some(paint) = background {
+            writeln!(svg, r#"<rect width="{}" height="{}"{}/>"#, num(frame.width), num(frame.height), paint_attributes("fill", paint))?;
+        }
+
+        for layer in artboard[LAYERS_KEY].members() {
+            self.render_layer(&mut svg, layer)?;
+        }
+
+        writeln!(svg, "</svg>")?;
+        Ok(svg)
+    }
+
+    /// Render `layer` and its children into `svg`.
+    fn render_layer(&self, svg: &mut String, layer: &JsonValue) -> Result<(), Error> {
+        if !model::is_visible(layer) {
+            return Ok(());
+        }
+
+        let frame       = Frame::from_layer(layer);
+        let rotation    = layer["rotation"].as_f64().unwrap_or_default();
+        let opacity     = layer["opacity"].as_f64()
+            .or(layer["style"]["opacity"].as_f64())
+            .unwrap_or(1.0);
+
+        write!(svg, r#"<g transform="translate({} {})"#, num(frame.x), num(frame.y))?;
+        if rotation != 0.0 {
+            write!(svg, " rotate({} {} {})", num(rotation), num(frame.width / 2.0), num(frame.height / 2.0))?;
+        }
+        write!(svg, "\"")?;
+        if opacity < 1.0 {
+            write!(svg, r#" opacity="{}""#, num(opacity))?;
+        }
+        writeln!(svg, ">")?;
+
+        let paint = self.paint_attributes(layer);
+        match model::layer_type(layer).as_str() {
+            layer_type::RECTANGLE   => {
+                let radius = layer["cornerRadius"].as_f64()
+                    .or(layer["radius"].as_f64())
+                    .unwrap_or_default();
+
+                write!(svg, r#"<rect width="{}" height="{}""#, num(frame.width), num(frame.height))?;
+                if radius > 0.0 {
+                    write!(svg, r#" rx="{}""#, num(radius))?;
+                }
+                writeln!(svg, "{paint}/>")?;
+            }
+            layer_type::OVAL        => {
+                let (rx, ry) = (frame.width / 2.0, frame.height / 2.0);
+                writeln!(svg, r#"<ellipse cx="{0}" cy="{1}" rx="{0}" ry="{1}"{paint}/>"#, num(rx), num(ry))?;
+            }
+            layer_type::PATH        => {
+                if let Some(path) = layer["path"].as_str() {
+                    writeln!(svg, r#"<path d="{}"{paint}/>"#, escape(path))?;
+                }
+            }
+            layer_type::TEXT        => self.render_text(svg, layer)?,
+            layer_type::IMAGE       => self.render_image(svg, layer, frame)?,
+            _                       => {}
+        }
+
+        for child in layer[LAYERS_KEY].members() {
+            self.render_layer(svg, child)?;
+        }
+
+        writeln!(svg, "</g>")?;
+        Ok(())
+    }
+
+    /// Render the text of `layer` into `svg`, one line per `tspan`.
+    fn render_text(&self, svg: &mut String, layer: &JsonValue) -> Result<(), Error> {
+        let Some(text) = model::text_key(layer).and_then(|x| layer[x].as_str()) else {
+            return Ok(());
+        };
+
+        let mut font = None;
+        model::visit_fonts(layer, &mut |x| { font.get_or_insert(x); });
+
+        let size = find_number(layer, "fontSize").unwrap_or(DEFAULT_FONT_SIZE);
+        let fill = parse_color(&layer["textColor"])
+            .or_else(|| self.fill(layer))
+            .unwrap_or(Paint { rgb: 0, opacity: 1.0 });
+
+        write!(svg, r#"<text font-size="{}""#, num(size))?;
+        if let Some(FontRef { family, weight, italic }) = font {
+            write!(svg, r#" font-family="{}" font-weight="{weight}""#, escape(&family))?;
+            if italic {
+                write!(svg, r#" font-style="italic""#)?;
+            }
+        }
+        writeln!(svg, "{}>", paint_attributes("fill", fill))?;
+
+        // Each line is offset from the previous one, with the first sitting on the top of the frame.
+        for (i, line) in text.lines().enumerate() {
+            let dy = if i == 0 { size } else { size * LINE_HEIGHT };
+            writeln!(svg, r#"<tspan x="0" dy="{}">{}</tspan>"#, num(dy), escape(line))?;
+        }
+
+        writeln!(svg, "</text>")?;
+        Ok(())
+    }
+
+    /// Render the image of `layer` into `svg`, embedding it as a data URL.
+    fn render_image(&self, svg: &mut String, layer: &JsonValue, frame: Frame) -> Result<(), Error> {
+        let entry = layer["image"].as_str()
+            .and_then(|x| self.images.get(x));
+
+        let Some(entry) = entry else {
+            return Ok(());
+        };
+
+        let data = self.doc.load_bytes(entry)?;
+        let mime = image::guess_format(&data)
+            .map(|x| x.to_mime_type())
+            .unwrap_or("application/octet-stream");
+
+        writeln!(
+            svg,
+            r#"<image width="{}" height="{}" preserveAspectRatio="none" href="data:{mime};base64,{}"/>"#,
+            num(frame.width),
+            num(frame.height),
+            STANDARD.encode(&data)
+        )?;
+
+        Ok(())
+    }
+
+    /// Get the fill and stroke attributes of `layer`.
+    fn paint_attributes(&self, layer: &JsonValue) -> String {
+        let mut attributes = match self.fill(layer) {
+            Some(paint) => paint_attributes("fill", paint),
+            None        => r#" fill="none""#.to_owned(),
+        };
+
+        let border = first_enabled(layer, "borders")
+            .and_then(|x| self.resolve(x).map(|paint| (x, paint)));
+        if let Some((border, paint)) = border {
+            let width = border["thickness"].as_f64()
+                .or(border["width"].as_f64())
+                .unwrap_or(1.0);
+
+            attributes.push_str(&paint_attributes("stroke", paint));
+            attributes.push_str(&format!(r#" stroke-width="{}""#, num(width)));
+        }
+
+        attributes
+    }
+
+    /// Get the first enabled fill of `layer`.
+    fn fill(&self, layer: &JsonValue) -> Option<Paint> {
+        first_enabled(layer, "fills").and_then(|x| self.resolve(x))
+    }
+
+    /// Resolve the color of a fill or border, preferring the color variable it refers to.
+    fn resolve(&self, json: &JsonValue) -> Option<Paint> {
+        let variable = json.entries()
+            .filter(|(key, _)| key.ends_with("Id"))
+            .find_map(|(_, value)| value.as_str().and_then(|x| self.variables.get(x)));
+
+        let mut paint = variable.copied().or_else(|| parse_color(&json["color"]))?;
+        paint.opacity *= json["opacity"].as_f64().unwrap_or(1.0);
+
+        Some(paint)
+    }
+}
+
+/// Converts rendered SVG to PNG and PDF, rendering text with the fonts installed on the system
+/// and embedded in the document.
+pub struct SvgConverter {
+    /// The fonts available to text.
+    fonts: fontdb::Database,
+}
+
+impl SvgConverter {
+    /// Create a converter for the artboards of `doc`.
+    pub fn new(doc: &LunacyDocument) -> Result<Self, Error> {
+        let mut fonts = fontdb::Database::new();
+        fonts.load_system_fonts();
+
+        for entry in doc.entries().iter().filter(|x| x.name.starts_with(FONTS_DIR)) {
+            fonts.load_font_data(doc.load_bytes(&entry.name)?);
+        }
+
+        Ok(Self {
+            fonts,
+        })
+    }
+
+    /// Rasterize `svg` to a PNG image, scaling it by `scale`.
+    pub fn to_png(&self, svg: &str, scale: f32) -> Result<Vec<u8>, Error> {
+        let tree = self.parse(svg)?;
+        let size = tree.size.to_int_size().scale_by(scale)
+            .ok_or(RenderError::InvalidSize)?;
+
+        let mut pixmap = tiny_skia::Pixmap::new(size.width(), size.height())
+            .ok_or(RenderError::InvalidSize)?;
+        resvg::render(&tree, tiny_skia::Transform::from_scale(scale, scale), &mut pixmap.as_mut());
+
+        Ok(pixmap.encode_png()?)
+    }
+
+    /// Convert each of `svgs` to a page of a PDF document, with one point per pixel.
+    pub fn to_pdf(&self, svgs: &[String]) -> Result<Vec<u8>, Error> {
+        let catalog_id      = Ref::new(1);
+        let page_tree_id    = Ref::new(2);
+        let mut next_id     = Ref::new(3);
+        let mut page_ids    = vec![];
+
+        let mut pdf = Pdf::new();
+        for svg in svgs {
+            let tree        = self.parse(svg)?;
+            let page_id     = next_id;
+            let content_id  = Ref::new(page_id.get() + 1);
+            let svg_id      = Ref::new(page_id.get() + 2);
+            let svg_name    = Name(b"S1");
+            let (width, height) = (tree.size.width(), tree.size.height());
+
+            let mut page = pdf.page(page_id);
+            page.media_box(Rect::new(0.0, 0.0, width, height));
+            page.parent(page_tree_id);
+            page.contents(content_id);
+            page.resources().x_objects().pair(svg_name, svg_id);
+            page.finish();
+
+            // The converted SVG is a unit sized object, so scale it up to fill the page.
+            let mut content = Content::new();
+            content.transform([width, 0.0, 0.0, height, 0.0, 0.0]);
+            content.x_object(svg_name);
+            pdf.stream(content_id, &content.finish());
+
+            next_id = svg2pdf::convert_tree_into(&tree, svg2pdf::Options::default(), &mut pdf, svg_id);
+            page_ids.push(page_id);
+        }
+
+        pdf.catalog(catalog_id).pages(page_tree_id);
+        pdf.pages(page_tree_id).count(page_ids.len() as i32).kids(page_ids);
+
+        Ok(pdf.finish())
+    }
+
+    /// Parse `svg`, converting its text to paths with the available fonts.
+    fn parse(&self, svg: &str) -> Result<usvg::Tree, Error> {
+        let mut tree = usvg::Tree::from_str(svg, &usvg::Options::default())?;
+        tree.postprocess(usvg::PostProcessingSteps::default(), &self.fonts);
+
+        Ok(tree)
+    }
+}
+
+/// An error encountered while rendering an artboard.
+#[derive(Debug)]
+enum RenderError {
+    /// The artboard is empty or too large to rasterize at the requested scale.
+    InvalidSize,
+}
+
+impl std::fmt::Display for RenderError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::InvalidSize => write!(f, "artboard is empty or too large to rasterize"),
+        }
+    }
+}
+
+impl std::error::Error for RenderError { }
+
+/// Get the first enabled fill or border of `layer` under `key`, stored on the layer itself or
+/// its `style`.
+pub fn first_enabled<'a>(layer: &'a JsonValue, key: &str) -> Option<&'a JsonValue> {
+    let list = match layer[key].is_array() {
+        true    => &layer[key],
+        false   => &layer["style"][key],
+    };
+
+    list.members().find(|x| {
+        x["enabled"].as_bool()
+            .or(x["isEnabled"].as_bool())
+            .unwrap_or(true)
+    })
+}
+
+/// Find the first number stored under `key` within `json`, without descending into child layers.
+fn find_number(json: &JsonValue, key: &str) -> Option<f64> {
+    if let Some(x) = json[key].as_f64() {
+        return Some(x);
+    }
+
+    match json {
+        JsonValue::Object(object) => {
+            object.iter()
+                .filter(|(name, _)| *name != LAYERS_KEY)
+                .find_map(|(_, value)| find_number(value, key))
+        }
+        JsonValue::Array(values) => values.iter().find_map(|x| find_number(x, key)),
+        _ => None,
+    }
+}
+
+/// Parse a color stored as a hex string, with or without a leading `#` and alpha, or as an
+/// object of `r`, `g`, `b` and `a` components from 0 to 1.
+pub fn parse_color(json: &JsonValue) -> Option<Paint> {
+    if let Some(hex) = json.as_str() {
+        let hex     = hex.trim_start_matches('#');
+        let value   = u32::from_str_radix(hex, 16).ok()?;
+
+        return match hex.len() {
+            6 => Some(Paint { rgb: value, opacity: 1.0 }),
+            8 => Some(Paint { rgb: value >> 8, opacity: (value & 0xff) as f64 / 255.0 }),
+            _ => None,
+        };
+    }
+
+    let channel = |key: &str| json[key].as_f64().map(|x| (x.clamp(0.0, 1.0) * 255.0).round() as u32);
+    Some(Paint {
+        rgb     : channel("r")? << 16 | channel("g")? << 8 | channel("b")?,
+        opacity : json["a"].as_f64().unwrap_or(1.0),
+    })
+}
+
+/// Format `paint` as the SVG color and opacity attributes for `property`, e.g. `fill`.
+fn paint_attributes(property: &str, paint: Paint) -> String {
+    let mut attributes = format!(r##" {property}="#{:06x}""##, paint.rgb);
+    if paint.opacity < 1.0 {
+        attributes.push_str(&format!(r#" {property}-opacity="{}""#, num(paint.opacity)));
+    }
+
+    attributes
+}
+
+/// Format a number for SVG output, rounded to a sensible precision.
+fn num(x: f64) -> f64 {
+    (x * 1000.0).round() / 1000.0
+}
+
+/// Escape `text` for use in SVG text and attribute values.
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
@@ -0,0 +1,335 @@
+use std::collections::{HashMap, HashSet};
+use std::ops::Range;
+
+use serde::Serialize;
+use serde_json::Value;
+
+use crate::schema;
+use crate::Error;
+
+/// Serialize `value` as a replacement for the JSON `original`, keeping the text of the original
+/// wherever it's unchanged.
+///
+/// Values equal to the value they replace are copied from the original as they were written, so
+/// the formatting of numbers, escaping of strings and order of keys Lunacy wrote them with are
+/// kept, and the only differences are in the values that changed. Keys keep their original
+/// order, with new keys after them, and array elements are matched to the original by `id`
+/// where they have one. Objects and arrays containing changes are written compactly, as Lunacy
+/// writes them.
+pub fn to_string<T: Serialize>(original: &str, value: &T) -> Result<String, Error> {
+    let value = serde_json::to_value(value)?;
+
+    let parsed = schema::from_slice::<Value>(&mut original.as_bytes().to_vec());
+    let (Ok(parsed), Some(span)) = (parsed, Span::parse(original)) else {
+        return Ok(serde_json::to_string(&value)?);
+    };
+
+    let mut out = String::with_capacity(original.len());
+    write(&mut out, original, &span, &parsed, &value)?;
+
+    Ok(out)
+}
+
+/// Find the value of the member `key` of the object in the JSON `text`, without parsing the rest
+/// of it, or `None` if the text isn't an object or has no such member.
+pub fn find_member(text: &str, key: &str) -> Option<Range<usize>> {
+    let bytes   = text.as_bytes();
+    let mut pos = 0;
+
+    skip_whitespace(bytes, &mut pos);
+    expect(bytes, &mut pos, b'{')?;
+    loop {
+        skip_whitespace(bytes, &mut pos);
+        let key_start = pos;
+        skip_string(bytes, &mut pos)?;
+        let name = decode_key(&bytes[key_start..pos])?;
+
+        skip_whitespace(bytes, &mut pos);
+        expect(bytes, &mut pos, b':')?;
+        skip_whitespace(bytes, &mut pos);
+
+        let start = pos;
+        skip_value(bytes, &mut pos)?;
+        if name == key {
+            return Some(start..pos);
+        }
+
+        skip_whitespace(bytes, &mut pos);
+        expect(bytes, &mut pos, b',')?;
+    }
+}
+
+/// Replace the value at `range` in the JSON `text` with `value`, keeping the text of the parts of
+/// it that didn't change as `to_string` does, and copying the rest of the text as it is.
+pub fn splice<T: Serialize>(text: &str, range: Range<usize>, value: &T) -> Result<String, Error> {
+    let replacement = to_string(&text[range.clone()], value)?;
+
+    let mut out = String::with_capacity(text.len() - range.len() + replacement.len());
+    out.push_str(&text[..range.start]);
+    out.push_str(&replacement);
+    out.push_str(&text[range.end..]);
+
+    Ok(out)
+}
+
+/// Write `value` to `out`, copying the text of `original`, found at `span` in `text`, where
+/// they're the same.
+fn write(out: &mut String, text: &str, span: &Span, original: &Value, value: &Value) -> Result<(), Error> {
+    if original == value {
+        out.push_str(&text[span.start..span.end]);
+        return Ok(());
+    }
+
+    match (value, original, &span.members) {
+        (Value::Object(map), Value::Object(originals), Members::Object(members)) => {
+            out.push('{');
+
+            let mut first   = true;
+            let mut written = HashSet::new();
+            for member in members {
+                let (Some(value), Some(original)) = (map.get(&member.key), originals.get(&member.key)) else {
+                    continue;
+                };
+                if !written.insert(member.key.as_str()) {
+                    continue;
+                }
+
+                if !std::mem::take(&mut first) {
+                    out.push(',');
+                }
+
+                out.push_str(&text[member.key_span.0..member.key_span.1]);
+                out.push(':');
+                write(out, text, &member.value, original, value)?;
+            }
+
+            for (key, value) in map.iter().filter(|(key, _)| !originals.contains_key(*key)) {
+                if !std::mem::take(&mut first) {
+                    out.push(',');
+                }
+
+                out.push_str(&serde_json::to_string(key)?);
+                out.push(':');
+                out.push_str(&serde_json::to_string(value)?);
+            }
+
+            out.push('}');
+        }
+        (Value::Array(items), Value::Array(originals), Members::Array(spans)) => {
+            let ids = originals.iter()
+                .enumerate()
+                .filter_map(|(i, x)| Some((x.get("id")?.as_str()?, i)))
+                .collect::<HashMap<_, _>>();
+
+            out.push('[');
+            for (i, item) in items.iter().enumerate() {
+                if i > 0 {
+                    out.push(',');
+                }
+
+                let matched = match item.get("id").and_then(|x| x.as_str()) {
+                    Some(id)    => ids.get(id).copied(),
+                    None        => Some(i).filter(|x| *x < originals.len()),
+                };
+
+                match matched.and_then(|x| Some((spans.get(x)?, &originals[x]))) {
+                    Some((span, original))  => write(out, text, span, original, item)?,
+                    None                    => out.push_str(&serde_json::to_string(item)?),
+                }
+            }
+
+            out.push(']');
+        }
+        _ => out.push_str(&serde_json::to_string(value)?),
+    }
+
+    Ok(())
+}
+
+/// The location of a value in JSON text, with the locations of its members.
+struct Span {
+    /// The offset of the first byte of the value.
+    start   : usize,
+    /// The offset after the last byte of the value.
+    end     : usize,
+    /// The members of the value, if it's an object or array.
+    members : Members,
+}
+
+/// The members of a value in JSON text.
+enum Members {
+    /// The value isn't an object or array.
+    None,
+    /// The members of an object, in order.
+    Object(Vec<Member>),
+    /// The elements of an array, in order.
+    Array(Vec<Span>),
+}
+
+/// A member of an object in JSON text.
+struct Member {
+    /// The decoded key of the member.
+    key         : String,
+    /// The location of the key, including its quotes.
+    key_span    : (usize, usize),
+    /// The location of the value.
+    value       : Span,
+}
+
+impl Span {
+    /// Find the location of every value in the JSON `text`, or `None` if it isn't valid.
+    fn parse(text: &str) -> Option<Self> {
+        let bytes   = text.as_bytes();
+        let mut pos = 0;
+        let span    = Self::parse_value(bytes, &mut pos)?;
+
+        skip_whitespace(bytes, &mut pos);
+        match pos == bytes.len() {
+            true    => Some(span),
+            false   => None,
+        }
+    }
+
+    /// Find the location of the value at `pos` in `bytes`, advancing past it.
+    fn parse_value(bytes: &[u8], pos: &mut usize) -> Option<Self> {
+        skip_whitespace(bytes, pos);
+
+        let start   = *pos;
+        let members = match *bytes.get(start)? {
+            b'{' => {
+                *pos += 1;
+
+                let mut members = vec![];
+                skip_whitespace(bytes, pos);
+                if bytes.get(*pos) == Some(&b'}') {
+                    *pos += 1;
+                }
+                else {
+                    loop {
+                        skip_whitespace(bytes, pos);
+                        let key_start = *pos;
+                        skip_string(bytes, pos)?;
+                        let key_span = (key_start, *pos);
+                        let key = decode_key(&bytes[key_span.0..key_span.1])?;
+
+                        skip_whitespace(bytes, pos);
+                        expect(bytes, pos, b':')?;
+                        let value = Self::parse_value(bytes, pos)?;
+                        members.push(Member { key, key_span, value });
+
+                        skip_whitespace(bytes, pos);
+                        match *bytes.get(*pos)? {
+                            b','    => *pos += 1,
+                            b'}'    => { *pos += 1; break; }
+                            _       => return None,
+                        }
+                    }
+                }
+
+                Members::Object(members)
+            }
+            b'[' => {
+                *pos += 1;
+
+                let mut items = vec![];
+                skip_whitespace(bytes, pos);
+                if bytes.get(*pos) == Some(&b']') {
+                    *pos += 1;
+                }
+                else {
+                    loop {
+                        items.push(Self::parse_value(bytes, pos)?);
+
+                        skip_whitespace(bytes, pos);
+                        match *bytes.get(*pos)? {
+                            b','    => *pos += 1,
+                            b']'    => { *pos += 1; break; }
+                            _       => return None,
+                        }
+                    }
+                }
+
+                Members::Array(items)
+            }
+            b'"' => {
+                skip_string(bytes, pos)?;
+                Members::None
+            }
+            _ => {
+                skip_scalar(bytes, pos);
+                Members::None
+            }
+        };
+
+        Some(Self { start, end: *pos, members })
+    }
+}
+
+/// Decode the quoted object key in `bytes`, only unescaping keys that need it since few do.
+fn decode_key(bytes: &[u8]) -> Option<String> {
+    match bytes.contains(&b'\\') {
+        true    => serde_json::from_slice(bytes).ok(),
+        false   => String::from_utf8(bytes[1..bytes.len() - 1].to_vec()).ok(),
+    }
+}
+
+/// Advance `pos` past the value starting at it, without finding the location of its members.
+fn skip_value(bytes: &[u8], pos: &mut usize) -> Option<()> {
+    match *bytes.get(*pos)? {
+        b'"'        => skip_string(bytes, pos),
+        b'{' | b'[' => {
+            let mut depth = 0usize;
+            loop {
+                match *bytes.get(*pos)? {
+                    b'"'        => { skip_string(bytes, pos)?; continue; }
+                    b'{' | b'[' => depth += 1,
+                    b'}' | b']' => depth -= 1,
+                    _           => {}
+                }
+
+                *pos += 1;
+                if depth == 0 {
+                    return Some(());
+                }
+            }
+        }
+        _ => {
+            skip_scalar(bytes, pos);
+            Some(())
+        }
+    }
+}
+
+/// Advance `pos` past the number, boolean or null starting at it.
+fn skip_scalar(bytes: &[u8], pos: &mut usize) {
+    while bytes.get(*pos).is_some_and(|x| !matches!(x, b',' | b']' | b'}' | b' ' | b'\t' | b'\r' | b'\n')) {
+        *pos += 1;
+    }
+}
+
+/// Advance `pos` past any whitespace.
+fn skip_whitespace(bytes: &[u8], pos: &mut usize) {
+    while bytes.get(*pos).is_some_and(|x| matches!(x, b' ' | b'\t' | b'\r' | b'\n')) {
+        *pos += 1;
+    }
+}
+
+/// Advance `pos` past the string starting at it, including its quotes.
+fn skip_string(bytes: &[u8], pos: &mut usize) -> Option<()> {
+    expect(bytes, pos, b'"')?;
+    loop {
+        match *bytes.get(*pos)? {
+            b'\\'   => *pos += 2,
+            b'"'    => { *pos += 1; return Some(()); }
+            _       => *pos += 1,
+        }
+    }
+}
+
+/// Advance `pos` past the byte `expected`, or return `None` if it's something else.
+fn expect(bytes: &[u8], pos: &mut usize, expected: u8) -> Option<()> {
+    match bytes.get(*pos) == Some(&expected) {
+        true    => { *pos += 1; Some(()) }
+        false   => None,
+    }
+}
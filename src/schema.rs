@@ -0,0 +1,232 @@
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+
+use crate::rewrite;
+use crate::Error;
+
+/// The directory pages are stored in within a document archive.
+pub const PAGES_DIR: &str = "pages/";
+
+/// The type of the top level frames on a page.
+pub const ARTBOARD: &str = "artboard";
+
+/// The fields of an object the model doesn't describe, kept so they're written back unchanged.
+pub type Extra = Map<String, Value>;
+
+/// Parse the JSON in `data` into `T`, overwriting `data` in the process.
+///
+/// With the `simd-json` feature the JSON is parsed with SIMD instructions, which is several times
+/// faster on the tens of megabytes `document.json` grows to in large documents.
+pub fn from_slice<T: DeserializeOwned>(data: &mut [u8]) -> Result<T, Error> {
+    #[cfg(feature = "simd-json")]
+    return Ok(simd_json::serde::from_slice(data)?);
+
+    #[cfg(not(feature = "simd-json"))]
+    return Ok(serde_json::from_slice(data)?);
+}
+
+/// Parse the color variables of the `document.json` text `json`, without parsing the rest of it.
+pub fn color_variables(json: &str) -> Result<Vec<ColorVariable>, Error> {
+    let variables = match rewrite::find_member(json, "colorVariables") {
+        Some(range) => from_slice::<Option<Vec<ColorVariable>>>(&mut json[range].as_bytes().to_vec())?,
+        None        => from_slice::<DocumentJson>(&mut json.as_bytes().to_vec())?.color_variables,
+    };
+
+    Ok(variables.unwrap_or_default())
+}
+
+/// The contents of `document.json`, holding the variables and shared styles of a document and
+/// the order of its pages.
+#[derive(Clone, Debug, Default, PartialEq, Deserialize, Serialize)]
+pub struct DocumentJson {
+    /// The color variables of the document.
+    #[serde(rename = "colorVariables", default, skip_serializing_if = "Option::is_none")]
+    pub color_variables : Option<Vec<ColorVariable>>,
+    /// The shared color styles of the document.
+    #[serde(rename = "colorStyles", default, skip_serializing_if = "Option::is_none")]
+    pub color_styles    : Option<Vec<Style>>,
+    /// The shared text styles of the document.
+    #[serde(rename = "textStyles", default, skip_serializing_if = "Option::is_none")]
+    pub text_styles     : Option<Vec<Style>>,
+    /// The shared effect styles of the document.
+    #[serde(rename = "effectStyles", default, skip_serializing_if = "Option::is_none")]
+    pub effect_styles   : Option<Vec<Style>>,
+    /// The pages of the document in order, absent from documents that order pages by archive
+    /// order.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pages           : Option<Vec<PageRef>>,
+    /// The rest of the document.
+    #[serde(flatten)]
+    pub extra           : Extra,
+}
+
+impl DocumentJson {
+    /// Get the color variables of the document.
+    pub fn color_variables(&self) -> &[ColorVariable] {
+        self.color_variables.as_deref().unwrap_or_default()
+    }
+
+    /// Get the shared styles of the document by kind, e.g. `text`.
+    pub fn styles(&self) -> [(&'static str, &[Style]); 3] {
+        [
+            ("color",   self.color_styles.as_deref().unwrap_or_default()),
+            ("text",    self.text_styles.as_deref().unwrap_or_default()),
+            ("effect",  self.effect_styles.as_deref().unwrap_or_default()),
+        ]
+    }
+
+    /// Get the color variables of the document to modify, adding the list if it's missing.
+    pub fn color_variables_mut(&mut self) -> &mut Vec<ColorVariable> {
+        self.color_variables.get_or_insert_with(Vec::new)
+    }
+}
+
+/// A named color shared across a document.
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+pub struct ColorVariable {
+    /// The unique id of the variable.
+    pub id      : String,
+    /// The number of times the variable has been changed.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub version : Option<u32>,
+    /// The hierarchical name of the variable, e.g. `theme / blue / blue.500`.
+    pub name    : String,
+    /// The hex value of the color, without a leading `#`.
+    pub value   : String,
+    /// The rest of the variable.
+    #[serde(flatten)]
+    pub extra   : Extra,
+}
+
+/// A shared style, of which only the identity is described.
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+pub struct Style {
+    /// The unique id of the style.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub id      : Option<String>,
+    /// The hierarchical name of the style.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name    : Option<String>,
+    /// The properties of the style.
+    #[serde(flatten)]
+    pub extra   : Extra,
+}
+
+/// A reference to a page from the `pages` list of `document.json`.
+///
+/// Pages are listed either by id or, in documents imported from Sketch, by an object with a file
+/// reference to the entry holding the page.
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+#[serde(untagged)]
+pub enum PageRef {
+    /// A page listed by id.
+    Id(String),
+    /// A page listed by an object referring to it.
+    Object(Extra),
+}
+
+impl PageRef {
+    /// Get the id of the page referred to, the name of its entry without the directory or
+    /// extension.
+    pub fn page_id(&self) -> Option<&str> {
+        let id = match self {
+            Self::Id(id)        => id.as_str(),
+            Self::Object(x)     => x.get("_ref").or(x.get("id")).and_then(|x| x.as_str())?,
+        };
+
+        let id = id.strip_prefix(PAGES_DIR).unwrap_or(id);
+        Some(id.strip_suffix(".json").unwrap_or(id))
+    }
+}
+
+/// A page of a document, stored in its own entry under `pages/`.
+#[derive(Clone, Debug, Default, PartialEq, Deserialize, Serialize)]
+pub struct Page {
+    /// The name of the page.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name    : Option<String>,
+    /// The top level layers on the page.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub layers  : Option<Vec<Layer>>,
+    /// The rest of the page.
+    #[serde(flatten)]
+    pub extra   : Extra,
+}
+
+impl Page {
+    /// Get the top level layers on the page.
+    pub fn layers(&self) -> &[Layer] {
+        self.layers.as_deref().unwrap_or_default()
+    }
+
+    /// Visit every layer on the page depth first.
+    pub fn visit<'a>(&'a self, f: &mut impl FnMut(&'a Layer)) {
+        for layer in self.layers() {
+            layer.visit(f);
+        }
+    }
+
+    /// Get every artboard on the page, in document order.
+    pub fn artboards(&self) -> Vec<&Layer> {
+        let mut artboards = vec![];
+        self.visit(&mut |x| {
+            if x.kind() == ARTBOARD {
+                artboards.push(x);
+            }
+        });
+
+        artboards
+    }
+}
+
+/// A layer on a page, of which the identity and children are described.
+#[derive(Clone, Debug, Default, PartialEq, Deserialize, Serialize)]
+pub struct Layer {
+    /// The type of the layer, e.g. `artboard`.
+    #[serde(rename = "type", default, skip_serializing_if = "Option::is_none")]
+    pub kind    : Option<String>,
+    /// The unique id of the layer.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub id      : Option<String>,
+    /// The name of the layer.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name    : Option<String>,
+    /// The child layers of the layer.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub layers  : Option<Vec<Layer>>,
+    /// The rest of the layer.
+    #[serde(flatten)]
+    pub extra   : Extra,
+}
+
+impl Layer {
+    /// Get the type of the layer, lowercased for comparison.
+    ///
+    /// Lunacy stores the type under `type`, documents imported from Sketch under `_class`.
+    pub fn kind(&self) -> String {
+        self.kind.as_deref()
+            .or(self.extra.get("_class").and_then(|x| x.as_str()))
+            .unwrap_or_default()
+            .to_lowercase()
+    }
+
+    /// Get the id of the layer, Lunacy stores this under `id` and Sketch under `do_objectID`.
+    pub fn id(&self) -> Option<&str> {
+        self.id.as_deref()
+            .or(self.extra.get("do_objectID").and_then(|x| x.as_str()))
+    }
+
+    /// Get the child layers of the layer.
+    pub fn layers(&self) -> &[Layer] {
+        self.layers.as_deref().unwrap_or_default()
+    }
+
+    /// Visit the layer and every layer below it depth first.
+    pub fn visit<'a>(&'a self, f: &mut impl FnMut(&'a Layer)) {
+        f(self);
+        for layer in self.layers() {
+            layer.visit(f);
+        }
+    }
+}
@@ -0,0 +1,311 @@
+use std::collections::HashMap;
+
+use base64::Engine;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use serde::{Deserialize, Serialize, Serializer};
+use serde_json::{json, Value};
+use uuid::Uuid;
+
+use crate::palette::ColorParseError;
+use crate::rewrite;
+use crate::schema::{self, ColorVariable, Extra};
+use crate::Error;
+
+/// The member of a Sketch `document.json` holding the shared swatches of the document.
+pub const SWATCHES_KEY: &str = "sharedSwatches";
+
+/// The members of a Sketch `document.json` holding the shared styles of the document, whose
+/// colors may refer to swatches.
+pub const STYLE_KEYS: &[&str] = &["layerStyles", "layerTextStyles"];
+
+/// Check whether the `document.json` text `json` is from a Sketch document.
+///
+/// Sketch tags every object with its `_class`, which Lunacy documents don't have, and writes it
+/// first so only the start of the text is looked at.
+pub fn is_sketch(json: &str) -> bool {
+    rewrite::find_member(json, "_class").is_some_and(|x| &json[x] == "\"document\"")
+}
+
+/// Parse the shared swatches of the Sketch `document.json` text `json` as color variables,
+/// without parsing the rest of it.
+///
+/// Swatch names are split into groups by `/` as in Sketch, and written with the ` / ` separator
+/// Lunacy uses, so color schemes apply to them as they do to Lunacy color variables.
+pub fn color_variables(json: &str) -> Result<Vec<ColorVariable>, Error> {
+    Ok(swatches(json)?.objects.iter().map(Swatch::to_variable).collect())
+}
+
+/// Write the color `variables` back to the shared swatches of the Sketch `document.json` text
+/// `json`, returning the updated text.
+///
+/// Swatches keep their order and the text of any that didn't change, new swatches are added
+/// after them. Colors in shared styles that refer to a changed swatch are updated with it, the
+/// rest of the document is copied as it is.
+pub fn write_color_variables(json: &str, variables: &[ColorVariable]) -> Result<String, Error> {
+    let mut container   = swatches(json)?;
+    let mut remaining   = variables.iter()
+        .map(|x| (x.id.as_str(), x))
+        .collect::<HashMap<_, _>>();
+
+    // Keep the swatches that weren't removed in their original order, then add the new ones.
+    let mut objects = vec![];
+    let mut changed = HashMap::new();
+    for swatch in &container.objects {
+        let Some(variable) = remaining.remove(swatch.to_variable().id.as_str()) else {
+            continue;
+        };
+
+        let updated = swatch.update(variable)?;
+        if updated.value != swatch.value {
+            changed.insert(updated.id.clone(), updated.value.clone());
+        }
+
+        objects.push(updated);
+    }
+
+    for variable in variables.iter().filter(|x| remaining.contains_key(x.id.as_str())) {
+        objects.push(Swatch::from_variable(variable)?);
+    }
+
+    container.objects = objects;
+
+    let mut json = match rewrite::find_member(json, SWATCHES_KEY) {
+        Some(range) => rewrite::splice(json, range, &container)?,
+        None        => {
+            let mut document = schema::from_slice::<serde_json::Map<String, Value>>(&mut json.as_bytes().to_vec())?;
+            document.insert(SWATCHES_KEY.to_owned(), serde_json::to_value(&container)?);
+
+            rewrite::to_string(json, &document)?
+        }
+    };
+
+    if changed.is_empty() {
+        return Ok(json);
+    }
+
+    for key in STYLE_KEYS {
+        let Some(range) = rewrite::find_member(&json, key) else {
+            continue;
+        };
+
+        let mut styles = schema::from_slice::<Value>(&mut json[range.clone()].as_bytes().to_vec())?;
+        if update_swatch_colors(&mut styles, &changed) {
+            json = rewrite::splice(&json, range, &styles)?;
+        }
+    }
+
+    Ok(json)
+}
+
+/// Parse the swatch container of the Sketch `document.json` text `json`, or an empty one if it
+/// has none.
+fn swatches(json: &str) -> Result<SwatchContainer, Error> {
+    let container = match rewrite::find_member(json, SWATCHES_KEY) {
+        Some(range) => schema::from_slice(&mut json[range].as_bytes().to_vec())?,
+        None        => None,
+    };
+
+    Ok(container.unwrap_or_default())
+}
+
+/// Update every color in `value` that refers to one of the `changed` swatches by id, returning
+/// whether any were.
+fn update_swatch_colors(value: &mut Value, changed: &HashMap<String, SketchColor>) -> bool {
+    match value {
+        Value::Object(map) => {
+            let swatch = map.get("swatchID").and_then(|x| x.as_str());
+            if let Some(color) = swatch.and_then(|x| changed.get(x)) {
+                map.insert("red".to_owned(),    component(color.red));
+                map.insert("green".to_owned(),  component(color.green));
+                map.insert("blue".to_owned(),   component(color.blue));
+                map.insert("alpha".to_owned(),  component(color.alpha));
+
+                return true;
+            }
+
+            map.values_mut().fold(false, |updated, x| update_swatch_colors(x, changed) | updated)
+        }
+        Value::Array(items) => {
+            items.iter_mut().fold(false, |updated, x| update_swatch_colors(x, changed) | updated)
+        }
+        _ => false,
+    }
+}
+
+/// The container of the shared swatches of a Sketch document.
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+pub struct SwatchContainer {
+    /// The swatches in the container.
+    #[serde(default)]
+    pub objects : Vec<Swatch>,
+    /// The rest of the container.
+    #[serde(flatten)]
+    pub extra   : Extra,
+}
+
+impl Default for SwatchContainer {
+    fn default() -> Self {
+        let mut extra = Extra::new();
+        extra.insert("_class".to_owned(), json!("swatchContainer"));
+        extra.insert("do_objectID".to_owned(), json!(sketch_id(SWATCHES_KEY)));
+
+        Self { objects: vec![], extra }
+    }
+}
+
+/// A named color shared across a Sketch document.
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+pub struct Swatch {
+    /// The unique id of the swatch, an uppercase uuid.
+    #[serde(rename = "do_objectID")]
+    pub id      : String,
+    /// The hierarchical name of the swatch, e.g. `theme/blue/blue.500`.
+    pub name    : String,
+    /// The color of the swatch.
+    pub value   : SketchColor,
+    /// The rest of the swatch.
+    #[serde(flatten)]
+    pub extra   : Extra,
+}
+
+impl Swatch {
+    /// Create a swatch from a color variable, as added by a color scheme.
+    pub fn from_variable(variable: &ColorVariable) -> Result<Self, Error> {
+        let mut extra = Extra::new();
+        extra.insert("_class".to_owned(), json!("swatch"));
+
+        let swatch = Self {
+            id      : sketch_id(&variable.id),
+            name    : String::new(),
+            value   : SketchColor::default(),
+            extra,
+        };
+
+        swatch.update(variable)
+    }
+
+    /// Convert the swatch to a color variable, the id of which is the swatch id as Lunacy
+    /// writes it, see `lunacy_id`.
+    pub fn to_variable(&self) -> ColorVariable {
+        ColorVariable {
+            id      : lunacy_id(&self.id),
+            version : None,
+            name    : self.name.split('/').map(str::trim).collect::<Vec<_>>().join(" / "),
+            value   : self.value.to_hex(),
+            extra   : Extra::new(),
+        }
+    }
+
+    /// Get a copy of the swatch with the name and color of `variable`, keeping the name and color
+    /// as they were written where they're unchanged.
+    fn update(&self, variable: &ColorVariable) -> Result<Self, Error> {
+        let current     = self.to_variable();
+        let mut swatch  = self.clone();
+
+        if current.name != variable.name {
+            swatch.name = variable.name.split(" / ").collect::<Vec<_>>().join("/");
+        }
+
+        if !current.value.eq_ignore_ascii_case(&variable.value) {
+            swatch.value.set_hex(&variable.value)?;
+        }
+
+        Ok(swatch)
+    }
+}
+
+/// A color in a Sketch document, with components from 0 to 1.
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+pub struct SketchColor {
+    /// The alpha component of the color.
+    #[serde(default = "opaque", serialize_with = "serialize_component")]
+    pub alpha   : f64,
+    /// The blue component of the color.
+    #[serde(default, serialize_with = "serialize_component")]
+    pub blue    : f64,
+    /// The green component of the color.
+    #[serde(default, serialize_with = "serialize_component")]
+    pub green   : f64,
+    /// The red component of the color.
+    #[serde(default, serialize_with = "serialize_component")]
+    pub red     : f64,
+    /// The rest of the color.
+    #[serde(flatten)]
+    pub extra   : Extra,
+}
+
+impl Default for SketchColor {
+    fn default() -> Self {
+        let mut extra = Extra::new();
+        extra.insert("_class".to_owned(), json!("color"));
+
+        Self { alpha: 1.0, blue: 0.0, green: 0.0, red: 0.0, extra }
+    }
+}
+
+impl SketchColor {
+    /// Get the color as Lunacy writes color variable values, as `RRGGBB` hex or `RRGGBBAA` if
+    /// it isn't opaque.
+    fn to_hex(&self) -> String {
+        let [r, g, b, a] = [self.red, self.green, self.blue, self.alpha].map(|x| (x.clamp(0.0, 1.0) * 255.0).round() as u8);
+        match a {
+            255 => format!("{r:02X}{g:02X}{b:02X}"),
+            _   => format!("{r:02X}{g:02X}{b:02X}{a:02X}"),
+        }
+    }
+
+    /// Set the color from a color variable value in `RRGGBB` or `RRGGBBAA` hex.
+    fn set_hex(&mut self, value: &str) -> Result<(), Error> {
+        let hex = u32::from_str_radix(value, 16)?;
+        let (hex, alpha) = match value.len() {
+            6 => (hex, 0xff),
+            8 => (hex >> 8, hex & 0xff),
+            _ => return Err(Box::new(ColorParseError::InvalidFormat)),
+        };
+
+        self.red    = (hex >> 16 & 0xff) as f64 / 255.0;
+        self.green  = (hex >> 8 & 0xff) as f64 / 255.0;
+        self.blue   = (hex & 0xff) as f64 / 255.0;
+        self.alpha  = alpha as f64 / 255.0;
+
+        Ok(())
+    }
+}
+
+/// The alpha of a color that doesn't record one.
+fn opaque() -> f64 {
+    1.0
+}
+
+/// Get a color component as Sketch writes it, with whole numbers written without a fraction.
+fn component(x: f64) -> Value {
+    match x.fract() == 0.0 {
+        true    => json!(x as i64),
+        false   => json!(x),
+    }
+}
+
+/// Serialize a color component as Sketch writes it, see `component`.
+fn serialize_component<S: Serializer>(x: &f64, serializer: S) -> Result<S::Ok, S::Error> {
+    component(*x).serialize(serializer)
+}
+
+/// Convert the Sketch object id `id` to the id Lunacy would write for it, the same uuid encoded
+/// as base64. Ids that aren't uuids are mapped to one derived from them.
+pub fn lunacy_id(id: &str) -> String {
+    let id = Uuid::parse_str(id)
+        .unwrap_or_else(|_| Uuid::new_v5(&Uuid::NAMESPACE_OID, id.as_bytes()));
+
+    URL_SAFE_NO_PAD.encode(id.as_bytes())
+}
+
+/// Convert the Lunacy object id `id` to the id Sketch would write for it, the same uuid as
+/// uppercase hex, so converting back with `lunacy_id` gives the original id. Ids that aren't
+/// encoded uuids are mapped to one derived from them.
+pub fn sketch_id(id: &str) -> String {
+    let id = URL_SAFE_NO_PAD.decode(id).ok()
+        .and_then(|x| Uuid::from_slice(&x).ok())
+        .unwrap_or_else(|| Uuid::new_v5(&Uuid::NAMESPACE_OID, id.as_bytes()));
+
+    id.hyphenated().to_string().to_uppercase()
+}
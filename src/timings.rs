@@ -0,0 +1,122 @@
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// The time spent in each phase of the run, when `--timings` is given.
+static TIMINGS: Mutex<Option<Timings>> = Mutex::new(None);
+
+/// A phase of a run timed by `--timings`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Phase {
+    /// Reading the archive index and extracting entries.
+    Extract,
+    /// Parsing json entries.
+    Parse,
+    /// Generating color palettes.
+    Generate,
+    /// Applying changes to parsed entries.
+    Apply,
+    /// Serializing changed entries.
+    Serialize,
+    /// Recording changes in the document history.
+    History,
+    /// Writing the archive.
+    Zip,
+}
+
+impl Phase {
+    /// Every phase, in the order they happen.
+    const ALL: [Self; 7] = [
+        Self::Extract,
+        Self::Parse,
+        Self::Generate,
+        Self::Apply,
+        Self::Serialize,
+        Self::History,
+        Self::Zip,
+    ];
+
+    /// Get the name the phase is reported with.
+    fn name(self) -> &'static str {
+        match self {
+            Self::Extract   => "extract",
+            Self::Parse     => "parse",
+            Self::Generate  => "generate",
+            Self::Apply     => "apply",
+            Self::Serialize => "serialize",
+            Self::History   => "history",
+            Self::Zip       => "zip",
+        }
+    }
+}
+
+/// The time spent in each phase of the run.
+struct Timings {
+    /// When the run started.
+    started : Instant,
+    /// The total time spent in each phase, by phase.
+    totals  : [Duration; Phase::ALL.len()],
+    /// The phase being timed and when it was last entered.
+    current : Option<(Phase, Instant)>,
+}
+
+/// Start timing the phases of the run.
+pub fn enable() {
+    if let Ok(mut timings) = TIMINGS.lock() {
+        *timings = Some(Timings {
+            started : Instant::now(),
+            totals  : Default::default(),
+            current : None,
+        });
+    }
+}
+
+/// Run `f`, counting the time it takes towards `phase` when timings are enabled.
+///
+/// Phases timed within `f` are counted towards themselves rather than `phase`, so no time is
+/// counted twice.
+pub fn time<T>(phase: Phase, f: impl FnOnce() -> T) -> T {
+    let previous    = switch(Some(phase));
+    let result      = f();
+    switch(previous);
+
+    result
+}
+
+/// Switch the phase being timed to `phase`, returning the phase timed before.
+fn switch(phase: Option<Phase>) -> Option<Phase> {
+    let mut timings = TIMINGS.lock().ok()?;
+    let timings     = timings.as_mut()?;
+
+    let now         = Instant::now();
+    let previous    = timings.current.take().map(|(phase, entered)| {
+        timings.totals[phase as usize] += now - entered;
+        phase
+    });
+
+    timings.current = phase.map(|x| (x, now));
+    previous
+}
+
+/// Print the time spent in each phase of the run to stderr, if timings are enabled.
+///
+/// Time spent outside the timed phases, such as in reading the command line or waiting for
+/// confirmation, is reported as `other`.
+pub fn report() {
+    let Some(timings) = TIMINGS.lock().ok().and_then(|mut x| x.take()) else {
+        return;
+    };
+
+    let total   = timings.started.elapsed();
+    let timed   = timings.totals.iter().sum::<Duration>();
+
+    let mut rows = Phase::ALL.iter()
+        .map(|x| (x.name(), timings.totals[*x as usize]))
+        .collect::<Vec<_>>();
+    rows.push(("other", total.saturating_sub(timed)));
+    rows.push(("total", total));
+
+    eprintln!("{:<10}  {:>10}", "phase", "time");
+    for (name, time) in rows {
+        eprintln!("{name:<10}  {:>7.1} ms", time.as_secs_f64() * 1000.0);
+    }
+}